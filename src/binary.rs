@@ -2,19 +2,57 @@ extern crate goblin;
 extern crate memmap;
 extern crate owning_ref;
 
+use std;
+
+use dwarf;
 use log;
+use unwind;
 use self::goblin::elf;
 use self::owning_ref::OwningHandle;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolType {
+    NoType,
+    Object,
+    Func,
+    Tls,
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binding {
+    Local,
+    Global,
+    Weak,
+}
+
 pub struct Symbol<'a> {
     pub name: &'a str,
     pub value: u64,
+    pub size: u64,
+    pub kind: SymbolType,
+    pub binding: Binding,
+    // The name of the defining section, None for undefined or absolute
+    // symbols.
+    pub section: Option<&'a str>,
+    // The source file of a local symbol, which tells apart statics with the
+    // same name.
+    pub file: Option<&'a str>,
+}
+
+pub struct Section<'a> {
+    pub addr: u64,
+    pub data: &'a [u8],
 }
 
 pub struct Binary<'a> {
     filename: String,
     o: OwningHandle<Box<memmap::Mmap>, Box<goblin::elf::Elf<'a>>>,
+    data: &'a [u8],
     bias: u64,
+    lines: dwarf::LineTable,
+    debug_info: Option<dwarf::DebugInfo<'a>>,
+    cfi: unwind::CallFrameInfo<'a>,
 }
 
 impl<'a> Binary<'a> {
@@ -23,6 +61,7 @@ impl<'a> Binary<'a> {
         let mem = try!(
             memmap::Mmap::open_path(&filename, memmap::Protection::Read)
                 .or(Err(format!("Failed to open: {}", &filename))));
+        let data = unsafe { std::slice::from_raw_parts(mem.ptr(), mem.len()) };
         let o = try!(OwningHandle::try_new(
             Box::new(mem), |mem| -> Result<_, _> {
                 let mem = unsafe { &*mem };
@@ -31,40 +70,234 @@ impl<'a> Binary<'a> {
                     Err(_) => Err(format!("Parse failed: {}", &filename)),
                 }
             }));
-        return Ok(Binary {
+        let mut bin = Binary {
             filename: filename,
             o: o,
+            data: data,
             bias: 0,
-        });
+            lines: dwarf::LineTable::empty(),
+            debug_info: None,
+            cfi: unwind::CallFrameInfo::empty(),
+        };
+        let secs = dwarf::Sections::new(&bin);
+        match dwarf::LineTable::parse(&secs) {
+            Ok(lines) => bin.lines = lines,
+            Err(msg) => {
+                log_info!("{}: {}", bin.filename, msg);
+            }
+        }
+        if !secs.debug_info.is_empty() {
+            match dwarf::DebugInfo::parse(secs) {
+                Ok(info) => bin.debug_info = Some(info),
+                Err(msg) => {
+                    log_info!("{}: {}", bin.filename, msg);
+                }
+            }
+        }
+        match unwind::CallFrameInfo::parse(&bin) {
+            Ok(cfi) => bin.cfi = cfi,
+            Err(msg) => {
+                log_info!("{}: {}", bin.filename, msg);
+            }
+        }
+        return Ok(bin);
     }
 
     pub fn filename(&self) -> &String { &self.filename }
 
+    // Returns symbols in .symtab followed by ones only in .dynsym.
     pub fn syms(&self) -> Vec<Symbol<'a>> {
-        let (syms, strtab) = if self.o.syms.len() == 0 {
-            (&self.o.dynsyms, &self.o.dynstrtab)
-        } else {
-            (&self.o.syms, &self.o.strtab)
-        };
+        let mut r: Vec<Symbol<'a>> = vec!();
+        let mut seen = std::collections::HashSet::new();
+        let tables = [(&self.o.syms, &self.o.strtab),
+                      (&self.o.dynsyms, &self.o.dynstrtab)];
+        for &(syms, strtab) in tables.iter() {
+            let mut file = None;
+            for sym in syms {
+                let name = if sym.st_name == 0 {
+                    ""
+                } else {
+                    match strtab.get(sym.st_name) {
+                        Ok(name) => name,
+                        Err(_) => {
+                            println!("{}: invalid strtab", self.filename);
+                            continue;
+                        }
+                    }
+                };
+                let kind = match sym.st_type() {
+                    elf::sym::STT_FILE => {
+                        file = if name.is_empty() { None } else { Some(name) };
+                        continue;
+                    }
+                    elf::sym::STT_SECTION => continue,
+                    elf::sym::STT_NOTYPE => SymbolType::NoType,
+                    elf::sym::STT_OBJECT => SymbolType::Object,
+                    elf::sym::STT_FUNC => SymbolType::Func,
+                    elf::sym::STT_TLS => SymbolType::Tls,
+                    _ => SymbolType::Other,
+                };
+                if name.is_empty() || !seen.insert((name, sym.st_value)) {
+                    continue;
+                }
+                let binding = match sym.st_bind() {
+                    elf::sym::STB_LOCAL => Binding::Local,
+                    elf::sym::STB_WEAK => Binding::Weak,
+                    _ => Binding::Global,
+                };
+                r.push(Symbol {
+                    name: name,
+                    value: sym.st_value as u64,
+                    size: sym.st_size as u64,
+                    kind: kind,
+                    binding: binding,
+                    section: self.section_name_by_index(sym.st_shndx as usize),
+                    file: if binding == Binding::Local { file } else { None },
+                });
+            }
+        }
+        return r;
+    }
 
+    // Synthesizes symbols of the x86-64 PLT entries, e.g. "atoi@plt", from
+    // the relocations of their GOT slots, which are in the order of the
+    // entries. Returns their names and addresses.
+    pub fn plt_syms(&self) -> Vec<(String, u64)> {
+        const PLT_ENTRY_SIZE: u64 = 16;
+        // With IBT, calls go to .plt.sec, which has no header entry.
+        let (start, end) = match self.section(".plt.sec") {
+            Some(plt) => (plt.addr, plt.addr + plt.data.len() as u64),
+            None => match self.section(".plt") {
+                Some(plt) => (plt.addr + PLT_ENTRY_SIZE,
+                              plt.addr + plt.data.len() as u64),
+                None => return vec!(),
+            },
+        };
+        let strtab = &self.o.dynstrtab;
         let mut r = vec!();
-        for sym in syms {
-            if sym.st_name == 0 {
-                continue;
+        for (i, rel) in self.o.pltrelocs.iter().enumerate() {
+            let addr = start + i as u64 * PLT_ENTRY_SIZE;
+            if addr >= end {
+                break;
             }
+            let sym = match self.o.dynsyms.get(rel.r_sym) {
+                Some(sym) => sym,
+                None => continue,
+            };
             match strtab.get(sym.st_name) {
-                Ok(name) => {
-                    r.push(Symbol {
-                        name: name,
-                        value: sym.st_value as u64
+                Ok(name) if !name.is_empty() => {
+                    r.push((format!("{}@plt", name), addr));
+                }
+                _ => {}
+            }
+        }
+        return r;
+    }
+
+    fn section_header_name(&self, sh: &elf::SectionHeader) -> Option<&'a str> {
+        let shdrs = &self.o.section_headers;
+        let shstrndx = self.o.header.e_shstrndx as usize;
+        if shstrndx >= shdrs.len() {
+            return None;
+        }
+        let shstrtab = shdrs[shstrndx].sh_offset as usize;
+        return dwarf::cstr_at(self.data, shstrtab + sh.sh_name as usize).ok();
+    }
+
+    fn section_name_by_index(&self, index: usize) -> Option<&'a str> {
+        if index == elf::section_header::SHN_UNDEF as usize ||
+            index >= self.o.section_headers.len() {
+            return None;
+        }
+        return self.section_header_name(&self.o.section_headers[index]);
+    }
+
+    pub fn section(&self, name: &str) -> Option<Section<'a>> {
+        let shdrs = &self.o.section_headers;
+        let shstrndx = self.o.header.e_shstrndx as usize;
+        if shstrndx >= shdrs.len() {
+            return None;
+        }
+        let shstrtab = shdrs[shstrndx].sh_offset as usize;
+        for sh in shdrs {
+            if sh.sh_type == elf::section_header::SHT_NOBITS {
+                continue;
+            }
+            match dwarf::cstr_at(self.data, shstrtab + sh.sh_name as usize) {
+                Ok(n) if n == name => {
+                    let start = sh.sh_offset as usize;
+                    let end = start + sh.sh_size as usize;
+                    if end > self.data.len() {
+                        return None;
+                    }
+                    return Some(Section {
+                        addr: sh.sh_addr,
+                        data: &self.data[start..end],
                     });
                 }
-                Err(_) => {
-                    println!("{}: invalid strtab", self.filename);
+                _ => {}
+            }
+        }
+        return None;
+    }
+
+    // Returns the name of the allocated section which contains `addr`
+    // (without the bias).
+    pub fn section_name(&self, addr: u64) -> Option<&'a str> {
+        for sh in &self.o.section_headers {
+            if sh.sh_flags & elf::section_header::SHF_ALLOC as u64 == 0 ||
+                addr < sh.sh_addr || addr >= sh.sh_addr + sh.sh_size {
+                continue;
+            }
+            return self.section_header_name(sh);
+        }
+        return None;
+    }
+
+    pub fn lines(&self) -> &dwarf::LineTable { &self.lines }
+
+    pub fn debug_info(&self) -> Option<&dwarf::DebugInfo<'a>> {
+        self.debug_info.as_ref()
+    }
+
+    pub fn cfi(&self) -> &unwind::CallFrameInfo<'a> { &self.cfi }
+
+    // Whether `addr` (without the bias) is in a loadable segment.
+    pub fn contains(&self, addr: u64) -> bool {
+        self.o.program_headers.iter().any(|ph| {
+            ph.p_type == elf::program_header::PT_LOAD &&
+                ph.p_vaddr <= addr && addr < ph.p_vaddr + ph.p_memsz
+        })
+    }
+
+    // Reads the initial contents of memory at `addr` (without the bias)
+    // from the loadable segments.
+    pub fn read_static(&self, addr: u64, len: usize) -> Option<Vec<u8>> {
+        for ph in &self.o.program_headers {
+            if ph.p_type != elf::program_header::PT_LOAD ||
+                addr < ph.p_vaddr ||
+                addr + len as u64 > ph.p_vaddr + ph.p_memsz {
+                continue;
+            }
+            let mut r = vec![0; len];
+            for i in 0..len {
+                let off = addr + i as u64 - ph.p_vaddr;
+                if off < ph.p_filesz {
+                    r[i] = self.data[(ph.p_offset + off) as usize];
                 }
             }
+            return Some(r);
         }
-        return r;
+        return None;
+    }
+
+    // The page-aligned address (without the bias) where the beginning of
+    // the file is mapped.
+    pub fn file_start_addr(&self) -> Option<u64> {
+        self.o.program_headers.iter()
+            .find(|ph| ph.p_type == elf::program_header::PT_LOAD &&
+                  ph.p_offset == 0)
+            .map(|ph| ph.p_vaddr & !(ph.p_align.max(1) - 1))
     }
 
     pub fn interp(&self) -> Option<&str> {
@@ -75,6 +308,14 @@ impl<'a> Binary<'a> {
         return self.o.entry;
     }
 
+    // The address of the program headers (without the bias), if they are
+    // loaded.
+    pub fn phdr(&self) -> Option<u64> {
+        self.o.program_headers.iter()
+            .find(|ph| ph.p_type == elf::program_header::PT_PHDR)
+            .map(|ph| ph.p_vaddr)
+    }
+
     #[allow(dead_code)]
     pub fn bias(&self) -> u64 {
         return self.bias;
@@ -97,3 +338,34 @@ fn test_c_binary() {
     }
     assert_eq!(1, found_count);
 }
+
+#[test]
+fn test_symbol_metadata() {
+    let bin = Binary::new("test/data/statics".to_string()).unwrap();
+    let syms = bin.syms();
+    let main = syms.iter().find(|s| s.name == "main").unwrap();
+    assert_eq!(SymbolType::Func, main.kind);
+    assert_eq!(Binding::Global, main.binding);
+    assert_eq!(Some(".text"), main.section);
+    assert!(main.size > 0);
+    // Statics with the same name are told apart by their files.
+    let counters: Vec<_> = syms.iter()
+        .filter(|s| s.name == "counter")
+        .map(|s| (s.kind, s.binding, s.size, s.section, s.file))
+        .collect();
+    assert_eq!(vec!((SymbolType::Object, Binding::Local, 4, Some(".data"),
+                     Some("statics.c")),
+                    (SymbolType::Object, Binding::Local, 4, Some(".data"),
+                     Some("statics2.c"))),
+               counters);
+    // Symbols in both .symtab and .dynsym appear once.
+    assert_eq!(1, syms.iter().filter(|s| s.name == "__cxa_finalize").count());
+}
+
+#[test]
+fn test_plt_syms() {
+    let bin = Binary::new("test/data/watch".to_string()).unwrap();
+    assert_eq!(vec!(("atoi@plt".to_string(), 0x401030)), bin.plt_syms());
+    let bin = Binary::new("test/data/hello".to_string()).unwrap();
+    assert!(bin.plt_syms().iter().any(|&(ref name, _)| name == "puts@plt"));
+}
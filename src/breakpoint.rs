@@ -1,8 +1,20 @@
 use ptracer;
+use types;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Action {
     EnterMainBinary,
     UpdateRDebug,
+    // Single-stepping for software watchpoints goes on after a call into
+    // uninteresting code returns here.
+    ResumeStepping,
+}
+
+// How a user breakpoint was specified, to find it again in a new program.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Spec {
+    Function (String),
+    Line (Option<String>, u32),
 }
 
 pub struct Breakpoint {
@@ -12,17 +24,119 @@ pub struct Breakpoint {
     token: u8,
     is_active: bool,
     action: Option<Action>,
+    spec: Option<Spec>,
 }
 
 impl Breakpoint {
     pub fn id(&self) -> i32 { self.id }
     pub fn addr(&self) -> u64 { self.addr }
     pub fn token(&self) -> u8 { self.token }
+    pub fn is_active(&self) -> bool { self.is_active }
     pub fn action(&self) -> &Option<Action> { &self.action }
 }
 
+// The accesses which trigger a watchpoint, for `watch`, `rwatch` and
+// `awatch`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+impl WatchKind {
+    // The name used in reports, e.g., "Hardware watchpoint 2: x".
+    pub fn title(&self) -> &'static str {
+        match *self {
+            WatchKind::Write => "Hardware watchpoint",
+            WatchKind::Read => "Hardware read watchpoint",
+            WatchKind::Access => "Hardware access (read/write) watchpoint",
+        }
+    }
+
+    // The type shown by `info breakpoints`.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            WatchKind::Write => "hw watchpoint",
+            WatchKind::Read => "read watchpoint",
+            WatchKind::Access => "acc watchpoint",
+        }
+    }
+}
+
+pub struct Watchpoint {
+    id: i32,
+    addr: u64,
+    kind: WatchKind,
+    // The expression as written by the user.
+    expr: String,
+    ty: types::Type,
+    // The contents when the watchpoint was set or last reported.
+    value: Vec<u8>,
+    // Software watchpoints are checked after every single step.
+    is_hardware: bool,
+    // The debug registers watching the region while inserted.
+    slots: Vec<usize>,
+}
+
+impl Watchpoint {
+    pub fn id(&self) -> i32 { self.id }
+    pub fn addr(&self) -> u64 { self.addr }
+    pub fn kind(&self) -> WatchKind { self.kind }
+    pub fn expr(&self) -> &str { &self.expr }
+    pub fn ty(&self) -> &types::Type { &self.ty }
+    pub fn value(&self) -> &[u8] { &self.value }
+    pub fn is_hardware(&self) -> bool { self.is_hardware }
+
+    pub fn title(&self) -> &'static str {
+        if self.is_hardware { self.kind.title() } else { "Watchpoint" }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        if self.is_hardware { self.kind.type_name() } else { "watchpoint" }
+    }
+}
+
+// The number of address registers of x86, DR0-DR3.
+const DEBUG_ADDR_REGS: usize = 4;
+// Larger regions are watched by software even if there are enough debug
+// registers, as they are likely arrays or structs changed by loops.
+const MAX_HARDWARE_WATCH: usize = 8;
+
+// Splits a region into aligned pieces of 1, 2, 4 or 8 bytes, which are
+// what a debug register can watch.
+fn split_region(addr: u64, len: usize) -> Vec<(u64, usize)> {
+    let mut pieces = vec!();
+    let end = addr + len as u64;
+    let mut cur = addr;
+    while cur < end {
+        let size = [8, 4, 2, 1].iter().cloned()
+            .find(|&s| cur % s == 0 && cur + s <= end).unwrap();
+        pieces.push((cur, size as usize));
+        cur += size;
+    }
+    return pieces;
+}
+
+// Returns the bits of DR7 which enable a debug register.
+fn debug_control(slot: usize, len: usize, kind: WatchKind) -> u64 {
+    // x86 cannot trap only reads, so reads are watched with writes.
+    let rw = match kind {
+        WatchKind::Write => 1,
+        WatchKind::Read | WatchKind::Access => 3,
+    };
+    let len_bits = match len {
+        1 => 0,
+        2 => 1,
+        8 => 2,
+        _ => 3,
+    };
+    return 1 << (slot * 2) | (rw | len_bits << 2) << (16 + slot * 4);
+}
+
 pub struct BreakpointManager {
     breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
     next_id: i32,
     next_sys_id: i32,
 }
@@ -31,30 +145,148 @@ impl BreakpointManager {
     pub fn new() -> Self {
         Self {
             breakpoints: vec!(),
+            watchpoints: vec!(),
             next_id: 0,
             next_sys_id: 0,
         }
     }
 
-    pub fn notify_start(&mut self, ptracer: &ptracer::Ptracer) {
+    // Inserts the breakpoints into a new process. Breakpoints which cannot
+    // be inserted stay inactive and are returned with the errors.
+    pub fn notify_start(&mut self, ptracer: &mut ptracer::Ptracer)
+                        -> Vec<(i32, ptracer::Error)> {
+        let mut failures = vec!();
         for bp in &mut self.breakpoints {
             assert!(!bp.is_active);
-            bp.token = ptracer.poke_breakpoint(bp.addr);
-            bp.is_active = true;
+            match ptracer.poke_breakpoint(bp.addr) {
+                Ok(token) => {
+                    bp.token = token;
+                    bp.is_active = true;
+                }
+                Err(err) => failures.push((bp.id, err)),
+            }
         }
+        if let Err(err) = self.insert_watchpoints(ptracer) {
+            failures.extend(self.watchpoints.iter().filter(|wp| {
+                wp.is_hardware
+            }).map(|wp| (wp.id, err.clone())));
+        }
+        return failures;
+    }
+
+    // Assigns debug registers to the watchpoints and sets them to all
+    // threads of the process.
+    fn insert_watchpoints(&mut self, ptracer: &mut ptracer::Ptracer)
+                          -> Result<(), ptracer::Error> {
+        let mut regs = ptracer::DebugRegs::default();
+        for wp in self.watchpoints.iter_mut().filter(|wp| wp.is_hardware) {
+            wp.slots.clear();
+            for (addr, len) in split_region(wp.addr, wp.ty.size()) {
+                let slot = regs.addrs.len();
+                regs.addrs.push(addr);
+                regs.control |= debug_control(slot, len, wp.kind);
+                wp.slots.push(slot);
+            }
+        }
+        let r = ptracer.set_debug_regs(regs);
+        if r.is_err() {
+            for wp in &mut self.watchpoints {
+                wp.slots.clear();
+            }
+        }
+        return r;
+    }
+
+    // Restores the original bytes before the process is released.
+    pub fn notify_detach(&mut self, ptracer: &mut ptracer::Ptracer)
+                         -> Result<(), ptracer::Error> {
+        let r = self.remove_from(ptracer);
+        let cleared = ptracer.set_debug_regs(ptracer::DebugRegs::default());
+        self.notify_finish();
+        return r.and(cleared);
+    }
+
+    // Restores the original bytes in a process which has a copy of the
+    // breakpoints, e.g. a forked child. All breakpoints are tried even if
+    // some of them fail.
+    pub fn remove_from(&self, ptracer: &ptracer::Ptracer)
+                       -> Result<(), ptracer::Error> {
+        let mut r = Ok(());
+        for bp in &self.breakpoints {
+            if bp.is_active {
+                if let Err(err) = ptracer.poke_byte(bp.addr, bp.token) {
+                    r = Err(err);
+                }
+            }
+        }
+        return r;
     }
 
     pub fn notify_finish(&mut self) {
         for bp in &mut self.breakpoints {
-            assert!(bp.is_active);
             bp.token = 0;
             bp.is_active = false;
         }
+        // Internal breakpoints are set again for the next process.
+        self.breakpoints.retain(|bp| bp.action.is_none());
+        for wp in &mut self.watchpoints {
+            wp.slots.clear();
+        }
     }
 
+    // Moves inactive breakpoints to the address returned by `f`, e.g. when
+    // the load bias of a binary changes between runs.
+    pub fn relocate<F: Fn(u64) -> Option<u64>>(&mut self, f: F) {
+        for bp in &mut self.breakpoints {
+            assert!(!bp.is_active);
+            if let Some(addr) = f(bp.addr) {
+                bp.addr = addr;
+            }
+        }
+        for wp in &mut self.watchpoints {
+            if let Some(addr) = f(wp.addr) {
+                wp.addr = addr;
+            }
+        }
+    }
+
+    // Finds inactive breakpoints again by their specs with `f`, e.g. after
+    // the process executed another program. Breakpoints which are not
+    // found are deleted and their IDs are returned, as are watchpoints,
+    // whose addresses mean nothing in another program.
+    pub fn respecify<F: Fn(&Spec) -> Option<u64>>(&mut self, f: F)
+                                                   -> Vec<i32> {
+        let mut deleted: Vec<i32> =
+            self.watchpoints.drain(..).map(|wp| wp.id).collect();
+        for bp in &mut self.breakpoints {
+            assert!(!bp.is_active);
+            if let Some(ref spec) = bp.spec {
+                match f(spec) {
+                    Some(addr) => bp.addr = addr,
+                    None => deleted.push(bp.id),
+                }
+            }
+        }
+        self.breakpoints.retain(|bp| !deleted.contains(&bp.id));
+        return deleted;
+    }
+
+    pub fn set_spec(&mut self, id: i32, spec: Spec) {
+        if let Some(bp) = self.breakpoints.iter_mut().find(|bp| bp.id == id) {
+            bp.spec = Some(spec);
+        }
+    }
+
+    // Fails without adding the breakpoint if it cannot be inserted into the
+    // running process.
     pub fn add(&mut self, addr: u64, by_user: bool,
                action: Option<Action>,
-               ptracer: Option<&ptracer::Ptracer>) -> &Breakpoint {
+               ptracer: Option<&ptracer::Ptracer>)
+               -> Result<&Breakpoint, ptracer::Error> {
+        let token = match ptracer {
+            Some(ptracer) => Some(try!(ptracer.poke_breakpoint(addr))),
+            None => None,
+        };
         let id = if by_user {
             self.next_id += 1;
             self.next_id
@@ -63,30 +295,122 @@ impl BreakpointManager {
             self.next_sys_id
         };
 
-        match ptracer {
-            Some(ptracer) => {
-                let token = ptracer.poke_breakpoint(addr);
-                let bp = Breakpoint {
-                    id: id,
-                    addr: addr,
-                    token: token,
-                    is_active: true,
-                    action: action,
-                };
-                self.breakpoints.push(bp);
-                return &self.breakpoints[self.breakpoints.len()-1];
+        let bp = Breakpoint {
+            id: id,
+            addr: addr,
+            token: token.unwrap_or(0),
+            is_active: token.is_some(),
+            action: action,
+            spec: None,
+        };
+        self.breakpoints.push(bp);
+        return Ok(&self.breakpoints[self.breakpoints.len()-1]);
+    }
+
+    // Adds a watchpoint of the value of `ty` at `addr`, whose current
+    // contents are `value`. A write watchpoint falls back to software when
+    // the region is too large or there are not enough debug registers.
+    pub fn add_watchpoint(&mut self, addr: u64, kind: WatchKind, expr: &str,
+                          ty: types::Type, value: Vec<u8>,
+                          ptracer: Option<&mut ptracer::Ptracer>)
+                          -> Result<&Watchpoint, String> {
+        let used: usize = self.watchpoints.iter().filter(|wp| wp.is_hardware)
+            .map(|wp| split_region(wp.addr, wp.ty.size()).len()).sum();
+        let fits = used + split_region(addr, ty.size()).len() <=
+            DEBUG_ADDR_REGS;
+        let is_hardware = ty.size() <= MAX_HARDWARE_WATCH && fits;
+        if !is_hardware && kind != WatchKind::Write {
+            if !fits {
+                return Err("Hardware watchpoints used exceeds limit."
+                           .to_string());
+            }
+            return Err("Expression cannot be implemented with read/access \
+                        watchpoint.".to_string());
+        }
+        self.watchpoints.push(Watchpoint {
+            id: self.next_id + 1,
+            addr: addr,
+            kind: kind,
+            expr: expr.to_string(),
+            ty: ty,
+            value: value,
+            is_hardware: is_hardware,
+            slots: vec!(),
+        });
+        if let (true, Some(ptracer)) = (is_hardware, ptracer) {
+            if let Err(err) = self.insert_watchpoints(ptracer) {
+                self.watchpoints.pop();
+                // Puts back the others.
+                let _ = self.insert_watchpoints(ptracer);
+                return Err(err.to_string());
+            }
+        }
+        self.next_id += 1;
+        return Ok(&self.watchpoints[self.watchpoints.len() - 1]);
+    }
+
+    // Deletes a user breakpoint or watchpoint, removing it from the process
+    // if it is inserted.
+    pub fn delete(&mut self, id: i32, ptracer: Option<&mut ptracer::Ptracer>)
+                  -> Result<(), String> {
+        if id > 0 {
+            if let Some(i) = self.breakpoints.iter().position(|b| b.id == id) {
+                let bp = self.breakpoints.remove(i);
+                if let (true, Some(ptracer)) = (bp.is_active, ptracer) {
+                    try!(ptracer.poke_byte(bp.addr, bp.token));
+                }
+                return Ok(());
             }
-            None => {
-                let bp = Breakpoint {
-                    id: id,
-                    addr: addr,
-                    token: 0,
-                    is_active: false,
-                    action: action,
-                };
-                self.breakpoints.push(bp);
-                return &self.breakpoints[self.breakpoints.len()-1];
+        }
+        if let Some(i) = self.watchpoints.iter().position(|w| w.id == id) {
+            let wp = self.watchpoints.remove(i);
+            if let (false, Some(ptracer)) = (wp.slots.is_empty(), ptracer) {
+                try!(self.insert_watchpoints(ptracer));
             }
+            return Ok(());
+        }
+        return Err(format!("No breakpoint number {}.", id));
+    }
+
+    // User breakpoints and watchpoints by their IDs.
+    pub fn user_ids(&self) -> Vec<i32> {
+        let mut ids: Vec<i32> = self.breakpoints.iter().map(|bp| bp.id)
+            .filter(|&id| id > 0)
+            .chain(self.watchpoints.iter().map(|wp| wp.id)).collect();
+        ids.sort();
+        return ids;
+    }
+
+    pub fn has_hardware_watchpoints(&self) -> bool {
+        self.watchpoints.iter().any(|wp| wp.is_hardware)
+    }
+
+    pub fn has_software_watchpoints(&self) -> bool {
+        self.watchpoints.iter().any(|wp| !wp.is_hardware)
+    }
+
+    // Finds the watchpoint which made the process stop from the status
+    // bits of the debug registers.
+    pub fn find_watchpoint(&self, status: u64) -> Option<&Watchpoint> {
+        self.watchpoints.iter()
+            .find(|wp| wp.slots.iter().any(|&slot| status & 1 << slot != 0))
+    }
+
+    // Drops a breakpoint whose original byte is already restored, e.g. a
+    // temporary one which was hit.
+    pub fn discard(&mut self, id: i32) {
+        self.breakpoints.retain(|bp| bp.id != id);
+    }
+
+    pub fn user_breakpoints(&self) -> Vec<&Breakpoint> {
+        self.breakpoints.iter().filter(|bp| bp.id > 0).collect()
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] { &self.watchpoints }
+
+    pub fn set_watch_value(&mut self, id: i32, value: Vec<u8>) {
+        if let Some(wp) = self.watchpoints.iter_mut().find(|wp| wp.id == id) {
+            wp.value = value;
         }
     }
 
@@ -108,3 +432,20 @@ impl BreakpointManager {
         return None;
     }
 }
+
+#[test]
+fn test_split_region() {
+    assert_eq!(vec!((0x1000, 8)), split_region(0x1000, 8));
+    assert_eq!(vec!((0x1004, 4)), split_region(0x1004, 4));
+    assert_eq!(vec!((0x1003, 1), (0x1004, 4), (0x1008, 2)),
+               split_region(0x1003, 7));
+    assert_eq!(vec!((0x1000, 8), (0x1008, 8)), split_region(0x1000, 16));
+}
+
+#[test]
+fn test_debug_control() {
+    // Writes of 4 bytes with DR0.
+    assert_eq!(0xd0001, debug_control(0, 4, WatchKind::Write));
+    // Accesses of 8 bytes with DR3.
+    assert_eq!(0xb000_0040, debug_control(3, 8, WatchKind::Access));
+}
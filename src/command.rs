@@ -1,34 +1,247 @@
+use breakpoint::WatchKind;
 use expr;
 use expr::Expr;
 
 #[derive(PartialEq, Debug)]
 pub enum Command {
+    Attach (i32),
+    Backtrace,
     Break (Expr),
+    BreakLine (Option<String>, u32),
     Cont,
+    // Breakpoint numbers, all if empty.
+    Delete (Vec<i32>),
+    Detach,
+    Disassemble (Option<Expr>, Option<Expr>),
+    Gcore (Option<String>),
+    // Signal names and keywords like "nostop".
+    Handle (Vec<String>),
     Info,
+    InfoBreakpoints,
+    InfoFloat,
+    // Register names, all registers if empty.
+    InfoRegisters (Vec<String>),
+    InfoSharedLibrary,
+    InfoSignals (Option<String>),
+    InfoSymbol (Expr),
+    InfoThreads,
+    InfoVector,
+    MaintInfoCache,
     Print (Expr),
+    Ptype (String),
     Run (Vec<String>),
+    // An option name and its value.
+    Set (String, String),
+    // The lvalue and the new value of `set var`.
+    SetVar (Expr, Expr),
     Start (Vec<String>),
     StepI,
-    X (usize, i32, Expr),
+    Thread (Option<usize>),
+    // The kind and the expression to watch.
+    Watch (WatchKind, String),
+    Whatis (String),
+    // The count, the format letter, the unit size and the address.
+    X (usize, char, usize, Expr),
 }
 
 fn parse_print(s: &str) -> Result<Command, String> {
     Ok(Command::Print(try!(expr::parse(s))))
 }
 
+fn parse_set(s: &str) -> Result<Command, String> {
+    let s = s.trim();
+    let mut args = s.split_whitespace();
+    let (name, value) = (args.next(), args.next());
+    if name == Some("var") || name == Some("variable") {
+        let (lhs, rhs) = try!(expr::parse_assignment(
+            &s[name.unwrap().len()..]));
+        return Ok(Command::SetVar(lhs, rhs));
+    }
+    // Like gdb, anything which is not an option is an assignment.
+    if s.starts_with('*') || s.starts_with('$') || s.starts_with('(') ||
+        s.contains('=') {
+        let (lhs, rhs) = try!(expr::parse_assignment(s));
+        return Ok(Command::SetVar(lhs, rhs));
+    }
+    match (name, value) {
+        (Some(name), Some(value)) => {
+            Ok(Command::Set(name.to_string(), value.to_string()))
+        }
+        _ => Err("Argument required (option and value).".to_string()),
+    }
+}
+
+fn parse_line_num(s: &str) -> Option<u32> {
+    if s.len() > 0 && s.chars().all(|c| c.is_digit(10)) {
+        return s.parse().ok();
+    }
+    return None;
+}
+
 fn parse_break(s: &str) -> Result<Command, String> {
+    let s = s.trim();
+    if s.starts_with("*") {
+        return Ok(Command::Break(try!(expr::parse(&s[1..]))));
+    }
+    if let Some(line) = parse_line_num(s) {
+        return Ok(Command::BreakLine(None, line));
+    }
+    if let Some(found) = s.rfind(':') {
+        if let Some(line) = parse_line_num(&s[found+1..]) {
+            return Ok(Command::BreakLine(Some(s[..found].to_string()), line));
+        }
+    }
+    // Demangled names with parameters or template arguments, e.g.
+    // "Foo::bar(int)", are not expressions.
+    if s.contains('(') || s.contains('<') {
+        return Ok(Command::Break(expr::Expr::Ident(s.to_string())));
+    }
     Ok(Command::Break(try!(expr::parse(s))))
 }
 
-fn parse_x(_: &str, s: &str) -> Result<Command, String> {
-    let num = 1;
-    let base = 16;
-    // TODO: Parse num and base.
-    match expr::parse(s) {
-        Ok(e) => Ok(Command::X(num, base, e)),
-        Err(e) => Err(e)
+// Parses `x/NFU ADDR`, where all of N, F and U are optional.
+fn parse_x(cmd: &str, s: &str) -> Result<Command, String> {
+    let spec = if cmd.len() > 2 { &cmd[2..] } else { "" };
+    let digits = spec.chars().take_while(|c| c.is_digit(10)).count();
+    let num = if digits == 0 {
+        1
+    } else {
+        try!(spec[..digits].parse::<usize>().map_err(|e| e.to_string()))
+    };
+    let mut format = 'x';
+    let mut size = 0;
+    for c in spec[digits..].chars() {
+        match c {
+            'x' | 'd' | 'u' | 'o' | 't' | 'c' | 'a' | 'i' => format = c,
+            'b' => size = 1,
+            'h' => size = 2,
+            'w' => size = 4,
+            'g' => size = 8,
+            _ => return Err(format!("Undefined output format \"{}\".", c)),
+        }
+    }
+    size = match format {
+        'a' => 8,
+        'c' if size == 0 => 1,
+        'i' => 0,
+        _ if size == 0 => 4,
+        _ => size,
+    };
+    if s.trim().is_empty() {
+        return Err("Argument required (starting display address).".to_string());
+    }
+    Ok(Command::X(num, format, size, try!(expr::parse(s))))
+}
+
+// Parses `disassemble [FUNC|START,END|START,+LENGTH]`.
+fn parse_disassemble(s: &str) -> Result<Command, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(Command::Disassemble(None, None));
+    }
+    if s.contains('(') || s.contains('<') {
+        let e = expr::Expr::Ident(s.to_string());
+        return Ok(Command::Disassemble(Some(e), None));
+    }
+    let (start, end) = match s.find(',') {
+        Some(found) => (&s[..found], Some(s[found+1..].trim())),
+        None => (s, None),
+    };
+    let start = try!(expr::parse(start));
+    let end = match end {
+        Some(end) if end.starts_with('+') => {
+            let len = try!(expr::parse(&end[1..]));
+            Some(Expr::Binary(expr::BinaryOp::Add, Box::new(start.clone()),
+                              Box::new(len)))
+        }
+        Some(end) => Some(try!(expr::parse(end))),
+        None => None,
+    };
+    Ok(Command::Disassemble(Some(start), end))
+}
+
+fn parse_info(s: &str) -> Result<Command, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(Command::Info);
+    }
+    let (sub, rest) = match s.find(' ') {
+        Some(found) => (&s[..found], &s[found+1..]),
+        None => (s, ""),
+    };
+    let subcommands = [
+        "breakpoints",
+        "float",
+        "registers",
+        "sharedlibrary",
+        "signals",
+        "symbol",
+        "threads",
+        "vector",
+    ];
+    let cands: Vec<&str> = subcommands.iter().cloned()
+        .filter(|name| name.starts_with(sub)).collect();
+    if cands.len() != 1 {
+        return Err(format!("Undefined info command: \"{}\".", sub));
+    }
+    match cands[0] {
+        "breakpoints" => Ok(Command::InfoBreakpoints),
+        "float" => Ok(Command::InfoFloat),
+        "registers" => {
+            Ok(Command::InfoRegisters(rest.split_whitespace()
+                                      .map(|a| a.to_string()).collect()))
+        }
+        "sharedlibrary" => Ok(Command::InfoSharedLibrary),
+        "signals" => {
+            let sig = rest.trim();
+            Ok(Command::InfoSignals(if sig.is_empty() {
+                None
+            } else {
+                Some(sig.to_string())
+            }))
+        }
+        "symbol" => {
+            if rest.trim().is_empty() {
+                return Err("Argument required (address).".to_string());
+            }
+            Ok(Command::InfoSymbol(try!(expr::parse(rest))))
+        }
+        "threads" => Ok(Command::InfoThreads),
+        "vector" => Ok(Command::InfoVector),
+        _ => Err(String::from("Shouldn't happen"))
+    }
+}
+
+// Parses `maintenance info cache`, which shows the memory cache.
+fn parse_maintenance(s: &str) -> Result<Command, String> {
+    let words: Vec<&str> = s.split_whitespace().collect();
+    if words.len() == 2 && "info".starts_with(words[0]) &&
+        "cache".starts_with(words[1]) {
+        return Ok(Command::MaintInfoCache);
+    }
+    return Err(format!("Undefined maintenance command: \"{}\".", s.trim()));
+}
+
+fn parse_delete(s: &str) -> Result<Command, String> {
+    let mut ids = vec!();
+    for arg in s.split_whitespace() {
+        match arg.parse() {
+            Ok(id) => ids.push(id),
+            Err(_) => {
+                return Err(format!("Invalid breakpoint number \"{}\".",
+                                   arg));
+            }
+        }
+    }
+    Ok(Command::Delete(ids))
+}
+
+fn parse_watch(kind: WatchKind, s: &str) -> Result<Command, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("Argument required (expression to compute).".to_string());
     }
+    Ok(Command::Watch(kind, s.to_string()))
 }
 
 fn parse_run(s: &str) -> Result<Command, String> {
@@ -50,14 +263,29 @@ pub fn parse(line: &str) -> Result<Command, String> {
     };
 
     let command_names = [
+        "attach",
+        "awatch",
+        "backtrace",
         "break",
         "continue",
+        "delete",
+        "detach",
+        "disassemble",
+        "gcore",
+        "handle",
         "info",
+        "maintenance",
         "print",
+        "ptype",
         "run",
+        "rwatch",
+        "set",
         "si",
         "start",
         "stepi",
+        "thread",
+        "watch",
+        "whatis",
         "x",
     ];
 
@@ -65,12 +293,28 @@ pub fn parse(line: &str) -> Result<Command, String> {
         return parse_x(cmd, rest);
     }
 
+    // Abbreviations which win over ambiguous prefixes, like gdb.
+    let aliases = [
+        ("b", "break"),
+        ("bt", "backtrace"),
+        ("c", "continue"),
+        ("d", "delete"),
+        ("i", "info"),
+        ("mt", "maintenance"),
+        ("p", "print"),
+    ];
+
     let mut cands = Vec::new();
     for name in command_names.iter() {
         if name.starts_with(cmd) {
-            cands.push(name);
+            cands.push(*name);
         }
     }
+    if let Some(&(_, name)) = aliases.iter().find(|a| a.0 == cmd) {
+        cands = vec!(name);
+    } else if cands.len() > 1 && cands.contains(&cmd) {
+        cands = vec!(cmd);
+    }
     if cands.len() == 0 {
         return Err(format!("No such command: {}", cmd));
     }
@@ -78,14 +322,55 @@ pub fn parse(line: &str) -> Result<Command, String> {
         return Err(format!("Multiple candidates for `{}': {:?}", cmd, cands));
     }
 
-    match *cands[0] {
+    match cands[0] {
+        "attach" => {
+            match rest.trim().parse() {
+                Ok(pid) => Ok(Command::Attach(pid)),
+                Err(_) => {
+                    Err("Argument required (process-id to attach).".to_string())
+                }
+            }
+        }
+        "awatch" => parse_watch(WatchKind::Access, rest),
+        "backtrace" => Ok(Command::Backtrace),
         "break" => parse_break(rest),
         "continue" => Ok(Command::Cont),
-        "info" => Ok(Command::Info),
+        "delete" => parse_delete(rest),
+        "detach" => Ok(Command::Detach),
+        "disassemble" => parse_disassemble(rest),
+        "gcore" => {
+            let file = rest.trim();
+            Ok(Command::Gcore(if file.is_empty() {
+                None
+            } else {
+                Some(file.to_string())
+            }))
+        }
+        "handle" => {
+            Ok(Command::Handle(rest.split_whitespace()
+                               .map(|a| a.to_string()).collect()))
+        }
+        "info" => parse_info(rest),
+        "maintenance" => parse_maintenance(rest),
         "print" => parse_print(rest),
+        "ptype" => Ok(Command::Ptype(rest.trim().to_string())),
         "run" => parse_run(rest),
+        "rwatch" => parse_watch(WatchKind::Read, rest),
+        "set" => parse_set(rest),
         "si" | "stepi"  => Ok(Command::StepI),
         "start" => parse_start(rest),
+        "thread" => {
+            let num = rest.trim();
+            if num.is_empty() {
+                return Ok(Command::Thread(None));
+            }
+            match num.parse() {
+                Ok(num) => Ok(Command::Thread(Some(num))),
+                Err(_) => Err(format!("Invalid thread ID: {}", num)),
+            }
+        }
+        "watch" => parse_watch(WatchKind::Write, rest),
+        "whatis" => Ok(Command::Whatis(rest.trim().to_string())),
         _ => Err(String::from("Shouldn't happen"))
     }
 }
@@ -104,6 +389,155 @@ fn test_print() {
 fn test_break() {
     assert_eq!(Ok(Command::Break(Expr::Ident("main".to_string()))),
                parse("b main"));
+    assert_eq!(Ok(Command::Break(Expr::Ident("ns::Foo::bar".to_string()))),
+               parse("b ns::Foo::bar"));
+    assert_eq!(Ok(Command::Break(Expr::Ident("Foo::bar(int)".to_string()))),
+               parse("break Foo::bar(int)"));
+}
+
+#[test]
+fn test_backtrace() {
+    assert_eq!(Ok(Command::Backtrace), parse("bt"));
+    assert_eq!(Ok(Command::Backtrace), parse("backtrace"));
+}
+
+#[test]
+fn test_break_line() {
+    assert_eq!(Ok(Command::BreakLine(None, 3)), parse("break 3"));
+    assert_eq!(Ok(Command::BreakLine(Some("hello.c".to_string()), 3)),
+               parse("break hello.c:3"));
+    assert_eq!(Ok(Command::Break(Expr::Num(3))), parse("break *3"));
+    assert_eq!(Ok(Command::Break(Expr::Num(0x4005d0))),
+               parse("break 0x4005d0"));
+}
+
+#[test]
+fn test_ptype() {
+    assert_eq!(Ok(Command::Ptype("struct point".to_string())),
+               parse("ptype struct point"));
+    assert_eq!(Ok(Command::Whatis("a[1]".to_string())), parse("whatis a[1]"));
+    assert!(parse("pt x").is_ok());
+}
+
+#[test]
+fn test_info() {
+    assert_eq!(Ok(Command::Info), parse("info"));
+    assert_eq!(Ok(Command::InfoSharedLibrary), parse("info sharedlibrary"));
+    assert_eq!(Ok(Command::InfoSharedLibrary), parse("i shared"));
+    assert_eq!(Ok(Command::InfoSymbol(Expr::Num(0x4005d0))),
+               parse("info symbol 0x4005d0"));
+    assert_eq!(Ok(Command::InfoThreads), parse("info threads"));
+    assert_eq!(Ok(Command::InfoSignals(Some("SIGINT".to_string()))),
+               parse("info signals SIGINT"));
+    assert_eq!(Ok(Command::InfoRegisters(vec!())), parse("info registers"));
+    assert_eq!(Ok(Command::InfoRegisters(vec!("rip".to_string(),
+                                              "$sp".to_string()))),
+               parse("i r rip $sp"));
+    assert_eq!(Ok(Command::InfoFloat), parse("info float"));
+    assert_eq!(Ok(Command::InfoVector), parse("i vec"));
+    assert!(parse("info nosuch").is_err());
+}
+
+#[test]
+fn test_maintenance() {
+    assert_eq!(Ok(Command::MaintInfoCache), parse("maintenance info cache"));
+    assert_eq!(Ok(Command::MaintInfoCache), parse("mt i c"));
+    assert!(parse("maintenance").is_err());
+    assert!(parse("maintenance info nosuch").is_err());
+}
+
+#[test]
+fn test_x() {
+    assert_eq!(Ok(Command::X(1, 'x', 4, Expr::Num(0x400000))),
+               parse("x 0x400000"));
+    assert_eq!(Ok(Command::X(4, 'i', 0, Expr::Ident("main".to_string()))),
+               parse("x/4i main"));
+    assert_eq!(Ok(Command::X(2, 'x', 8, Expr::Num(16))), parse("x/2xg 16"));
+    assert_eq!(Ok(Command::X(3, 'c', 1, Expr::Num(16))), parse("x/3c 16"));
+    assert!(parse("x/2z 16").is_err());
+    assert!(parse("x/i").is_err());
+}
+
+#[test]
+fn test_disassemble() {
+    assert_eq!(Ok(Command::Disassemble(None, None)), parse("disas"));
+    assert_eq!(Ok(Command::Disassemble(Some(Expr::Ident("main".to_string())),
+                                       None)),
+               parse("disassemble main"));
+    assert_eq!(Ok(Command::Disassemble(Some(Expr::Num(16)),
+                                       Some(Expr::Num(32)))),
+               parse("disassemble 16,32"));
+    assert_eq!(Ok(Command::Disassemble(
+        Some(Expr::Num(16)),
+        Some(Expr::Binary(expr::BinaryOp::Add, Box::new(Expr::Num(16)),
+                          Box::new(Expr::Num(8)))))),
+               parse("disassemble 16,+8"));
+}
+
+#[test]
+fn test_gcore() {
+    assert_eq!(Ok(Command::Gcore(None)), parse("gcore"));
+    assert_eq!(Ok(Command::Gcore(Some("a.core".to_string()))),
+               parse("gcore a.core"));
+}
+
+#[test]
+fn test_attach() {
+    assert_eq!(Ok(Command::Attach(1234)), parse("attach 1234"));
+    assert!(parse("attach").is_err());
+    assert_eq!(Ok(Command::Detach), parse("detach"));
+}
+
+#[test]
+fn test_thread() {
+    assert_eq!(Ok(Command::Thread(None)), parse("thread"));
+    assert_eq!(Ok(Command::Thread(Some(2))), parse("thread 2"));
+    assert!(parse("thread x").is_err());
+}
+
+#[test]
+fn test_set() {
+    assert_eq!(Ok(Command::Set("follow-fork-mode".to_string(),
+                               "child".to_string())),
+               parse("set follow-fork-mode child"));
+    assert!(parse("set detach-on-fork").is_err());
+    let x = Expr::Ident("x".to_string());
+    assert_eq!(Ok(Command::SetVar(x.clone(), Expr::Num(3))),
+               parse("set var x = 3"));
+    assert_eq!(Ok(Command::SetVar(x.clone(), Expr::Num(3))),
+               parse("set x=3"));
+    assert_eq!(Ok(Command::SetVar(Expr::Ident("$rax".to_string()),
+                                  Expr::Num(0))),
+               parse("set $rax = 0"));
+    assert_eq!(Ok(Command::SetVar(
+        Expr::Unary(expr::UnaryOp::Deref,
+                    Box::new(Expr::Cast("long *".to_string(),
+                                        Box::new(Expr::Num(0x1000))))),
+        Expr::Num(-1))),
+               parse("set *(long*)0x1000 = -1"));
+    assert!(parse("set variable x").is_err());
+}
+
+#[test]
+fn test_watch() {
+    assert_eq!(Ok(Command::Watch(WatchKind::Write, "x".to_string())),
+               parse("watch x"));
+    assert_eq!(Ok(Command::Watch(WatchKind::Read, "*p".to_string())),
+               parse("rwatch *p"));
+    assert_eq!(Ok(Command::Watch(WatchKind::Access, "a[1]".to_string())),
+               parse("awatch  a[1] "));
+    assert!(parse("watch").is_err());
+    assert_eq!(Ok(Command::InfoBreakpoints), parse("i b"));
+    assert_eq!(Ok(Command::Delete(vec!())), parse("delete"));
+    assert_eq!(Ok(Command::Delete(vec!(1, 3))), parse("d 1 3"));
+    assert!(parse("delete x").is_err());
+}
+
+#[test]
+fn test_handle() {
+    assert_eq!(Ok(Command::Handle(vec!("SIGSEGV".to_string(),
+                                       "nostop".to_string()))),
+               parse("handle SIGSEGV nostop"));
 }
 
 #[test]
@@ -3,41 +3,196 @@ extern crate regex;
 use binary;
 use breakpoint;
 use command;
+use corefile;
+use demangle;
+use disasm;
+use dwarf;
 use eval;
+use expr;
+use fp;
 use log;
 use ptracer;
+use signal;
 use target_desc;
+use types;
+use unwind;
+use std;
 use std::collections::HashMap;
 
+// Registers of a stopped frame, used to evaluate DWARF locations.
+struct FrameState<'b, 'a: 'b> {
+    ctx: &'b Context<'a>,
+    regs: ptracer::Registers,
+    // The DW_AT_frame_base expression of the function and the bias of its
+    // binary.
+    frame_base: Option<(&'a [u8], u64)>,
+}
+
+impl<'b, 'a> FrameState<'b, 'a> {
+    fn new(ctx: &'b Context<'a>) -> Self {
+        Self {
+            ctx: ctx,
+            regs: ctx.frame_regs().unwrap_or(ptracer::Registers::empty()),
+            frame_base: None,
+        }
+    }
+}
+
+impl<'b, 'a> dwarf::Frame for FrameState<'b, 'a> {
+    fn reg(&self, reg: u16) -> Result<u64, String> {
+        if !self.ctx.has_frame() {
+            return Err("No frame selected.".to_string());
+        }
+        match self.ctx.target.dwarf_regs.get(reg as usize) {
+            Some(&index) => Ok(self.regs.gp(index)),
+            None => Err(format!("Unknown DWARF register: {}", reg)),
+        }
+    }
+
+    fn frame_base(&self) -> Result<u64, String> {
+        let (expr, bias) = try!(self.frame_base.ok_or(
+            "No frame base.".to_string()));
+        match try!(dwarf::eval_location(expr, self.ctx.target.gp_size,
+                                        bias, self)) {
+            dwarf::Location::Register(reg) => self.reg(reg),
+            dwarf::Location::Memory(addr) => Ok(addr),
+            dwarf::Location::Value(v) => Ok(v),
+            _ => Err("Invalid frame base.".to_string()),
+        }
+    }
+
+    fn cfa(&self) -> Result<u64, String> {
+        if !self.ctx.has_frame() {
+            return Err("No frame selected.".to_string());
+        }
+        self.ctx.frame_cfa(&self.ctx.unwind_regs(&self.regs))
+    }
+
+    fn read_word(&self, addr: u64) -> Result<u64, String> {
+        self.ctx.read_word(addr)
+    }
+}
+
+// Limits the number of frames shown by `backtrace`.
+const MAX_FRAMES: usize = 256;
+
+// Guards against a broken link_map list.
+const MAX_LINK_MAPS: usize = 4096;
+
+// r_state of struct r_debug.
+const RT_CONSISTENT: u32 = 0;
+
+// Auxiliary vector entries.
+const AT_PHDR: u64 = 3;
+const AT_BASE: u64 = 7;
+const AT_ENTRY: u64 = 9;
+
+// An entry of the address-sorted symbol index.
+struct SymbolRange {
+    addr: u64,
+    size: u64,
+    // The demangled name if any.
+    name: String,
+    binding: binary::Binding,
+    // The defining section, which a sizeless symbol does not extend past.
+    section: Option<String>,
+}
+
+// Where a single step for software watchpoints started.
+#[derive(Clone, Copy)]
+struct WatchStep {
+    tid: i32,
+    ip: u64,
+    // No other threads ran during the step.
+    alone: bool,
+    // The step is over a breakpoint whose original byte was restored.
+    over_breakpoint: bool,
+}
+
 pub struct Context<'a> {
     main_binary: Option<binary::Binary<'a>>,
     interp: Option<binary::Binary<'a>>,
+    // Shared objects in the link_map list of the dynamic linker.
+    shlibs: Vec<binary::Binary<'a>>,
 
     args: Vec<String>,
-    symtab: HashMap<&'a str, u64>,
+    // Symbols by their raw names and normalized demangled names. Local
+    // symbols are also keyed by their source files, which tell apart
+    // statics with the same name.
+    symtab: HashMap<(Option<String>, String), (u64, binary::SymbolType)>,
+    // Symbols of all loaded binaries sorted by their addresses.
+    addrtab: Vec<SymbolRange>,
     ptracer: Option<ptracer::Ptracer>,
+    // The core file being debugged instead of a live process.
+    core: Option<corefile::Core>,
     breakpoints: breakpoint::BreakpointManager,
     needs_wait: bool,
     regs: ptracer::Registers,
     target: target_desc::Target,
     cur_breakpoint: i32,
+    // The thread which stopped at cur_breakpoint.
+    cur_breakpoint_tid: i32,
+    // The stop after the step over cur_breakpoint and the thread selected
+    // before it, when the stop is handled by the next wait instead of
+    // letting the process go on.
+    pending_state: Option<(ptracer::ProcessState, i32)>,
+    // The debug status for a pending SIGTRAP.
+    pending_debug_status: Option<u64>,
+    // The single step in progress for software watchpoints.
+    watch_step: Option<WatchStep>,
+    // The temporary breakpoint at which single-stepping resumes after
+    // uninteresting code returns, and the stack pointer after the return.
+    skip_breakpoint: i32,
+    skip_sp: u64,
+    // Processes suspended after forks, which are debugged after the
+    // current one finishes.
+    forks: Vec<ptracer::Ptracer>,
+    // The parent of a vfork child being followed. It shares the memory
+    // with the child until the child execs or exits.
+    vfork_parent: Option<ptracer::Ptracer>,
+    // `set follow-fork-mode child`.
+    follow_fork_child: bool,
+    // `set detach-on-fork`.
+    detach_on_fork: bool,
+    signals: signal::SignalTable,
 
     r_map: u64,
 }
 
+// Lets a process which cannot be debugged go.
+fn release(ptracer: &ptracer::Ptracer) {
+    if let Err(err) = ptracer.detach() {
+        log_info!("Failed to detach: {}", err);
+    }
+}
+
 impl<'a> Context<'a> {
     pub fn new(args: &Vec<String>) -> Self {
         Self {
             main_binary: None,
             interp: None,
+            shlibs: vec!(),
             args: args.iter().map(|a|a.clone()).collect(),
             symtab: HashMap::new(),
+            addrtab: vec!(),
             ptracer: None,
+            core: None,
             breakpoints: breakpoint::BreakpointManager::new(),
             needs_wait: false,
             regs: ptracer::Registers::empty(),
             target: target_desc::get_target(),
             cur_breakpoint: 0,
+            cur_breakpoint_tid: 0,
+            pending_state: None,
+            pending_debug_status: None,
+            watch_step: None,
+            skip_breakpoint: 0,
+            skip_sp: 0,
+            forks: vec!(),
+            vfork_parent: None,
+            follow_fork_child: false,
+            detach_on_fork: true,
+            signals: signal::SignalTable::new(),
             r_map: 0,
         }
     }
@@ -48,6 +203,20 @@ impl<'a> Context<'a> {
     #[allow(dead_code)]
     pub fn is_running(&self) -> bool { self.ptracer.is_some() }
 
+    // Whether there are registers and memory of a process, either live or
+    // from a core file.
+    pub fn has_frame(&self) -> bool {
+        self.ptracer.is_some() || self.core.is_some()
+    }
+
+    fn frame_regs(&self) -> Option<ptracer::Registers> {
+        match (&self.ptracer, &self.core) {
+            (&Some(ref ptracer), _) => ptracer.get_regs().ok(),
+            (&None, &Some(ref core)) => Some(core.threads()[0].regs.clone()),
+            _ => None,
+        }
+    }
+
     #[cfg(test)]
     fn interp(&self) -> Option<&binary::Binary<'a>> {
         return self.interp.as_ref();
@@ -60,351 +229,3479 @@ impl<'a> Context<'a> {
 
     pub fn set_main_binary(&mut self, main_binary: &str)
                            -> Result<String, String> {
-        self.symtab.clear();
         let bin = try!(binary::Binary::new(main_binary.to_string()));
-        for sym in bin.syms() {
-            self.symtab.insert(sym.name, sym.value);
-        }
-
+        self.interp = None;
+        self.shlibs.clear();
         if let Some(interp) = bin.interp() {
             let interp = try!(binary::Binary::new(interp.to_string()));
             self.interp = Some(interp);
         }
 
         self.main_binary = Some(bin);
+        self.rebuild_symtab();
         return Ok(format!("Reading symbols from {}...done.", main_binary));
     }
 
+    // Merges symbols of the main binary and the shared objects. Global
+    // symbols take precedence over local ones, and symbols in the main
+    // binary over ones in shared objects.
+    fn rebuild_symtab(&mut self) {
+        let mut symtab = HashMap::new();
+        for &local in [false, true].iter() {
+            for bin in self.main_binary.iter().chain(self.shlibs.iter()) {
+                for sym in bin.syms() {
+                    // Skip undefined symbols and TLS offsets.
+                    if sym.value == 0 || sym.kind == binary::SymbolType::Tls ||
+                        (sym.binding == binary::Binding::Local) != local {
+                        continue;
+                    }
+                    let v = (sym.value + bin.bias(), sym.kind);
+                    if let Some(file) = sym.file {
+                        symtab.entry((Some(file.to_string()),
+                                      sym.name.to_string())).or_insert(v);
+                    }
+                    symtab.entry((None, sym.name.to_string())).or_insert(v);
+                    // C++ and Rust functions are also looked up by their
+                    // demangled names with or without parameters.
+                    let names = [demangle::demangle(sym.name),
+                                 demangle::demangle_name(sym.name)];
+                    for name in names.iter().filter_map(|n| n.as_ref()) {
+                        symtab.entry((None, demangle::normalize(name)))
+                            .or_insert(v);
+                    }
+                }
+            }
+        }
+        self.symtab = symtab;
+
+        let mut addrtab = vec!();
+        for bin in self.binaries() {
+            for sym in bin.syms() {
+                if sym.value == 0 || sym.kind == binary::SymbolType::Tls {
+                    continue;
+                }
+                addrtab.push(SymbolRange {
+                    addr: sym.value + bin.bias(),
+                    size: sym.size,
+                    name: demangle::demangle(sym.name)
+                        .unwrap_or(sym.name.to_string()),
+                    binding: sym.binding,
+                    section: sym.section.map(|s| s.to_string()),
+                });
+            }
+            for (name, addr) in bin.plt_syms() {
+                addrtab.push(SymbolRange {
+                    addr: addr + bin.bias(),
+                    size: 16,
+                    name: name,
+                    binding: binary::Binding::Local,
+                    section: bin.section_name(addr).map(|s| s.to_string()),
+                });
+            }
+        }
+        addrtab.sort_by(|a, b| (a.addr, &a.name).cmp(&(b.addr, &b.name)));
+        self.addrtab = addrtab;
+    }
+
     pub fn needs_wait(&self) -> bool { self.needs_wait }
 
     pub fn resolve(&self, name: &str) -> Option<u64> {
-        return self.symtab.get(name).map(|v|*v);
+        return self.resolve_symbol(name).map(|v| v.0);
     }
 
-    fn pid(&self) -> i32 {
-        assert!(self.ptracer.is_some());
-        return self.ptracer.as_ref().unwrap().pid() as i32;
+    // Whether `name` is a function, which is not shadowed by a variable.
+    fn is_function(&self, name: &str) -> bool {
+        if self.lookup_variable(name).is_some() {
+            return false;
+        }
+        if self.lookup_function(name).is_some() {
+            return true;
+        }
+        match self.resolve_symbol(name) {
+            Some((_, binary::SymbolType::Object)) |
+            Some((_, binary::SymbolType::Tls)) | None => false,
+            Some(_) => true,
+        }
     }
 
-    pub fn wait(&mut self) -> Result<String, String> {
-        assert!(self.needs_wait);
-        return self.wait_impl(false);
+    // Like resolve, but also returns the type of the symbol. A name may be
+    // in a file scope like 'foo.c'::counter.
+    pub fn resolve_symbol(&self, name: &str)
+                          -> Option<(u64, binary::SymbolType)> {
+        if name.starts_with('\'') {
+            let end = match name[1..].find("'::") {
+                Some(end) => end + 1,
+                None => return None,
+            };
+            let key = (Some(name[1..end].to_string()),
+                       name[end + 3..].to_string());
+            return self.symtab.get(&key).cloned();
+        }
+        return self.symtab.get(&(None, name.to_string()))
+            .or_else(|| self.symtab.get(&(None, demangle::normalize(name))))
+            .cloned();
     }
 
-    fn handle_breakpoint(&mut self, is_single_step: bool)
-                         -> Result<String, String> {
-        {
-            let ptracer = self.ptracer.as_ref().unwrap();
-            self.regs = ptracer.get_regs();
-            // TODO: Handle single-step-to-braekpoint case.
-            if is_single_step {
-                return Ok("".to_string());
-            }
-
-            let ip = self.regs.ip() - self.target.breakpoint_size as u64;
-            match self.breakpoints.find_by_addr(ip) {
-                Some(bp) => {
-                    self.regs.update_ip(ip, &self.target);
-                    ptracer.set_regs(&self.regs);
-                    ptracer.poke_byte(ip, bp.token());
-                    self.cur_breakpoint = bp.id();
-                    match bp.action() {
-                        &Some(breakpoint::Action::UpdateRDebug) => {
-                        }
+    fn binaries(&self) -> Vec<&binary::Binary<'a>> {
+        let mut bins = vec!();
+        if let Some(ref bin) = self.main_binary {
+            bins.push(bin);
+        }
+        if let Some(ref bin) = self.interp {
+            bins.push(bin);
+        }
+        bins.extend(self.shlibs.iter());
+        return bins;
+    }
 
-                        &Some(breakpoint::Action::EnterMainBinary) => {
-                            log_info!("Entering main binary");
-                        }
+    pub fn find_line_by_addr(&self, addr: u64) -> Option<(String, u32)> {
+        for bin in self.binaries() {
+            if addr < bin.bias() {
+                continue;
+            }
+            if let Some((file, line)) = bin.lines().find_addr(addr - bin.bias()) {
+                return Some((file.to_string(), line));
+            }
+        }
+        return None;
+    }
 
-                        &None => {
-                            return Ok(format!("Breakpoint {}, 0x{:x}",
-                                              bp.id(), self.regs.ip()));
-                        }
-                    }
+    pub fn read_memory(&self, addr: u64, len: usize)
+                       -> Result<Vec<u8>, String> {
+        match self.ptracer {
+            Some(ref ptracer) => {
+                return Ok(try!(ptracer.read_memory(addr, len)));
+            }
+            None => {
+                if let Some(data) = self.core.as_ref()
+                    .and_then(|core| core.read(addr, len)) {
+                    return Ok(data);
                 }
-                None => {
-                    return Ok("".to_string());
+                for bin in self.binaries() {
+                    if addr < bin.bias() {
+                        continue;
+                    }
+                    if let Some(data) = bin.read_static(addr - bin.bias(), len) {
+                        return Ok(data);
+                    }
                 }
+                return Err(format!("Cannot access memory at address 0x{:x}",
+                                   addr));
             }
         }
+    }
 
-        try!(self.cont());
-        return Ok(format!(""));
+    pub fn read_word(&self, addr: u64) -> Result<u64, String> {
+        let data = try!(self.read_memory(addr, 8));
+        let mut v = 0;
+        for i in 0..8 {
+            v |= (data[i] as u64) << (i * 8);
+        }
+        return Ok(v);
     }
 
-    fn wait_impl(&mut self, is_single_step: bool) -> Result<String, String> {
-        assert!(self.ptracer.is_some());
-        self.needs_wait = false;
-        let status = {
-            let ptracer = self.ptracer.as_mut().unwrap();
-            ptracer.wait()
-        };
+    // Finds the binary mapped at `addr`.
+    fn find_binary(&self, addr: u64) -> Option<&binary::Binary<'a>> {
+        self.binaries().into_iter().find(|bin| {
+            addr >= bin.bias() && bin.contains(addr - bin.bias())
+        })
+    }
 
-        match status {
-            ptracer::ProcessState::Stop(_) => {
-                return self.handle_breakpoint(is_single_step);
-            }
+    // Returns the symbol which contains `addr` and the offset from it.
+    fn symbolize(&self, addr: u64) -> Option<(&str, u64)> {
+        return self.find_symbol(addr).map(|sym| {
+            (sym.name.as_str(), addr - sym.addr)
+        });
+    }
 
-            ptracer::ProcessState::Exit(st) => {
-                let pid = self.pid();
-                self.breakpoints.notify_finish();
-                self.ptracer = None;
-                return Ok(format!("Process {} exited with code {}",
-                                  pid, st));
+    // Finds the symbol which contains `addr`. Sized symbols must cover
+    // `addr`; sizeless ones extend to the next symbol in the same section.
+    fn find_symbol(&self, addr: u64) -> Option<&SymbolRange> {
+        let bin = match self.find_binary(addr) {
+            Some(bin) => bin,
+            None => return None,
+        };
+        let end = match self.addrtab.binary_search_by(|s| {
+            if s.addr <= addr {
+                std::cmp::Ordering::Less
+            } else {
+                std::cmp::Ordering::Greater
             }
-
-            ptracer::ProcessState::Signal(sig) => {
-                let pid = self.pid();
-                self.breakpoints.notify_finish();
-                self.ptracer = None;
-                return Ok(format!("Process {} signaled with code {}",
-                                  pid, sig));
+        }) {
+            Ok(i) | Err(i) => i,
+        };
+        if end == 0 {
+            return None;
+        }
+        // Aliases share the nearest address.
+        let nearest = self.addrtab[end - 1].addr;
+        // Prefer sized symbols, then global ones.
+        let rank = |sym: &SymbolRange| {
+            (sym.size > 0, sym.binding != binary::Binding::Local)
+        };
+        let mut found: Option<&SymbolRange> = None;
+        for sym in self.addrtab[..end].iter().rev()
+            .take_while(|s| s.addr == nearest) {
+            if sym.size > 0 && addr >= sym.addr + sym.size {
+                continue;
+            }
+            if found.map_or(true, |f| rank(sym) > rank(f)) {
+                found = Some(sym);
             }
         }
+        match found {
+            Some(sym) if sym.size > 0 => Some(sym),
+            Some(sym) if sym.addr >= bin.bias() &&
+                bin.contains(sym.addr - bin.bias()) &&
+                bin.section_name(addr - bin.bias()) ==
+                sym.section.as_ref().map(|s| s.as_str()) => Some(sym),
+            _ => None,
+        }
     }
 
-    pub fn cont(&mut self) -> Result<String, String> {
-        if self.ptracer.is_none() {
-            return Err("The program is not being run.".to_string());
+    // Returns the name and the address range of the function which
+    // contains `addr`.
+    fn function_range(&self, addr: u64) -> Option<(&str, u64, u64)> {
+        let sym = match self.find_symbol(addr) {
+            Some(sym) => sym,
+            None => return None,
+        };
+        if sym.size > 0 {
+            return Some((&sym.name, sym.addr, sym.addr + sym.size));
         }
-        let ptracer = self.ptracer.as_mut().unwrap();
+        return self.addrtab.iter().find(|s| s.addr > sym.addr).map(|next| {
+            (sym.name.as_str(), sym.addr, next.addr)
+        });
+    }
 
-        if self.cur_breakpoint != 0 {
-            if let Some(bp) = self.breakpoints.find_by_id(self.cur_breakpoint) {
-                ptracer.poke_byte(bp.addr(), bp.token());
-                ptracer.single_step();
-                ptracer.wait();
-                ptracer.poke_breakpoint(bp.addr());
+    // Reads instructions at `addr`, showing the original bytes instead of
+    // the traps of breakpoints. The result may be shorter than `len` at
+    // the end of a mapping.
+    fn read_code(&self, addr: u64, len: usize) -> Result<Vec<u8>, String> {
+        let mut code = match self.read_memory(addr, len) {
+            Ok(code) => code,
+            Err(err) => {
+                let mut code = vec!();
+                while code.len() < len {
+                    match self.read_memory(addr + code.len() as u64, 1) {
+                        Ok(b) => code.push(b[0]),
+                        Err(_) => break,
+                    }
+                }
+                if code.is_empty() {
+                    return Err(err);
+                }
+                code
+            }
+        };
+        for (i, b) in code.iter_mut().enumerate() {
+            if let Some(bp) = self.breakpoints.find_by_addr(addr + i as u64) {
+                if bp.is_active() {
+                    *b = bp.token();
+                }
             }
-            self.cur_breakpoint = 0;
         }
-
-        ptracer.cont();
-        assert!(!self.needs_wait);
-        self.needs_wait = true;
-        return Ok("Continuing.".to_string());
+        return Ok(code);
     }
 
-    fn set_entry_bias(&mut self, ip: u64) {
-        if let Some(ref mut interp) = self.interp {
-            let entry = interp.entry();
-            interp.set_bias(ip - entry);
-        } else if let Some(ref mut main_binary) = self.main_binary {
-            let entry = main_binary.entry();
-            main_binary.set_bias(ip - entry);
-        } else {
-            panic!("No binary is set");
-        }
+    // Disassembles an instruction at `addr` into the text and its length.
+    // Branch targets and RIP-relative addresses are annotated with their
+    // symbols, like gdb.
+    fn disassemble_insn(&self, addr: u64) -> Result<(String, usize), String> {
+        let code = try!(self.read_code(addr, disasm::MAX_INSN_LEN));
+        let insn = disasm::decode(&code, addr);
+        let text = match insn.target {
+            Some(disasm::Target::Branch(target)) => {
+                match self.symbolize(target) {
+                    Some((name, 0)) => format!("{} <{}>", insn.text, name),
+                    Some((name, off)) => {
+                        format!("{} <{}+{}>", insn.text, name, off)
+                    }
+                    None => insn.text,
+                }
+            }
+            Some(disasm::Target::Memory(target)) => {
+                format!("{}        # {}", insn.text, self.format_addr(target))
+            }
+            None => insn.text,
+        };
+        return Ok((text, insn.len));
     }
 
-    pub fn run(&mut self, args: Vec<String>) -> Result<String, String> {
-        let msg = try!(self.start(args));
-        try!(self.cont());
-        return Ok(msg);
+    // The marker of an instruction line: "=>" for the current pc and "B"
+    // for user breakpoints.
+    fn insn_marker(&self, addr: u64) -> &'static str {
+        if self.has_frame() && self.regs.ip() == addr {
+            return "=> ";
+        }
+        match self.breakpoints.find_by_addr(addr) {
+            Some(bp) if bp.id() > 0 => "B  ",
+            _ => "   ",
+        }
     }
 
-    fn handle_boot_entry(&mut self) {
-        if self.interp.is_some() {
-            // TODO: Handle PIE.
-            let main_binary = match self.main_binary.as_ref() {
-                Some(bin) => bin,
-                None => panic!("No start binary"),
+    fn disassemble(&self, start: Option<&expr::Expr>,
+                   end: Option<&expr::Expr>) -> Result<String, String> {
+        let (header, name, start, end) = match (start, end) {
+            (start, Some(end)) => {
+                let start = try!(eval::eval_address(self, start.unwrap()));
+                let end = try!(eval::eval_address(self, end));
+                (format!("Dump of assembler code from 0x{:x} to 0x{:x}:",
+                         start, end), None, start, end)
+            }
+            (start, None) => {
+                let addr = match start {
+                    Some(start) => try!(eval::eval_address(self, start)),
+                    None if self.has_frame() => self.regs.ip(),
+                    None => return Err("No frame selected.".to_string()),
+                };
+                let (name, start, end) = match self.function_range(addr) {
+                    Some(range) => range,
+                    None => {
+                        return Err("No function contains specified address."
+                                   .to_string());
+                    }
+                };
+                (format!("Dump of assembler code for function {}:", name),
+                 Some(name), start, end)
+            }
+        };
+        let mut lines = vec!(header);
+        let mut addr = start;
+        while addr < end {
+            let (text, len) = try!(self.disassemble_insn(addr));
+            let loc = match (name, self.symbolize(addr)) {
+                (Some(_), _) => format!("0x{:016x} <+{}>", addr, addr - start),
+                (None, Some((sym, off))) => {
+                    format!("0x{:016x} <{}+{}>", addr, sym, off)
+                }
+                (None, None) => format!("0x{:016x}", addr),
             };
-            self.breakpoints.add(main_binary.entry(), false,
-                                 Some(breakpoint::Action::EnterMainBinary),
-                                 self.ptracer.as_ref());
-            return;
-        } else {
-            self.read_r_debug();
+            lines.push(format!("{}{}:\t{}", self.insn_marker(addr), loc, text));
+            addr += len as u64;
         }
+        lines.push("End of assembler dump.".to_string());
+        return Ok(lines.join("\n"));
     }
 
-    fn read_r_debug(&mut self) {
-        let bin = {
-            match self.interp.as_ref() {
-                Some(bin) => bin,
-                None => match self.main_binary.as_ref() {
-                    Some(bin) => bin,
-                    None => panic!("No start binary"),
-                }
+    // Examines memory like `x/NFU ADDR` of gdb.
+    fn examine(&self, num: usize, format: char, size: usize, addr: u64)
+               -> Result<String, String> {
+        let mut lines = vec!();
+        let mut addr = addr;
+        if format == 'i' {
+            for _ in 0..num {
+                let (text, len) = try!(self.disassemble_insn(addr));
+                lines.push(format!("{}{}:\t{}", self.insn_marker(addr),
+                                   self.format_addr(addr), text));
+                addr += len as u64;
             }
+            return Ok(lines.join("\n"));
+        }
+        let per_line = match (format, size) {
+            ('a', _) | (_, 8) => 2,
+            ('c', _) | (_, 1) => 8,
+            _ => 4,
         };
-
-        for sym in bin.syms() {
-            if sym.name == "_r_debug" {
-                let r_debug_addr = sym.value + bin.bias();
-                self.r_map = r_debug_addr + (self.target.gp_size as u64);
-                let ptracer = self.ptracer.as_ref();
-                let bp = ptracer.unwrap().peek_word(
-                    r_debug_addr + (self.target.gp_size as u64) * 2);
-                log_info!("r_debug_addr={:x} bp={:x}", r_debug_addr, bp);
-                self.breakpoints.add(bp, false,
-                                     Some(breakpoint::Action::UpdateRDebug),
-                                     ptracer);
+        let data = try!(self.read_memory(addr, num * size));
+        for (i, unit) in data.chunks(size).enumerate() {
+            if i % per_line == 0 {
+                lines.push(format!("{}:", self.format_addr(addr)));
             }
+            let mut v = 0;
+            for (j, b) in unit.iter().enumerate() {
+                v |= (*b as u64) << (j * 8);
+            }
+            let bits = size * 8;
+            let signed = if bits == 64 {
+                v as i64
+            } else {
+                ((v << (64 - bits)) as i64) >> (64 - bits)
+            };
+            let text = match format {
+                'd' => format!("{}", signed),
+                'u' => format!("{}", v),
+                'o' if v == 0 => "0".to_string(),
+                'o' => format!("0{:o}", v),
+                't' => format!("{:0width$b}", v, width = bits),
+                'c' => {
+                    let c = v as u8;
+                    if c >= 0x20 && c < 0x7f {
+                        format!("{} '{}'", signed, c as char)
+                    } else {
+                        format!("{} '\\{:03o}'", signed, c)
+                    }
+                }
+                'a' => self.format_addr(v),
+                _ => format!("0x{:0width$x}", v, width = size * 2),
+            };
+            let last = lines.len() - 1;
+            lines[last] += &format!("\t{}", text);
+            addr += size as u64;
         }
+        return Ok(lines.join("\n"));
     }
 
-    pub fn start(&mut self, args: Vec<String>) -> Result<String, String> {
-        let mut argv = vec![];
-        {
-            let main_binary = try!(self.main_binary.as_mut().ok_or(
-                "No executable specified.".to_string()));
-            argv.push(main_binary.filename().clone());
+    // Formats `addr` with the symbol which contains it, e.g.
+    // "0x401136 <main+4>".
+    pub fn format_addr(&self, addr: u64) -> String {
+        match self.symbolize(addr) {
+            Some((name, 0)) => format!("0x{:x} <{}>", addr, name),
+            Some((name, off)) => format!("0x{:x} <{}+{}>", addr, name, off),
+            None => format!("0x{:x}", addr),
         }
-        if args.len() > 0 {
-            argv.extend(args);
+    }
+
+    fn info_symbol(&self, addr: u64) -> String {
+        let (name, off) = match self.symbolize(addr) {
+            Some(s) => s,
+            None => return format!("No symbol matches 0x{:x}.", addr),
+        };
+        let bin = self.find_binary(addr).unwrap();
+        let mut msg = if off == 0 {
+            name.to_string()
         } else {
-            argv.extend(self.args.iter().cloned());
+            format!("{} + {}", name, off)
+        };
+        if let Some(section) = bin.section_name(addr - bin.bias()) {
+            msg += &format!(" in section {}", section);
         }
-        // TODO: Stop at main if it exists.
-        let ptracer = ptracer::Ptracer::new(&argv);
-        let msg = format!("Starting program: {} (pid={})",
-                          argv[0], ptracer.pid());
-
-        let regs = ptracer.get_regs();
-        self.set_entry_bias(regs.ip());
-
-        self.ptracer = Some(ptracer);
-        self.breakpoints.notify_start(&self.ptracer.as_ref().unwrap());
-
-        self.handle_boot_entry();
-        return Ok(msg);
+        if self.shlibs.iter().any(|b| std::ptr::eq(b, bin)) ||
+            self.interp.as_ref().map_or(false, |b| std::ptr::eq(b, bin)) {
+            msg += &format!(" of {}", bin.filename());
+        }
+        return msg;
     }
 
-    pub fn single_step(&mut self) -> Result<String, String> {
-        if self.ptracer.is_none() {
-            return Err("The program is not being run.".to_string());
-        }
-        {
-            let ptracer = self.ptracer.as_mut().unwrap();
-            ptracer.single_step();
+    fn unwind_regs(&self, regs: &ptracer::Registers) -> unwind::Regs {
+        let mut r = unwind::Regs { regs: [None; unwind::NUM_REGS] };
+        for i in 0..unwind::NUM_REGS {
+            r.regs[i] = Some(regs.gp(self.target.dwarf_regs[i]));
         }
-        try!(self.wait_impl(true));
-        return Ok("".to_string());
+        return r;
     }
 
-    pub fn add_breakpoint(&mut self, addr: u64) -> Result<String, String> {
-        let bp = self.breakpoints.add(addr, true, None,
-                                      self.ptracer.as_ref());
-        return Ok(format!("Breakpoint {} at 0x{:x}", bp.id(), bp.addr()));
+    // Finds the CFI row for a frame. The pc of a caller frame is a return
+    // address, so the call instruction is looked up instead.
+    fn find_cfi_row(&self, pc: u64, is_caller: bool)
+                    -> Option<(Result<unwind::Row<'a>, String>, u64)> {
+        let pc = if is_caller { pc - 1 } else { pc };
+        match self.find_binary(pc) {
+            Some(bin) => {
+                bin.cfi().find_row(pc - bin.bias()).map(|r| (r, bin.bias()))
+            }
+            None => None,
+        }
     }
 
-    pub fn run_command(&mut self, cmd: command::Command)
-                       -> Result<String, String> {
-        match cmd {
-            command::Command::Break(addr) => {
-                let addr = eval::eval(self, addr);
-                return self.add_breakpoint(addr);
+    fn frame_cfa(&self, regs: &unwind::Regs) -> Result<u64, String> {
+        let pc = try!(regs.pc().ok_or("No frame selected.".to_string()));
+        let read_word = |addr| self.read_word(addr);
+        match self.find_cfi_row(pc, false) {
+            Some((row, bias)) => {
+                unwind::compute_cfa(&try!(row), regs, bias, &read_word)
+            }
+            // Assume the standard prologue has run.
+            None => {
+                let bp = try!(regs.get(unwind::FP_REG).ok_or(
+                    "No frame pointer.".to_string()));
+                Ok(bp + 16)
             }
+        }
+    }
 
-            command::Command::Cont => {
-                return self.cont();
+    // Computes the registers of the caller of a frame.
+    fn caller_regs(&self, regs: &unwind::Regs, is_caller: bool)
+                   -> Result<unwind::Regs, String> {
+        let pc = try!(regs.pc().ok_or("No frame selected.".to_string()));
+        let read_word = |addr| self.read_word(addr);
+        match self.find_cfi_row(pc, is_caller) {
+            Some((row, bias)) => {
+                unwind::unwind_step(&try!(row), regs, bias, &read_word)
             }
+            None => unwind::frame_pointer_step(regs, &read_word),
+        }
+    }
 
-            command::Command::Info => {
-                if self.ptracer.is_none() {
-                    return Err("The program is not being run.".to_string());
+    // Unwinds the stack and returns the registers of each frame, from the
+    // innermost one.
+    pub fn backtrace(&self) -> Result<Vec<unwind::Regs>, String> {
+        let regs = match self.frame_regs() {
+            Some(regs) => self.unwind_regs(&regs),
+            None => return Err("No stack.".to_string()),
+        };
+        let mut frames = vec!(regs);
+        while frames.len() < MAX_FRAMES {
+            let caller = {
+                let callee = frames.last().unwrap();
+                match self.caller_regs(callee, frames.len() > 1) {
+                    Ok(caller) => {
+                        // Stop at the outermost frame or garbage.
+                        match (caller.pc(), caller.sp(), callee.sp()) {
+                            (Some(pc), Some(sp), Some(prev_sp))
+                                if pc != 0 && sp > prev_sp => caller,
+                            _ => break,
+                        }
+                    }
+                    Err(_) => break,
                 }
-                let ptracer = self.ptracer.as_mut().unwrap();
+            };
+            frames.push(caller);
+        }
+        return Ok(frames);
+    }
 
-                let regs = ptracer.get_regs();
-                println!("ip={:x} sp={:x} bp={:x}",
-                         regs.ip(), regs.sp(), regs.bp());
-            }
+    fn format_frame(&self, index: usize, regs: &unwind::Regs) -> String {
+        let pc = regs.pc().unwrap_or(0);
+        let func = match self.symbolize(pc) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, off)) => format!("{}+{}", name, off),
+            None => "??".to_string(),
+        };
+        let mut r = format!("#{:<2} 0x{:016x} in {}", index, pc, func);
+        let line_pc = if index > 0 { pc - 1 } else { pc };
+        if let Some((file, line)) = self.find_line_by_addr(line_pc) {
+            r += &format!(" at {}:{}", file, line);
+        } else if let Some(bin) = self.find_binary(pc) {
+            r += &format!(" from {}", bin.filename());
+        }
+        return r;
+    }
 
-            command::Command::Print(val) => {
-                println!("{}", eval::eval(self, val));
+    // Finds a variable visible from the current pc and returns its binary
+    // and the index of its DIE.
+    fn find_variable(&self, name: &str)
+                     -> Option<(&binary::Binary<'a>, usize)> {
+        let pc = self.frame_regs().map(|regs| regs.ip());
+        for bin in self.binaries() {
+            if let Some(info) = bin.debug_info() {
+                let pc = pc.and_then(|pc| pc.checked_sub(bin.bias()));
+                if let Some(var) = info.find_variable(pc, name) {
+                    return Some((bin, var));
+                }
             }
+        }
+        return None;
+    }
 
-            command::Command::Run(args) => {
-                return self.run(args);
+    fn variable_location<'b>(&self, frame: &mut FrameState<'b, 'a>,
+                         bin: &binary::Binary<'a>, var: usize)
+                         -> Result<dwarf::Location<'a>, String> {
+        let info = bin.debug_info().unwrap();
+        // As without a frame if the pc is below the binary, e.g., for a
+        // global of a shared object loaded above the main binary.
+        let pc = if self.has_frame() {
+            frame.regs.ip().checked_sub(bin.bias()).unwrap_or(0)
+        } else {
+            0
+        };
+        if let Some(func) = info.find_function(pc) {
+            if let Some(fb) = try!(info.location_expr(
+                func, dwarf::DW_AT_FRAME_BASE, pc)) {
+                frame.frame_base = Some((fb, bin.bias()));
             }
+        }
+        return match try!(info.location_expr(var, dwarf::DW_AT_LOCATION, pc)) {
+            Some(expr) => dwarf::eval_location(expr, info.addr_size(var),
+                                               bin.bias(), frame),
+            None => Ok(dwarf::Location::OptimizedOut),
+        };
+    }
 
-            command::Command::Start(args) => {
-                return self.start(args);
-            }
+    pub fn read_register(&self, reg: u16) -> Result<u64, String> {
+        return dwarf::Frame::reg(&FrameState::new(self), reg);
+    }
 
-            command::Command::StepI => {
+    // Returns the index of a general purpose register, which may be one of
+    // the aliases "pc", "sp" and "fp".
+    fn register_index(&self, name: &str) -> Option<usize> {
+        match name {
+            "pc" => Some(self.target.ip_index),
+            "sp" => Some(self.target.sp_index),
+            "fp" => Some(self.target.bp_index),
+            _ => self.target.gp_names.iter().position(|n| n == name),
+        }
+    }
+
+    // Returns the value of a register like "rax" or "pc", or None if there
+    // is no such register.
+    pub fn lookup_register(&self, name: &str)
+                           -> Option<Result<eval::Value, String>> {
+        let index = match self.register_index(name) {
+            Some(index) => index,
+            None => return self.lookup_fp_register(name),
+        };
+        let regs = match self.frame_regs() {
+            Some(regs) => regs,
+            None => return Some(Err("No registers.".to_string())),
+        };
+        // Like gdb, the pc is a code pointer and sp and fp are data pointers.
+        let ty = if index == self.target.ip_index {
+            types::Type::pointer_to(types::Type::Function(
+                Box::new(types::Type::Void), vec!(), true))
+        } else if index == self.target.sp_index ||
+            index == self.target.bp_index {
+            types::Type::pointer_to(types::Type::Void)
+        } else if index == self.target.flags_index || (
+            name.len() == 2 && name.ends_with('s')) {
+            // The flags and the segment registers like cs are 32-bit.
+            types::Type::int()
+        } else {
+            types::Type::long()
+        };
+        return Some(Ok(eval::Value::from_u64(ty, regs.gp(index))));
+    }
+
+    // Returns the x87, SSE and AVX state in the XSAVE layout.
+    fn fp_regs(&self) -> Result<Vec<u8>, String> {
+        match self.ptracer {
+            Some(ref ptracer) => Ok(try!(ptracer.get_fp_regs())),
+            None => Err("The program has no registers now.".to_string()),
+        }
+    }
+
+    fn has_avx(&self, data: &[u8]) -> bool {
+        data.len() >= self.target.ymmh_off + self.target.xmm_count * 16
+    }
+
+    // Returns the value of a register like "st0", "xmm1", "ymm2" or
+    // "mxcsr", or None if there is no such register.
+    fn lookup_fp_register(&self, name: &str)
+                          -> Option<Result<eval::Value, String>> {
+        let indexed = |prefix: &str, count: usize| {
+            if !name.starts_with(prefix) {
+                return None;
+            }
+            name[prefix.len()..].parse::<usize>().ok().filter(|&i| i < count)
+        };
+        let t = &self.target;
+        let st = indexed("st", t.st_count);
+        let xmm = indexed("xmm", t.xmm_count);
+        let ymm = indexed("ymm", t.xmm_count);
+        let ctrl = t.fp_ctrl.iter().find(|c| c.0 == name);
+        if st.is_none() && xmm.is_none() && ymm.is_none() && ctrl.is_none() {
+            return None;
+        }
+        let data = match self.fp_regs() {
+            Ok(data) => data,
+            Err(e) => return Some(Err(e)),
+        };
+        let (ty, bytes) = if let Some(i) = st {
+            let off = t.st_off + i * 16;
+            (types::x87_ext(), data[off..off + 10].to_vec())
+        } else if let Some(i) = xmm {
+            let off = t.xmm_off + i * 16;
+            (types::vector_union(16), data[off..off + 16].to_vec())
+        } else if let Some(i) = ymm {
+            if !self.has_avx(&data) {
+                return Some(Err(format!("Register ${} is not available.",
+                                        name)));
+            }
+            let (lo, hi) = (t.xmm_off + i * 16, t.ymmh_off + i * 16);
+            let mut bytes = data[lo..lo + 16].to_vec();
+            bytes.extend_from_slice(&data[hi..hi + 16]);
+            (types::vector_union(32), bytes)
+        } else {
+            let (_, off, size) = *ctrl.unwrap();
+            let ty = if size > 4 {
+                types::Type::long()
+            } else {
+                types::Type::int()
+            };
+            let mut bytes = data[off..off + size].to_vec();
+            bytes.resize(ty.size(), 0);
+            (ty, bytes)
+        };
+        return Some(Ok(eval::Value::new(ty, eval::Loc::Bytes(bytes))));
+    }
+
+    fn info_float(&self) -> Result<String, String> {
+        let data = try!(self.fp_regs());
+        let format_value = |raw: &[u8]| {
+            let v = eval::Value::new(types::x87_ext(),
+                                     eval::Loc::Bytes(raw.to_vec()));
+            eval::format(self, &v).unwrap_or_default()
+        };
+        return Ok(fp::info_float(&self.target, &data, format_value));
+    }
+
+    fn info_vector(&self) -> Result<String, String> {
+        let data = try!(self.fp_regs());
+        let mut names: Vec<String> = (0..self.target.xmm_count)
+            .map(|i| format!("xmm{}", i)).collect();
+        if self.has_avx(&data) {
+            names.extend((0..self.target.xmm_count)
+                         .map(|i| format!("ymm{}", i)));
+        }
+        let mut lines = vec!();
+        for name in names {
+            let v = self.lookup_fp_register(&name).unwrap().unwrap();
+            lines.push(format!("{:<15}{}", name, try!(eval::format(self, &v))));
+        }
+        let mxcsr = fp::ctrl(&self.target, &data, "mxcsr").unwrap();
+        lines.push(format!("{:<15}{:<19}{}", "mxcsr", format!("0x{:x}", mxcsr),
+                           fp::format_mxcsr(mxcsr)));
+        return Ok(lines.join("\n"));
+    }
+
+    fn format_flags(&self, v: u64) -> String {
+        let names: Vec<&str> = self.target.flag_names.iter()
+            .filter(|&&(bit, _)| v & (1 << bit) != 0)
+            .map(|&(_, name)| name).collect();
+        if names.is_empty() {
+            return "[ ]".to_string();
+        }
+        return format!("[ {} ]", names.join(" "));
+    }
+
+    fn info_registers(&self, names: &[String]) -> Result<String, String> {
+        let regs = try!(self.frame_regs().ok_or(
+            "The program has no registers now.".to_string()));
+        let names: Vec<&str> = if names.is_empty() {
+            self.target.gp_names.iter().map(|n| n.as_str()).collect()
+        } else {
+            names.iter().map(|n| n.trim_start_matches('$')).collect()
+        };
+        let mut lines = vec!();
+        for name in names {
+            let index = match self.register_index(name) {
+                Some(index) => index,
+                None => {
+                    let v = try!(self.lookup_fp_register(name).unwrap_or(
+                        Err(format!("Invalid register `{}'", name))));
+                    lines.push(format!("{:<15}{}", name,
+                                       try!(eval::format(self, &v))));
+                    continue;
+                }
+            };
+            let v = regs.gp(index);
+            let natural = if index == self.target.ip_index {
+                self.format_addr(v)
+            } else if index == self.target.flags_index {
+                self.format_flags(v)
+            } else {
+                format!("{}", v as i64)
+            };
+            lines.push(format!("{:<15}{:<19}{}", name, format!("0x{:x}", v),
+                               natural));
+        }
+        return Ok(lines.join("\n"));
+    }
+
+    // Returns the value of a variable visible from the current pc, or None
+    // if there is no such variable.
+    pub fn lookup_variable(&self, name: &str)
+                           -> Option<Result<eval::Value, String>> {
+        let (bin, var) = match self.find_variable(name) {
+            Some(v) => v,
+            None => return None,
+        };
+        let info = bin.debug_info().unwrap();
+        let ty = types::Decoder::new(info, bin.filename()).type_of(var);
+        let mut frame = FrameState::new(self);
+        let loc = match self.variable_location(&mut frame, bin, var) {
+            Ok(dwarf::Location::Memory(addr)) => eval::Loc::Memory(addr),
+            Ok(dwarf::Location::Register(reg)) => eval::Loc::Register(reg),
+            Ok(dwarf::Location::Value(v)) => {
+                return Some(Ok(eval::Value::from_u64(ty, v)));
+            }
+            Ok(dwarf::Location::Implicit(data)) => {
+                eval::Loc::Bytes(data.to_vec())
+            }
+            Ok(dwarf::Location::OptimizedOut) => eval::Loc::OptimizedOut,
+            Err(msg) => return Some(Err(msg)),
+        };
+        return Some(Ok(eval::Value::new(ty, loc)));
+    }
+
+    pub fn lookup_function(&self, name: &str) -> Option<eval::Value> {
+        for bin in self.binaries() {
+            let info = match bin.debug_info() {
+                Some(info) => info,
+                None => continue,
+            };
+            if let Some(func) = info.find_function_by_name(name) {
+                let ty = types::Decoder::new(info, bin.filename())
+                    .decode(func, false);
+                let addr = info.ranges(func).iter().map(|r| r.0).min()
+                    .unwrap_or(0);
+                return Some(eval::Value::new(
+                    ty, eval::Loc::Memory(addr + bin.bias())));
+            }
+        }
+        return None;
+    }
+
+    // Fills the members of a struct which was decoded without them.
+    pub fn complete_type(&self, ty: &types::Type) -> types::Type {
+        match *ty {
+            types::Type::Typedef(ref name, ref t) => {
+                types::Type::Typedef(name.clone(),
+                                     Box::new(self.complete_type(t)))
+            }
+            types::Type::Const(ref t) => {
+                types::Type::Const(Box::new(self.complete_type(t)))
+            }
+            types::Type::Volatile(ref t) => {
+                types::Type::Volatile(Box::new(self.complete_type(t)))
+            }
+            types::Type::Struct(ref s) if s.members.is_none() => {
+                for bin in self.binaries() {
+                    let info = match bin.debug_info() {
+                        Some(info) => info,
+                        None => continue,
+                    };
+                    let decoder = types::Decoder::new(info, bin.filename());
+                    let found = if *bin.filename() == s.origin.0 {
+                        decoder.decode(s.origin.1, false)
+                    } else {
+                        match s.name {
+                            Some(ref name) => {
+                                let name = format!("{} {}", s.kind.keyword(),
+                                                   name);
+                                match decoder.find(&name) {
+                                    Some(found) => found,
+                                    None => continue,
+                                }
+                            }
+                            None => continue,
+                        }
+                    };
+                    if let types::Type::Struct(ref f) = found {
+                        if f.members.is_some() {
+                            return found.clone();
+                        }
+                    }
+                }
+                ty.clone()
+            }
+            ref t => t.clone(),
+        }
+    }
+
+    // Finds a type by its name, e.g., "struct point" or "char *".
+    pub fn lookup_type(&self, name: &str) -> Option<types::Type> {
+        let name = name.trim();
+        if name.ends_with('*') {
+            return self.lookup_type(&name[..name.len() - 1])
+                .map(types::Type::pointer_to);
+        }
+        for bin in self.binaries() {
+            if let Some(info) = bin.debug_info() {
+                let found = types::Decoder::new(info, bin.filename())
+                    .find(name);
+                if found.is_some() {
+                    return found;
+                }
+            }
+        }
+        return types::c_base_type(name);
+    }
+
+    // Returns the type of `ptype` and `whatis` arguments, which may be
+    // either a type name or an expression. The flag is set for type names.
+    fn type_of_arg(&self, arg: &str) -> Result<(types::Type, bool), String> {
+        if let Some(ty) = self.lookup_type(arg) {
+            return Ok((ty, true));
+        }
+        let e = try!(expr::parse(arg));
+        let v = try!(eval::eval(self, &e));
+        return Ok((v.ty, false));
+    }
+
+    fn default_source_file(&self) -> Result<String, String> {
+        let addr = if self.has_frame() {
+            Some(self.ip())
+        } else {
+            self.resolve("main")
+        };
+        return addr.and_then(|addr| self.find_line_by_addr(addr))
+            .map(|(file, _)| file)
+            .ok_or("No default source file.".to_string());
+    }
+
+    pub fn resolve_line(&self, file: Option<&str>, line: u32)
+                        -> Result<u64, String> {
+        let file = match file {
+            Some(file) => file.to_string(),
+            None => try!(self.default_source_file()),
+        };
+        let mut has_file = false;
+        for bin in self.binaries() {
+            if let Some((addr, _)) = bin.lines().find_line(&file, line) {
+                return Ok(addr + bin.bias());
+            }
+            has_file |= bin.lines().has_file(&file);
+        }
+        if has_file {
+            return Err(format!("Line {} out of range; \"{}\" has fewer lines.",
+                               line, file));
+        }
+        return Err(format!("No source file named {}.", file));
+    }
+
+    fn pid(&self) -> i32 {
+        assert!(self.ptracer.is_some());
+        return self.ptracer.as_ref().unwrap().pid() as i32;
+    }
+
+    pub fn wait(&mut self) -> Result<String, String> {
+        assert!(self.needs_wait);
+        loop {
+            let msg = try!(self.wait_impl(false));
+            // Single steps and other stops only for software watchpoints
+            // are not reported.
+            if !msg.is_empty() || !self.needs_wait ||
+                !self.breakpoints.has_software_watchpoints() {
+                return Ok(msg);
+            }
+        }
+    }
+
+    fn handle_breakpoint(&mut self, is_single_step: bool)
+                         -> Result<String, String> {
+        let status = {
+            let ptracer = self.ptracer.as_ref().unwrap();
+            self.regs = try!(ptracer.get_regs());
+            match self.pending_debug_status.take() {
+                Some(status) => status,
+                None if self.breakpoints.has_hardware_watchpoints() => {
+                    try!(ptracer.take_debug_status())
+                }
+                None => 0,
+            }
+        };
+        let (tid, stepped) = {
+            let ptracer = self.ptracer.as_ref().unwrap();
+            (ptracer.tid(), ptracer.stopped_by_step())
+        };
+        // Other threads run during the step and may stop first. Then the
+        // step is reported later, when it is no longer awaited.
+        let (step, stale_step) = match self.watch_step.take() {
+            Some(step) if step.tid == tid => (Some(step), false),
+            Some(_) => (None, !is_single_step && stepped),
+            None => (None, !is_single_step && stepped),
+        };
+        if step.is_some() || stale_step {
+            // The change is not the step's if other threads ran.
+            let insn = step.and_then(|step| {
+                if step.alone { Some(step.ip) } else { None }
+            });
+            if let Some(msg) = try!(self.check_software_watchpoints(insn)) {
+                return Ok(msg);
+            }
+        }
+        if status != 0 {
+            return self.handle_watchpoint(status, is_single_step);
+        }
+        if stale_step {
+            try!(self.skip_uninteresting_code());
+            try!(self.cont());
+            return Ok("".to_string());
+        }
+        if let Some(ref step) = step {
+            let bp_size = self.target.breakpoint_size as u64;
+            let hit = !step.over_breakpoint &&
+                self.regs.ip() == step.ip + bp_size &&
+                self.breakpoints.find_by_addr(step.ip)
+                .map_or(false, |bp| bp.is_active());
+            if !hit {
+                try!(self.skip_uninteresting_code());
+                try!(self.cont());
+                return Ok("".to_string());
+            }
+        }
+        let action = {
+            let ptracer = self.ptracer.as_ref().unwrap();
+            // TODO: Handle single-step-to-braekpoint case.
+            if is_single_step {
+                return Ok("".to_string());
+            }
+
+            let ip = self.regs.ip() - self.target.breakpoint_size as u64;
+            match self.breakpoints.find_by_addr(ip) {
+                Some(bp) => {
+                    self.regs.update_ip(ip, &self.target);
+                    try!(ptracer.set_regs(&self.regs));
+                    try!(ptracer.poke_byte(ip, bp.token()));
+                    self.cur_breakpoint = bp.id();
+                    self.cur_breakpoint_tid = ptracer.tid();
+                    match *bp.action() {
+                        Some(action) => Some(action),
+                        None => {
+                            return Ok(format!("Breakpoint {}, {}", bp.id(),
+                                              self.format_addr(ip)));
+                        }
+                    }
+                }
+                None => None,
+            }
+        };
+
+        // Not ours, e.g. an int3 in the program.
+        let action = match action {
+            Some(action) => action,
+            None => return self.handle_signal(signal::SIGTRAP),
+        };
+        match action {
+            breakpoint::Action::UpdateRDebug => {
+                if let Err(msg) = self.update_shared_libraries() {
+                    log_info!("Failed to read link_map: {}", msg);
+                }
+            }
+
+            breakpoint::Action::EnterMainBinary => {
+                log_info!("Entering main binary");
+                // The dynamic linker has loaded the initial shared objects.
+                self.read_r_debug();
+                if let Err(msg) = self.update_shared_libraries() {
+                    log_info!("Failed to read link_map: {}", msg);
+                }
+            }
+
+            breakpoint::Action::ResumeStepping => {
+                // A nested call may reach the address before the return.
+                if self.regs.sp() >= self.skip_sp {
+                    self.breakpoints.discard(self.cur_breakpoint);
+                    self.cur_breakpoint = 0;
+                    self.skip_breakpoint = 0;
+                    if let Some(msg) =
+                        try!(self.check_software_watchpoints(None)) {
+                        return Ok(msg);
+                    }
+                }
+            }
+        }
+
+        try!(self.cont());
+        return Ok(format!(""));
+    }
+
+    // Reports a watchpoint which made the process stop with the old and
+    // new values. The process goes on if a write left the value as it was,
+    // or if a read watchpoint, which traps writes too, saw a write.
+    fn handle_watchpoint(&mut self, status: u64, is_single_step: bool)
+                         -> Result<String, String> {
+        let (id, title, expr, data, values) = {
+            let wp = match self.breakpoints.find_watchpoint(status) {
+                Some(wp) => wp,
+                None => return self.handle_signal(signal::SIGTRAP),
+            };
+            let data = try!(self.read_memory(wp.addr(), wp.value().len()));
+            let format = |data| self.format_watch_value(wp.ty(), data);
+            let changed = data != wp.value();
+            let values = match (wp.kind(), changed) {
+                (breakpoint::WatchKind::Write, false) => None,
+                (breakpoint::WatchKind::Read, true) => None,
+                (_, true) => Some(format!("Old value = {}\nNew value = {}",
+                                          try!(format(wp.value())),
+                                          try!(format(&data)))),
+                (_, false) => Some(format!("Value = {}",
+                                           try!(format(&data)))),
+            };
+            (wp.id(), wp.title(), wp.expr().to_string(), data, values)
+        };
+        self.breakpoints.set_watch_value(id, data);
+        let values = match values {
+            Some(values) => values,
+            None if is_single_step => return Ok("".to_string()),
+            None => {
+                try!(self.cont());
+                return Ok("".to_string());
+            }
+        };
+        let regs = self.unwind_regs(&self.regs);
+        return Ok(format!("{} {}: {}\n\n{}\n{}", title, id, expr, values,
+                          self.format_frame(0, &regs)));
+    }
+
+    fn format_watch_value(&self, ty: &types::Type, data: &[u8])
+                          -> Result<String, String> {
+        let v = eval::Value::new(ty.clone(), eval::Loc::Bytes(data.to_vec()));
+        return eval::format(self, &v);
+    }
+
+    // Reports the software watchpoints whose values changed by the
+    // instruction at `insn`, if it is known.
+    fn check_software_watchpoints(&mut self, insn: Option<u64>)
+                                  -> Result<Option<String>, String> {
+        let mut changes = vec!();
+        for wp in self.breakpoints.watchpoints() {
+            if wp.is_hardware() {
+                continue;
+            }
+            let data = try!(self.read_memory(wp.addr(), wp.value().len()));
+            if data == wp.value() {
+                continue;
+            }
+            let msg = format!("{} {}: {}\n\nOld value = {}\nNew value = {}",
+                              wp.title(), wp.id(), wp.expr(),
+                              try!(self.format_watch_value(wp.ty(),
+                                                           wp.value())),
+                              try!(self.format_watch_value(wp.ty(), &data)));
+            changes.push((wp.id(), data, msg));
+        }
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        let mut msgs = vec!();
+        for (id, data, msg) in changes {
+            self.breakpoints.set_watch_value(id, data);
+            msgs.push(msg);
+        }
+        let mut msg = msgs.join("\n\n");
+        if let Some(addr) = insn {
+            let (text, _) = try!(self.disassemble_insn(addr));
+            let mut loc = self.format_addr(addr);
+            if let Some((file, line)) = self.find_line_by_addr(addr) {
+                loc += &format!(" at {}:{}", file, line);
+            }
+            msg += &format!("\nChanged by {}: {}", loc, text);
+        }
+        let regs = self.unwind_regs(&self.regs);
+        return Ok(Some(format!("{}\n{}", msg, self.format_frame(0, &regs))));
+    }
+
+    // Code which software watchpoints run through without single steps.
+    fn is_uninteresting(&self, addr: u64) -> bool {
+        self.interp.as_ref().map_or(false, |bin| {
+            addr >= bin.bias() && bin.contains(addr - bin.bias())
+        })
+    }
+
+    // Lets uninteresting code like the dynamic linker run without single
+    // steps up to the return address in the frame of its caller. The code
+    // may be entered by a call, or by a jump like that of a lazy PLT entry,
+    // which the CFI of the callee tells.
+    fn skip_uninteresting_code(&mut self) -> Result<(), String> {
+        let ip = self.regs.ip();
+        if self.skip_breakpoint != 0 || !self.is_uninteresting(ip) {
+            return Ok(());
+        }
+        let caller = {
+            let frames = try!(self.backtrace());
+            frames.iter().skip(1).filter_map(|regs| {
+                match (regs.pc(), regs.sp()) {
+                    (Some(pc), Some(sp)) => Some((pc, sp)),
+                    _ => None,
+                }
+            }).find(|&(pc, _)| !self.is_uninteresting(pc))
+        };
+        let (ret, sp) = match caller {
+            Some(caller) => caller,
+            // E.g. the dynamic linker starting the process.
+            None => return Ok(()),
+        };
+        if self.breakpoints.find_by_addr(ret).is_some() {
+            return Ok(());
+        }
+        let bp = try!(self.breakpoints.add(
+            ret, false, Some(breakpoint::Action::ResumeStepping),
+            self.ptracer.as_ref()));
+        self.skip_breakpoint = bp.id();
+        self.skip_sp = sp;
+        return Ok(());
+    }
+
+    fn read_string(&self, addr: u64) -> Result<String, String> {
+        let mut data = vec!();
+        loop {
+            let word = try!(self.read_word(addr + data.len() as u64));
+            for i in 0..8 {
+                let c = (word >> (i * 8)) as u8;
+                if c == 0 {
+                    return Ok(String::from_utf8_lossy(&data).into_owned());
+                }
+                data.push(c);
+            }
+        }
+    }
+
+    // Walks the link_map list of the dynamic linker and loads the shared
+    // objects which were not seen yet.
+    fn update_shared_libraries(&mut self) -> Result<(), String> {
+        if self.r_map == 0 {
+            return Ok(());
+        }
+        // The list is being modified unless r_state is RT_CONSISTENT.
+        let r_state = try!(self.read_word(self.r_map + 16)) as u32;
+        if r_state != RT_CONSISTENT {
+            return Ok(());
+        }
+
+        let mut loaded = vec!();
+        let mut lm = try!(self.read_word(self.r_map));
+        while lm != 0 && loaded.len() < MAX_LINK_MAPS {
+            let l_addr = try!(self.read_word(lm));
+            let l_name = try!(self.read_word(lm + 8));
+            let name = if l_name == 0 {
+                String::new()
+            } else {
+                try!(self.read_string(l_name))
+            };
+            // The main binary and the vDSO have no files.
+            if std::path::Path::new(&name).is_file() {
+                loaded.push((name, l_addr));
+            }
+            lm = try!(self.read_word(lm + 24));
+        }
+
+        let interp = self.interp.as_ref().map(|bin| bin.filename().clone());
+        self.shlibs.retain(|bin| {
+            loaded.iter().any(|l| &l.0 == bin.filename())
+        });
+        for (name, l_addr) in loaded {
+            if Some(&name) == interp.as_ref() ||
+                self.shlibs.iter().any(|bin| bin.filename() == &name) {
+                continue;
+            }
+            match binary::Binary::new(name) {
+                Ok(mut bin) => {
+                    bin.set_bias(l_addr);
+                    self.shlibs.push(bin);
+                }
+                Err(msg) => {
+                    log_info!("{}", msg);
+                }
+            }
+        }
+        self.rebuild_symtab();
+        return Ok(());
+    }
+
+    fn unload_shared_libraries(&mut self) {
+        self.r_map = 0;
+        self.shlibs.clear();
+        self.rebuild_symtab();
+    }
+
+    fn info_shared_library(&self) -> String {
+        let mut lines = vec!(format!("{:<19} {:<19} {:<11} {}", "From", "To",
+                                     "Syms Read", "Shared Object Library"));
+        let mut no_debug = false;
+        // The interpreter is not mapped until the process starts.
+        let interp = self.interp.iter().filter(|_| self.has_frame());
+        let libs = interp.chain(self.shlibs.iter());
+        for bin in libs {
+            let (from, to) = match bin.section(".text") {
+                Some(text) => (text.addr + bin.bias(),
+                               text.addr + bin.bias() + text.data.len() as u64),
+                None => (bin.bias(), bin.bias()),
+            };
+            let syms = if bin.debug_info().is_some() {
+                "Yes"
+            } else {
+                no_debug = true;
+                "Yes (*)"
+            };
+            lines.push(format!("0x{:016x}  0x{:016x}  {:<11} {}",
+                               from, to, syms, bin.filename()));
+        }
+        if lines.len() == 1 {
+            return "No shared libraries loaded at this time.".to_string();
+        }
+        if no_debug {
+            lines.push("(*): Shared library is missing debugging information."
+                       .to_string());
+        }
+        return lines.join("\n");
+    }
+
+    fn wait_impl(&mut self, is_single_step: bool) -> Result<String, String> {
+        assert!(self.ptracer.is_some());
+        self.needs_wait = false;
+        let (status, prev_tid) = match self.pending_state.take() {
+            Some(pending) => pending,
+            None => {
+                let ptracer = self.ptracer.as_mut().unwrap();
+                let prev_tid = ptracer.tid();
+                (try!(ptracer.wait()), prev_tid)
+            }
+        };
+
+        match status {
+            ptracer::ProcessState::Stop(sig) => {
+                let msg = if sig == signal::SIGTRAP {
+                    try!(self.handle_breakpoint(is_single_step))
+                } else {
+                    try!(self.handle_signal(sig))
+                };
+                let tid = match self.ptracer {
+                    Some(ref ptracer) => ptracer.tid(),
+                    None => return Ok(msg),
+                };
+                if msg.is_empty() || tid == prev_tid {
+                    return Ok(msg);
+                }
+                return Ok(format!("{}\n{}", self.thread_title(tid), msg));
+            }
+
+            ptracer::ProcessState::Exit(st) => {
+                let pid = self.pid();
+                let next = self.finish_process();
+                return Ok(format!("Process {} exited with code {}{}",
+                                  pid, st, next));
+            }
+
+            ptracer::ProcessState::Signal(sig) => {
+                let pid = self.pid();
+                let next = self.finish_process();
+                return Ok(format!("Process {} signaled with code {}{}",
+                                  pid, sig, next));
+            }
+
+            ptracer::ProcessState::Fork(child) => {
+                return self.handle_fork(child, false);
+            }
+
+            ptracer::ProcessState::VFork(child) => {
+                return self.handle_fork(child, true);
+            }
+
+            ptracer::ProcessState::Exec => {
+                return self.handle_exec();
+            }
+        }
+    }
+
+    // Reports a signal which stopped the process, or lets the process go on
+    // depending on the handle table. The signal is delivered when the
+    // process continues if it is set to pass at that time.
+    fn handle_signal(&mut self, sig: i32) -> Result<String, String> {
+        let handling = self.signals.get(sig);
+        let info = {
+            let ptracer = self.ptracer.as_mut().unwrap();
+            self.regs = try!(ptracer.get_regs());
+            let tid = ptracer.tid();
+            ptracer.set_signal(tid, sig);
+            ptracer.get_siginfo(tid)
+        };
+        let mut msg = format!("Program received signal {}, {}.",
+                              signal::name(sig), signal::description(sig));
+        if let Ok(info) = info {
+            msg += &self.format_siginfo(&info);
+        }
+        if handling.stop {
+            let regs = self.unwind_regs(&self.regs);
+            return Ok(format!("{}\n{}", msg, self.format_frame(0, &regs)));
+        }
+        try!(self.cont());
+        return Ok(if handling.print { msg } else { "".to_string() });
+    }
+
+    // Explains why a signal was raised, e.g. the faulting address of
+    // SIGSEGV with the symbol and the mapping which contain it.
+    fn format_siginfo(&self, info: &signal::SigInfo) -> String {
+        let mut r = match signal::code_name(info.signo, info.code) {
+            Some((name, desc)) => format!("\n{} ({})", desc, name),
+            None => format!("\nsi_code {}", info.code),
+        };
+        if signal::has_sender(info) {
+            r += &format!(" from process {}", info.pid);
+        } else if signal::has_fault_addr(info) {
+            let maps = self.ptracer.as_ref().unwrap().maps()
+                .unwrap_or(vec!());
+            r += &format!(" at address {}", self.format_addr(info.addr));
+            match maps.iter().find(|m| m.start <= info.addr &&
+                                   info.addr < m.end) {
+                Some(m) if m.path.is_empty() => {
+                    r += &format!(" in anonymous mapping ({})", m.perms);
+                }
+                Some(m) => r += &format!(" in {} ({})", m.path, m.perms),
+                None => r += " (not mapped)",
+            }
+        }
+        return r;
+    }
+
+    // Applies `handle SIGNAL... [KEYWORD...]` and shows the result.
+    fn handle(&mut self, args: &[String]) -> Result<String, String> {
+        let mut sigs = vec!();
+        let mut keywords = vec!();
+        for arg in args {
+            if arg == "all" {
+                // Like gdb, the signals used by the debugger are excluded.
+                sigs.extend((1..65).filter(|&sig| {
+                    sig != signal::SIGTRAP && signal::name(sig) != "SIGINT"
+                }));
+            } else if let Some(sig) = signal::from_name(arg) {
+                sigs.push(sig);
+            } else {
+                keywords.push(arg.as_str());
+            }
+        }
+        if sigs.is_empty() {
+            return Err("Argument required (signal to handle).".to_string());
+        }
+        for &sig in sigs.iter() {
+            for keyword in keywords.iter() {
+                try!(self.signals.set(sig, keyword));
+            }
+        }
+        return Ok(self.signals.format(&sigs));
+    }
+
+    // Forgets the finished process. If a process was kept suspended after
+    // a fork, it is debugged next and messages for it are returned.
+    fn finish_process(&mut self) -> String {
+        let mut msg = String::new();
+        if let Some(parent) = self.vfork_parent.take() {
+            match self.leave_process(parent, "parent") {
+                Ok(left) | Err(left) => msg += &format!("\n{}", left),
+            }
+        }
+        self.breakpoints.notify_finish();
+        self.skip_breakpoint = 0;
+        self.ptracer = None;
+        self.relocate_main_binary(0);
+        self.unload_shared_libraries();
+        if self.forks.is_empty() {
+            return msg;
+        }
+        let ptracer = self.forks.remove(0);
+        let pid = ptracer.pid();
+        let r = std::fs::read_link(format!("/proc/{}/exe", pid))
+            .map_err(|e| format!("Cannot read the program of {}: {}", pid, e))
+            .and_then(|exe| self.reload_main_binary(&exe))
+            .and_then(|_| self.adopt(ptracer));
+        match r {
+            Ok(frame) => {
+                msg += &format!("\n[Switching to process {}]\n{}", pid, frame);
+            }
+            Err(err) => msg += &format!("\n{}", err),
+        }
+        return msg;
+    }
+
+    // Follows the parent or the child after a fork. The other process is
+    // detached or kept suspended.
+    fn handle_fork(&mut self, child: i32, is_vfork: bool)
+                   -> Result<String, String> {
+        let pid = self.pid();
+        let child = ptracer::Ptracer::from_fork(child);
+        let msg;
+        if self.follow_fork_child {
+            let attaching = format!("[Attaching after process {} {} to \
+                                     child process {}]", pid,
+                                    if is_vfork { "vfork" } else { "fork" },
+                                    child.pid());
+            let parent = self.ptracer.take().unwrap();
+            self.ptracer = Some(child);
+            if is_vfork {
+                // The breakpoints are left until the parent has its own
+                // memory again.
+                self.vfork_parent = Some(parent);
+                msg = attaching;
+            } else {
+                let left = try!(self.leave_process(parent, "parent"));
+                msg = format!("{}\n{}", attaching, left);
+            }
+        } else if is_vfork {
+            // Removing the breakpoints from a vfork child would remove them
+            // from the parent, and suspending it would block the parent.
+            try!(child.detach());
+            msg = format!("[Detaching after vfork from child process {}]",
+                          child.pid());
+        } else {
+            msg = try!(self.leave_process(child, "child"));
+        }
+        try!(self.cont());
+        return Ok(msg);
+    }
+
+    // Removes the breakpoints from a process which is not followed after a
+    // fork. It is then detached, or suspended without detach-on-fork.
+    fn leave_process(&mut self, ptracer: ptracer::Ptracer, role: &str)
+                     -> Result<String, String> {
+        try!(self.breakpoints.remove_from(&ptracer));
+        let pid = ptracer.pid();
+        if self.detach_on_fork {
+            try!(ptracer.detach());
+            return Ok(format!("[Detaching after fork from {} process {}]",
+                              role, pid));
+        }
+        self.forks.push(ptracer);
+        return Ok(format!("[Suspending {} process {}]", role, pid));
+    }
+
+    // Reloads the program after the process executed a new one.
+    fn handle_exec(&mut self) -> Result<String, String> {
+        let pid = self.pid();
+        let mut msgs = vec!();
+        if let Some(parent) = self.vfork_parent.take() {
+            msgs.push(try!(self.leave_process(parent, "parent")));
+        }
+        // The breakpoints were gone with the old program.
+        self.breakpoints.notify_finish();
+        self.cur_breakpoint = 0;
+        self.skip_breakpoint = 0;
+        self.relocate_main_binary(0);
+        self.unload_shared_libraries();
+
+        let exe = try!(std::fs::read_link(format!("/proc/{}/exe", pid))
+                       .map_err(|e| format!("Cannot read the program of {}: \
+                                             {}", pid, e)));
+        msgs.push(format!("process {} is executing new program: {}",
+                          pid, exe.display()));
+        msgs.extend(try!(self.reload_main_binary(&exe)));
+        msgs.extend(try!(self.notify_exec()));
+        try!(self.cont());
+        return Ok(msgs.join("\n"));
+    }
+
+    // Switches the main binary if the process runs another program, and
+    // finds the breakpoints in it again. Returns messages for breakpoints
+    // which are not found, and for watchpoints, which are deleted.
+    fn reload_main_binary(&mut self, exe: &std::path::Path)
+                          -> Result<Vec<String>, String> {
+        let same_exe = self.main_binary.as_ref().map_or(false, |bin| {
+            std::fs::canonicalize(bin.filename()).ok().as_ref().map(|p| {
+                p.as_path()
+            }) == Some(exe)
+        });
+        if same_exe {
+            return Ok(vec!());
+        }
+        try!(self.set_main_binary(&exe.to_string_lossy()));
+        let mut breakpoints = std::mem::replace(
+            &mut self.breakpoints, breakpoint::BreakpointManager::new());
+        let deleted = breakpoints.respecify(|spec| self.resolve_spec(spec));
+        self.breakpoints = breakpoints;
+        return Ok(deleted.iter().map(|id| {
+            format!("Breakpoint {} deleted: not found in {}",
+                    id, exe.display())
+        }).collect());
+    }
+
+    fn resolve_spec(&self, spec: &breakpoint::Spec) -> Option<u64> {
+        match *spec {
+            breakpoint::Spec::Function(ref name) => {
+                if !self.is_function(name) {
+                    return None;
+                }
+                let name = expr::Expr::Ident(name.clone());
+                return eval::eval_address(self, &name).ok();
+            }
+            breakpoint::Spec::Line(ref file, line) => {
+                let file = file.as_ref().map(|f| f.as_str());
+                return self.resolve_line(file, line).ok();
+            }
+        }
+    }
+
+    pub fn cont(&mut self) -> Result<String, String> {
+        if self.ptracer.is_none() {
+            return Err("The program is not being run.".to_string());
+        }
+        let ptracer = self.ptracer.as_mut().unwrap();
+        let signals = &self.signals;
+        ptracer.retain_signals(|sig| signals.get(sig).pass);
+        // Software watchpoints single-step the process except while it
+        // runs uninteresting code.
+        let step_watch = self.breakpoints.has_software_watchpoints() &&
+            self.skip_breakpoint == 0;
+        self.watch_step = None;
+
+        if self.cur_breakpoint != 0 {
+            let cur_breakpoint = self.cur_breakpoint;
+            self.cur_breakpoint = 0;
+            if let Some(bp) = self.breakpoints.find_by_id(cur_breakpoint) {
+                let prev_tid = ptracer.tid();
+                try!(ptracer.poke_byte(bp.addr(), bp.token()));
+                try!(ptracer.single_step_thread(self.cur_breakpoint_tid));
+                let state = try!(ptracer.wait());
+                match state {
+                    // The memory is gone or replaced with the process.
+                    ptracer::ProcessState::Exit(_) |
+                    ptracer::ProcessState::Signal(_) |
+                    ptracer::ProcessState::Exec => {}
+                    _ => {
+                        try!(ptracer.poke_breakpoint(bp.addr()));
+                    }
+                }
+                match state {
+                    ptracer::ProcessState::Stop(signal::SIGTRAP) => {
+                        // The step may have hit a watchpoint.
+                        let status =
+                            if self.breakpoints.has_hardware_watchpoints() {
+                                try!(ptracer.take_debug_status())
+                            } else {
+                                0
+                            };
+                        if status != 0 || step_watch {
+                            self.pending_debug_status = Some(status);
+                            self.pending_state = Some((state, prev_tid));
+                        }
+                        if step_watch {
+                            self.watch_step = Some(WatchStep {
+                                tid: self.cur_breakpoint_tid,
+                                ip: bp.addr(),
+                                alone: true,
+                                over_breakpoint: true,
+                            });
+                        }
+                    }
+                    _ => {
+                        // A signal which arrived before the instruction was
+                        // executed makes the step be done again.
+                        if state.is_stopped() &&
+                            try!(ptracer.get_regs()).ip() == bp.addr() {
+                            self.cur_breakpoint = cur_breakpoint;
+                        }
+                        // Faults, signals and events of the step are
+                        // reported by the next wait.
+                        self.pending_state = Some((state, prev_tid));
+                    }
+                }
+            }
+        }
+
+        if self.pending_state.is_none() {
+            if step_watch {
+                self.watch_step = Some(WatchStep {
+                    tid: ptracer.tid(),
+                    ip: self.regs.ip(),
+                    alone: ptracer.threads().len() == 1,
+                    over_breakpoint: false,
+                });
+                try!(ptracer.single_step_with_others());
+            } else {
+                try!(ptracer.cont());
+            }
+        }
+        assert!(!self.needs_wait);
+        self.needs_wait = true;
+        return Ok("Continuing.".to_string());
+    }
+
+    fn set_entry_bias(&mut self, ip: u64) {
+        if let Some(ref mut interp) = self.interp {
+            let entry = interp.entry();
+            interp.set_bias(ip - entry);
+        }
+    }
+
+    // Computes where the main binary was loaded from the auxiliary vector,
+    // which is non-zero for PIE.
+    fn main_binary_bias(&self) -> Result<u64, String> {
+        let main_binary = try!(self.main_binary.as_ref().ok_or(
+            "No executable specified.".to_string()));
+        let auxv = match self.core {
+            Some(ref core) => core.auxv().clone(),
+            None => try!(self.ptracer.as_ref().unwrap().auxv()),
+        };
+        let find = |key| auxv.iter().find(|a| a.0 == key).map(|a| a.1);
+        if let Some(entry) = find(AT_ENTRY) {
+            return Ok(entry.wrapping_sub(main_binary.entry()));
+        }
+        if let (Some(phdr), Some(vaddr)) = (find(AT_PHDR), main_binary.phdr()) {
+            return Ok(phdr.wrapping_sub(vaddr));
+        }
+        return Err("No AT_ENTRY nor AT_PHDR in auxv".to_string());
+    }
+
+    // Changes the bias of the main binary, moving breakpoints in it along.
+    fn relocate_main_binary(&mut self, bias: u64) {
+        {
+            let main_binary = match self.main_binary.as_mut() {
+                Some(bin) => bin,
+                None => return,
+            };
+            let old_bias = main_binary.bias();
+            if old_bias == bias {
+                return;
+            }
+            {
+                let bin = &*main_binary;
+                self.breakpoints.relocate(|addr| {
+                    if addr >= old_bias && bin.contains(addr - old_bias) {
+                        Some(addr - old_bias + bias)
+                    } else {
+                        None
+                    }
+                });
+            }
+            main_binary.set_bias(bias);
+        }
+    }
+
+    pub fn run(&mut self, args: Vec<String>) -> Result<String, String> {
+        let msg = try!(self.start(args));
+        try!(self.cont());
+        return Ok(msg);
+    }
+
+    fn handle_boot_entry(&mut self) {
+        if self.interp.is_some() {
+            let main_binary = match self.main_binary.as_ref() {
+                Some(bin) => bin,
+                None => panic!("No start binary"),
+            };
+            let r = self.breakpoints.add(
+                main_binary.entry() + main_binary.bias(), false,
+                Some(breakpoint::Action::EnterMainBinary),
+                self.ptracer.as_ref());
+            if let Err(err) = r {
+                log_info!("Failed to break at the entry: {}", err);
+            }
+            return;
+        } else {
+            self.read_r_debug();
+        }
+    }
+
+    fn read_r_debug(&mut self) {
+        let bin = {
+            match self.interp.as_ref() {
+                Some(bin) => bin,
+                None => match self.main_binary.as_ref() {
+                    Some(bin) => bin,
+                    None => panic!("No start binary"),
+                }
+            }
+        };
+
+        for sym in bin.syms() {
+            if sym.name == "_r_debug" {
+                let r_debug_addr = sym.value + bin.bias();
+                self.r_map = r_debug_addr + (self.target.gp_size as u64);
+                // Nothing can be loaded into a core file.
+                let ptracer = match self.ptracer.as_ref() {
+                    Some(ptracer) => ptracer,
+                    None => continue,
+                };
+                let bp = match ptracer.peek_word(
+                    r_debug_addr + (self.target.gp_size as u64) * 2) {
+                    Ok(bp) => bp,
+                    Err(err) => {
+                        log_info!("Failed to read r_debug: {}", err);
+                        continue;
+                    }
+                };
+                log_info!("r_debug_addr={:x} bp={:x}", r_debug_addr, bp);
+                let r = self.breakpoints.add(
+                    bp, false, Some(breakpoint::Action::UpdateRDebug),
+                    Some(ptracer));
+                if let Err(err) = r {
+                    log_info!("Failed to break at r_brk: {}", err);
+                }
+            }
+        }
+    }
+
+    pub fn start(&mut self, args: Vec<String>) -> Result<String, String> {
+        let mut argv = vec![];
+        {
+            let main_binary = try!(self.main_binary.as_mut().ok_or(
+                "No executable specified.".to_string()));
+            argv.push(main_binary.filename().clone());
+        }
+        if args.len() > 0 {
+            argv.extend(args);
+        } else {
+            argv.extend(self.args.iter().cloned());
+        }
+        if self.core.take().is_some() {
+            self.unload_shared_libraries();
+        }
+        // TODO: Stop at main if it exists.
+        let ptracer = try!(ptracer::Ptracer::new(&argv));
+        let mut msgs = vec!(format!("Starting program: {} (pid={})",
+                                    argv[0], ptracer.pid()));
+        self.ptracer = Some(ptracer);
+        msgs.extend(try!(self.notify_exec()));
+        return Ok(msgs.join("\n"));
+    }
+
+    // Sets up breakpoints for a process which has just executed the main
+    // binary. Returns warnings for breakpoints which cannot be inserted.
+    fn notify_exec(&mut self) -> Result<Vec<String>, String> {
+        let regs = try!(self.ptracer.as_ref().unwrap().get_regs());
+        self.set_entry_bias(regs.ip());
+
+        match self.main_binary_bias() {
+            Ok(bias) => self.relocate_main_binary(bias),
+            Err(msg) => {
+                log_info!("Failed to compute the load bias: {}", msg);
+            }
+        }
+        self.rebuild_symtab();
+        let warnings = self.insert_breakpoints();
+
+        self.handle_boot_entry();
+        return Ok(warnings);
+    }
+
+    fn insert_breakpoints(&mut self) -> Vec<String> {
+        let failures =
+            self.breakpoints.notify_start(self.ptracer.as_mut().unwrap());
+        return failures.iter().map(|&(id, ref err)| {
+            format!("Warning: Cannot insert breakpoint {}.\n{}", id, err)
+        }).collect();
+    }
+
+    // Loads a core file of the main binary for post-mortem debugging.
+    pub fn load_core(&mut self, filename: &str) -> Result<String, String> {
+        if self.ptracer.is_some() {
+            return Err("A program is being debugged already.".to_string());
+        }
+        if self.main_binary.is_none() {
+            return Err("No executable specified.".to_string());
+        }
+        let core = try!(corefile::Core::new(filename.to_string(), &self.target));
+        let signal = core.threads()[0].signal;
+        let mut msg = format!("Core was generated by `{}'.\n", core.psargs());
+        msg += &format!("Program terminated with signal {}, {}.",
+                        signal::name(signal), signal::description(signal));
+        self.regs = core.threads()[0].regs.clone();
+        let base = core.auxv().iter().find(|a| a.0 == AT_BASE).map(|a| a.1);
+        self.unload_shared_libraries();
+        self.core = Some(core);
+
+        if let (Some(interp), Some(base)) = (self.interp.as_mut(), base) {
+            interp.set_bias(base);
+        }
+        match self.main_binary_bias() {
+            Ok(bias) => self.relocate_main_binary(bias),
+            Err(msg) => {
+                log_info!("Failed to compute the load bias: {}", msg);
+            }
+        }
+        self.read_r_debug();
+        if let Err(msg) = self.update_shared_libraries() {
+            log_info!("Failed to read link_map: {}", msg);
+        }
+        self.rebuild_symtab();
+        let regs = self.unwind_regs(&self.regs);
+        return Ok(format!("{}\n{}", msg, self.format_frame(0, &regs)));
+    }
+
+    // Attaches to a running process. The executable is found from
+    // /proc/PID/exe if no binary is given or it is a different one.
+    pub fn attach(&mut self, pid: i32) -> Result<String, String> {
+        if self.ptracer.is_some() {
+            return Err("A program is being debugged already.".to_string());
+        }
+        let exe = try!(std::fs::read_link(format!("/proc/{}/exe", pid))
+                       .map_err(|e| format!("Cannot attach to process {}: {}",
+                                            pid, e)));
+        let ptracer = try!(ptracer::Ptracer::attach(pid));
+        // The program is switched only for a process we can debug.
+        let deleted = match self.reload_main_binary(&exe) {
+            Ok(deleted) => deleted,
+            Err(msg) => {
+                release(&ptracer);
+                return Err(msg);
+            }
+        };
+        if self.core.take().is_some() {
+            self.unload_shared_libraries();
+        }
+        let mut msgs = vec!(format!("Attaching to program: {}, process {}",
+                                    exe.display(), pid));
+        msgs.extend(deleted);
+        msgs.push(try!(self.adopt(ptracer)));
+        return Ok(msgs.join("\n"));
+    }
+
+    // Starts debugging a stopped process which runs the main binary, and
+    // returns its innermost frame. The process is detached if it cannot be
+    // debugged.
+    fn adopt(&mut self, ptracer: ptracer::Ptracer) -> Result<String, String> {
+        let state = ptracer.maps().and_then(|maps| {
+            match ptracer.get_regs() {
+                Ok(regs) => Ok((maps, regs)),
+                Err(err) => Err(String::from(err)),
+            }
+        });
+        let (maps, regs) = match state {
+            Ok(state) => state,
+            Err(msg) => {
+                release(&ptracer);
+                return Err(msg);
+            }
+        };
+        // Biases are where the beginnings of the files are mapped.
+        let bias_of = |bin: &binary::Binary| {
+            let path = match std::fs::canonicalize(bin.filename()) {
+                Ok(path) => path,
+                Err(_) => return None,
+            };
+            let map = maps.iter().find(|m| {
+                m.offset == 0 && std::path::Path::new(&m.path) == path
+            });
+            match (map, bin.file_start_addr()) {
+                (Some(map), Some(addr)) => Some(map.start.wrapping_sub(addr)),
+                _ => None,
+            }
+        };
+        let main_bias = self.main_binary.as_ref().and_then(|b| bias_of(b));
+        let interp_bias = self.interp.as_ref().and_then(|b| bias_of(b));
+        if let (Some(interp), Some(bias)) = (self.interp.as_mut(),
+                                             interp_bias) {
+            interp.set_bias(bias);
+        }
+        if let Some(bias) = main_bias {
+            self.relocate_main_binary(bias);
+        }
+
+        self.regs = regs;
+        self.ptracer = Some(ptracer);
+        let mut msgs = self.insert_breakpoints();
+        self.read_r_debug();
+        if let Err(msg) = self.update_shared_libraries() {
+            log_info!("Failed to read link_map: {}", msg);
+        }
+        self.rebuild_symtab();
+        let regs = self.unwind_regs(&self.regs);
+        msgs.push(self.format_frame(0, &regs));
+        return Ok(msgs.join("\n"));
+    }
+
+    // Removes breakpoints and lets the process run free.
+    pub fn detach(&mut self) -> Result<String, String> {
+        let mut ptracer = match self.ptracer.take() {
+            Some(ptracer) => ptracer,
+            None => return Err("The program is not being run.".to_string()),
+        };
+        // The process is released even if some breakpoints are left.
+        let removed = self.breakpoints.notify_detach(&mut ptracer);
+        let detached = ptracer.detach();
+        // It shares the memory with the child, whose breakpoints are gone.
+        if let Some(parent) = self.vfork_parent.take() {
+            let _ = parent.detach();
+        }
+        for fork in self.forks.drain(..) {
+            let _ = fork.detach();
+        }
+        self.cur_breakpoint = 0;
+        self.pending_state = None;
+        self.pending_debug_status = None;
+        self.skip_breakpoint = 0;
+        self.relocate_main_binary(0);
+        self.unload_shared_libraries();
+        try!(removed);
+        try!(detached);
+        let filename = self.main_binary.as_ref()
+            .map_or(String::new(), |bin| bin.filename().clone());
+        return Ok(format!("Detaching from program: {}, process {}",
+                          filename, ptracer.pid()));
+    }
+
+    // Saves the stopped process into a core file, "core.PID" by default.
+    fn gcore(&self, filename: Option<String>) -> Result<String, String> {
+        let ptracer = match self.ptracer {
+            Some(ref ptracer) => ptracer,
+            None => return Err("The program is not being run.".to_string()),
+        };
+        let filename = filename.unwrap_or(format!("core.{}", ptracer.pid()));
+        let mut threads: Vec<(i32, ptracer::Registers)> = vec!();
+        for (_, tid) in ptracer.threads() {
+            threads.push((tid, try!(ptracer.get_thread_regs(tid))));
+        }
+        try!(corefile::write(&filename, ptracer, &threads));
+        return Ok(format!("Saved corefile {}", filename));
+    }
+
+    fn thread_title(&self, tid: i32) -> String {
+        return format!("[Switching to thread {} (LWP {})]",
+                       self.ptracer.as_ref().unwrap().thread_num(tid), tid);
+    }
+
+    fn info_threads(&self) -> Result<String, String> {
+        let ptracer = match self.ptracer {
+            Some(ref ptracer) => ptracer,
+            None => return Ok("No threads.".to_string()),
+        };
+        let mut lines = vec!(format!("  {:<4} {:<24} {}",
+                                     "Id", "Target Id", "Frame"));
+        for (num, tid) in ptracer.threads() {
+            let mark = if tid == ptracer.tid() { "*" } else { " " };
+            let name = ptracer.thread_name(tid).unwrap_or(String::new());
+            let target_id = format!("LWP {} \"{}\"", tid, name);
+            let regs = self.unwind_regs(&try!(ptracer.get_thread_regs(tid)));
+            // Drops "#0  " of the frame.
+            let frame = self.format_frame(0, &regs)[4..].to_string();
+            lines.push(format!("{} {:<4} {:<24} {}",
+                               mark, num, target_id, frame));
+        }
+        return Ok(lines.join("\n"));
+    }
+
+    fn select_thread(&mut self, num: Option<usize>)
+                     -> Result<String, String> {
+        let tid = {
+            let ptracer = match self.ptracer {
+                Some(ref mut ptracer) => ptracer,
+                None => return Err("No thread selected.".to_string()),
+            };
+            match num {
+                Some(num) => try!(ptracer.select_thread(num)),
+                None => {
+                    let tid = ptracer.tid();
+                    return Ok(format!("[Current thread is {} (LWP {})]",
+                                      ptracer.thread_num(tid), tid));
+                }
+            }
+        };
+        self.regs = try!(self.ptracer.as_ref().unwrap().get_regs());
+        let regs = self.unwind_regs(&self.regs);
+        return Ok(format!("{}\n{}", self.thread_title(tid),
+                          self.format_frame(0, &regs)));
+    }
+
+    fn set_option(&mut self, name: &str, value: &str)
+                  -> Result<String, String> {
+        let on_off = || match value {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            _ => Err("\"on\" or \"off\" expected.".to_string()),
+        };
+        match name {
+            "follow-fork-mode" => {
+                self.follow_fork_child = match value {
+                    "parent" => false,
+                    "child" => true,
+                    _ => return Err(format!("Undefined item: \"{}\".", value)),
+                };
+            }
+            "detach-on-fork" => {
+                self.detach_on_fork = try!(on_off());
+            }
+            _ => return Err(format!("Undefined set command: \"{}\".", name)),
+        }
+        return Ok("".to_string());
+    }
+
+    // Writes a general purpose register of the current thread.
+    fn write_register(&mut self, index: usize, v: u64)
+                      -> Result<(), String> {
+        let ptracer = self.ptracer.as_ref().unwrap();
+        let mut regs = try!(ptracer.get_regs());
+        regs.set_gp(index, v, &self.target);
+        try!(ptracer.set_regs(&regs));
+        self.regs = regs;
+        return Ok(());
+    }
+
+    fn assign(&mut self, lhs: &expr::Expr, rhs: &expr::Expr)
+              -> Result<String, String> {
+        if self.ptracer.is_none() {
+            return Err("The program is not being run.".to_string());
+        }
+        if let expr::Expr::Ident(ref name) = *lhs {
+            if name.starts_with('$') {
+                let index = try!(self.register_index(&name[1..]).ok_or(
+                    format!("Invalid register \"{}\".", name)));
+                let v = try!(eval::eval(self, rhs));
+                let v = try!(v.to_u64(self));
+                try!(self.write_register(index, v));
+                return Ok("".to_string());
+            }
+        }
+        let (loc, data) = {
+            let l = try!(eval::eval(self, lhs));
+            let r = try!(eval::eval(self, rhs));
+            let v = try!(eval::cast(self, r, &self.complete_type(&l.ty)));
+            (l.loc, try!(v.bytes(self)))
+        };
+        match loc {
+            eval::Loc::Memory(addr) => {
+                let ptracer = self.ptracer.as_ref().unwrap();
+                try!(ptracer.write_memory(addr, &data));
+            }
+            eval::Loc::Register(reg) => {
+                let index = try!(self.target.dwarf_regs.get(reg as usize)
+                                 .cloned().ok_or(format!(
+                                     "Unknown DWARF register: {}", reg)));
+                // Only the bytes of the value are replaced.
+                let mut bytes = try!(self.read_register(reg)).to_le_bytes();
+                let n = data.len().min(bytes.len());
+                bytes[..n].copy_from_slice(&data[..n]);
+                try!(self.write_register(index, u64::from_le_bytes(bytes)));
+            }
+            eval::Loc::OptimizedOut => {
+                return Err("value has been optimized out".to_string());
+            }
+            eval::Loc::Bytes(_) => {
+                return Err("Left operand of assignment is not an lvalue."
+                           .to_string());
+            }
+        }
+        return Ok("".to_string());
+    }
+
+    pub fn single_step(&mut self) -> Result<String, String> {
+        if self.ptracer.is_none() {
+            return Err("The program is not being run.".to_string());
+        }
+        {
+            let ptracer = self.ptracer.as_mut().unwrap();
+            let signals = &self.signals;
+            ptracer.retain_signals(|sig| signals.get(sig).pass);
+            try!(ptracer.single_step());
+        }
+        self.watch_step = None;
+        try!(self.wait_impl(true));
+        return Ok("".to_string());
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u64,
+                          spec: Option<breakpoint::Spec>)
+                          -> Result<String, String> {
+        let (msg, id) = {
+            let bp = try!(self.breakpoints.add(addr, true, None,
+                                               self.ptracer.as_ref()));
+            (format!("Breakpoint {} at 0x{:x}", bp.id(), bp.addr()), bp.id())
+        };
+        if let Some(spec) = spec {
+            self.breakpoints.set_spec(id, spec);
+        }
+        if let Some((file, line)) = self.find_line_by_addr(addr) {
+            return Ok(format!("{}: file {}, line {}.", msg, file, line));
+        }
+        return Ok(msg);
+    }
+
+    // Sets a watchpoint on the object of an expression, by hardware if
+    // possible.
+    fn watch(&mut self, kind: breakpoint::WatchKind, text: &str)
+             -> Result<String, String> {
+        let (addr, ty) = {
+            let v = try!(eval::eval(self, &try!(expr::parse(text))));
+            let addr = match v.loc {
+                eval::Loc::Memory(addr) => addr,
+                _ => {
+                    return Err(format!("Cannot watch constant value `{}'.",
+                                       text));
+                }
+            };
+            (addr, self.complete_type(&v.ty))
+        };
+        if ty.size() == 0 {
+            return Err(format!("Cannot watch `{}' of an incomplete type.",
+                               text));
+        }
+        let value = try!(self.read_memory(addr, ty.size()));
+        let wp = try!(self.breakpoints.add_watchpoint(addr, kind, text, ty,
+                                                      value,
+                                                      self.ptracer.as_mut()));
+        return Ok(format!("{} {}: {}", wp.title(), wp.id(), text));
+    }
+
+    // Deletes the given breakpoints and watchpoints, or all of them.
+    fn delete(&mut self, ids: Vec<i32>) -> Result<String, String> {
+        let ids = if ids.is_empty() {
+            self.breakpoints.user_ids()
+        } else {
+            ids
+        };
+        for id in ids {
+            try!(self.breakpoints.delete(id, self.ptracer.as_mut()));
+        }
+        return Ok("".to_string());
+    }
+
+    fn info_breakpoints(&self) -> String {
+        let mut rows = vec!();
+        for bp in self.breakpoints.user_breakpoints() {
+            let what = match self.find_line_by_addr(bp.addr()) {
+                Some((file, line)) => {
+                    let func = self.symbolize(bp.addr())
+                        .map_or("??", |(name, _)| name);
+                    format!("in {} at {}:{}", func, file, line)
+                }
+                None => format!("<{}>", self.format_addr(bp.addr())),
+            };
+            rows.push((bp.id(), format!("{:<7} {:<14} keep y   0x{:016x} {}",
+                                        bp.id(), "breakpoint", bp.addr(),
+                                        what)));
+        }
+        for wp in self.breakpoints.watchpoints() {
+            rows.push((wp.id(), format!("{:<7} {:<14} keep y {:<20} {}",
+                                        wp.id(), wp.type_name(), "",
+                                        wp.expr())));
+        }
+        if rows.is_empty() {
+            return "No breakpoints or watchpoints.".to_string();
+        }
+        rows.sort_by_key(|&(id, _)| id);
+        let mut lines = vec!(
+            "Num     Type           Disp Enb Address            What"
+                .to_string());
+        lines.extend(rows.into_iter().map(|(_, row)| row));
+        return lines.join("\n");
+    }
+
+    pub fn run_command(&mut self, cmd: command::Command)
+                       -> Result<String, String> {
+        match cmd {
+            command::Command::Attach(pid) => {
+                return self.attach(pid);
+            }
+
+            command::Command::Backtrace => {
+                let frames = try!(self.backtrace());
+                let lines: Vec<String> = frames.iter().enumerate()
+                    .map(|(i, regs)| self.format_frame(i, regs))
+                    .collect();
+                return Ok(lines.join("\n"));
+            }
+
+            command::Command::Break(addr) => {
+                let mut spec = None;
+                if let expr::Expr::Ident(ref name) = addr {
+                    if !self.is_function(name) {
+                        return Err(format!("Function \"{}\" not defined.",
+                                           name));
+                    }
+                    spec = Some(breakpoint::Spec::Function(name.clone()));
+                }
+                let addr = try!(eval::eval_address(self, &addr));
+                return self.add_breakpoint(addr, spec);
+            }
+
+            command::Command::BreakLine(file, line) => {
+                let addr = try!(self.resolve_line(
+                    file.as_ref().map(|f| f.as_str()), line));
+                return self.add_breakpoint(
+                    addr, Some(breakpoint::Spec::Line(file, line)));
+            }
+
+            command::Command::Cont => {
+                return self.cont();
+            }
+
+            command::Command::Delete(ids) => {
+                return self.delete(ids);
+            }
+
+            command::Command::Detach => {
+                return self.detach();
+            }
+
+            command::Command::Disassemble(start, end) => {
+                return self.disassemble(start.as_ref(), end.as_ref());
+            }
+
+            command::Command::Gcore(file) => {
+                return self.gcore(file);
+            }
+
+            command::Command::Handle(args) => {
+                return self.handle(&args);
+            }
+
+            command::Command::InfoSignals(sig) => {
+                let sigs = match sig {
+                    Some(name) => match signal::from_name(&name) {
+                        Some(sig) => vec!(sig),
+                        None => {
+                            return Err(format!("Unknown signal: {}", name));
+                        }
+                    },
+                    None => vec!(),
+                };
+                return Ok(self.signals.format(&sigs));
+            }
+
+            command::Command::InfoSymbol(addr) => {
+                let addr = try!(eval::eval_address(self, &addr));
+                return Ok(self.info_symbol(addr));
+            }
+
+            command::Command::InfoSharedLibrary => {
+                return Ok(self.info_shared_library());
+            }
+
+            command::Command::Info => {
+                let regs = match self.frame_regs() {
+                    Some(regs) => regs,
+                    None => {
+                        return Err("The program is not being run."
+                                   .to_string());
+                    }
+                };
+                return Ok(format!("ip={} sp={} bp={}",
+                                  self.format_addr(regs.ip()),
+                                  self.format_addr(regs.sp()),
+                                  self.format_addr(regs.bp())));
+            }
+
+            command::Command::InfoBreakpoints => {
+                return Ok(self.info_breakpoints());
+            }
+
+            command::Command::InfoFloat => {
+                return self.info_float();
+            }
+
+            command::Command::InfoRegisters(names) => {
+                return self.info_registers(&names);
+            }
+
+            command::Command::InfoThreads => {
+                return self.info_threads();
+            }
+
+            command::Command::InfoVector => {
+                return self.info_vector();
+            }
+
+            command::Command::MaintInfoCache => {
+                let ptracer = match self.ptracer {
+                    Some(ref ptracer) => ptracer,
+                    None => {
+                        return Err("The program is not being run."
+                                   .to_string());
+                    }
+                };
+                let (pages, hits, misses) = ptracer.cache_stats();
+                return Ok(format!("Memory cache: {} pages cached, {} hits, \
+                                   {} misses", pages, hits, misses));
+            }
+
+            command::Command::Print(val) => {
+                return eval::print(self, &val);
+            }
+
+            command::Command::Ptype(arg) => {
+                let (ty, _) = try!(self.type_of_arg(&arg));
+                let ty = types::map_base(&ty, &|t| self.complete_type(t));
+                return Ok(format!("type = {}", types::definition(&ty)));
+            }
+
+            command::Command::Run(args) => {
+                return self.run(args);
+            }
+
+            command::Command::Set(name, value) => {
+                return self.set_option(&name, &value);
+            }
+
+            command::Command::SetVar(lhs, rhs) => {
+                return self.assign(&lhs, &rhs);
+            }
+
+            command::Command::Start(args) => {
+                return self.start(args);
+            }
+
+            command::Command::StepI => {
                 return self.single_step();
             }
 
-            command::Command::X(num, _, addr) => {
-                if self.ptracer.is_none() {
-                    return Err("The program is not being run.".to_string());
-                }
-                let ptracer = self.ptracer.as_ref().unwrap();
+            command::Command::Thread(num) => {
+                return self.select_thread(num);
+            }
+
+            command::Command::Watch(kind, text) => {
+                return self.watch(kind, &text);
+            }
+
+            command::Command::Whatis(arg) => {
+                let (ty, is_type_name) = try!(self.type_of_arg(&arg));
+                // Like gdb, unroll one level of typedef for type names.
+                let ty = match ty {
+                    types::Type::Typedef(_, ref t) if is_type_name => {
+                        (**t).clone()
+                    }
+                    ty => ty,
+                };
+                return Ok(format!("type = {}", ty.name()));
+            }
+
+            command::Command::X(num, format, size, addr) => {
+                let addr = try!(eval::eval_address(self, &addr));
+                return self.examine(num, format, size, addr);
+            }
+
+        }
+    }
+}
+
+#[cfg(test)]
+use expr::Expr;
+
+#[cfg(test)]
+fn ok_match(pat: &str, result: Result<String, String>, expr: &str) {
+    match result {
+        Err(err) => {
+            panic!("assertion failed: `{}` is an err: {}", expr, err);
+        }
+        Ok(msg) => {
+            let re = regex::Regex::new(pat).unwrap();
+            if !re.is_match(&msg) {
+                panic!("assertion failed: `{}` (\"{}\"), does not match {}",
+                       expr, msg, pat);
+            }
+        }
+    }
+}
+
+macro_rules! assert_ok_match {
+    ($pat:expr, $result:expr) => {
+        ok_match($pat, $result, stringify!($result));
+    };
+}
+
+#[test]
+fn test_start() {
+    let args = vec!["test/data/hello".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(!ctx.is_running());
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert!(ctx.start(vec!()).is_ok());
+    assert!(ctx.is_running());
+    assert!(ctx.interp().unwrap().bias() != 0);
+}
+
+#[test]
+fn test_hello() {
+    let args = vec!["test/data/hello".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(!ctx.is_running());
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+
+    let addr = ctx.resolve("main");
+    assert!(addr.is_some());
+    let addr = addr.unwrap();
+    assert_ok_match!(r"Breakpoint 1 at 0x", ctx.add_breakpoint(addr, None));
+    assert!(!ctx.is_running());
+
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
+    assert!(ctx.is_running());
+    assert_eq!(ctx.ip(), addr);
+    assert!(ctx.single_step().is_ok());
+    assert_eq!(ctx.ip(), addr + 1);
+    assert!(ctx.is_running());
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"Process \d+ exited with code 0", ctx.wait());
+    assert!(!ctx.is_running());
+}
+
+#[test]
+fn test_memory_errors() {
+    let args = vec!["test/data/hello".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    // Inserted when the process starts.
+    assert_eq!(Ok("Breakpoint 1 at 0x0".to_string()),
+               ctx.run_command(command::parse("break *0").unwrap()));
+    assert!(ctx.run_command(command::parse("break main").unwrap()).is_ok());
+    assert_ok_match!(concat!(r"^Starting program: test/data/hello ",
+                             r"\(pid=\d+\)\n",
+                             r"Warning: Cannot insert breakpoint 1\.\n",
+                             r"Cannot access memory at address 0x0$"),
+                     ctx.run(vec!()));
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"^Breakpoint 2, ", ctx.wait());
+
+    let err = Err("Cannot access memory at address 0x0".to_string());
+    assert_eq!(err, ctx.run_command(command::parse("x 0").unwrap()));
+    assert_eq!(err, ctx.run_command(command::parse("break *0").unwrap()));
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"Process \d+ exited with code 0", ctx.wait());
+}
+
+#[test]
+fn test_memory_access() {
+    let args = vec!["test/data/hello".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert!(ctx.run_command(command::parse("break main").unwrap()).is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"^Breakpoint 1, ", ctx.wait());
+    let main = ctx.ip();
+    {
+        let ptracer = ctx.ptracer();
+        let word = ptracer.peek_word(main).unwrap();
+        let data = ptracer.read_memory(main + 1, 7).unwrap();
+        for i in 0..7 {
+            assert_eq!((word >> ((i + 1) * 8)) as u8, data[i]);
+        }
+
+        // Reads stop at the end of a mapping which is not followed by
+        // another one.
+        let maps = ptracer.maps().unwrap();
+        let end = maps.iter().map(|m| m.end).find(|&end| {
+            !maps.iter().any(|m| m.start == end)
+        }).unwrap();
+        assert_eq!(5, ptracer.read_partial(end - 5, 4096).len());
+        assert_eq!(Err(ptracer::Error::Memory(end)),
+                   ptracer.read_memory(end - 5, 10));
+        assert_eq!(0, ptracer.read_partial(0, 8).len());
+
+        // Code is read-only for the process.
+        let code = ptracer.read_memory(main + 3, 5).unwrap();
+        assert!(ptracer.write_memory(main + 3, &[1, 2, 3, 4, 5]).is_ok());
+        assert_eq!(Ok(vec!(1, 2, 3, 4, 5)), ptracer.read_memory(main + 3, 5));
+        assert!(ptracer.write_memory(main + 3, &code).is_ok());
+        assert_eq!(Err(ptracer::Error::Memory(0)),
+                   ptracer.write_memory(0, &[0]));
+    }
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"Process \d+ exited with code 0", ctx.wait());
+}
+
+#[test]
+fn test_memory_cache() {
+    let args = vec!["test/data/hello".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let cache = "maintenance info cache";
+    assert!(ctx.run_command(command::parse(cache).unwrap()).is_err());
+    assert!(ctx.run_command(command::parse("break main").unwrap()).is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"^Breakpoint 1, ", ctx.wait());
+
+    let sp = ctx.regs.sp();
+    let (_, hits, misses) = ctx.ptracer().cache_stats();
+    let word = ctx.read_word(sp).unwrap();
+    assert_eq!(Ok(word), ctx.read_word(sp));
+    assert_eq!(Ok(word), ctx.read_word(sp));
+    let (pages, new_hits, new_misses) = ctx.ptracer().cache_stats();
+    assert_eq!((hits + 2, misses + 1), (new_hits, new_misses));
+    assert_eq!(Ok(format!("Memory cache: {} pages cached, {} hits, {} misses",
+                          pages, new_hits, new_misses)),
+               ctx.run_command(command::parse(cache).unwrap()));
+
+    // Writes and resumes invalidate the cache.
+    assert!(ctx.ptracer().write_memory(sp, &[0x42]).is_ok());
+    assert_eq!(Ok((word & !0xff) | 0x42), ctx.read_word(sp));
+    assert!(ctx.ptracer().write_memory(sp, &[word as u8]).is_ok());
+    assert!(ctx.read_word(sp).is_ok());
+    assert!(ctx.single_step().is_ok());
+    assert_eq!(0, ctx.ptracer().cache_stats().0);
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"Process \d+ exited with code 0", ctx.wait());
+}
+
+#[test]
+fn test_exec_error() {
+    use std::os::unix::fs::PermissionsExt;
+    let path = std::env::temp_dir().join(
+        format!("varsan_test_exec_error.{}", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    std::fs::copy("test/data/hello", &path).unwrap();
+    // Not executable.
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644))
+        .unwrap();
+    let args = vec![path.clone()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&path).is_ok());
+    let r = ctx.run(vec!());
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(Err(format!("{}: Permission denied.", path)), r);
+    assert!(!ctx.is_running());
+}
+
+#[test]
+fn test_break_line() {
+    let args = vec!["test/data/hello".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert!(ctx.resolve_line(Some("nosuch.c"), 3).is_err());
+    assert!(ctx.resolve_line(Some("hello.c"), 100).is_err());
+    assert_eq!(Ok(0x4005e2), ctx.resolve_line(None, 3));
+
+    let cmd = command::parse("break hello.c:3").unwrap();
+    assert_ok_match!(r"Breakpoint 1 at 0x4005e2: file hello.c, line 3.",
+                     ctx.run_command(cmd));
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
+    assert_eq!(0x4005e2, ctx.ip());
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"Process \d+ exited with code 0", ctx.wait());
+}
+
+#[test]
+fn test_print_variables() {
+    let args = vec!["test/data/vars".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let print = |ctx: &Context, name: &str| {
+        eval::print(ctx, &Expr::Ident(name.to_string()))
+    };
+    assert_eq!(Ok("42".to_string()), print(&ctx, "global_int"));
+    assert!(print(&ctx, "sum").is_err());
+
+    let cmd = command::parse("break vars.c:11").unwrap();
+    assert!(ctx.run_command(cmd).is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
 
-                let addr = eval::eval(self, addr);
-                for i in 0..num {
-                    let addr = addr + (i * 4) as u64;
-                    let data = ptracer.peek_word(addr);
-                    println!("{:x}: {:x}", addr, data as i32);
-                }
-            }
+    assert_eq!(Ok("3".to_string()), print(&ctx, "a"));
+    assert_eq!(Ok("4".to_string()), print(&ctx, "b"));
+    assert_eq!(Ok("7".to_string()), print(&ctx, "sum"));
+    assert_eq!(Ok("42".to_string()), print(&ctx, "global_int"));
+    assert_eq!(Ok("-3".to_string()), print(&ctx, "static_short"));
+    assert_eq!(Ok("65 'A'".to_string()), print(&ctx, "global_char"));
+    assert_eq!(Ok("18446744073709551615".to_string()),
+               print(&ctx, "global_ulong"));
+    assert_eq!(Ok("2.5".to_string()), print(&ctx, "global_double"));
+    assert_eq!(Ok("true".to_string()), print(&ctx, "global_bool"));
+    assert_eq!(Ok(format!("(int *) 0x{:x} <global_int>",
+                          ctx.resolve("global_int").unwrap())),
+               print(&ctx, "global_ptr"));
+    assert!(print(&ctx, "local").is_err());
+    assert_eq!(Err("Function \"global_int\" not defined.".to_string()),
+               ctx.run_command(command::parse("break global_int").unwrap()));
+    assert!(print(&ctx, "no_such_variable").is_err());
+}
 
-        }
-        return Ok("".to_string());
-    }
+#[test]
+fn test_set_var() {
+    let args = vec!["test/data/vars".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let run = |ctx: &mut Context, cmd: &str| {
+        ctx.run_command(command::parse(cmd).unwrap())
+    };
+    assert_eq!(Err("The program is not being run.".to_string()),
+               run(&mut ctx, "set var global_int = 1"));
+
+    assert!(run(&mut ctx, "break vars.c:11").is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
+
+    assert_eq!(Ok("".to_string()), run(&mut ctx, "set var sum = 100"));
+    assert_eq!(Ok("100".to_string()), run(&mut ctx, "print sum"));
+    assert!(run(&mut ctx, "set global_int = global_double * 3").is_ok());
+    assert_eq!(Ok("7".to_string()), run(&mut ctx, "print global_int"));
+    assert!(run(&mut ctx, "set var global_double = 1").is_ok());
+    assert_eq!(Ok("1".to_string()), run(&mut ctx, "print global_double"));
+    assert!(run(&mut ctx, "set var global_bool = 5").is_ok());
+    assert_eq!(Ok("true".to_string()), run(&mut ctx, "print global_bool"));
+
+    // Only the bytes of the lvalue are written.
+    assert!(run(&mut ctx, "set *(char *)&global_ulong = 0").is_ok());
+    assert_eq!(Ok("18446744073709551360".to_string()),
+               run(&mut ctx, "print global_ulong"));
+    assert!(run(&mut ctx, "set *(int*)&global_ulong = 0").is_ok());
+    assert_eq!(Ok("18446744069414584320".to_string()),
+               run(&mut ctx, "print global_ulong"));
+    let addr = ctx.resolve("global_ulong").unwrap();
+    assert!(run(&mut ctx, &format!("set *(long*){} = -1", addr)).is_ok());
+    assert_eq!(Ok("18446744073709551615".to_string()),
+               run(&mut ctx, "print global_ulong"));
+    assert_eq!(Ok("65 'A'".to_string()), run(&mut ctx, "print global_char"));
+
+    assert!(run(&mut ctx, "set $rax = 0x1234").is_ok());
+    assert_eq!(0x1234, ctx.ptracer().get_regs().unwrap().gp(10));
+    assert_eq!(0x1234, ctx.regs.gp(10));
+    assert_eq!(Err("Invalid register \"$foo\".".to_string()),
+               run(&mut ctx, "set $foo = 1"));
+    assert_eq!(Err("Left operand of assignment is not an lvalue."
+                   .to_string()),
+               run(&mut ctx, "set var sum + 1 = 2"));
+
+    // add() returns the new sum and main() returns it minus 7.
+    assert!(run(&mut ctx, "continue").is_ok());
+    assert_ok_match!(r"Process \d+ exited with code 93", ctx.wait());
 }
 
-#[cfg(test)]
-fn ok_match(pat: &str, result: Result<String, String>, expr: &str) {
-    match result {
-        Err(err) => {
-            panic!("assertion failed: `{}` is an err: {}", expr, err);
-        }
-        Ok(msg) => {
-            let re = regex::Regex::new(pat).unwrap();
-            if !re.is_match(&msg) {
-                panic!("assertion failed: `{}` (\"{}\"), does not match {}",
-                       expr, msg, pat);
-            }
-        }
+#[test]
+fn test_watchpoints() {
+    let args = vec!["test/data/watch".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let run = |ctx: &mut Context, cmd: &str| {
+        ctx.run_command(command::parse(cmd).unwrap())
+    };
+    assert_eq!(Ok("No breakpoints or watchpoints.".to_string()),
+               run(&mut ctx, "info breakpoints"));
+    assert!(run(&mut ctx, "break main").is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
+
+    assert_eq!(Ok("Hardware watchpoint 2: counter".to_string()),
+               run(&mut ctx, "watch counter"));
+    assert_eq!(Err("Cannot watch constant value `counter + 1'.".to_string()),
+               run(&mut ctx, "watch counter + 1"));
+    assert_ok_match!(concat!(r"Num +Type +Disp Enb Address +What\n",
+                             r"1 +breakpoint +keep y +0x0+[0-9a-f]+ ",
+                             r"in main at watch.c:\d+\n",
+                             r"2 +hw watchpoint +keep y +counter$"),
+                     run(&mut ctx, "info breakpoints"));
+
+    // The first continue steps over the breakpoint at main.
+    assert!(run(&mut ctx, "continue").is_ok());
+    assert_ok_match!(concat!(r"^Hardware watchpoint 2: counter\n\n",
+                             r"Old value = 0\nNew value = 1\n",
+                             r"#0 +0x[0-9a-f]+ in bump\+\d+ at watch.c:\d+"),
+                     ctx.wait());
+    assert!(run(&mut ctx, "continue").is_ok());
+    assert_ok_match!(r"Old value = 1\nNew value = 3\n", ctx.wait());
+
+    assert_eq!(Ok("Hardware read watchpoint 3: counter".to_string()),
+               run(&mut ctx, "rwatch counter"));
+    assert!(run(&mut ctx, "delete 2").is_ok());
+    assert_eq!(Err("No breakpoint number 2.".to_string()),
+               run(&mut ctx, "delete 2"));
+    // main() reads it after bump() returns.
+    assert!(run(&mut ctx, "continue").is_ok());
+    assert_ok_match!(concat!(r"^Hardware read watchpoint 3: counter\n\n",
+                             r"Value = 3\n#0 +0x[0-9a-f]+ in main\+"),
+                     ctx.wait());
+
+    // The 16 bytes of pair are watched by single steps.
+    assert_eq!(Ok("Watchpoint 4: pair".to_string()),
+               run(&mut ctx, "watch pair"));
+    assert_eq!(Err("Expression cannot be implemented with read/access \
+                    watchpoint.".to_string()),
+               run(&mut ctx, "rwatch pair"));
+    // Write watchpoints fall back to software when all four debug
+    // registers are in use.
+    assert!(run(&mut ctx, "awatch sink").is_ok());
+    assert!(run(&mut ctx, "rwatch pair[0]").is_ok());
+    assert!(run(&mut ctx, "rwatch pair[1]").is_ok());
+    assert_eq!(Err("Hardware watchpoints used exceeds limit.".to_string()),
+               run(&mut ctx, "rwatch i"));
+    assert_eq!(Ok("Watchpoint 8: i".to_string()), run(&mut ctx, "watch i"));
+    assert!(run(&mut ctx, "delete 3 6 7 8").is_ok());
+
+    // Hardware watchpoints still trap while single-stepping.
+    assert!(run(&mut ctx, "continue").is_ok());
+    assert_ok_match!(concat!(r"^Hardware access \(read/write\) watchpoint ",
+                             r"5: sink\n\nOld value = 1\nNew value = 3\n"),
+                     ctx.wait());
+    assert!(run(&mut ctx, "delete 5").is_ok());
+    assert_ok_match!(r"\n4 +watchpoint +keep y +pair$",
+                     run(&mut ctx, "info breakpoints"));
+    // The watchpoint survives the lazy binding of atoi(), which enters the
+    // dynamic linker through a jump from its PLT entry.
+    assert!(run(&mut ctx, "continue").is_ok());
+    assert_ok_match!(concat!(r"^Watchpoint 4: pair\n\n",
+                             r"Old value = \{0, 0\}\nNew value = \{0, 6\}\n",
+                             r"Changed by 0x[0-9a-f]+ <main\+\d+> ",
+                             r"at watch.c:18: mov +%rax,.*\n",
+                             r"#0 +0x[0-9a-f]+ in main\+\d+ at watch.c:19$"),
+                     ctx.wait());
+
+    assert!(run(&mut ctx, "delete").is_ok());
+    assert_eq!(Ok("No breakpoints or watchpoints.".to_string()),
+               run(&mut ctx, "info breakpoints"));
+    assert!(run(&mut ctx, "continue").is_ok());
+    assert_ok_match!(r"Process \d+ exited with code 6", ctx.wait());
+}
+
+#[test]
+fn test_registers() {
+    let args = vec!["test/data/vars".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let run = |ctx: &mut Context, cmd: &str| {
+        ctx.run_command(command::parse(cmd).unwrap())
+    };
+    assert_eq!(Err("The program has no registers now.".to_string()),
+               run(&mut ctx, "info registers"));
+    assert_eq!(Err("No registers.".to_string()), run(&mut ctx, "p $pc"));
+
+    assert!(run(&mut ctx, "break add").is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
+
+    let all = run(&mut ctx, "info registers").unwrap();
+    assert_eq!(27, all.lines().count());
+    assert_ok_match!(r"(?m)^rax            0x[0-9a-f]+ +-?\d+$",
+                     Ok(all.clone()));
+    assert_ok_match!(concat!(r"(?m)^rip            0x[0-9a-f]+ +",
+                             r"0x[0-9a-f]+ <add(\+\d+)?>$"),
+                     Ok(all.clone()));
+    assert_ok_match!(r"(?m)^eflags         0x[0-9a-f]+ +\[ [A-Z ]*\]$",
+                     Ok(all));
+    assert_ok_match!(r"^ip=0x[0-9a-f]+ <add(\+\d+)?> sp=0x[0-9a-f]+ bp=0x",
+                     ctx.run_command(command::Command::Info));
+    assert_ok_match!(concat!(r"^pc             0x[0-9a-f]+ +",
+                             r"0x[0-9a-f]+ <add(\+\d+)?>\n",
+                             r"rsp            0x[0-9a-f]+ +\d+$"),
+                     run(&mut ctx, "info registers pc $rsp"));
+    assert_eq!(Err("Invalid register `foo'".to_string()),
+               run(&mut ctx, "info registers foo"));
+
+    assert!(run(&mut ctx, "set $rax = -2").is_ok());
+    assert_eq!(Ok("-2".to_string()), run(&mut ctx, "p $rax"));
+    assert!(run(&mut ctx, "set $eflags = 0x246").is_ok());
+    assert_eq!(Ok("eflags         0x246              [ PF ZF IF ]"
+                  .to_string()),
+               run(&mut ctx, "info registers eflags"));
+    assert_ok_match!(r"^\(void \(\*\)\(\)\) 0x[0-9a-f]+ <add(\+\d+)?>$",
+                     run(&mut ctx, "p $pc"));
+    assert_ok_match!(r"^\(void \*\) 0x[0-9a-f]+$", run(&mut ctx, "p $sp"));
+    assert_eq!(Ok("1".to_string()), run(&mut ctx, "p $sp == $rsp"));
+    assert_eq!(Ok("1".to_string()), run(&mut ctx, "p $fp == $rbp"));
+    assert_eq!(Ok("3".to_string()), run(&mut ctx, "p $rdi"));
+}
+
+#[test]
+fn test_fp_registers() {
+    let args = vec!["test/data/float".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let run = |ctx: &mut Context, cmd: &str| {
+        ctx.run_command(command::parse(cmd).unwrap())
+    };
+    assert_eq!(Err("The program has no registers now.".to_string()),
+               run(&mut ctx, "info float"));
+
+    assert!(run(&mut ctx, "break scale").is_ok());
+    assert!(run(&mut ctx, "break *(long)half + 15").is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
+
+    assert_eq!(Ok("type = union vec128".to_string()),
+               run(&mut ctx, "whatis $xmm0"));
+    assert_eq!(Ok("1.5".to_string()), run(&mut ctx, "p $xmm0.v2_double[0]"));
+    assert_eq!(Ok("{-2, 0}".to_string()), run(&mut ctx, "p $xmm1.v2_double"));
+    assert_eq!(Ok("-1073741824".to_string()),
+               run(&mut ctx, "p $xmm1.v4_int32[1]"));
+    assert_eq!(Err("no such vector element".to_string()),
+               run(&mut ctx, "p $xmm0.v2_double[2]"));
+    assert_eq!(Ok("8064".to_string()), run(&mut ctx, "p $mxcsr"));
+    let vector = run(&mut ctx, "info vector").unwrap();
+    assert_ok_match!(concat!(r"(?m)^xmm0           \{v4_float = \{.*",
+                             r"v2_double = \{1\.5, 0\}"),
+                     Ok(vector.clone()));
+    assert_ok_match!(r"(?m)^xmm15          \{", Ok(vector.clone()));
+    assert_ok_match!(concat!(r"\nmxcsr          0x1f80             ",
+                             r"\[ IM DM ZM OM UM PM \]$"),
+                     Ok(vector.clone()));
+    if vector.contains("ymm0") {
+        assert_eq!(Ok("{1.5, 0, 0, 0}".to_string()),
+                   run(&mut ctx, "p $ymm0.v4_double"));
     }
+
+    assert!(run(&mut ctx, "continue").is_ok());
+    assert_ok_match!(r"Breakpoint 2, ", ctx.wait());
+    assert_eq!(Ok("2.5".to_string()), run(&mut ctx, "p $st0"));
+    assert_eq!(Ok("st0            2.5".to_string()),
+               run(&mut ctx, "info registers st0"));
+    let float = run(&mut ctx, "info float").unwrap();
+    assert_ok_match!(concat!(r"^=>R7: Valid   0x4000a000000000000000 2\.5\n",
+                             r"  R6: Empty "),
+                     Ok(float.clone()));
+    assert_ok_match!(concat!(r"(?m)^Control Word:        0x037f   ",
+                             r"\[ IM DM ZM OM UM PM \]$"),
+                     Ok(float));
+
+    // Lanes of floats print as many digits as floats need.
+    assert!(run(&mut ctx, "break shrink").is_ok());
+    assert!(run(&mut ctx, "continue").is_ok());
+    assert_ok_match!(r"Breakpoint 3, ", ctx.wait());
+    assert_eq!(Ok("0.100000001".to_string()),
+               run(&mut ctx, "p $xmm0.v4_float[0]"));
+    assert_ok_match!(r"(?m)^xmm0           \{v4_float = \{0\.100000001, ",
+                     run(&mut ctx, "info vector"));
 }
 
-macro_rules! assert_ok_match {
-    ($pat:expr, $result:expr) => {
-        ok_match($pat, $result, stringify!($result));
+#[test]
+fn test_file_statics() {
+    let args = vec!["test/data/statics".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let run = |ctx: &mut Context, cmd: &str| {
+        ctx.run_command(command::parse(cmd).unwrap())
     };
+    // Statics with the same name are told apart by their files.
+    let first = ctx.resolve("'statics.c'::counter").unwrap();
+    let second = ctx.resolve("'statics2.c'::counter").unwrap();
+    assert!(first != second);
+    assert_eq!(None, ctx.resolve("'statics3.c'::counter"));
+    assert_eq!(Ok("counter in section .data".to_string()),
+               run(&mut ctx, "info symbol &'statics2.c'::counter"));
+
+    assert!(run(&mut ctx, "break other_counter").is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
+    assert_eq!(Ok("1".to_string()), run(&mut ctx, "p 'statics.c'::counter"));
+    assert_eq!(Ok("2".to_string()), run(&mut ctx, "p 'statics2.c'::counter"));
+    assert_eq!(Ok("2".to_string()), run(&mut ctx, "p counter"));
 }
 
 #[test]
-fn test_start() {
+fn test_types() {
+    let args = vec!["test/data/types".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let run = |ctx: &mut Context, cmd: &str| {
+        ctx.run_command(command::parse(cmd).unwrap())
+    };
+    assert_eq!(Ok("type = struct point {\n    int x;\n    int y;\n}"
+                  .to_string()), run(&mut ctx, "ptype struct point"));
+    assert_eq!(Ok("type = struct point".to_string()),
+               run(&mut ctx, "whatis origin"));
+    assert_eq!(Ok("type = unsigned int".to_string()),
+               run(&mut ctx, "whatis uint"));
+    assert_eq!(Ok("type = uint".to_string()), run(&mut ctx, "whatis counter"));
+    assert_eq!(Ok("type = enum color {RED, GREEN = 4, BLUE}".to_string()),
+               run(&mut ctx, "ptype color"));
+    assert_eq!(Ok("type = int [2][3]".to_string()),
+               run(&mut ctx, "whatis matrix"));
+    assert_eq!(Ok("type = int (*)(int, int)".to_string()),
+               run(&mut ctx, "whatis callback"));
+    assert_eq!(Ok("type = const char *".to_string()),
+               run(&mut ctx, "whatis greeting"));
+    assert_eq!(Ok("type = struct {\n    char name[8];\n    \
+                   struct point pos;\n    enum color color;\n    \
+                   unsigned int flags : 3;\n    unsigned int mode : 5;\n}"
+                  .to_string()), run(&mut ctx, "ptype shape"));
+    assert_eq!(Ok("type = struct node {\n    int value;\n    \
+                   struct node *next;\n} *".to_string()),
+               run(&mut ctx, "ptype &first"));
+    assert_eq!(Ok("type = int (int, int)".to_string()),
+               run(&mut ctx, "ptype add"));
+
+    let print = |ctx: &Context, e: &str| {
+        eval::print(ctx, &expr::parse(e).unwrap())
+    };
+    assert_eq!(Ok("{x = 1, y = 2}".to_string()), print(&ctx, "origin"));
+    assert_eq!(Ok("{i = 1069547520, f = 1.5}".to_string()),
+               print(&ctx, "num"));
+    assert_eq!(Ok("{name = \"box\", pos = {x = 3, y = 4}, color = GREEN, \
+                   flags = 5, mode = 17}".to_string()), print(&ctx, "shape"));
+    assert_eq!(Ok("{2, 3, 5, 7, 11}".to_string()), print(&ctx, "primes"));
+    assert_eq!(Ok("{{1, 2, 3}, {4, 5, 6}}".to_string()),
+               print(&ctx, "matrix"));
+    assert_eq!(Ok("6".to_string()), print(&ctx, "matrix[1][2]"));
+    assert_eq!(Ok("BLUE".to_string()), print(&ctx, "color"));
+    assert_eq!(Ok("0.25".to_string()), print(&ctx, "ratio"));
+    assert_eq!(Ok("0.333333343".to_string()), print(&ctx, "third"));
+    assert_eq!(Ok("6.0221407599999999e+23".to_string()),
+               print(&ctx, "avogadro"));
+    assert_eq!(Ok("-1.50000005e-07".to_string()), print(&ctx, "tiny"));
+    assert_eq!(Ok("11".to_string()), print(&ctx, "*(primes + 4)"));
+    assert_eq!(Ok("4".to_string()), print(&ctx, "shape.pos.y * 2 - 4"));
+    assert_ok_match!(r#"^0x[0-9a-f]+ "hello"$"#, print(&ctx, "greeting"));
+    assert_ok_match!(r"^\(struct point \*\) 0x[0-9a-f]+ <origin>$",
+                     print(&ctx, "&origin"));
+    assert_ok_match!(r"^\{int \(int, int\)\} 0x[0-9a-f]+ <add>$",
+                     print(&ctx, "add"));
+    assert_eq!(Ok("2".to_string()), print(&ctx, "first.next->value"));
+    assert!(print(&ctx, "origin.z").is_err());
+    assert!(print(&ctx, "origin + 1").is_err());
+
+    assert!(run(&mut ctx, "break types.c:39").is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
+    assert_eq!(Ok("2".to_string()), print(&ctx, "head->next->value"));
+    assert_eq!(Ok(format!("{{value = 1, next = 0x{:x} <second>}}",
+                          ctx.resolve("second").unwrap())),
+               print(&ctx, "*head"));
+    assert_eq!(Ok("type = struct node *".to_string()),
+               run(&mut ctx, "whatis head"));
+}
+
+#[test]
+fn test_backtrace() {
+    let args = vec!["test/data/types".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert!(ctx.run_command(command::Command::Backtrace).is_err());
+    let cmd = command::parse("break types.c:33").unwrap();
+    assert!(ctx.run_command(cmd).is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
+
+    let frames = ctx.backtrace().unwrap();
+    assert!(frames.len() >= 2);
+    assert_ok_match!(concat!(r"^#0  0x0000000000[0-9a-f]{6} in add\+\d+ ",
+                             r"at types.c:33\n",
+                             r"#1  0x0000000000[0-9a-f]{6} in main\+\d+ ",
+                             r"at types.c:39(\n|$)"),
+                     ctx.run_command(command::Command::Backtrace));
+    // The CFA of add is just above its return address.
+    let cfa = dwarf::Frame::cfa(&FrameState::new(&ctx)).unwrap();
+    assert_eq!(frames[1].pc(), ctx.read_word(cfa - 8).ok());
+    assert_eq!(frames[1].sp(), Some(cfa));
+}
+
+#[test]
+fn test_shared_libraries() {
     let args = vec!["test/data/hello".to_string()];
     let mut ctx = Context::new(&args);
-    assert!(!ctx.is_running());
     assert!(ctx.set_main_binary(&args[0]).is_ok());
-    assert!(ctx.start(vec!()).is_ok());
-    assert!(ctx.is_running());
-    assert!(ctx.interp().unwrap().bias() != 0);
+    assert_eq!(Ok("No shared libraries loaded at this time.".to_string()),
+               ctx.run_command(command::Command::InfoSharedLibrary));
+    let cmd = command::parse("break main").unwrap();
+    assert!(ctx.run_command(cmd).is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
+
+    assert_ok_match!(r"(?m)^0x[0-9a-f]{16}  0x[0-9a-f]{16}  Yes.*/libc\.so\.6$",
+                     ctx.run_command(command::Command::InfoSharedLibrary));
+    let puts = ctx.resolve("puts").unwrap();
+    let libc = ctx.shlibs.iter().find(|bin| {
+        bin.filename().contains("libc.so")
+    }).unwrap();
+    assert!(libc.bias() != 0);
+    assert!(libc.contains(puts - libc.bias()));
+    // Frames in libc are symbolized with its symbols.
+    assert_ok_match!(r"(?m)^#2  0x[0-9a-f]{16} in \w+\+\d+ from .*libc",
+                     ctx.run_command(command::parse("bt").unwrap()));
+
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"Process \d+ exited with code 0", ctx.wait());
+    assert!(ctx.resolve("puts").is_none());
 }
 
 #[test]
-fn test_hello() {
+fn test_pie() {
+    let args = vec!["test/data/pie".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let static_main = ctx.resolve("main").unwrap();
+    let cmd = command::parse("break main").unwrap();
+    assert!(ctx.run_command(cmd).unwrap().starts_with(
+        &format!("Breakpoint 1 at 0x{:x}:", static_main)));
+
+    for _ in 0..2 {
+        assert!(ctx.run(vec!()).is_ok());
+        assert!(ctx.wait().is_ok());
+        let bias = ctx.main_binary.as_ref().unwrap().bias();
+        assert!(bias != 0);
+        let main = ctx.resolve("main").unwrap();
+        assert_eq!(static_main + bias, main);
+        assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
+        assert_eq!(main,
+                   ctx.ptracer.as_ref().unwrap().get_regs().unwrap().ip());
+
+        assert_eq!(Ok("3".to_string()),
+                   ctx.run_command(command::parse("p counter").unwrap()));
+        let addr = ctx.resolve("counter").unwrap();
+        assert_eq!(Ok(format!("(int *) 0x{:x} <counter>", addr)),
+                   ctx.run_command(command::parse("p &counter").unwrap()));
+        assert!(ctx.run_command(command::parse("break pie.c:6")
+                                .unwrap()).is_ok());
+        assert!(ctx.cont().is_ok());
+        assert_ok_match!(r"Breakpoint \d+, ", ctx.wait());
+        assert_eq!(Ok("3".to_string()),
+                   ctx.run_command(command::parse("p x").unwrap()));
+        assert_ok_match!(concat!(r"^#0  0x[0-9a-f]{16} in twice\+\d+ ",
+                                 r"at pie.c:6\n",
+                                 r"#1  0x[0-9a-f]{16} in main\+\d+ ",
+                                 r"at pie.c:10(\n|$)"),
+                         ctx.run_command(command::Command::Backtrace));
+
+        assert!(ctx.cont().is_ok());
+        assert_ok_match!(r"Process \d+ exited with code 0", ctx.wait());
+        assert_eq!(Some(static_main), ctx.resolve("main"));
+    }
+}
+
+#[test]
+fn test_info_symbol() {
     let args = vec!["test/data/hello".to_string()];
     let mut ctx = Context::new(&args);
-    assert!(!ctx.is_running());
     assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let info_symbol = |ctx: &mut Context, arg: &str| {
+        ctx.run_command(command::parse(&format!("info symbol {}", arg))
+                        .unwrap())
+    };
+    assert_eq!(Ok("main in section .text".to_string()),
+               info_symbol(&mut ctx, "main"));
+    assert_eq!(Ok("main + 4 in section .text".to_string()),
+               info_symbol(&mut ctx, "0x4005d4"));
+    // Sizeless symbols extend to the next one.
+    assert_eq!(Ok("frame_dummy + 4 in section .text".to_string()),
+               info_symbol(&mut ctx, "0x4005a4"));
+    // The padding after main is not in main.
+    assert_eq!(Ok("No symbol matches 0x4005f8.".to_string()),
+               info_symbol(&mut ctx, "0x4005f8"));
+    assert_eq!(Ok("No symbol matches 0x0.".to_string()),
+               info_symbol(&mut ctx, "0"));
 
-    let addr = ctx.resolve("main");
-    assert!(addr.is_some());
-    let addr = addr.unwrap();
-    assert_ok_match!(r"Breakpoint 1 at 0x", ctx.add_breakpoint(addr));
+    assert!(ctx.run_command(command::parse("break main").unwrap()).is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_eq!(Ok("Breakpoint 1, 0x4005d0 <main>".to_string()), ctx.wait());
+    assert_ok_match!(r"^puts in section \.text of .*/libc\.so\.6$",
+                     info_symbol(&mut ctx, "puts"));
+    assert!(ctx.cont().is_ok());
+    assert!(ctx.wait().is_ok());
+}
+
+#[test]
+fn test_cxx() {
+    let args = vec!["test/data/cxx".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let bar_int = ctx.resolve("_ZN2ns3Foo3barEi").unwrap();
+    let bar_str = ctx.resolve("_ZN2ns3Foo3barEPKc").unwrap();
+    let twice_long = ctx.resolve("_ZN2ns5twiceIlEET_S1_").unwrap();
+    // Demangled names match regardless of whitespace.
+    assert_eq!(Some(bar_int), ctx.resolve("ns::Foo::bar(int)"));
+    assert_eq!(Some(bar_str), ctx.resolve("ns::Foo::bar(char const *)"));
+    assert_eq!(Some(twice_long), ctx.resolve("ns::twice<long>"));
+    assert!(ctx.resolve("ns::Foo::bar").is_some());
+    assert_eq!(Ok("ns::Foo::bar(int) + 4 in section .text".to_string()),
+               ctx.run_command(command::parse(
+                   &format!("info symbol 0x{:x}", bar_int + 4)).unwrap()));
+
+    let brk = ctx.run_command(
+        command::parse("break ns::Foo::bar(char const*)").unwrap());
+    assert!(brk.unwrap().starts_with(
+        &format!("Breakpoint 1 at 0x{:x}", bar_str)));
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_eq!(Ok(format!("Breakpoint 1, 0x{:x} <ns::Foo::bar(char const*)>",
+                          bar_str)),
+               ctx.wait());
+    assert_ok_match!(concat!(r"^#0  0x[0-9a-f]{16} in ",
+                             r"ns::Foo::bar\(char const\*\) at cxx.cc:12\n",
+                             r"#1  0x[0-9a-f]{16} in main\+\d+ at cxx.cc:26"),
+                     ctx.run_command(command::Command::Backtrace));
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"Process \d+ exited with code 0", ctx.wait());
+}
+
+#[test]
+fn test_disassemble() {
+    let args = vec!["test/data/cxx".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let main = ctx.resolve("main").unwrap();
+    let bar_int = ctx.resolve("_ZN2ns3Foo3barEi").unwrap();
+    let bar_str = ctx.resolve("_ZN2ns3Foo3barEPKc").unwrap();
+    let dump = ctx.run_command(command::parse("disassemble main").unwrap())
+        .unwrap();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!("Dump of assembler code for function main:", lines[0]);
+    assert_eq!(format!("   0x{:016x} <+0>:\tpush   %rbp", main), lines[1]);
+    assert!(lines.contains(&format!(
+        "   0x{:016x} <+21>:\tcall   0x{:x} <ns::Foo::bar(int)>",
+        main + 21, bar_int).as_str()));
+    assert!(lines.iter().any(|l| {
+        l.contains("lea    0xeac(%rip),%rdx        # 0x402004")
+    }));
+    assert_eq!(Some(&"End of assembler dump."), lines.last());
+    assert_eq!(Ok(format!("0x{:x} <main>:\t0x55\t0x48\t0x89\t0xe5", main)),
+               ctx.run_command(command::parse("x/4xb main").unwrap()));
+
+    assert!(ctx.run_command(command::Command::Break(
+        Expr::Num(bar_str as i64))).is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert!(ctx.wait().is_ok());
+    assert!(ctx.run_command(command::Command::Break(
+        Expr::Num((main + 46) as i64))).is_ok());
+    // The current pc is marked and breakpoints show their original bytes.
+    let dump = ctx.run_command(command::parse("disas").unwrap()).unwrap();
+    assert_eq!(Some(format!("=> 0x{:016x} <+0>:\tpush   %rbp", bar_str)),
+               dump.lines().nth(1).map(|l| l.to_string()));
+    assert_eq!(Ok(format!(concat!(
+        "B  0x{:x} <main+46>:\tcall   0x{:x} <ns::Foo::bar(char const*)>\n",
+        "   0x{:x} <main+51>:\tadd    %eax,-0x14(%rbp)"),
+                          main + 46, bar_str, main + 51)),
+               ctx.run_command(command::parse(
+                   &format!("x/2i 0x{:x}", main + 46)).unwrap()));
+}
+
+#[test]
+fn test_disassemble_plt_call() {
+    let args = vec!["test/data/watch".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert_eq!(Ok("   0x40117c <main+59>:\tcall   0x401030 <atoi@plt>"
+                  .to_string()),
+               ctx.run_command(command::parse("x/i 0x40117c").unwrap()));
+}
+
+#[test]
+fn test_core() {
+    let args = vec!["test/data/crash".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert!(ctx.load_core("test/data/hello").is_err());
+    assert_ok_match!(concat!(r"^Core was generated by `\./crash'\.\n",
+                             r"Program terminated with signal SIGSEGV, ",
+                             r"Segmentation fault\.\n",
+                             r"#0  0x[0-9a-f]{16} in crash\+\d+ at crash.c:5$"),
+                     ctx.load_core("test/data/crash.core"));
     assert!(!ctx.is_running());
+    assert_ok_match!(concat!(r"^#0  0x[0-9a-f]{16} in crash\+\d+ ",
+                             r"at crash.c:5\n",
+                             r"#1  0x[0-9a-f]{16} in main\+\d+ at crash.c:10\n",
+                             r"#2  0x[0-9a-f]{16} in .* from .*libc"),
+                     ctx.run_command(command::Command::Backtrace));
+    assert_eq!(Ok("42".to_string()),
+               ctx.run_command(command::parse("p counter").unwrap()));
+    assert_eq!(Ok("6".to_string()),
+               ctx.run_command(command::parse("p local").unwrap()));
+    assert_eq!(Ok("3".to_string()),
+               ctx.run_command(command::parse("p depth").unwrap()));
+    assert_ok_match!(r"^0x[0-9a-f]+ <counter>:\t42$",
+                     ctx.run_command(command::parse("x/d &counter").unwrap()));
+    assert_ok_match!(r"^=> 0x401120 <crash\+26>:\tmov    %edx,\(%rax\)$",
+                     ctx.run_command(command::parse("x/i 0x401120").unwrap()));
+    assert_ok_match!(r"libc\.so",
+                     ctx.run_command(command::Command::InfoSharedLibrary));
+}
 
+#[test]
+fn test_gcore() {
+    let args = vec!["test/data/crash".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert!(ctx.run_command(command::parse("gcore").unwrap()).is_err());
+    assert!(ctx.run_command(command::parse("break crash.c:5").unwrap())
+            .is_ok());
     assert!(ctx.run(vec!()).is_ok());
     assert!(ctx.wait().is_ok());
-    assert_ok_match!(r"Breakpoint 1, ", ctx.wait());
-    assert!(ctx.is_running());
-    assert_eq!(ctx.ip(), addr);
-    assert!(ctx.single_step().is_ok());
-    assert_eq!(ctx.ip(), addr + 1);
+    assert_ok_match!(r"^Breakpoint 1, ", ctx.wait());
+    let path = std::env::temp_dir().join(
+        format!("varsan_test_gcore.{}", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    assert_eq!(Ok(format!("Saved corefile {}", path)),
+               ctx.run_command(command::Command::Gcore(Some(path.clone()))));
+    // The memory is dumped without the cache.
+    assert_eq!(0, ctx.ptracer().cache_stats().0);
+
+    let mut core_ctx = Context::new(&args);
+    assert!(core_ctx.set_main_binary(&args[0]).is_ok());
+    let loaded = core_ctx.load_core(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_ok_match!(concat!(r"^Core was generated by `test/data/crash.*'\.\n",
+                             r"Program terminated with signal SIGTRAP, ",
+                             r"Trace/breakpoint trap\.\n",
+                             r"#0  0x[0-9a-f]{16} in crash\+\d+ at crash.c:5$"),
+                     loaded);
+    assert_ok_match!(concat!(r"^#0  0x[0-9a-f]{16} in crash\+\d+ ",
+                             r"at crash.c:5\n",
+                             r"#1  0x[0-9a-f]{16} in main\+\d+ at crash.c:10"),
+                     core_ctx.run_command(command::Command::Backtrace));
+    assert_eq!(Ok("42".to_string()),
+               core_ctx.run_command(command::parse("p counter").unwrap()));
+    assert_eq!(Ok("6".to_string()),
+               core_ctx.run_command(command::parse("p local").unwrap()));
+    // The stack is saved as is.
+    let x = format!("x/8xg 0x{:x}", ctx.regs.sp());
+    assert_eq!(ctx.run_command(command::parse(&x).unwrap()),
+               core_ctx.run_command(command::parse(&x).unwrap()));
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(concat!(r"^Program received signal SIGSEGV, ",
+                             r"Segmentation fault\.\n",
+                             r"Address not mapped to object \(SEGV_MAPERR\) ",
+                             r"at address 0x0 \(not mapped\)\n",
+                             r"#0  0x[0-9a-f]{16} in crash\+\d+ at crash.c:5$"),
+                     ctx.wait());
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"Process \d+ signaled with code 11", ctx.wait());
+}
+
+#[test]
+fn test_attach() {
+    use std::io::BufRead;
+    let mut child = std::process::Command::new("test/data/loop")
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn().unwrap();
+    let pid = child.id() as i32;
+    // Wait until the child gets past the dynamic loader into main.
+    let mut line = String::new();
+    let mut stdout = std::io::BufReader::new(child.stdout.take().unwrap());
+    assert!(stdout.read_line(&mut line).is_ok());
+    assert_eq!("ready\n", line);
+    // Breakpoints which are not in another program are deleted.
+    let args = vec!["test/data/vars".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert!(ctx.run_command(command::parse("break add").unwrap()).is_ok());
+    assert_ok_match!(&format!(concat!(r"^Attaching to program: ",
+                                      r".*/test/data/loop, process {}\n",
+                                      r"Breakpoint 1 deleted: not found in ",
+                                      r".*/test/data/loop\n#0  0x"), pid),
+                     ctx.attach(pid));
+    assert!(ctx.detach().is_ok());
+
+    let args = vec!["test/data/loop".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert!(ctx.detach().is_err());
+    // A process which cannot be traced does not replace the program.
+    assert!(ctx.attach(std::process::id() as i32).is_err());
+    assert!(ctx.resolve("tick").is_some());
+    // Watchpoints are kept for the same program.
+    assert!(ctx.run_command(command::parse("watch counter").unwrap())
+            .is_ok());
+    assert_ok_match!(&format!(concat!(r"^Attaching to program: ",
+                                      r".*/test/data/loop, process {}\n",
+                                      r"#0  0x"), pid),
+                     ctx.attach(pid));
+    assert_ok_match!(r"\n1 +hw watchpoint +keep y +counter$",
+                     ctx.run_command(command::Command::InfoBreakpoints));
+    assert!(ctx.run_command(command::parse("delete 1").unwrap()).is_ok());
     assert!(ctx.is_running());
+    assert!(ctx.attach(pid).is_err());
+    assert_ok_match!(r"(?m)^#\d+  0x[0-9a-f]{16} in main\+\d+ at .*loop\.c:1\d",
+                     ctx.run_command(command::Command::Backtrace));
+    assert_ok_match!(r"libc\.so",
+                     ctx.run_command(command::Command::InfoSharedLibrary));
+
+    assert!(ctx.run_command(command::parse("break tick").unwrap()).is_ok());
     assert!(ctx.cont().is_ok());
-    assert_ok_match!(r"Process \d+ exited with code 0", ctx.wait());
+    assert_ok_match!(r"^Breakpoint 2, 0x[0-9a-f]+ <tick>$", ctx.wait());
+    let counter = ctx.run_command(command::parse("p counter").unwrap())
+        .unwrap().parse::<i32>().unwrap();
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"^Breakpoint 2, 0x[0-9a-f]+ <tick>$", ctx.wait());
+    assert_eq!(Ok(format!("{}", counter + 1)),
+               ctx.run_command(command::parse("p counter").unwrap()));
+    assert!(ctx.run_command(command::parse("break main").unwrap()).is_ok());
+    assert_ok_match!(&format!(r"^Detaching from program: .*, process {}$",
+                              pid),
+                     ctx.run_command(command::Command::Detach));
     assert!(!ctx.is_running());
+    // The process keeps running without the breakpoints.
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert!(child.try_wait().unwrap().is_none());
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_threads() {
+    let args = vec!["test/data/thread".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert_eq!(Ok("No threads.".to_string()),
+               ctx.run_command(command::Command::InfoThreads));
+    assert!(ctx.run_command(command::parse("break hit").unwrap()).is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    let pid = ctx.pid();
+    assert_ok_match!(concat!(r"^\[Switching to thread 2 \(LWP \d+\)\]\n",
+                             r"Breakpoint 1, 0x[0-9a-f]+ <hit>$"),
+                     ctx.wait());
+    let threads = ctx.run_command(command::Command::InfoThreads).unwrap();
+    let lines: Vec<&str> = threads.lines().collect();
+    assert_eq!(3, lines.len());
+    assert!(lines[0].starts_with("  Id   Target Id"));
+    assert!(lines[1].starts_with(&format!("  1    LWP {} \"thread\"", pid)));
+    assert!(lines[2].starts_with("* 2    LWP "));
+    assert!(lines[2].ends_with("0x0000000000401136 in hit at thread.c:6"));
+
+    // Registers and backtraces are of the selected thread.
+    assert_ok_match!(&format!(r"^\[Switching to thread 1 \(LWP {}\)\]\n#0  ",
+                              pid),
+                     ctx.run_command(command::parse("thread 1").unwrap()));
+    assert_ok_match!(r"(?m)^#\d+  0x[0-9a-f]{16} in main\+\d+ at thread\.c:20",
+                     ctx.run_command(command::Command::Backtrace));
+    assert_ok_match!(&format!(r"^\[Current thread is 1 \(LWP {}\)\]$", pid),
+                     ctx.run_command(command::parse("thread").unwrap()));
+    assert!(ctx.run_command(command::parse("thread 3").unwrap()).is_err());
+
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"Process \d+ exited with code 42", ctx.wait());
+}
+
+#[test]
+fn test_threads_software_watchpoint() {
+    let args = vec!["test/data/thread".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    let run = |ctx: &mut Context, cmd: &str| {
+        ctx.run_command(command::parse(cmd).unwrap())
+    };
+    assert!(run(&mut ctx, "break thread.c:20").is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"^Breakpoint 1, ", ctx.wait());
+    assert!(run(&mut ctx, "watch done").is_ok());
+    // The worker runs while main steps, which waits for it in
+    // pthread_join(). The change is not attributed to main's step.
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(concat!(r"^Watchpoint 2: done\n\n",
+                             r"Old value = \{0, 0, 0, 0\}\n",
+                             r"New value = \{42, 0, 0, 0\}\n#0 "),
+                     ctx.wait());
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"Process \d+ exited with code 42", ctx.wait());
+}
+
+#[test]
+fn test_fork() {
+    let args = vec!["test/data/fork".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert!(ctx.run_command(command::parse("break in_child").unwrap())
+            .is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    // The child does not stop at the breakpoint removed from it.
+    assert_ok_match!(r"^\[Detaching after fork from child process \d+\]$",
+                     ctx.wait());
+    // SIGCHLD does not stop the process.
+    assert_eq!(Ok("".to_string()), ctx.wait());
+    assert_ok_match!(r"^Process \d+ exited with code 43$", ctx.wait());
+
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert_eq!(Err("Undefined item: \"both\".".to_string()),
+               ctx.run_command(command::parse("set follow-fork-mode both")
+                               .unwrap()));
+    assert!(ctx.run_command(command::parse("set follow-fork-mode child")
+                            .unwrap()).is_ok());
+    assert!(ctx.run_command(command::parse("break in_child").unwrap())
+            .is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    let parent = ctx.pid();
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(&format!(concat!(r"^\[Attaching after process {} fork ",
+                                      r"to child process \d+\]\n",
+                                      r"\[Detaching after fork from parent ",
+                                      r"process {}\]$"), parent, parent),
+                     ctx.wait());
+    assert!(ctx.pid() != parent);
+    assert_ok_match!(r"^Breakpoint 1, 0x[0-9a-f]+ <in_child>$", ctx.wait());
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"^Process \d+ exited with code 3$", ctx.wait());
+}
+
+#[test]
+fn test_exec() {
+    let args = vec!["test/data/exec".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert!(ctx.run_command(command::parse("break again").unwrap()).is_ok());
+    // Executes itself without arguments.
+    assert!(ctx.run(args.clone()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"^process \d+ is executing new program: .*/data/exec$",
+                     ctx.wait());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"^Breakpoint 1, 0x[0-9a-f]+ <again>$", ctx.wait());
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"^Process \d+ exited with code 1$", ctx.wait());
+
+    // Breakpoints are found again in another program.
+    assert!(ctx.run_command(command::parse("break main").unwrap()).is_ok());
+    assert!(ctx.run(vec!("test/data/hello".to_string())).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"^Breakpoint 2, 0x[0-9a-f]+ <main>$", ctx.wait());
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(concat!(r"^process \d+ is executing new program: ",
+                             r".*/data/hello\n",
+                             r"Breakpoint 1 deleted: not found in "),
+                     ctx.wait());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"^Breakpoint 2, 0x4005d0 <main>$", ctx.wait());
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"^Process \d+ exited with code 0$", ctx.wait());
 }
 
 #[test]
@@ -415,8 +3712,66 @@ fn test_segv() {
     assert!(ctx.set_main_binary(&args[0]).is_ok());
     assert!(ctx.run(vec!()).is_ok());
     assert!(ctx.wait().is_ok());
-    assert_ok_match!(r"Process \d+ signaled with code \d+", ctx.wait());
+    // The process stays stopped at the signal, which is raised by writing
+    // to a read-only string literal.
+    assert_ok_match!(concat!(r"^Program received signal SIGSEGV, ",
+                             r"Segmentation fault\.\n",
+                             r"Invalid permissions for mapped object ",
+                             r"\(SEGV_ACCERR\) at address 0x[0-9a-f]+ ",
+                             r"in /\S+/test/data/segv \(r-.p\)\n#0  0x"),
+                     ctx.wait());
+    assert!(ctx.is_running());
+    let ip = ctx.ip();
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"Process \d+ signaled with code 11", ctx.wait());
     assert!(!ctx.is_running());
+
+    // The fault is reported while stepping over a breakpoint on the
+    // faulting instruction.
+    assert!(ctx.run_command(command::parse(&format!("break *{}", ip))
+                            .unwrap()).is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(r"^Breakpoint 1, ", ctx.wait());
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"^Program received signal SIGSEGV, ", ctx.wait());
+    assert!(ctx.is_running());
+}
+
+#[test]
+fn test_signal() {
+    let args = vec!["test/data/signal".to_string()];
+    let mut ctx = Context::new(&args);
+    assert!(ctx.set_main_binary(&args[0]).is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    let received = concat!(r"^Program received signal SIGUSR1, ",
+                           r"User defined signal 1\.\n",
+                           r"Sent by tkill \(SI_TKILL\) from process \d+");
+    assert_ok_match!(&format!(r"{}\n#0  0x", received), ctx.wait());
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(&format!(r"{}\n#0  0x", received), ctx.wait());
+    // Only the first signal reaches the handler.
+    assert_eq!(Ok(concat!("Signal        Stop  Print Pass to program ",
+                          "Description\n",
+                          "SIGUSR1       Yes   Yes   No              ",
+                          "User defined signal 1").to_string()),
+               ctx.run_command(command::parse("handle SIGUSR1 nopass")
+                               .unwrap()));
+    assert!(ctx.cont().is_ok());
+    assert_ok_match!(r"^Process \d+ exited with code 10$", ctx.wait());
+
+    assert!(ctx.run_command(command::parse("handle usr1 nostop pass")
+                            .unwrap()).is_ok());
+    assert!(ctx.run(vec!()).is_ok());
+    assert!(ctx.wait().is_ok());
+    assert_ok_match!(&format!(r"{}$", received), ctx.wait());
+    assert_ok_match!(&format!(r"{}$", received), ctx.wait());
+    assert_ok_match!(r"^Process \d+ exited with code 20$", ctx.wait());
+    assert!(ctx.run_command(command::parse("handle nostop").unwrap())
+            .is_err());
+    assert!(ctx.run_command(command::parse("handle SIGUSR1 bogus").unwrap())
+            .is_err());
 }
 
 #[test]
@@ -428,5 +3783,7 @@ fn test_neg_one() {
     assert!(ctx.start(vec!()).is_ok());
 
     let addr = ctx.resolve("neg_one").unwrap();
-    assert_eq!(-1, ctx.ptracer().peek_word(addr) as i64);
+    assert_eq!(-1, ctx.ptracer().peek_word(addr).unwrap() as i64);
+    assert_eq!(Ok("(void *) 0xffffffffffffffff".to_string()),
+               eval::print(&ctx, &Expr::Ident("neg_one".to_string())));
 }
@@ -0,0 +1,413 @@
+extern crate goblin;
+extern crate libc;
+
+use std;
+
+use ptracer;
+use target_desc;
+use self::goblin::elf;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_FILE: u32 = 0x46494c45;
+
+// Offsets in struct elf_prstatus of x86-64.
+const PRSTATUS_CURSIG: usize = 12;
+const PRSTATUS_PID: usize = 32;
+const PRSTATUS_REG: usize = 112;
+const PRSTATUS_SIZE: usize = 336;
+// Offsets in struct elf_prpsinfo of x86-64.
+const PRPSINFO_PID: usize = 24;
+const PRPSINFO_FNAME: usize = 40;
+const PRPSINFO_FNAME_LEN: usize = 16;
+const PRPSINFO_PSARGS: usize = 56;
+const PRPSINFO_PSARGS_LEN: usize = 80;
+const PRPSINFO_SIZE: usize = 136;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const PAGE_SIZE: usize = 4096;
+
+// A thread which was running when the core was dumped.
+pub struct Thread {
+    #[allow(dead_code)]
+    pub pid: i32,
+    // The signal which stopped the thread.
+    pub signal: i32,
+    pub regs: ptracer::Registers,
+}
+
+struct Segment {
+    vaddr: u64,
+    offset: u64,
+    filesz: u64,
+}
+
+pub struct Core {
+    filename: String,
+    // The whole file.
+    mem: Vec<u8>,
+    segments: Vec<Segment>,
+    // The first thread is the one which caused the dump.
+    threads: Vec<Thread>,
+    auxv: Vec<(u64, u64)>,
+    psargs: String,
+}
+
+fn read_u16(data: &[u8], off: usize) -> u16 {
+    return data[off] as u16 | (data[off + 1] as u16) << 8;
+}
+
+fn read_u32(data: &[u8], off: usize) -> u32 {
+    let mut v = 0;
+    for i in 0..4 {
+        v |= (data[off + i] as u32) << (i * 8);
+    }
+    return v;
+}
+
+fn read_u64(data: &[u8], off: usize) -> u64 {
+    let mut v = 0;
+    for i in 0..8 {
+        v |= (data[off + i] as u64) << (i * 8);
+    }
+    return v;
+}
+
+// Returns (type, desc) of the notes in a PT_NOTE segment.
+fn parse_notes(data: &[u8]) -> Vec<(u32, &[u8])> {
+    let align = |n: usize| (n + 3) & !3;
+    let mut r = vec!();
+    let mut off = 0;
+    while off + 12 <= data.len() {
+        let namesz = read_u32(data, off) as usize;
+        let descsz = read_u32(data, off + 4) as usize;
+        let kind = read_u32(data, off + 8);
+        let desc = off + 12 + align(namesz);
+        if desc + descsz > data.len() {
+            break;
+        }
+        r.push((kind, &data[desc..desc + descsz]));
+        off = desc + align(descsz);
+    }
+    return r;
+}
+
+impl Core {
+    pub fn new(filename: String, target: &target_desc::Target)
+               -> Result<Self, String> {
+        let mem = try!(std::fs::read(&filename)
+                       .or(Err(format!("Failed to open: {}", &filename))));
+        let mut core = Core {
+            filename: filename,
+            mem: mem,
+            segments: vec!(),
+            threads: vec!(),
+            auxv: vec!(),
+            psargs: String::new(),
+        };
+        let mut segments = vec!();
+        let mut notes = vec!();
+        {
+            let data = core.data();
+            let elf = match elf::Elf::parse(data) {
+                Ok(elf) => elf,
+                Err(_) => {
+                    return Err(format!("Parse failed: {}", core.filename));
+                }
+            };
+            if elf.header.e_type != elf::header::ET_CORE {
+                return Err(format!("\"{}\" is not a core dump",
+                                   core.filename));
+            }
+            for ph in &elf.program_headers {
+                let end = ph.p_offset + ph.p_filesz;
+                if end > data.len() as u64 {
+                    continue;
+                }
+                match ph.p_type {
+                    elf::program_header::PT_LOAD => {
+                        segments.push(Segment {
+                            vaddr: ph.p_vaddr,
+                            offset: ph.p_offset,
+                            filesz: ph.p_filesz,
+                        });
+                    }
+                    elf::program_header::PT_NOTE => {
+                        notes.push((ph.p_offset as usize, end as usize));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut threads = vec!();
+        let mut auxv = vec!();
+        let mut psargs = String::new();
+        for &(start, end) in notes.iter() {
+            for (kind, desc) in parse_notes(&core.data()[start..end]) {
+                match kind {
+                    NT_PRSTATUS => {
+                        let nregs = target.gp_names.len();
+                        if desc.len() < PRSTATUS_REG + nregs * 8 {
+                            continue;
+                        }
+                        let gps = (0..nregs).map(|i| {
+                            read_u64(desc, PRSTATUS_REG + i * 8)
+                        }).collect();
+                        threads.push(Thread {
+                            pid: read_u32(desc, PRSTATUS_PID) as i32,
+                            signal: read_u16(desc, PRSTATUS_CURSIG) as i32,
+                            regs: ptracer::Registers::new(gps, target),
+                        });
+                    }
+                    NT_PRPSINFO => {
+                        let end = PRPSINFO_PSARGS + PRPSINFO_PSARGS_LEN;
+                        if desc.len() < end {
+                            continue;
+                        }
+                        let args = &desc[PRPSINFO_PSARGS..end];
+                        let mut len = args.iter().position(|&c| c == 0)
+                            .unwrap_or(args.len());
+                        // The kernel leaves a space after the last one.
+                        while len > 0 && args[len - 1] == b' ' {
+                            len -= 1;
+                        }
+                        psargs = String::from_utf8_lossy(&args[..len])
+                            .into_owned();
+                    }
+                    NT_AUXV => auxv = ptracer::parse_auxv(desc),
+                    _ => {}
+                }
+            }
+        }
+        if threads.is_empty() {
+            return Err(format!("{}: No NT_PRSTATUS note", core.filename));
+        }
+        core.segments = segments;
+        core.threads = threads;
+        core.auxv = auxv;
+        core.psargs = psargs;
+        return Ok(core);
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.mem
+    }
+
+    pub fn threads(&self) -> &Vec<Thread> { &self.threads }
+
+    pub fn auxv(&self) -> &Vec<(u64, u64)> { &self.auxv }
+
+    // The command line of the dumped process.
+    pub fn psargs(&self) -> &str { &self.psargs }
+
+    // Reads memory saved in the core. Returns None if any byte is not in
+    // the file, e.g. for read-only pages of mapped files, which should be
+    // read from the binaries instead.
+    pub fn read(&self, addr: u64, len: usize) -> Option<Vec<u8>> {
+        let data = self.data();
+        let mut r = Vec::with_capacity(len);
+        while r.len() < len {
+            let cur = addr + r.len() as u64;
+            let seg = match self.segments.iter().find(|s| {
+                s.vaddr <= cur && cur < s.vaddr + s.filesz
+            }) {
+                Some(seg) => seg,
+                None => return None,
+            };
+            let n = std::cmp::min((seg.vaddr + seg.filesz - cur) as usize,
+                                  len - r.len());
+            let off = (seg.offset + cur - seg.vaddr) as usize;
+            r.extend_from_slice(&data[off..off + n]);
+        }
+        return Some(r);
+    }
+}
+
+fn push_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.extend_from_slice(&[v as u8, (v >> 8) as u8]);
+}
+
+fn push_u32(buf: &mut Vec<u8>, v: u32) {
+    for i in 0..4 {
+        buf.push((v >> (i * 8)) as u8);
+    }
+}
+
+fn push_u64(buf: &mut Vec<u8>, v: u64) {
+    for i in 0..8 {
+        buf.push((v >> (i * 8)) as u8);
+    }
+}
+
+fn write_at(buf: &mut [u8], off: usize, data: &[u8]) {
+    buf[off..off + data.len()].copy_from_slice(data);
+}
+
+fn write_u32_at(buf: &mut [u8], off: usize, v: u32) {
+    let mut data = vec!();
+    push_u32(&mut data, v);
+    write_at(buf, off, &data);
+}
+
+fn push_note(buf: &mut Vec<u8>, kind: u32, desc: &[u8]) {
+    let name = b"CORE\0";
+    push_u32(buf, name.len() as u32);
+    push_u32(buf, desc.len() as u32);
+    push_u32(buf, kind);
+    buf.extend_from_slice(name);
+    while buf.len() % 4 != 0 {
+        buf.push(0);
+    }
+    buf.extend_from_slice(desc);
+    while buf.len() % 4 != 0 {
+        buf.push(0);
+    }
+}
+
+// Saves the memory of a mapping unless it can be read from the mapped
+// file, like the kernel does by default.
+fn should_dump(map: &ptracer::Mapping) -> bool {
+    let is_file = map.path.starts_with('/');
+    return map.perms.starts_with('r') && map.path != "[vsyscall]" &&
+        (!is_file || map.perms.contains('w'));
+}
+
+// Writes an ELF core file of the stopped process traced by `ptracer`.
+// `threads` are the IDs and registers of its threads, the current one
+// first.
+pub fn write(filename: &str, ptracer: &ptracer::Ptracer,
+             threads: &[(i32, ptracer::Registers)]) -> Result<(), String> {
+    use std::io::Write;
+
+    let maps = try!(ptracer.maps());
+    let auxv = try!(ptracer.auxv());
+    let psargs = try!(ptracer.cmdline());
+
+    let mut notes = vec!();
+    for (i, &(pid, ref regs)) in threads.iter().enumerate() {
+        let mut prstatus = vec![0; PRSTATUS_SIZE];
+        // Threads stop by breakpoints or single steps. The signal is in
+        // both pr_info.si_signo and pr_cursig.
+        let sig = libc::SIGTRAP as u32;
+        write_u32_at(&mut prstatus, 0, sig);
+        write_at(&mut prstatus, PRSTATUS_CURSIG, &[sig as u8, 0]);
+        write_u32_at(&mut prstatus, PRSTATUS_PID, pid as u32);
+        let mut buf = vec!();
+        for &r in regs.gps() {
+            push_u64(&mut buf, r);
+        }
+        write_at(&mut prstatus, PRSTATUS_REG, &buf);
+        push_note(&mut notes, NT_PRSTATUS, &prstatus);
+
+        // Like the kernel, process-wide notes follow the first thread.
+        if i > 0 {
+            continue;
+        }
+        let mut prpsinfo = vec![0; PRPSINFO_SIZE];
+        write_u32_at(&mut prpsinfo, PRPSINFO_PID, pid as u32);
+        let arg0 = psargs.split(' ').next().unwrap_or("");
+        let fname = arg0.rsplit('/').next().unwrap_or("").as_bytes();
+        let len = std::cmp::min(fname.len(), PRPSINFO_FNAME_LEN - 1);
+        write_at(&mut prpsinfo, PRPSINFO_FNAME, &fname[..len]);
+        let len = std::cmp::min(psargs.len(), PRPSINFO_PSARGS_LEN - 1);
+        write_at(&mut prpsinfo, PRPSINFO_PSARGS, &psargs.as_bytes()[..len]);
+        push_note(&mut notes, NT_PRPSINFO, &prpsinfo);
+
+        let mut buf = vec!();
+        for &(key, value) in auxv.iter() {
+            push_u64(&mut buf, key);
+            push_u64(&mut buf, value);
+        }
+        push_u64(&mut buf, 0);
+        push_u64(&mut buf, 0);
+        push_note(&mut notes, NT_AUXV, &buf);
+
+        let files: Vec<&ptracer::Mapping> =
+            maps.iter().filter(|m| m.path.starts_with('/')).collect();
+        let mut buf = vec!();
+        push_u64(&mut buf, files.len() as u64);
+        push_u64(&mut buf, PAGE_SIZE as u64);
+        for map in files.iter() {
+            push_u64(&mut buf, map.start);
+            push_u64(&mut buf, map.end);
+            push_u64(&mut buf, map.offset / PAGE_SIZE as u64);
+        }
+        for map in files.iter() {
+            buf.extend_from_slice(map.path.as_bytes());
+            buf.push(0);
+        }
+        push_note(&mut notes, NT_FILE, &buf);
+    }
+
+    let contents: Vec<Vec<u8>> = maps.iter().map(|map| {
+        if !should_dump(map) {
+            return vec!();
+        }
+        // Only the readable beginning is dumped, e.g. of a file mapping
+        // which extends past the end of the file.
+        ptracer.read_uncached(map.start, (map.end - map.start) as usize)
+    }).collect();
+
+    let num_phdrs = maps.len() + 1;
+    let notes_off = EHDR_SIZE + PHDR_SIZE * num_phdrs;
+    let align = |n: usize| (n + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    let mut data_off = align(notes_off + notes.len());
+
+    let mut buf = vec!();
+    buf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+    buf.resize(16, 0);
+    push_u16(&mut buf, elf::header::ET_CORE);
+    push_u16(&mut buf, elf::header::EM_X86_64);
+    push_u32(&mut buf, 1);
+    push_u64(&mut buf, 0);
+    push_u64(&mut buf, EHDR_SIZE as u64);
+    push_u64(&mut buf, 0);
+    push_u32(&mut buf, 0);
+    push_u16(&mut buf, EHDR_SIZE as u16);
+    push_u16(&mut buf, PHDR_SIZE as u16);
+    push_u16(&mut buf, num_phdrs as u16);
+    push_u16(&mut buf, 0);
+    push_u16(&mut buf, 0);
+    push_u16(&mut buf, 0);
+
+    push_u32(&mut buf, elf::program_header::PT_NOTE);
+    push_u32(&mut buf, 0);
+    push_u64(&mut buf, notes_off as u64);
+    push_u64(&mut buf, 0);
+    push_u64(&mut buf, 0);
+    push_u64(&mut buf, notes.len() as u64);
+    push_u64(&mut buf, 0);
+    push_u64(&mut buf, 0);
+    for (map, content) in maps.iter().zip(contents.iter()) {
+        let perms = map.perms.as_bytes();
+        let flags = (if perms[0] == b'r' { elf::program_header::PF_R }
+                     else { 0 }) |
+            (if perms[1] == b'w' { elf::program_header::PF_W } else { 0 }) |
+            (if perms[2] == b'x' { elf::program_header::PF_X } else { 0 });
+        push_u32(&mut buf, elf::program_header::PT_LOAD);
+        push_u32(&mut buf, flags);
+        push_u64(&mut buf, data_off as u64);
+        push_u64(&mut buf, map.start);
+        push_u64(&mut buf, 0);
+        push_u64(&mut buf, content.len() as u64);
+        push_u64(&mut buf, map.end - map.start);
+        push_u64(&mut buf, PAGE_SIZE as u64);
+        data_off += content.len();
+    }
+    buf.extend_from_slice(&notes);
+    buf.resize(align(notes_off + notes.len()), 0);
+
+    let mut file = try!(std::fs::File::create(filename).map_err(|e| {
+        format!("Failed to open '{}' for output: {}", filename, e)
+    }));
+    let r = file.write_all(&buf).and_then(|_| {
+        for content in contents.iter() {
+            try!(file.write_all(content));
+        }
+        Ok(())
+    });
+    return r.map_err(|e| format!("Failed to write {}: {}", filename, e));
+}
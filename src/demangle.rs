@@ -0,0 +1,1488 @@
+// Demangles C++ (Itanium ABI) and Rust symbol names.
+
+use std;
+
+macro_rules! some {
+    ($e:expr) => (match $e { Some(v) => v, None => return None })
+}
+
+// Nesting limit which guards against malicious or broken names.
+const MAX_DEPTH: usize = 256;
+
+// Returns the demangled form of `sym`, e.g. "ns::Foo::bar(int) const" for
+// "_ZNK2ns3Foo3barEi". Rust names are shown without their hashes.
+pub fn demangle(sym: &str) -> Option<String> {
+    demangle_parts(sym).map(|d| d.1)
+}
+
+// Returns the qualified name of `sym` without the return and parameter
+// types, e.g. "ns::Foo::bar" for "_ZNK2ns3Foo3barEi".
+pub fn demangle_name(sym: &str) -> Option<String> {
+    demangle_parts(sym).map(|d| d.0)
+}
+
+fn demangle_parts(sym: &str) -> Option<(String, String)> {
+    if sym.starts_with("_R") {
+        let name = some!(RustV0::new(&sym[2..]).parse());
+        return Some((name.clone(), name));
+    }
+    if let Some(name) = rust_legacy(sym) {
+        return Some((name.clone(), name));
+    }
+    if sym.starts_with("_Z") {
+        return Itanium::new(&sym[2..]).parse();
+    }
+    return None;
+}
+
+// Removes whitespace which does not separate words so that user input like
+// "Foo::bar(int,char *)" matches "Foo::bar(int, char*)".
+pub fn normalize(s: &str) -> String {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut r = String::new();
+    let mut pending_space = false;
+    for c in s.chars() {
+        if c.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+        if pending_space && is_word(c) &&
+            r.chars().last().map_or(false, is_word) {
+            r.push(' ');
+        }
+        pending_space = false;
+        r.push(c);
+    }
+    return r;
+}
+
+fn is_rust_hash(s: &str) -> bool {
+    s.len() == 17 && s.starts_with('h') &&
+        s[1..].chars().all(|c| c.is_digit(16))
+}
+
+// The legacy Rust mangling, which is Itanium-like with a trailing hash,
+// e.g. "_ZN4core3fmt5write17h0123456789abcdefE".
+fn rust_legacy(sym: &str) -> Option<String> {
+    if !sym.starts_with("_ZN") || !sym.ends_with('E') {
+        return None;
+    }
+    let mut rest = &sym[3..sym.len() - 1];
+    let mut parts = vec!();
+    while !rest.is_empty() {
+        let digits = rest.bytes().take_while(|c| c.is_ascii_digit()).count();
+        let len: usize = some!(rest[..digits].parse().ok());
+        if len > rest.len() - digits || !rest.is_char_boundary(digits + len) {
+            return None;
+        }
+        parts.push(&rest[digits..digits + len]);
+        rest = &rest[digits + len..];
+    }
+    if !parts.last().map_or(false, |p| is_rust_hash(p)) {
+        return None;
+    }
+    parts.pop();
+    let mut names = vec!();
+    for part in parts {
+        names.push(some!(rust_unescape(part)));
+    }
+    return Some(names.join("::"));
+}
+
+fn rust_unescape(s: &str) -> Option<String> {
+    let mut s = if s.starts_with("_$") { &s[1..] } else { s };
+    let mut r = String::new();
+    while !s.is_empty() {
+        if s.starts_with("..") {
+            r += "::";
+            s = &s[2..];
+        } else if s.starts_with('$') {
+            let end = some!(s[1..].find('$')) + 1;
+            let esc = &s[1..end];
+            r.push(match esc {
+                "SP" => '@',
+                "BP" => '*',
+                "RF" => '&',
+                "LT" => '<',
+                "GT" => '>',
+                "LP" => '(',
+                "RP" => ')',
+                "C" => ',',
+                _ if esc.starts_with('u') => {
+                    let code = some!(u32::from_str_radix(&esc[1..], 16).ok());
+                    some!(std::char::from_u32(code))
+                }
+                _ => return None,
+            });
+            s = &s[end + 1..];
+        } else {
+            let c = s.chars().next().unwrap();
+            r.push(c);
+            s = &s[c.len_utf8()..];
+        }
+    }
+    return Some(r);
+}
+
+// The v0 Rust mangling, e.g. "_RNvCs1234_7mycrate3foo". Paths are shown
+// without disambiguators.
+struct RustV0<'a> {
+    s: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> RustV0<'a> {
+    fn new(s: &'a str) -> Self {
+        RustV0 { s: s.as_bytes(), pos: 0, depth: 0 }
+    }
+
+    fn peek(&self) -> u8 {
+        if self.pos < self.s.len() { self.s[self.pos] } else { 0 }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == c {
+            self.pos += 1;
+            return true;
+        }
+        return false;
+    }
+
+    fn parse(&mut self) -> Option<String> {
+        // An optional encoding version.
+        while self.peek().is_ascii_digit() {
+            self.pos += 1;
+        }
+        let path = some!(self.path(true));
+        // An instantiating crate and a vendor suffix may follow.
+        return Some(path);
+    }
+
+    fn base62(&mut self) -> Option<u64> {
+        if self.eat(b'_') {
+            return Some(0);
+        }
+        let mut v: u64 = 0;
+        loop {
+            let c = self.peek();
+            self.pos += 1;
+            let d = match c {
+                b'0'..=b'9' => c - b'0',
+                b'a'..=b'z' => c - b'a' + 10,
+                b'A'..=b'Z' => c - b'A' + 36,
+                b'_' => return v.checked_add(1),
+                _ => return None,
+            };
+            v = some!(v.checked_mul(62).and_then(|v| v.checked_add(d as u64)));
+        }
+    }
+
+    fn disambiguator(&mut self) -> Option<u64> {
+        if self.eat(b's') {
+            return self.base62().and_then(|v| v.checked_add(1));
+        }
+        return Some(0);
+    }
+
+    fn decimal(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_ascii_digit() {
+            self.pos += 1;
+        }
+        let digits = some!(std::str::from_utf8(&self.s[start..self.pos]).ok());
+        return digits.parse().ok();
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        some!(self.disambiguator());
+        if self.peek() == b'u' {
+            // Punycode is not supported.
+            return None;
+        }
+        let len = some!(self.decimal());
+        self.eat(b'_');
+        if len > self.s.len() - self.pos {
+            return None;
+        }
+        let r = some!(std::str::from_utf8(&self.s[self.pos..self.pos + len])
+                      .ok());
+        self.pos += len;
+        return Some(r.to_string());
+    }
+
+    fn backref<T, F: Fn(&mut Self) -> Option<T>>(&mut self, f: F)
+                                                  -> Option<T> {
+        let target = some!(self.base62()) as usize;
+        if target >= self.pos {
+            return None;
+        }
+        let saved = self.pos;
+        self.pos = target;
+        let r = f(self);
+        self.pos = saved;
+        return r;
+    }
+
+    fn enter(&mut self) -> Option<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return None;
+        }
+        return Some(());
+    }
+
+    // `in_value` selects "foo::<T>" over "Foo<T>" for generic arguments.
+    fn path(&mut self, in_value: bool) -> Option<String> {
+        some!(self.enter());
+        let c = self.peek();
+        self.pos += 1;
+        let r = match c {
+            b'C' => some!(self.ident()),
+            b'N' => {
+                let ns = self.peek();
+                self.pos += 1;
+                let parent = some!(self.path(in_value));
+                let disambiguator = some!(self.disambiguator());
+                let name = {
+                    let len = some!(self.decimal());
+                    self.eat(b'_');
+                    if len > self.s.len() - self.pos {
+                        return None;
+                    }
+                    let name = some!(std::str::from_utf8(
+                        &self.s[self.pos..self.pos + len]).ok());
+                    self.pos += len;
+                    name.to_string()
+                };
+                match ns {
+                    b'C' => format!("{}::{{closure#{}}}", parent,
+                                    disambiguator),
+                    b'S' => format!("{}::{{shim:{}#{}}}", parent, name,
+                                    disambiguator),
+                    b'A'..=b'Z' => format!("{}::{{{}#{}}}", parent,
+                                           (ns as char).to_lowercase()
+                                           .collect::<String>(),
+                                           disambiguator),
+                    _ if name.is_empty() => parent,
+                    _ => format!("{}::{}", parent, name),
+                }
+            }
+            b'M' => {
+                some!(self.disambiguator());
+                some!(self.path(in_value));
+                format!("<{}>", some!(self.ty()))
+            }
+            b'X' => {
+                some!(self.disambiguator());
+                some!(self.path(in_value));
+                let ty = some!(self.ty());
+                format!("<{} as {}>", ty, some!(self.path(false)))
+            }
+            b'Y' => {
+                let ty = some!(self.ty());
+                format!("<{} as {}>", ty, some!(self.path(false)))
+            }
+            b'I' => {
+                let path = some!(self.path(in_value));
+                let mut args = vec!();
+                while !self.eat(b'E') {
+                    if self.pos >= self.s.len() {
+                        return None;
+                    }
+                    if let Some(arg) = some!(self.generic_arg()) {
+                        args.push(arg);
+                    }
+                }
+                format!("{}{}<{}>", path, if in_value { "::" } else { "" },
+                        args.join(", "))
+            }
+            b'B' => some!(self.backref(|p| p.path(in_value))),
+            _ => return None,
+        };
+        self.depth -= 1;
+        return Some(r);
+    }
+
+    // Lifetimes are elided, hence the inner option.
+    fn generic_arg(&mut self) -> Option<Option<String>> {
+        if self.eat(b'L') {
+            some!(self.base62());
+            return Some(None);
+        }
+        if self.eat(b'K') {
+            return self.konst().map(Some);
+        }
+        return self.ty().map(Some);
+    }
+
+    fn konst(&mut self) -> Option<String> {
+        if self.eat(b'p') {
+            return Some("_".to_string());
+        }
+        if self.eat(b'B') {
+            return self.backref(|p| p.konst());
+        }
+        let ty = self.peek();
+        self.pos += 1;
+        let neg = self.eat(b'n');
+        let start = self.pos;
+        while self.peek() != b'_' {
+            if self.pos >= self.s.len() {
+                return None;
+            }
+            self.pos += 1;
+        }
+        let hex = some!(std::str::from_utf8(&self.s[start..self.pos]).ok());
+        self.pos += 1;
+        let v = if hex.is_empty() {
+            0
+        } else {
+            some!(u64::from_str_radix(hex, 16).ok())
+        };
+        return Some(match ty {
+            b'b' => (if v != 0 { "true" } else { "false" }).to_string(),
+            b'c' => format!("{:?}", some!(std::char::from_u32(v as u32))),
+            _ => format!("{}{}", if neg { "-" } else { "" }, v),
+        });
+    }
+
+    fn ty(&mut self) -> Option<String> {
+        let basic = match self.peek() {
+            b'a' => "i8",
+            b'b' => "bool",
+            b'c' => "char",
+            b'd' => "f64",
+            b'e' => "str",
+            b'f' => "f32",
+            b'h' => "u8",
+            b'i' => "isize",
+            b'j' => "usize",
+            b'l' => "i32",
+            b'm' => "u32",
+            b'n' => "i128",
+            b'o' => "u128",
+            b's' => "i16",
+            b't' => "u16",
+            b'u' => "()",
+            b'v' => "...",
+            b'x' => "i64",
+            b'y' => "u64",
+            b'z' => "!",
+            b'p' => "_",
+            _ => "",
+        };
+        if !basic.is_empty() {
+            self.pos += 1;
+            return Some(basic.to_string());
+        }
+        some!(self.enter());
+        let c = self.peek();
+        self.pos += 1;
+        let r = match c {
+            b'A' => {
+                let ty = some!(self.ty());
+                format!("[{}; {}]", ty, some!(self.konst()))
+            }
+            b'S' => format!("[{}]", some!(self.ty())),
+            b'T' => {
+                let mut tys = vec!();
+                while !self.eat(b'E') {
+                    if self.pos >= self.s.len() {
+                        return None;
+                    }
+                    tys.push(some!(self.ty()));
+                }
+                if tys.len() == 1 {
+                    format!("({},)", tys[0])
+                } else {
+                    format!("({})", tys.join(", "))
+                }
+            }
+            b'R' | b'Q' => {
+                if self.eat(b'L') {
+                    some!(self.base62());
+                }
+                let mutability = if c == b'Q' { "mut " } else { "" };
+                format!("&{}{}", mutability, some!(self.ty()))
+            }
+            b'P' => format!("*const {}", some!(self.ty())),
+            b'O' => format!("*mut {}", some!(self.ty())),
+            b'F' => {
+                if self.eat(b'G') {
+                    some!(self.base62());
+                }
+                let unsafety = if self.eat(b'U') { "unsafe " } else { "" };
+                let abi = if self.eat(b'K') {
+                    if self.eat(b'C') {
+                        "extern \"C\" ".to_string()
+                    } else {
+                        format!("extern \"{}\" ",
+                                some!(self.ident()).replace('_', "-"))
+                    }
+                } else {
+                    String::new()
+                };
+                let mut params = vec!();
+                while !self.eat(b'E') {
+                    if self.pos >= self.s.len() {
+                        return None;
+                    }
+                    params.push(some!(self.ty()));
+                }
+                let ret = some!(self.ty());
+                format!("{}{}fn({}){}", unsafety, abi, params.join(", "),
+                        if ret == "()" { String::new() }
+                        else { format!(" -> {}", ret) })
+            }
+            b'D' => {
+                if self.eat(b'G') {
+                    some!(self.base62());
+                }
+                let mut traits = vec!();
+                while !self.eat(b'E') {
+                    if self.pos >= self.s.len() {
+                        return None;
+                    }
+                    let mut t = some!(self.path(false));
+                    let mut assoc = vec!();
+                    while self.eat(b'p') {
+                        let name = some!(self.ident());
+                        assoc.push(format!("{} = {}", name, some!(self.ty())));
+                    }
+                    if !assoc.is_empty() {
+                        if t.ends_with('>') {
+                            t.pop();
+                            t = format!("{}, {}>", t, assoc.join(", "));
+                        } else {
+                            t = format!("{}<{}>", t, assoc.join(", "));
+                        }
+                    }
+                    traits.push(t);
+                }
+                // The lifetime bound.
+                if !self.eat(b'L') {
+                    return None;
+                }
+                some!(self.base62());
+                format!("dyn {}", traits.join(" + "))
+            }
+            b'B' => some!(self.backref(|p| p.ty())),
+            _ => {
+                self.pos -= 1;
+                some!(self.path(false))
+            }
+        };
+        self.depth -= 1;
+        return Some(r);
+    }
+}
+
+// A C++ type, which is kept structured to print declarators like
+// "void (*)(int)".
+#[derive(Clone, Debug)]
+enum Node {
+    Name(String),
+    Qual(Box<Node>, &'static str),
+    Pointer(Box<Node>),
+    Ref(Box<Node>, &'static str),
+    Func(Box<Node>, Vec<Node>, String),
+    Array(Box<Node>, String),
+    PtrMem(Box<Node>, Box<Node>),
+    Pack(Box<Node>),
+    // Template arguments of a parameter pack.
+    ArgPack(Vec<Node>),
+}
+
+fn print(n: &Node) -> String {
+    let (l, r) = split(n);
+    return l + &r;
+}
+
+// Prints a list of types, expanding parameter packs.
+fn print_list(nodes: &[Node]) -> String {
+    let mut r = vec!();
+    for n in nodes {
+        match *n {
+            Node::Pack(ref t) => {
+                match find_pack(t) {
+                    Some(args) => {
+                        for arg in args {
+                            r.push(print(&replace_pack(t, arg)));
+                        }
+                    }
+                    None => r.push(print(t) + "..."),
+                }
+            }
+            Node::ArgPack(ref args) => {
+                r.extend(args.iter().map(print).filter(|a| !a.is_empty()));
+            }
+            _ => r.push(print(n)),
+        }
+    }
+    return r.join(", ");
+}
+
+fn print_params(params: &[Node]) -> String {
+    if params.len() == 1 {
+        if let Node::Name(ref n) = params[0] {
+            if n == "void" {
+                return String::new();
+            }
+        }
+    }
+    return print_list(params);
+}
+
+// Finds template arguments of a parameter pack in `n`.
+fn find_pack(n: &Node) -> Option<&Vec<Node>> {
+    match *n {
+        Node::ArgPack(ref args) => Some(args),
+        Node::Qual(ref t, _) | Node::Pointer(ref t) | Node::Ref(ref t, _) |
+        Node::Array(ref t, _) => find_pack(t),
+        Node::PtrMem(ref c, ref t) => find_pack(c).or_else(|| find_pack(t)),
+        Node::Func(ref ret, ref params, _) => {
+            find_pack(ret)
+                .or_else(|| params.iter().filter_map(find_pack).next())
+        }
+        Node::Name(_) | Node::Pack(_) => None,
+    }
+}
+
+// Replaces the parameter pack in `n` with one of its arguments.
+fn replace_pack(n: &Node, arg: &Node) -> Node {
+    let f = |t: &Node| Box::new(replace_pack(t, arg));
+    match *n {
+        Node::ArgPack(_) => arg.clone(),
+        Node::Qual(ref t, q) => Node::Qual(f(t), q),
+        Node::Pointer(ref t) => Node::Pointer(f(t)),
+        Node::Ref(ref t, r) => Node::Ref(f(t), r),
+        Node::Array(ref t, ref d) => Node::Array(f(t), d.clone()),
+        Node::PtrMem(ref c, ref t) => Node::PtrMem(f(c), f(t)),
+        Node::Func(ref ret, ref params, ref s) => {
+            Node::Func(f(ret), params.iter().map(|p| replace_pack(p, arg))
+                       .collect(), s.clone())
+        }
+        Node::Name(_) | Node::Pack(_) => n.clone(),
+    }
+}
+
+// Splits the printed form of `n` into the parts which go left and right of
+// a declarator.
+fn split(n: &Node) -> (String, String) {
+    match *n {
+        Node::Name(ref s) => (s.clone(), String::new()),
+        Node::Qual(ref t, q) => {
+            let (l, r) = split(t);
+            match **t {
+                Node::Func(..) => (l, r + q),
+                Node::Qual(_, q2) if q2 == q => (l, r),
+                // Qualifiers of an array apply to its elements.
+                Node::Array(ref e, ref dim) => {
+                    split(&Node::Array(Box::new(Node::Qual(e.clone(), q)),
+                                       dim.clone()))
+                }
+                _ => (l + q, r),
+            }
+        }
+        Node::Pointer(ref t) => wrap(t, "*"),
+        Node::Ref(ref t, r) => match **t {
+            // Reference collapsing, e.g. T&& with T = int& is int&.
+            Node::Ref(ref u, r2) => {
+                let r = if r == "&&" && r2 == "&&" { "&&" } else { "&" };
+                split(&Node::Ref(u.clone(), r))
+            }
+            _ => wrap(t, r),
+        },
+        Node::PtrMem(ref class, ref t) => {
+            let tok = format!("{}::*", print(class));
+            if is_declarator(t) {
+                wrap(t, &tok)
+            } else {
+                let (l, r) = split(t);
+                (format!("{} {}", l, tok), r)
+            }
+        }
+        Node::Func(ref ret, ref params, ref suffix) => {
+            // The return type wraps the declarator, e.g. int (*f(long))(char)
+            // for a function returning a function pointer.
+            let (l, r) = split(ret);
+            let sep = if r.is_empty() { " " } else { "" };
+            (l + sep, format!("({}){}{}", print_params(params), suffix, r))
+        }
+        Node::Array(ref t, ref dim) => {
+            let (l, r) = split(t);
+            let r = if r.starts_with(' ') { &r[1..] } else { &r[..] };
+            (l, format!(" [{}]{}", dim, r))
+        }
+        Node::Pack(_) | Node::ArgPack(_) => {
+            (print_list(&[n.clone()]), String::new())
+        }
+    }
+}
+
+// Returns the unqualified name of `name` without template arguments and
+// ABI tags, e.g. "vector" for "std::vector<int>".
+fn last_component(name: &str) -> &str {
+    let mut depth = 0;
+    let mut end = name.len();
+    for (i, c) in name.char_indices().rev() {
+        match c {
+            '>' | ']' => depth += 1,
+            '<' | '[' => {
+                depth -= 1;
+                if depth == 0 {
+                    end = i;
+                }
+            }
+            ':' if depth == 0 => return &name[i + 1..end],
+            _ => {}
+        }
+    }
+    return &name[..end];
+}
+
+// Appends template arguments, keeping "operator<" apart from them.
+fn with_args(name: &str, args: &str) -> String {
+    let sep = if name.ends_with('<') { " " } else { "" };
+    return format!("{}{}{}", name, sep, args);
+}
+
+// Whether `t` is printed around a declarator, like functions and arrays.
+fn is_declarator(t: &Node) -> bool {
+    match *t {
+        Node::Func(..) | Node::Array(..) => true,
+        Node::Qual(ref t, _) => is_declarator(t),
+        _ => false,
+    }
+}
+
+fn wrap(t: &Node, tok: &str) -> (String, String) {
+    let (l, r) = split(t);
+    if !is_declarator(t) {
+        return (l + tok, r);
+    }
+    // No space inside an outer declarator, e.g. void (*(*)(int))(char).
+    let open = l.matches('(').count() > l.matches(')').count();
+    let sep = if l.ends_with(' ') || open { "" } else { " " };
+    return (format!("{}{}({}", l, sep, tok), format!("){}", r));
+}
+
+const OPERATORS: [(&'static str, &'static str); 49] = [
+    ("nw", "new"), ("na", "new[]"), ("dl", "delete"), ("da", "delete[]"),
+    ("ps", "+"), ("ng", "-"), ("ad", "&"), ("de", "*"), ("co", "~"),
+    ("pl", "+"), ("mi", "-"), ("ml", "*"), ("dv", "/"), ("rm", "%"),
+    ("an", "&"), ("or", "|"), ("eo", "^"), ("aS", "="), ("pL", "+="),
+    ("mI", "-="), ("mL", "*="), ("dV", "/="), ("rM", "%="), ("aN", "&="),
+    ("oR", "|="), ("eO", "^="), ("ls", "<<"), ("rs", ">>"), ("lS", "<<="),
+    ("rS", ">>="), ("eq", "=="), ("ne", "!="), ("lt", "<"), ("gt", ">"),
+    ("le", "<="), ("ge", ">="), ("ss", "<=>"), ("nt", "!"), ("aa", "&&"),
+    ("oo", "||"), ("pp", "++"), ("mm", "--"), ("cm", ","), ("pm", "->*"),
+    ("pt", "->"), ("cl", "()"), ("ix", "[]"), ("qu", "?"), ("aw", "co_await"),
+];
+
+const BUILTINS: [(u8, &'static str); 21] = [
+    (b'v', "void"), (b'w', "wchar_t"), (b'b', "bool"), (b'c', "char"),
+    (b'a', "signed char"), (b'h', "unsigned char"), (b's', "short"),
+    (b't', "unsigned short"), (b'i', "int"), (b'j', "unsigned int"),
+    (b'l', "long"), (b'm', "unsigned long"), (b'x', "long long"),
+    (b'y', "unsigned long long"), (b'n', "__int128"),
+    (b'o', "unsigned __int128"), (b'f', "float"), (b'd', "double"),
+    (b'e', "long double"), (b'g', "__float128"), (b'z', "..."),
+];
+
+// The result of parsing a <name>.
+struct Name {
+    name: String,
+    // Whether the name ends with template arguments, in which case a
+    // function has its return type mangled.
+    is_template: bool,
+    // Constructors, destructors and conversions have no return type.
+    no_return: bool,
+    // Qualifiers of a member function, e.g. " const".
+    suffix: String,
+}
+
+struct Itanium<'a> {
+    s: &'a [u8],
+    pos: usize,
+    subs: Vec<Node>,
+    // Substitutions which are template parameters, by their indices. They
+    // refer to the template arguments at the point of use.
+    param_subs: std::collections::HashMap<usize, usize>,
+    template_args: Vec<Node>,
+    depth: usize,
+}
+
+impl<'a> Itanium<'a> {
+    fn new(s: &'a str) -> Self {
+        Itanium {
+            s: s.as_bytes(),
+            pos: 0,
+            subs: vec!(),
+            param_subs: std::collections::HashMap::new(),
+            template_args: vec!(),
+            depth: 0,
+        }
+    }
+
+    fn peek(&self) -> u8 {
+        if self.pos < self.s.len() { self.s[self.pos] } else { 0 }
+    }
+
+    fn peek_at(&self, off: usize) -> u8 {
+        if self.pos + off < self.s.len() { self.s[self.pos + off] } else { 0 }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == c {
+            self.pos += 1;
+            return true;
+        }
+        return false;
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.s.len() || self.peek() == b'.'
+    }
+
+    fn enter(&mut self) -> Option<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return None;
+        }
+        return Some(());
+    }
+
+    fn parse(&mut self) -> Option<(String, String)> {
+        let (name, mut full) = some!(self.encoding(true, true));
+        if self.pos < self.s.len() {
+            // Suffixes of clones made by the compiler, e.g. ".cold".
+            if self.peek() != b'.' {
+                return None;
+            }
+            let rest = some!(std::str::from_utf8(&self.s[self.pos..]).ok());
+            full += &format!(" [clone {}]", rest);
+        }
+        return Some((name, full));
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let neg = self.eat(b'n');
+        let start = self.pos;
+        while self.peek().is_ascii_digit() {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return None;
+        }
+        let digits = some!(std::str::from_utf8(&self.s[start..self.pos]).ok());
+        let v: i64 = some!(digits.parse().ok());
+        return Some(if neg { -v } else { v });
+    }
+
+    // <seq-id> _, where an empty <seq-id> is 0.
+    fn seq_id(&mut self) -> Option<usize> {
+        if self.eat(b'_') {
+            return Some(0);
+        }
+        let mut v: usize = 0;
+        loop {
+            let c = self.peek();
+            self.pos += 1;
+            let d = match c {
+                b'0'..=b'9' => c - b'0',
+                b'A'..=b'Z' => c - b'A' + 10,
+                b'_' => return v.checked_add(1),
+                _ => return None,
+            };
+            v = some!(v.checked_mul(36)
+                      .and_then(|v| v.checked_add(d as usize)));
+        }
+    }
+
+    fn encoding(&mut self, is_top: bool, with_return: bool)
+                -> Option<(String, String)> {
+        some!(self.enter());
+        if self.peek() == b'T' || self.peek() == b'G' {
+            let s = some!(self.special_name());
+            self.depth -= 1;
+            return Some((s.clone(), s));
+        }
+        let saved_args = self.template_args.clone();
+        let name = some!(self.name(true));
+        if self.at_end() || self.peek() == b'E' {
+            if !is_top {
+                self.template_args = saved_args;
+            }
+            self.depth -= 1;
+            return Some((name.name.clone(), name.name));
+        }
+        let ret = if name.is_template && !name.no_return {
+            Some(some!(self.ty()))
+        } else {
+            None
+        };
+        let mut params = vec!();
+        while !self.at_end() && self.peek() != b'E' {
+            params.push(some!(self.ty()));
+        }
+        if params.is_empty() {
+            return None;
+        }
+        let full = format!("{}{}({}){}",
+                           ret.filter(|_| with_return)
+                           .map(|r| print(&r) + " ")
+                           .unwrap_or(String::new()),
+                           name.name, print_params(&params), name.suffix);
+        if !is_top {
+            self.template_args = saved_args;
+        }
+        self.depth -= 1;
+        return Some((name.name, full));
+    }
+
+    fn special_name(&mut self) -> Option<String> {
+        let (c0, c1) = (self.peek(), self.peek_at(1));
+        self.pos += 2;
+        let r = match (c0, c1) {
+            (b'T', b'V') => format!("vtable for {}", print(&some!(self.ty()))),
+            (b'T', b'T') => format!("VTT for {}", print(&some!(self.ty()))),
+            (b'T', b'I') => {
+                format!("typeinfo for {}", print(&some!(self.ty())))
+            }
+            (b'T', b'S') => {
+                format!("typeinfo name for {}", print(&some!(self.ty())))
+            }
+            (b'T', b'h') => {
+                some!(self.call_offset(b'h'));
+                format!("non-virtual thunk to {}",
+                        some!(self.encoding(false, true)).1)
+            }
+            (b'T', b'v') => {
+                some!(self.call_offset(b'v'));
+                format!("virtual thunk to {}",
+                        some!(self.encoding(false, true)).1)
+            }
+            (b'T', b'c') => {
+                let c = self.peek();
+                self.pos += 1;
+                some!(self.call_offset(c));
+                let c = self.peek();
+                self.pos += 1;
+                some!(self.call_offset(c));
+                format!("covariant return thunk to {}",
+                        some!(self.encoding(false, true)).1)
+            }
+            (b'T', b'H') => {
+                format!("TLS init function for {}",
+                        some!(self.name(false)).name)
+            }
+            (b'T', b'W') => {
+                format!("TLS wrapper function for {}",
+                        some!(self.name(false)).name)
+            }
+            (b'G', b'V') => {
+                format!("guard variable for {}", some!(self.name(false)).name)
+            }
+            (b'G', b'R') => {
+                let name = some!(self.name(false)).name;
+                let seq = if self.at_end() { 0 } else { some!(self.seq_id()) };
+                format!("reference temporary #{} for {}", seq, name)
+            }
+            (b'G', b'T') => {
+                self.pos += 1;
+                format!("transaction clone for {}",
+                        some!(self.encoding(false, true)).1)
+            }
+            _ => return None,
+        };
+        return Some(r);
+    }
+
+    // The letter of a call offset has been consumed.
+    fn call_offset(&mut self, c: u8) -> Option<()> {
+        match c {
+            b'h' => {
+                some!(self.number());
+            }
+            b'v' => {
+                some!(self.number());
+                if !self.eat(b'_') {
+                    return None;
+                }
+                some!(self.number());
+            }
+            _ => return None,
+        }
+        if !self.eat(b'_') {
+            return None;
+        }
+        return Some(());
+    }
+
+    // `is_encoding` tells whether template arguments of the name are the
+    // ones referred by template parameters.
+    fn name(&mut self, is_encoding: bool) -> Option<Name> {
+        some!(self.enter());
+        let r = match self.peek() {
+            b'N' => some!(self.nested_name(is_encoding)),
+            b'Z' => some!(self.local_name()),
+            b'S' if self.peek_at(1) != b't' => {
+                let sub = some!(self.substitution());
+                if self.peek() != b'I' {
+                    return None;
+                }
+                let args = some!(self.template_args(is_encoding));
+                Name {
+                    name: print(&sub) + &args,
+                    is_template: true,
+                    no_return: false,
+                    suffix: String::new(),
+                }
+            }
+            _ => {
+                let mut name = if self.peek() == b'S' {
+                    self.pos += 2;
+                    let (name, _) = some!(self.unqualified_name(""));
+                    format!("std::{}", name)
+                } else {
+                    let (name, _) = some!(self.unqualified_name(""));
+                    name
+                };
+                let is_template = self.peek() == b'I';
+                if is_template {
+                    self.subs.push(Node::Name(name.clone()));
+                    let args = some!(self.template_args(is_encoding));
+                    name = with_args(&name, &args);
+                }
+                Name {
+                    name: name,
+                    is_template: is_template,
+                    no_return: false,
+                    suffix: String::new(),
+                }
+            }
+        };
+        self.depth -= 1;
+        return Some(r);
+    }
+
+    fn cv_qualifiers(&mut self) -> String {
+        let mut r = String::new();
+        if self.eat(b'r') {
+            r += " restrict";
+        }
+        if self.eat(b'V') {
+            r += " volatile";
+        }
+        if self.eat(b'K') {
+            r += " const";
+        }
+        return r;
+    }
+
+    fn nested_name(&mut self, is_encoding: bool) -> Option<Name> {
+        self.pos += 1;
+        let mut suffix = self.cv_qualifiers();
+        if self.eat(b'R') {
+            suffix += " &";
+        } else if self.eat(b'O') {
+            suffix += " &&";
+        }
+        let mut prefix = String::new();
+        let mut is_template = false;
+        let mut no_return = false;
+        while !self.eat(b'E') {
+            if self.pos >= self.s.len() {
+                return None;
+            }
+            match self.peek() {
+                b'S' if self.peek_at(1) == b't' => {
+                    self.pos += 2;
+                    prefix = "std".to_string();
+                    continue;
+                }
+                b'S' => {
+                    prefix = print(&some!(self.substitution()));
+                    continue;
+                }
+                b'I' => {
+                    if prefix.is_empty() {
+                        return None;
+                    }
+                    let args = some!(self.template_args(is_encoding));
+                    prefix = with_args(&prefix, &args);
+                    is_template = true;
+                }
+                b'T' => {
+                    prefix = print(&some!(self.template_param()).1);
+                    is_template = false;
+                }
+                b'M' => {
+                    // A data member prefix of a closure.
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {
+                    let (name, ctor) = {
+                        let class = last_component(&prefix);
+                        some!(self.unqualified_name(class))
+                    };
+                    prefix = if prefix.is_empty() {
+                        name
+                    } else {
+                        format!("{}::{}", prefix, name)
+                    };
+                    is_template = false;
+                    no_return = ctor;
+                }
+            }
+            if self.peek() != b'E' {
+                self.subs.push(Node::Name(prefix.clone()));
+            }
+        }
+        return Some(Name {
+            name: prefix,
+            is_template: is_template,
+            no_return: no_return,
+            suffix: suffix,
+        });
+    }
+
+    fn local_name(&mut self) -> Option<Name> {
+        self.pos += 1;
+        // Like c++filt, omit the return type of the enclosing function.
+        let (_, func) = some!(self.encoding(false, false));
+        if !self.eat(b'E') {
+            return None;
+        }
+        let name = if self.eat(b's') {
+            format!("{}::string literal", func)
+        } else {
+            let name = some!(self.name(false));
+            format!("{}::{}", func, name.name)
+        };
+        // A discriminator.
+        if self.eat(b'_') {
+            if self.eat(b'_') {
+                some!(self.number());
+                if !self.eat(b'_') {
+                    return None;
+                }
+            } else if self.peek().is_ascii_digit() {
+                self.pos += 1;
+            } else {
+                return None;
+            }
+        }
+        return Some(Name {
+            name: name,
+            is_template: false,
+            no_return: false,
+            suffix: String::new(),
+        });
+    }
+
+    fn source_name(&mut self) -> Option<String> {
+        let len = some!(self.number()) as usize;
+        if len > self.s.len() - self.pos {
+            return None;
+        }
+        let name = some!(std::str::from_utf8(&self.s[self.pos..self.pos + len])
+                         .ok());
+        self.pos += len;
+        if name.starts_with("_GLOBAL__N") {
+            return Some("(anonymous namespace)".to_string());
+        }
+        return Some(name.to_string());
+    }
+
+    // Returns the name and whether it is a constructor, a destructor or a
+    // conversion. `class` is the enclosing class for constructors.
+    fn unqualified_name(&mut self, class: &str) -> Option<(String, bool)> {
+        let (c0, c1) = (self.peek(), self.peek_at(1));
+        let (mut name, special) = match c0 {
+            b'0'..=b'9' => (some!(self.source_name()), false),
+            b'L' => {
+                // Internal linkage.
+                self.pos += 1;
+                (some!(self.source_name()), false)
+            }
+            b'C' if c1 == b'I' || (c1 >= b'1' && c1 <= b'5') => {
+                self.pos += 2;
+                if c1 == b'I' {
+                    self.pos += 1;
+                    some!(self.ty());
+                }
+                if class.is_empty() {
+                    return None;
+                }
+                (class.to_string(), true)
+            }
+            b'D' if c1 == b'0' || c1 == b'1' || c1 == b'2' ||
+                c1 == b'4' || c1 == b'5' => {
+                self.pos += 2;
+                if class.is_empty() {
+                    return None;
+                }
+                (format!("~{}", class), true)
+            }
+            b'U' if c1 == b't' => {
+                self.pos += 2;
+                // "_" is the first one and "<n>_" is the (n + 2)th one.
+                let n = if self.peek() == b'_' {
+                    0
+                } else {
+                    some!(self.number()) + 1
+                };
+                if !self.eat(b'_') {
+                    return None;
+                }
+                (format!("{{unnamed type#{}}}", n + 1), false)
+            }
+            b'U' if c1 == b'l' => {
+                self.pos += 2;
+                let mut params = vec!();
+                while !self.eat(b'E') {
+                    if self.pos >= self.s.len() {
+                        return None;
+                    }
+                    params.push(some!(self.ty()));
+                }
+                // "_" is the first one and "<n>_" is the (n + 2)th one.
+                let n = if self.peek() == b'_' {
+                    0
+                } else {
+                    some!(self.number()) + 1
+                };
+                if !self.eat(b'_') {
+                    return None;
+                }
+                (format!("{{lambda({})#{}}}", print_params(&params), n + 1),
+                 false)
+            }
+            b'c' if c1 == b'v' => {
+                self.pos += 2;
+                (format!("operator {}", print(&some!(self.ty()))), true)
+            }
+            b'l' if c1 == b'i' => {
+                self.pos += 2;
+                (format!("operator\"\" {}", some!(self.source_name())), false)
+            }
+            b'a'..=b'z' => {
+                let code = [c0, c1];
+                let code = some!(std::str::from_utf8(&code).ok());
+                let op = some!(OPERATORS.iter().find(|o| o.0 == code)).1;
+                self.pos += 2;
+                let sep = if op.as_bytes()[0].is_ascii_alphabetic() {
+                    " "
+                } else {
+                    ""
+                };
+                (format!("operator{}{}", sep, op), false)
+            }
+            _ => return None,
+        };
+        // ABI tags.
+        while self.eat(b'B') {
+            name += &format!("[abi:{}]", some!(self.source_name()));
+        }
+        return Some((name, special));
+    }
+
+    fn substitution(&mut self) -> Option<Node> {
+        self.pos += 1;
+        let name = match self.peek() {
+            b'a' => "std::allocator",
+            b'b' => "std::basic_string",
+            b's' => "std::basic_string<char, std::char_traits<char>, \
+                     std::allocator<char>>",
+            b'i' => "std::basic_istream<char, std::char_traits<char>>",
+            b'o' => "std::basic_ostream<char, std::char_traits<char>>",
+            b'd' => "std::basic_iostream<char, std::char_traits<char>>",
+            _ => "",
+        };
+        if !name.is_empty() {
+            self.pos += 1;
+            return Some(Node::Name(name.to_string()));
+        }
+        let id = some!(self.seq_id());
+        if let Some(&param) = self.param_subs.get(&id) {
+            return self.template_args.get(param).cloned();
+        }
+        return self.subs.get(id).cloned();
+    }
+
+    fn template_param(&mut self) -> Option<(usize, Node)> {
+        self.pos += 1;
+        let id = some!(self.seq_id());
+        return self.template_args.get(id).cloned().map(|n| (id, n));
+    }
+
+    fn template_args(&mut self, is_encoding: bool) -> Option<String> {
+        self.pos += 1;
+        let mut args = vec!();
+        while !self.eat(b'E') {
+            if self.pos >= self.s.len() {
+                return None;
+            }
+            args.push(some!(self.template_arg()));
+        }
+        if is_encoding {
+            self.template_args = args.clone();
+        }
+        return Some(format!("<{}>", print_list(&args)));
+    }
+
+    fn template_arg(&mut self) -> Option<Node> {
+        match self.peek() {
+            b'L' => {
+                self.pos += 1;
+                let r = if self.peek() == b'_' && self.peek_at(1) == b'Z' {
+                    self.pos += 2;
+                    some!(self.encoding(false, true)).1
+                } else {
+                    some!(self.literal())
+                };
+                if !self.eat(b'E') {
+                    return None;
+                }
+                Some(Node::Name(r))
+            }
+            b'J' => {
+                self.pos += 1;
+                let mut args = vec!();
+                while !self.eat(b'E') {
+                    if self.pos >= self.s.len() {
+                        return None;
+                    }
+                    args.push(some!(self.template_arg()));
+                }
+                Some(Node::ArgPack(args))
+            }
+            // Expressions are not supported.
+            b'X' => None,
+            _ => self.ty(),
+        }
+    }
+
+    fn literal(&mut self) -> Option<String> {
+        let ty = some!(self.ty());
+        let v = some!(self.number());
+        let ty = print(&ty);
+        let r = match ty.as_str() {
+            "bool" => (if v != 0 { "true" } else { "false" }).to_string(),
+            "int" => format!("{}", v),
+            "unsigned int" => format!("{}u", v),
+            "long" => format!("{}l", v),
+            "unsigned long" => format!("{}ul", v),
+            "long long" => format!("{}ll", v),
+            "unsigned long long" => format!("{}ull", v),
+            _ => format!("({}){}", ty, v),
+        };
+        return Some(r);
+    }
+
+    fn ty(&mut self) -> Option<Node> {
+        let c = self.peek();
+        if let Some(b) = BUILTINS.iter().find(|b| b.0 == c) {
+            self.pos += 1;
+            return Some(Node::Name(b.1.to_string()));
+        }
+        some!(self.enter());
+        let node = match c {
+            b'u' => {
+                self.pos += 1;
+                Node::Name(some!(self.source_name()))
+            }
+            b'D' => {
+                let name = match self.peek_at(1) {
+                    b'n' => "decltype(nullptr)",
+                    b'i' => "char32_t",
+                    b's' => "char16_t",
+                    b'u' => "char8_t",
+                    b'a' => "auto",
+                    b'c' => "decltype(auto)",
+                    b'f' => "decimal32",
+                    b'd' => "decimal64",
+                    b'e' => "decimal128",
+                    b'h' => "half",
+                    b'p' => "",
+                    _ => return None,
+                };
+                self.pos += 2;
+                if !name.is_empty() {
+                    self.depth -= 1;
+                    return Some(Node::Name(name.to_string()));
+                }
+                Node::Pack(Box::new(some!(self.ty())))
+            }
+            b'r' | b'V' | b'K' => {
+                let q = self.cv_qualifiers();
+                let t = some!(self.ty());
+                let q = match q.as_str() {
+                    " restrict" => " restrict",
+                    " volatile" => " volatile",
+                    " const" => " const",
+                    " volatile const" => " const volatile",
+                    " restrict const" => " const restrict",
+                    " restrict volatile" => " volatile restrict",
+                    _ => " const volatile restrict",
+                };
+                Node::Qual(Box::new(t), q)
+            }
+            b'P' => {
+                self.pos += 1;
+                Node::Pointer(Box::new(some!(self.ty())))
+            }
+            b'R' => {
+                self.pos += 1;
+                Node::Ref(Box::new(some!(self.ty())), "&")
+            }
+            b'O' => {
+                self.pos += 1;
+                Node::Ref(Box::new(some!(self.ty())), "&&")
+            }
+            b'C' => {
+                self.pos += 1;
+                Node::Qual(Box::new(some!(self.ty())), " _Complex")
+            }
+            b'G' => {
+                self.pos += 1;
+                Node::Qual(Box::new(some!(self.ty())), " _Imaginary")
+            }
+            b'F' => {
+                self.pos += 1;
+                self.eat(b'Y');
+                let ret = some!(self.ty());
+                let mut params = vec!();
+                let mut suffix = "";
+                while !self.eat(b'E') {
+                    if self.pos >= self.s.len() {
+                        return None;
+                    }
+                    if self.peek_at(1) == b'E' {
+                        if self.eat(b'R') {
+                            suffix = " &";
+                            continue;
+                        }
+                        if self.eat(b'O') {
+                            suffix = " &&";
+                            continue;
+                        }
+                    }
+                    params.push(some!(self.ty()));
+                }
+                Node::Func(Box::new(ret), params, suffix.to_string())
+            }
+            b'A' => {
+                self.pos += 1;
+                let dim = if self.peek() == b'_' {
+                    String::new()
+                } else {
+                    format!("{}", some!(self.number()))
+                };
+                if !self.eat(b'_') {
+                    return None;
+                }
+                Node::Array(Box::new(some!(self.ty())), dim)
+            }
+            b'M' => {
+                self.pos += 1;
+                let class = some!(self.ty());
+                Node::PtrMem(Box::new(class), Box::new(some!(self.ty())))
+            }
+            b'T' => {
+                let (id, param) = some!(self.template_param());
+                self.param_subs.insert(self.subs.len(), id);
+                self.subs.push(param.clone());
+                if self.peek() != b'I' {
+                    self.depth -= 1;
+                    return Some(param);
+                }
+                let args = some!(self.template_args(false));
+                Node::Name(print(&param) + &args)
+            }
+            b'S' if self.peek_at(1) != b't' => {
+                let sub = some!(self.substitution());
+                if self.peek() == b'I' {
+                    let args = some!(self.template_args(false));
+                    Node::Name(print(&sub) + &args)
+                } else {
+                    // Substitutions are not candidates again.
+                    self.depth -= 1;
+                    return Some(sub);
+                }
+            }
+            b'S' | b'N' | b'Z' | b'0'..=b'9' => {
+                Node::Name(some!(self.name(false)).name)
+            }
+            _ => return None,
+        };
+        self.subs.push(node.clone());
+        self.depth -= 1;
+        return Some(node);
+    }
+}
+
+#[cfg(test)]
+fn check(mangled: &str, expected: &str) {
+    assert_eq!(Some(expected.to_string()), demangle(mangled), "{}", mangled);
+}
+
+#[test]
+fn test_cxx() {
+    check("_Z3fooi", "foo(int)");
+    check("_Z3foov", "foo()");
+    check("_ZN2ns3Foo3barEi", "ns::Foo::bar(int)");
+    check("_ZNK2ns3Foo3barEPKc", "ns::Foo::bar(char const*) const");
+    check("_ZN3FooC1Ev", "Foo::Foo()");
+    check("_ZN3FooD2Ev", "Foo::~Foo()");
+    check("_ZN3FooplERKS_", "Foo::operator+(Foo const&)");
+    check("_Z3maxIiET_S0_S0_", "int max<int>(int, int)");
+    check("_Z5applyPFviEi", "apply(void (*)(int), int)");
+    check("_Z1fRA3_i", "f(int (&) [3])");
+    check("_Z1fM3FooFivE", "f(int (Foo::*)())");
+    check("_ZNSt6vectorIiSaIiEE9push_backEOi",
+          "std::vector<int, std::allocator<int>>::push_back(int&&)");
+    check("_ZN12_GLOBAL__N_14funcEv", "(anonymous namespace)::func()");
+    check("_ZZ4mainE5count", "main::count");
+    check("_ZTV3Foo", "vtable for Foo");
+    check("_ZGVZ4mainE1x", "guard variable for main::x");
+    check("_Z3fooi.cold", "foo(int) [clone .cold]");
+    check("_ZN3Foo3getB5cxx11Ev", "Foo::get[abi:cxx11]()");
+    check("_Z1fIJidEEvDpT_", "void f<int, double>(int, double)");
+    assert_eq!(Some("ns::Foo::bar".to_string()),
+               demangle_name("_ZNK2ns3Foo3barEPKc"));
+    assert_eq!(None, demangle("main"));
+    assert_eq!(None, demangle("_Z"));
+    assert_eq!(None, demangle("_ZN3foo"));
+}
+
+#[test]
+fn test_rust() {
+    check("_ZN4core3fmt5write17h0123456789abcdefE", "core::fmt::write");
+    check("_ZN1m4main28_$u7b$$u7b$closure$u7d$$u7d$17h0dba90a458fa0a8dE",
+          "m::main::{{closure}}");
+    check("_ZN60_$LT$alloc..vec..Vec$LT$T$GT$$u20$as$u20$core..ops..Drop$GT$\
+           4drop17h0123456789abcdefE",
+          "<alloc::vec::Vec<T> as core::ops::Drop>::drop");
+    check("_RNvCskK7mfDs1mzF_1m4main", "m::main");
+    check("_RNCNvCskK7mfDs1mzF_1m4main0B3_", "m::main::{closure#0}");
+    check("_RNvMNtCskK7mfDs1mzF_1m6moduleNtB2_3Bar6method",
+          "<m::module::Bar>::method");
+    check("_RINvNtCskK7mfDs1mzF_1m6module4funchEB4_",
+          "m::module::func::<u8>");
+    // Lengths which overflow.
+    assert_eq!(None, demangle("_ZN18446744073709551615aE"));
+    assert_eq!(None, demangle("_ZN20aE"));
+    assert_eq!(None, demangle("_RNvC18446744073709551615a1b"));
+    assert_eq!(None, demangle("_Z9223372036854775807a"));
+}
+
+#[test]
+fn test_normalize() {
+    assert_eq!("Foo::bar(int,char*)", normalize(" Foo::bar( int, char * )"));
+    assert_eq!("f(unsigned int)", normalize("f(unsigned  int)"));
+}
@@ -0,0 +1,1441 @@
+// Decodes x86-64 instructions into the AT&T syntax of gdb and objdump.
+
+use std;
+
+macro_rules! some {
+    ($e:expr) => (match $e { Some(v) => v, None => return None })
+}
+
+// The longest x86 instruction.
+pub const MAX_INSN_LEN: usize = 15;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    // The destination of a relative branch, which ends the text.
+    Branch(u64),
+    // The address of a RIP-relative memory operand.
+    Memory(u64),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Insn {
+    pub len: usize,
+    pub text: String,
+    pub target: Option<Target>,
+}
+
+// Decodes the instruction at the beginning of `code`, which is at `addr`.
+// Unknown or truncated instructions are "(bad)" of a byte.
+pub fn decode(code: &[u8], addr: u64) -> Insn {
+    let code = &code[..std::cmp::min(code.len(), MAX_INSN_LEN)];
+    let mut d = Decoder::new(code, addr);
+    match d.insn() {
+        Some(text) => {
+            let next = addr.wrapping_add(d.pos as u64);
+            let target = match (d.branch, d.rip_disp) {
+                (Some(t), _) => Some(Target::Branch(t)),
+                (None, Some(disp)) => {
+                    Some(Target::Memory(next.wrapping_add(disp as u64)))
+                }
+                _ => None,
+            };
+            Insn { len: d.pos, text: text, target: target }
+        }
+        None => Insn { len: 1, text: "(bad)".to_string(), target: None },
+    }
+}
+
+const REGS64: [&'static str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi",
+    "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15",
+];
+const REGS32: [&'static str; 16] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi",
+    "r8d", "r9d", "r10d", "r11d", "r12d", "r13d", "r14d", "r15d",
+];
+const REGS16: [&'static str; 16] = [
+    "ax", "cx", "dx", "bx", "sp", "bp", "si", "di",
+    "r8w", "r9w", "r10w", "r11w", "r12w", "r13w", "r14w", "r15w",
+];
+const REGS8: [&'static str; 16] = [
+    "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil",
+    "r8b", "r9b", "r10b", "r11b", "r12b", "r13b", "r14b", "r15b",
+];
+// Byte registers without a REX prefix.
+const REGS8_LEGACY: [&'static str; 8] = [
+    "al", "cl", "dl", "bl", "ah", "ch", "dh", "bh",
+];
+
+const ALU_OPS: [&'static str; 8] = [
+    "add", "or", "adc", "sbb", "and", "sub", "xor", "cmp",
+];
+const SHIFT_OPS: [&'static str; 8] = [
+    "rol", "ror", "rcl", "rcr", "shl", "shr", "shl", "sar",
+];
+const CONDS: [&'static str; 16] = [
+    "o", "no", "b", "ae", "e", "ne", "be", "a",
+    "s", "ns", "p", "np", "l", "ge", "le", "g",
+];
+const SSE_CMP_PREDS: [&'static str; 8] = [
+    "eq", "lt", "le", "unord", "neq", "nlt", "nle", "ord",
+];
+
+// SSE instructions by their map (0x0f, 0x0f38 or 0x0f3a), opcode and
+// mandatory prefix (0, 0x66, 0xf3 or 0xf2). Operands are in the Intel
+// order:
+//   V: xmm in ModRM.reg, W: xmm or memory in ModRM.rm, U: xmm in ModRM.rm,
+//   H: xmm in VEX.vvvv (only for VEX), M: memory, G: general register,
+//   E: general register or memory, I: imm8, X: xmm or memory in ModRM.rm
+//   even with VEX.L, B: general register in VEX.vvvv, R: general register
+//   or memory which tells the operand size.
+// General registers are 64-bit with REX.W and 32-bit otherwise.
+const SSE_OPS: &'static [(u32, u8, u8, &'static str, &'static str)] = &[
+    (0x0f, 0x10, 0, "movups", "V,W"),
+    (0x0f, 0x10, 0x66, "movupd", "V,W"),
+    (0x0f, 0x10, 0xf3, "movss", "V,W"),
+    (0x0f, 0x10, 0xf2, "movsd", "V,W"),
+    (0x0f, 0x11, 0, "movups", "W,V"),
+    (0x0f, 0x11, 0x66, "movupd", "W,V"),
+    (0x0f, 0x11, 0xf3, "movss", "W,V"),
+    (0x0f, 0x11, 0xf2, "movsd", "W,V"),
+    (0x0f, 0x12, 0, "movlps", "V,H,W"),
+    (0x0f, 0x12, 0x66, "movlpd", "V,H,M"),
+    (0x0f, 0x12, 0xf2, "movddup", "V,W"),
+    (0x0f, 0x12, 0xf3, "movsldup", "V,W"),
+    (0x0f, 0x13, 0, "movlps", "M,V"),
+    (0x0f, 0x13, 0x66, "movlpd", "M,V"),
+    (0x0f, 0x14, 0, "unpcklps", "V,H,W"),
+    (0x0f, 0x14, 0x66, "unpcklpd", "V,H,W"),
+    (0x0f, 0x15, 0, "unpckhps", "V,H,W"),
+    (0x0f, 0x15, 0x66, "unpckhpd", "V,H,W"),
+    (0x0f, 0x16, 0, "movhps", "V,H,W"),
+    (0x0f, 0x16, 0x66, "movhpd", "V,H,M"),
+    (0x0f, 0x16, 0xf3, "movshdup", "V,W"),
+    (0x0f, 0x17, 0, "movhps", "M,V"),
+    (0x0f, 0x17, 0x66, "movhpd", "M,V"),
+    (0x0f, 0x28, 0, "movaps", "V,W"),
+    (0x0f, 0x28, 0x66, "movapd", "V,W"),
+    (0x0f, 0x29, 0, "movaps", "W,V"),
+    (0x0f, 0x29, 0x66, "movapd", "W,V"),
+    (0x0f, 0x2a, 0xf3, "cvtsi2ss", "V,H,E"),
+    (0x0f, 0x2a, 0xf2, "cvtsi2sd", "V,H,E"),
+    (0x0f, 0x2b, 0, "movntps", "M,V"),
+    (0x0f, 0x2b, 0x66, "movntpd", "M,V"),
+    (0x0f, 0x2c, 0xf3, "cvttss2si", "G,W"),
+    (0x0f, 0x2c, 0xf2, "cvttsd2si", "G,W"),
+    (0x0f, 0x2d, 0xf3, "cvtss2si", "G,W"),
+    (0x0f, 0x2d, 0xf2, "cvtsd2si", "G,W"),
+    (0x0f, 0x2e, 0, "ucomiss", "V,W"),
+    (0x0f, 0x2e, 0x66, "ucomisd", "V,W"),
+    (0x0f, 0x2f, 0, "comiss", "V,W"),
+    (0x0f, 0x2f, 0x66, "comisd", "V,W"),
+    (0x0f, 0x50, 0, "movmskps", "G,U"),
+    (0x0f, 0x50, 0x66, "movmskpd", "G,U"),
+    (0x0f, 0x51, 0, "sqrtps", "V,W"),
+    (0x0f, 0x51, 0x66, "sqrtpd", "V,W"),
+    (0x0f, 0x51, 0xf3, "sqrtss", "V,H,W"),
+    (0x0f, 0x51, 0xf2, "sqrtsd", "V,H,W"),
+    (0x0f, 0x54, 0, "andps", "V,H,W"),
+    (0x0f, 0x54, 0x66, "andpd", "V,H,W"),
+    (0x0f, 0x55, 0, "andnps", "V,H,W"),
+    (0x0f, 0x55, 0x66, "andnpd", "V,H,W"),
+    (0x0f, 0x56, 0, "orps", "V,H,W"),
+    (0x0f, 0x56, 0x66, "orpd", "V,H,W"),
+    (0x0f, 0x57, 0, "xorps", "V,H,W"),
+    (0x0f, 0x57, 0x66, "xorpd", "V,H,W"),
+    (0x0f, 0x58, 0, "addps", "V,H,W"),
+    (0x0f, 0x58, 0x66, "addpd", "V,H,W"),
+    (0x0f, 0x58, 0xf3, "addss", "V,H,W"),
+    (0x0f, 0x58, 0xf2, "addsd", "V,H,W"),
+    (0x0f, 0x59, 0, "mulps", "V,H,W"),
+    (0x0f, 0x59, 0x66, "mulpd", "V,H,W"),
+    (0x0f, 0x59, 0xf3, "mulss", "V,H,W"),
+    (0x0f, 0x59, 0xf2, "mulsd", "V,H,W"),
+    (0x0f, 0x5a, 0, "cvtps2pd", "V,W"),
+    (0x0f, 0x5a, 0x66, "cvtpd2ps", "V,W"),
+    (0x0f, 0x5a, 0xf3, "cvtss2sd", "V,H,W"),
+    (0x0f, 0x5a, 0xf2, "cvtsd2ss", "V,H,W"),
+    (0x0f, 0x5b, 0, "cvtdq2ps", "V,W"),
+    (0x0f, 0x5b, 0x66, "cvtps2dq", "V,W"),
+    (0x0f, 0x5b, 0xf3, "cvttps2dq", "V,W"),
+    (0x0f, 0x5c, 0, "subps", "V,H,W"),
+    (0x0f, 0x5c, 0x66, "subpd", "V,H,W"),
+    (0x0f, 0x5c, 0xf3, "subss", "V,H,W"),
+    (0x0f, 0x5c, 0xf2, "subsd", "V,H,W"),
+    (0x0f, 0x5d, 0, "minps", "V,H,W"),
+    (0x0f, 0x5d, 0x66, "minpd", "V,H,W"),
+    (0x0f, 0x5d, 0xf3, "minss", "V,H,W"),
+    (0x0f, 0x5d, 0xf2, "minsd", "V,H,W"),
+    (0x0f, 0x5e, 0, "divps", "V,H,W"),
+    (0x0f, 0x5e, 0x66, "divpd", "V,H,W"),
+    (0x0f, 0x5e, 0xf3, "divss", "V,H,W"),
+    (0x0f, 0x5e, 0xf2, "divsd", "V,H,W"),
+    (0x0f, 0x5f, 0, "maxps", "V,H,W"),
+    (0x0f, 0x5f, 0x66, "maxpd", "V,H,W"),
+    (0x0f, 0x5f, 0xf3, "maxss", "V,H,W"),
+    (0x0f, 0x5f, 0xf2, "maxsd", "V,H,W"),
+    (0x0f, 0x60, 0x66, "punpcklbw", "V,H,W"),
+    (0x0f, 0x61, 0x66, "punpcklwd", "V,H,W"),
+    (0x0f, 0x62, 0x66, "punpckldq", "V,H,W"),
+    (0x0f, 0x63, 0x66, "packsswb", "V,H,W"),
+    (0x0f, 0x64, 0x66, "pcmpgtb", "V,H,W"),
+    (0x0f, 0x65, 0x66, "pcmpgtw", "V,H,W"),
+    (0x0f, 0x66, 0x66, "pcmpgtd", "V,H,W"),
+    (0x0f, 0x67, 0x66, "packuswb", "V,H,W"),
+    (0x0f, 0x68, 0x66, "punpckhbw", "V,H,W"),
+    (0x0f, 0x69, 0x66, "punpckhwd", "V,H,W"),
+    (0x0f, 0x6a, 0x66, "punpckhdq", "V,H,W"),
+    (0x0f, 0x6b, 0x66, "packssdw", "V,H,W"),
+    (0x0f, 0x6c, 0x66, "punpcklqdq", "V,H,W"),
+    (0x0f, 0x6d, 0x66, "punpckhqdq", "V,H,W"),
+    (0x0f, 0x6e, 0x66, "movd", "V,E"),
+    (0x0f, 0x6f, 0x66, "movdqa", "V,W"),
+    (0x0f, 0x6f, 0xf3, "movdqu", "V,W"),
+    (0x0f, 0x70, 0x66, "pshufd", "V,W,I"),
+    (0x0f, 0x70, 0xf3, "pshufhw", "V,W,I"),
+    (0x0f, 0x70, 0xf2, "pshuflw", "V,W,I"),
+    (0x0f, 0x74, 0x66, "pcmpeqb", "V,H,W"),
+    (0x0f, 0x75, 0x66, "pcmpeqw", "V,H,W"),
+    (0x0f, 0x76, 0x66, "pcmpeqd", "V,H,W"),
+    (0x0f, 0x7e, 0x66, "movd", "E,V"),
+    (0x0f, 0x7e, 0xf3, "movq", "V,W"),
+    (0x0f, 0x7f, 0x66, "movdqa", "W,V"),
+    (0x0f, 0x7f, 0xf3, "movdqu", "W,V"),
+    (0x0f, 0xc2, 0, "cmpps", "V,H,W,I"),
+    (0x0f, 0xc2, 0x66, "cmppd", "V,H,W,I"),
+    (0x0f, 0xc2, 0xf3, "cmpss", "V,H,W,I"),
+    (0x0f, 0xc2, 0xf2, "cmpsd", "V,H,W,I"),
+    (0x0f, 0xc4, 0x66, "pinsrw", "V,H,E,I"),
+    (0x0f, 0xc5, 0x66, "pextrw", "G,U,I"),
+    (0x0f, 0xc6, 0, "shufps", "V,H,W,I"),
+    (0x0f, 0xc6, 0x66, "shufpd", "V,H,W,I"),
+    (0x0f, 0xd1, 0x66, "psrlw", "V,H,W"),
+    (0x0f, 0xd2, 0x66, "psrld", "V,H,W"),
+    (0x0f, 0xd3, 0x66, "psrlq", "V,H,W"),
+    (0x0f, 0xd4, 0x66, "paddq", "V,H,W"),
+    (0x0f, 0xd5, 0x66, "pmullw", "V,H,W"),
+    (0x0f, 0xd6, 0x66, "movq", "W,V"),
+    (0x0f, 0xd7, 0x66, "pmovmskb", "G,U"),
+    (0x0f, 0xd8, 0x66, "psubusb", "V,H,W"),
+    (0x0f, 0xd9, 0x66, "psubusw", "V,H,W"),
+    (0x0f, 0xda, 0x66, "pminub", "V,H,W"),
+    (0x0f, 0xdb, 0x66, "pand", "V,H,W"),
+    (0x0f, 0xdc, 0x66, "paddusb", "V,H,W"),
+    (0x0f, 0xdd, 0x66, "paddusw", "V,H,W"),
+    (0x0f, 0xde, 0x66, "pmaxub", "V,H,W"),
+    (0x0f, 0xdf, 0x66, "pandn", "V,H,W"),
+    (0x0f, 0xe0, 0x66, "pavgb", "V,H,W"),
+    (0x0f, 0xe1, 0x66, "psraw", "V,H,W"),
+    (0x0f, 0xe2, 0x66, "psrad", "V,H,W"),
+    (0x0f, 0xe3, 0x66, "pavgw", "V,H,W"),
+    (0x0f, 0xe4, 0x66, "pmulhuw", "V,H,W"),
+    (0x0f, 0xe5, 0x66, "pmulhw", "V,H,W"),
+    (0x0f, 0xe6, 0xf3, "cvtdq2pd", "V,W"),
+    (0x0f, 0xe6, 0x66, "cvttpd2dq", "V,W"),
+    (0x0f, 0xe6, 0xf2, "cvtpd2dq", "V,W"),
+    (0x0f, 0xe7, 0x66, "movntdq", "M,V"),
+    (0x0f, 0xe8, 0x66, "psubsb", "V,H,W"),
+    (0x0f, 0xe9, 0x66, "psubsw", "V,H,W"),
+    (0x0f, 0xea, 0x66, "pminsw", "V,H,W"),
+    (0x0f, 0xeb, 0x66, "por", "V,H,W"),
+    (0x0f, 0xec, 0x66, "paddsb", "V,H,W"),
+    (0x0f, 0xed, 0x66, "paddsw", "V,H,W"),
+    (0x0f, 0xee, 0x66, "pmaxsw", "V,H,W"),
+    (0x0f, 0xef, 0x66, "pxor", "V,H,W"),
+    (0x0f, 0xf1, 0x66, "psllw", "V,H,W"),
+    (0x0f, 0xf2, 0x66, "pslld", "V,H,W"),
+    (0x0f, 0xf3, 0x66, "psllq", "V,H,W"),
+    (0x0f, 0xf4, 0x66, "pmuludq", "V,H,W"),
+    (0x0f, 0xf5, 0x66, "pmaddwd", "V,H,W"),
+    (0x0f, 0xf6, 0x66, "psadbw", "V,H,W"),
+    (0x0f, 0xf8, 0x66, "psubb", "V,H,W"),
+    (0x0f, 0xf9, 0x66, "psubw", "V,H,W"),
+    (0x0f, 0xfa, 0x66, "psubd", "V,H,W"),
+    (0x0f, 0xfb, 0x66, "psubq", "V,H,W"),
+    (0x0f, 0xfc, 0x66, "paddb", "V,H,W"),
+    (0x0f, 0xfd, 0x66, "paddw", "V,H,W"),
+    (0x0f, 0xfe, 0x66, "paddd", "V,H,W"),
+    (0x0f38, 0x00, 0x66, "pshufb", "V,H,W"),
+    (0x0f38, 0x17, 0x66, "ptest", "V,W"),
+    (0x0f38, 0x29, 0x66, "pcmpeqq", "V,H,W"),
+    (0x0f38, 0x37, 0x66, "pcmpgtq", "V,H,W"),
+    (0x0f38, 0x38, 0x66, "pminsb", "V,H,W"),
+    (0x0f38, 0x39, 0x66, "pminsd", "V,H,W"),
+    (0x0f38, 0x3a, 0x66, "pminuw", "V,H,W"),
+    (0x0f38, 0x3b, 0x66, "pminud", "V,H,W"),
+    (0x0f38, 0x3c, 0x66, "pmaxsb", "V,H,W"),
+    (0x0f38, 0x3d, 0x66, "pmaxsd", "V,H,W"),
+    (0x0f38, 0x3e, 0x66, "pmaxuw", "V,H,W"),
+    (0x0f38, 0x3f, 0x66, "pmaxud", "V,H,W"),
+    (0x0f38, 0x40, 0x66, "pmulld", "V,H,W"),
+    (0x0f3a, 0x0a, 0x66, "roundss", "V,H,W,I"),
+    (0x0f3a, 0x0b, 0x66, "roundsd", "V,H,W,I"),
+    (0x0f3a, 0x0f, 0x66, "palignr", "V,H,W,I"),
+    (0x0f3a, 0x16, 0x66, "pextrd", "E,V,I"),
+    (0x0f3a, 0x22, 0x66, "pinsrd", "V,H,E,I"),
+    (0x0f3a, 0x63, 0x66, "pcmpistri", "V,W,I"),
+];
+
+// Instructions which only have VEX forms.
+const VEX_OPS: &'static [(u32, u8, u8, &'static str, &'static str)] = &[
+    (0x0f38, 0x18, 0x66, "vbroadcastss", "V,X"),
+    (0x0f38, 0x58, 0x66, "vpbroadcastd", "V,X"),
+    (0x0f38, 0x59, 0x66, "vpbroadcastq", "V,X"),
+    (0x0f38, 0x78, 0x66, "vpbroadcastb", "V,X"),
+    (0x0f38, 0x79, 0x66, "vpbroadcastw", "V,X"),
+    (0x0f3a, 0x18, 0x66, "vinsertf128", "V,H,X,I"),
+    (0x0f3a, 0x19, 0x66, "vextractf128", "X,V,I"),
+    (0x0f3a, 0x38, 0x66, "vinserti128", "V,H,X,I"),
+    (0x0f3a, 0x39, 0x66, "vextracti128", "X,V,I"),
+    // BMI1 and BMI2.
+    (0x0f38, 0xf2, 0, "andn", "G,B,R"),
+    (0x0f38, 0xf5, 0, "bzhi", "G,R,B"),
+    (0x0f38, 0xf5, 0xf3, "pext", "G,B,R"),
+    (0x0f38, 0xf5, 0xf2, "pdep", "G,B,R"),
+    (0x0f38, 0xf6, 0xf2, "mulx", "G,B,R"),
+    (0x0f38, 0xf7, 0, "bextr", "G,R,B"),
+    (0x0f38, 0xf7, 0x66, "shlx", "G,R,B"),
+    (0x0f38, 0xf7, 0xf3, "sarx", "G,R,B"),
+    (0x0f38, 0xf7, 0xf2, "shrx", "G,R,B"),
+    (0x0f3a, 0xf0, 0xf2, "rorx", "G,R,I"),
+];
+
+// x87 instructions with memory operands by the opcode (0xd8-0xdf) and
+// ModRM.reg.
+const X87_MEM_OPS: [[&'static str; 8]; 8] = [
+    ["fadds", "fmuls", "fcoms", "fcomps", "fsubs", "fsubrs", "fdivs",
+     "fdivrs"],
+    ["flds", "", "fsts", "fstps", "fldenv", "fldcw", "fnstenv", "fnstcw"],
+    ["fiaddl", "fimull", "ficoml", "ficompl", "fisubl", "fisubrl", "fidivl",
+     "fidivrl"],
+    ["fildl", "fisttpl", "fistl", "fistpl", "", "fldt", "", "fstpt"],
+    ["faddl", "fmull", "fcoml", "fcompl", "fsubl", "fsubrl", "fdivl",
+     "fdivrl"],
+    ["fldl", "fisttpll", "fstl", "fstpl", "frstor", "", "fnsave", "fnstsw"],
+    ["fiadds", "fimuls", "ficoms", "ficomps", "fisubs", "fisubrs", "fidivs",
+     "fidivrs"],
+    ["filds", "fisttps", "fists", "fistps", "fbld", "fildll", "fbstp",
+     "fistpll"],
+];
+
+#[derive(Clone)]
+enum Arg {
+    // A register which tells the operand size.
+    Reg(String),
+    // A register which does not, like the count of shifts.
+    Count(String),
+    Mem(String),
+    Imm(u64),
+    Addr(u64),
+}
+
+impl Arg {
+    fn to_string(&self) -> String {
+        match *self {
+            Arg::Reg(ref r) | Arg::Count(ref r) | Arg::Mem(ref r) => r.clone(),
+            Arg::Imm(v) => format!("$0x{:x}", v),
+            Arg::Addr(a) => format!("0x{:x}", a),
+        }
+    }
+}
+
+// A decoded instruction before formatting. `size` is the operand size
+// which becomes the suffix of the mnemonic when no register tells it, or
+// 0 for instructions without suffixes.
+struct Op {
+    name: String,
+    args: Vec<Arg>,
+    size: usize,
+}
+
+fn op(name: &str, args: Vec<Arg>, size: usize) -> Option<Op> {
+    return Some(Op { name: name.to_string(), args: args, size: size });
+}
+
+fn mask(v: i64, size: usize) -> u64 {
+    if size >= 8 {
+        return v as u64;
+    }
+    return (v as u64) & ((1 << (size * 8)) - 1);
+}
+
+fn suffix(size: usize) -> &'static str {
+    match size {
+        1 => "b",
+        2 => "w",
+        4 => "l",
+        _ => "q",
+    }
+}
+
+fn signed_hex(v: i64) -> String {
+    if v < 0 {
+        return format!("-0x{:x}", (v as i128).abs());
+    }
+    return format!("0x{:x}", v);
+}
+
+struct Decoder<'a> {
+    code: &'a [u8],
+    addr: u64,
+    pos: usize,
+    rex: u8,
+    opsize: bool,
+    // Extra operand size prefixes, shown as "data16".
+    data16: usize,
+    adsize: bool,
+    rep: u8,
+    lock: bool,
+    seg: u8,
+    // VEX.L and VEX.vvvv of VEX-encoded instructions.
+    vex: Option<(bool, usize)>,
+    modrm: Option<u8>,
+    rip_disp: Option<i64>,
+    branch: Option<u64>,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(code: &'a [u8], addr: u64) -> Self {
+        Decoder {
+            code: code,
+            addr: addr,
+            pos: 0,
+            rex: 0,
+            opsize: false,
+            data16: 0,
+            adsize: false,
+            rep: 0,
+            lock: false,
+            seg: 0,
+            vex: None,
+            modrm: None,
+            rip_disp: None,
+            branch: None,
+        }
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let b = some!(self.code.get(self.pos).cloned());
+        self.pos += 1;
+        return Some(b);
+    }
+
+    // Reads a sign-extended little-endian immediate.
+    fn imm(&mut self, len: usize) -> Option<i64> {
+        if self.pos + len > self.code.len() {
+            return None;
+        }
+        let mut v: u64 = 0;
+        for i in 0..len {
+            v |= (self.code[self.pos + i] as u64) << (i * 8);
+        }
+        self.pos += len;
+        let shift = 64 - len * 8;
+        return Some(((v << shift) as i64) >> shift);
+    }
+
+    fn rex_w(&self) -> bool { self.rex & 8 != 0 }
+
+    fn op_size(&self) -> usize {
+        if self.rex_w() {
+            8
+        } else if self.opsize {
+            2
+        } else {
+            4
+        }
+    }
+
+    // The operand size of instructions which default to 64-bit.
+    fn op_size64(&self) -> usize {
+        if self.opsize && !self.rex_w() { 2 } else { 8 }
+    }
+
+    fn modrm(&mut self) -> Option<u8> {
+        if let Some(m) = self.modrm {
+            return Some(m);
+        }
+        let m = some!(self.byte());
+        self.modrm = Some(m);
+        return Some(m);
+    }
+
+    fn is_reg_form(&mut self) -> Option<bool> {
+        return Some(some!(self.modrm()) >> 6 == 3);
+    }
+
+    fn reg_field(&mut self) -> Option<usize> {
+        let m = some!(self.modrm());
+        return Some((((m >> 3) & 7) | ((self.rex & 4) << 1)) as usize);
+    }
+
+    fn rm_field(&mut self) -> Option<usize> {
+        let m = some!(self.modrm());
+        return Some(((m & 7) | ((self.rex & 1) << 3)) as usize);
+    }
+
+    fn gpr(&self, n: usize, size: usize) -> String {
+        let name = match size {
+            1 if self.rex == 0 && n < 8 => REGS8_LEGACY[n],
+            1 => REGS8[n],
+            2 => REGS16[n],
+            4 => REGS32[n],
+            _ => REGS64[n],
+        };
+        return format!("%{}", name);
+    }
+
+    fn xmm_name(&self, n: usize) -> String {
+        match self.vex {
+            Some((true, _)) => format!("%ymm{}", n),
+            _ => format!("%xmm{}", n),
+        }
+    }
+
+    fn reg(&mut self, size: usize) -> Option<Arg> {
+        let n = some!(self.reg_field());
+        return Some(Arg::Reg(self.gpr(n, size)));
+    }
+
+    fn rm(&mut self, size: usize) -> Option<Arg> {
+        if some!(self.is_reg_form()) {
+            let n = some!(self.rm_field());
+            return Some(Arg::Reg(self.gpr(n, size)));
+        }
+        return Some(Arg::Mem(some!(self.mem())));
+    }
+
+    fn xmm(&mut self) -> Option<Arg> {
+        let n = some!(self.reg_field());
+        return Some(Arg::Reg(self.xmm_name(n)));
+    }
+
+    fn xmm_rm(&mut self) -> Option<Arg> {
+        if some!(self.is_reg_form()) {
+            let n = some!(self.rm_field());
+            return Some(Arg::Reg(self.xmm_name(n)));
+        }
+        return Some(Arg::Mem(some!(self.mem())));
+    }
+
+    fn seg_name(&self) -> Option<&'static str> {
+        match self.seg {
+            0x64 => Some("fs"),
+            0x65 => Some("gs"),
+            _ => None,
+        }
+    }
+
+    // Formats the memory operand of ModRM, e.g. "-0x4(%rbp)".
+    fn mem(&mut self) -> Option<String> {
+        let m = some!(self.modrm());
+        let md = m >> 6;
+        let areg = |d: &Decoder, n: usize| {
+            d.gpr(n, if d.adsize { 4 } else { 8 })
+        };
+        let mut base = None;
+        let mut index = None;
+        let mut absolute = false;
+        if m & 7 == 4 {
+            let sib = some!(self.byte());
+            let scale = 1 << (sib >> 6);
+            let idx = (((sib >> 3) & 7) | ((self.rex & 2) << 2)) as usize;
+            let b = (sib & 7) as usize;
+            if b == 5 && md == 0 {
+                absolute = idx == 4;
+            } else {
+                base = Some(areg(self, b | ((self.rex as usize & 1) << 3)));
+            }
+            if idx != 4 {
+                index = Some((areg(self, idx), scale));
+            } else if base.is_some() && b != 4 {
+                let riz = if self.adsize { "%eiz" } else { "%riz" };
+                index = Some((riz.to_string(), scale));
+            }
+        } else if m & 7 == 5 && md == 0 {
+            let disp = some!(self.imm(4));
+            self.rip_disp = Some(disp);
+            let rip = if self.adsize { "%eip" } else { "%rip" };
+            return Some(format!("{}{}({})", self.seg_prefix(),
+                                signed_hex(disp), rip));
+        } else {
+            let n = some!(self.rm_field());
+            base = Some(areg(self, n));
+        }
+        let disp = match md {
+            1 => Some(some!(self.imm(1))),
+            2 => Some(some!(self.imm(4))),
+            _ if base.is_none() => Some(some!(self.imm(4))),
+            _ => None,
+        };
+        let mut r = self.seg_prefix();
+        if let Some(disp) = disp {
+            if absolute {
+                r += &format!("0x{:x}", if self.adsize {
+                    disp as u32 as u64
+                } else {
+                    disp as u64
+                });
+            } else {
+                r += &signed_hex(disp);
+            }
+        }
+        if base.is_some() || index.is_some() {
+            r += "(";
+            if let Some(base) = base {
+                r += &base;
+            }
+            if let Some((index, scale)) = index {
+                r += &format!(",{},{}", index, scale);
+            }
+            r += ")";
+        }
+        return Some(r);
+    }
+
+    fn seg_prefix(&self) -> String {
+        match self.seg_name() {
+            Some(seg) => format!("%{}:", seg),
+            None => String::new(),
+        }
+    }
+
+    fn rel(&mut self, len: usize) -> Option<Arg> {
+        let disp = some!(self.imm(len));
+        let target = self.addr.wrapping_add(self.pos as u64)
+            .wrapping_add(disp as u64);
+        self.branch = Some(target);
+        return Some(Arg::Addr(target));
+    }
+
+    fn prefixes(&mut self) -> Option<()> {
+        loop {
+            match some!(self.code.get(self.pos).cloned()) {
+                0x66 => {
+                    if self.opsize {
+                        self.data16 += 1;
+                    }
+                    self.opsize = true;
+                }
+                0x67 => self.adsize = true,
+                0xf0 => self.lock = true,
+                b @ 0xf2 | b @ 0xf3 => self.rep = b,
+                b @ 0x26 | b @ 0x2e | b @ 0x36 | b @ 0x3e | b @ 0x64 |
+                b @ 0x65 => self.seg = b,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        let b = some!(self.code.get(self.pos).cloned());
+        if b & 0xf0 == 0x40 {
+            self.rex = b;
+            self.pos += 1;
+        }
+        return Some(());
+    }
+
+    fn insn(&mut self) -> Option<String> {
+        some!(self.prefixes());
+        let opcode = some!(self.byte());
+        let mut rep_used = false;
+        let op = match opcode {
+            0x0f => some!(self.insn_0f(&mut rep_used)),
+            0xc4 | 0xc5 => some!(self.insn_vex(opcode)),
+            0xa4..=0xa7 | 0xaa..=0xaf => {
+                rep_used = true;
+                some!(self.string_op(opcode))
+            }
+            _ => some!(self.insn_1byte(opcode)),
+        };
+
+        let mut prefix = String::new();
+        for _ in 0..self.data16 {
+            prefix += "data16 ";
+        }
+        match self.seg {
+            0x26 => prefix += "es ",
+            0x2e => prefix += "cs ",
+            0x36 => prefix += "ss ",
+            0x3e if opcode == 0xff => {
+                prefix += "notrack "
+            }
+            0x3e => prefix += "ds ",
+            _ => {}
+        }
+        if self.lock {
+            prefix += "lock ";
+        }
+        if !rep_used {
+            let is_branch = match opcode {
+                0x70..=0x7f | 0xc2 | 0xc3 | 0xe8 | 0xe9 | 0xeb => true,
+                0xff => op.name == "call" || op.name == "jmp",
+                _ => false,
+            };
+            match self.rep {
+                0xf2 if is_branch => prefix += "bnd ",
+                0xf2 => prefix += "repnz ",
+                0xf3 => prefix += "repz ",
+                _ => {}
+            }
+        }
+
+        let has_mem = op.args.iter().any(|a| match *a {
+            Arg::Mem(_) => true,
+            _ => false,
+        });
+        let has_reg = op.args.iter().any(|a| match *a {
+            Arg::Reg(_) => true,
+            _ => false,
+        });
+        let mut name = prefix + &op.name;
+        if op.size > 0 && has_mem && !has_reg {
+            name += suffix(op.size);
+        }
+        if op.args.is_empty() {
+            return Some(name);
+        }
+        let args: Vec<String> =
+            op.args.iter().rev().map(|a| a.to_string()).collect();
+        return Some(format!("{:<6} {}", name, args.join(",")));
+    }
+
+    fn string_op(&mut self, opcode: u8) -> Option<Op> {
+        let size = if opcode & 1 == 0 { 1 } else { self.op_size() };
+        let (name, args) = match opcode & !1 {
+            0xa4 => ("movs", vec!(Arg::Mem("%es:(%rdi)".to_string()),
+                                  Arg::Mem("%ds:(%rsi)".to_string()))),
+            0xa6 => ("cmps", vec!(Arg::Mem("%ds:(%rsi)".to_string()),
+                                  Arg::Mem("%es:(%rdi)".to_string()))),
+            0xaa => ("stos", vec!(Arg::Mem("%es:(%rdi)".to_string()),
+                                  Arg::Reg(self.gpr(0, size)))),
+            0xac => ("lods", vec!(Arg::Reg(self.gpr(0, size)),
+                                  Arg::Mem("%ds:(%rsi)".to_string()))),
+            _ => ("scas", vec!(Arg::Reg(self.gpr(0, size)),
+                               Arg::Mem("%es:(%rdi)".to_string()))),
+        };
+        // Comparisons repeat while equal.
+        let rep = match (self.rep, opcode & !1) {
+            (0xf3, 0xa6) | (0xf3, 0xae) => "repz ",
+            (0xf3, _) => "rep ",
+            (0xf2, _) => "repnz ",
+            _ => "",
+        };
+        return op(&format!("{}{}", rep, name), args, size);
+    }
+
+    fn insn_1byte(&mut self, opcode: u8) -> Option<Op> {
+        let size = self.op_size();
+        match opcode {
+            0x00..=0x3f if opcode & 7 < 6 => {
+                let name = ALU_OPS[(opcode >> 3) as usize];
+                let size = if opcode & 1 == 0 { 1 } else { size };
+                match opcode & 7 {
+                    0 | 1 => {
+                        let rm = some!(self.rm(size));
+                        op(name, vec!(rm, some!(self.reg(size))), size)
+                    }
+                    2 | 3 => {
+                        let reg = some!(self.reg(size));
+                        op(name, vec!(reg, some!(self.rm(size))), size)
+                    }
+                    _ => {
+                        let imm = some!(self.imm(std::cmp::min(size, 4)));
+                        op(name, vec!(Arg::Reg(self.gpr(0, size)),
+                                      Arg::Imm(mask(imm, size))), size)
+                    }
+                }
+            }
+            0x50..=0x57 | 0x58..=0x5f => {
+                let n = (opcode & 7) as usize | ((self.rex as usize & 1) << 3);
+                let name = if opcode < 0x58 { "push" } else { "pop" };
+                op(name, vec!(Arg::Reg(self.gpr(n, self.op_size64()))), 0)
+            }
+            0x63 if self.rex_w() => {
+                let reg = some!(self.reg(8));
+                op("movslq", vec!(reg, some!(self.rm(4))), 0)
+            }
+            0x68 | 0x6a => {
+                let imm = some!(self.imm(if opcode == 0x68 { 4 } else { 1 }));
+                op("push", vec!(Arg::Imm(mask(imm, self.op_size64()))), 0)
+            }
+            0x69 | 0x6b => {
+                let reg = some!(self.reg(size));
+                let rm = some!(self.rm(size));
+                let len =
+                    if opcode == 0x69 { std::cmp::min(size, 4) } else { 1 };
+                let imm = some!(self.imm(len));
+                op("imul", vec!(reg, rm, Arg::Imm(mask(imm, size))), size)
+            }
+            0x70..=0x7f => {
+                let name = format!("j{}", CONDS[(opcode & 15) as usize]);
+                op(&name, vec!(some!(self.rel(1))), 0)
+            }
+            0x80 | 0x81 | 0x83 => {
+                let size = if opcode == 0x80 { 1 } else { size };
+                let name = ALU_OPS[some!(self.reg_field()) & 7];
+                let rm = some!(self.rm(size));
+                let len =
+                    if opcode == 0x81 { std::cmp::min(size, 4) } else { 1 };
+                let imm = some!(self.imm(len));
+                op(name, vec!(rm, Arg::Imm(mask(imm, size))), size)
+            }
+            0x84..=0x8b => {
+                let name = match opcode {
+                    0x84 | 0x85 => "test",
+                    0x86 | 0x87 => "xchg",
+                    _ => "mov",
+                };
+                let size = if opcode & 1 == 0 { 1 } else { size };
+                if opcode >= 0x8a {
+                    let reg = some!(self.reg(size));
+                    op(name, vec!(reg, some!(self.rm(size))), size)
+                } else {
+                    let rm = some!(self.rm(size));
+                    op(name, vec!(rm, some!(self.reg(size))), size)
+                }
+            }
+            0x8d => {
+                if some!(self.is_reg_form()) {
+                    return None;
+                }
+                let reg = some!(self.reg(size));
+                op("lea", vec!(reg, Arg::Mem(some!(self.mem()))), 0)
+            }
+            0x8f if some!(self.reg_field()) & 7 == 0 => {
+                let size = self.op_size64();
+                op("pop", vec!(some!(self.rm(size))), size)
+            }
+            0x90 if self.rex & 1 == 0 => {
+                if self.rep == 0xf3 {
+                    self.rep = 0;
+                    return op("pause", vec!(), 0);
+                }
+                if self.opsize {
+                    return op("xchg", vec!(Arg::Reg("%ax".to_string()),
+                                           Arg::Reg("%ax".to_string())), 0);
+                }
+                op("nop", vec!(), 0)
+            }
+            0x90..=0x97 => {
+                let n = (opcode & 7) as usize | ((self.rex as usize & 1) << 3);
+                op("xchg", vec!(Arg::Reg(self.gpr(n, size)),
+                                Arg::Reg(self.gpr(0, size))), 0)
+            }
+            0x98 => op(match size { 8 => "cltq", 2 => "cbtw", _ => "cwtl" },
+                       vec!(), 0),
+            0x99 => op(match size { 8 => "cqto", 2 => "cwtd", _ => "cltd" },
+                       vec!(), 0),
+            0x9b => op("fwait", vec!(), 0),
+            0x9c => op("pushf", vec!(), 0),
+            0x9d => op("popf", vec!(), 0),
+            0x9e => op("sahf", vec!(), 0),
+            0x9f => op("lahf", vec!(), 0),
+            0xa8 | 0xa9 => {
+                let size = if opcode == 0xa8 { 1 } else { size };
+                let imm = some!(self.imm(std::cmp::min(size, 4)));
+                op("test", vec!(Arg::Reg(self.gpr(0, size)),
+                                Arg::Imm(mask(imm, size))), 0)
+            }
+            0xb0..=0xb7 => {
+                let n = (opcode & 7) as usize | ((self.rex as usize & 1) << 3);
+                let imm = some!(self.imm(1));
+                let reg = Arg::Reg(self.gpr(n, 1));
+                op("mov", vec!(reg, Arg::Imm(mask(imm, 1))), 0)
+            }
+            0xb8..=0xbf => {
+                let n = (opcode & 7) as usize | ((self.rex as usize & 1) << 3);
+                let imm = some!(self.imm(size));
+                let name = if size == 8 { "movabs" } else { "mov" };
+                op(name, vec!(Arg::Reg(self.gpr(n, size)),
+                              Arg::Imm(mask(imm, size))), 0)
+            }
+            0xc0 | 0xc1 | 0xd0..=0xd3 => {
+                let size = if opcode & 1 == 0 { 1 } else { size };
+                let name = SHIFT_OPS[some!(self.reg_field()) & 7];
+                let rm = some!(self.rm(size));
+                let args = match opcode {
+                    0xc0 | 0xc1 => {
+                        vec!(rm, Arg::Imm(mask(some!(self.imm(1)), 1)))
+                    }
+                    0xd2 | 0xd3 => vec!(rm, Arg::Count("%cl".to_string())),
+                    _ => vec!(rm),
+                };
+                op(name, args, size)
+            }
+            0xc2 => {
+                let imm = some!(self.imm(2));
+                op("ret", vec!(Arg::Imm(mask(imm, 2))), 0)
+            }
+            0xc3 => {
+                if self.rep == 0xf3 {
+                    self.rep = 0;
+                    return op("repz ret", vec!(), 0);
+                }
+                op("ret", vec!(), 0)
+            }
+            0xc6 if some!(self.modrm()) == 0xf8 => {
+                let imm = some!(self.imm(1));
+                op("xabort", vec!(Arg::Imm(mask(imm, 1))), 0)
+            }
+            0xc7 if some!(self.modrm()) == 0xf8 => {
+                op("xbegin", vec!(some!(self.rel(4))), 0)
+            }
+            0xc6 | 0xc7 if some!(self.reg_field()) & 7 == 0 => {
+                let size = if opcode == 0xc6 { 1 } else { size };
+                let rm = some!(self.rm(size));
+                let imm = some!(self.imm(std::cmp::min(size, 4)));
+                op("mov", vec!(rm, Arg::Imm(mask(imm, size))), size)
+            }
+            0xc9 => op("leave", vec!(), 0),
+            0xcc => op("int3", vec!(), 0),
+            0xcd => {
+                let imm = some!(self.imm(1));
+                op("int", vec!(Arg::Imm(mask(imm, 1))), 0)
+            }
+            0xd8..=0xdf => self.x87(opcode),
+            0xe0..=0xe3 => {
+                let name = ["loopne", "loope", "loop", "jrcxz"]
+                    [(opcode & 3) as usize];
+                op(name, vec!(some!(self.rel(1))), 0)
+            }
+            0xe8 => op("call", vec!(some!(self.rel(4))), 0),
+            0xe9 => op("jmp", vec!(some!(self.rel(4))), 0),
+            0xeb => op("jmp", vec!(some!(self.rel(1))), 0),
+            0xf4 => op("hlt", vec!(), 0),
+            0xf5 => op("cmc", vec!(), 0),
+            0xf6 | 0xf7 => {
+                let size = if opcode == 0xf6 { 1 } else { size };
+                let ext = some!(self.reg_field()) & 7;
+                let name = ["test", "test", "not", "neg", "mul", "imul", "div",
+                            "idiv"][ext];
+                let rm = some!(self.rm(size));
+                if ext < 2 {
+                    let imm = some!(self.imm(std::cmp::min(size, 4)));
+                    op(name, vec!(rm, Arg::Imm(mask(imm, size))), size)
+                } else {
+                    op(name, vec!(rm), size)
+                }
+            }
+            0xf8 => op("clc", vec!(), 0),
+            0xf9 => op("stc", vec!(), 0),
+            0xfc => op("cld", vec!(), 0),
+            0xfd => op("std", vec!(), 0),
+            0xfe | 0xff => {
+                let size = if opcode == 0xfe { 1 } else { size };
+                match some!(self.reg_field()) & 7 {
+                    0 => op("inc", vec!(some!(self.rm(size))), size),
+                    1 => op("dec", vec!(some!(self.rm(size))), size),
+                    2 if opcode == 0xff => {
+                        let rm = some!(self.rm(8));
+                        op("call", vec!(indirect(rm)), 0)
+                    }
+                    4 if opcode == 0xff => {
+                        let rm = some!(self.rm(8));
+                        op("jmp", vec!(indirect(rm)), 0)
+                    }
+                    6 if opcode == 0xff => {
+                        let size = self.op_size64();
+                        op("push", vec!(some!(self.rm(size))), 0)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Decodes an x87 instruction. Memory operands are in the mnemonics.
+    fn x87(&mut self, opcode: u8) -> Option<Op> {
+        let ext = some!(self.reg_field()) & 7;
+        let group = (opcode - 0xd8) as usize;
+        if !some!(self.is_reg_form()) {
+            let name = X87_MEM_OPS[group][ext];
+            if name.is_empty() {
+                return None;
+            }
+            return op(name, vec!(Arg::Mem(some!(self.mem()))), 0);
+        }
+        let m = some!(self.modrm());
+        let i = (m & 7) as usize;
+        let st = || Arg::Reg("%st".to_string());
+        let sti = Arg::Reg(format!("%st({})", i));
+        let arith = ["add", "mul", "com", "comp", "sub", "subr", "div", "divr"];
+        match (opcode, ext) {
+            (0xd8, 2) | (0xd8, 3) => {
+                op(&format!("f{}", arith[ext]), vec!(sti), 0)
+            }
+            (0xd8, _) => op(&format!("f{}", arith[ext]), vec!(st(), sti), 0),
+            (0xd9, 0) => op("fld", vec!(sti), 0),
+            (0xd9, 1) => op("fxch", vec!(sti), 0),
+            (0xd9, _) => {
+                let name = match m {
+                    0xd0 => "fnop",
+                    0xe0 => "fchs",
+                    0xe1 => "fabs",
+                    0xe4 => "ftst",
+                    0xe5 => "fxam",
+                    0xe8..=0xee => ["fld1", "fldl2t", "fldl2e", "fldpi",
+                                    "fldlg2", "fldln2", "fldz"][i],
+                    0xf0..=0xff => ["f2xm1", "fyl2x", "fptan", "fpatan",
+                                    "fxtract", "fprem1", "fdecstp",
+                                    "fincstp", "fprem", "fyl2xp1", "fsqrt",
+                                    "fsincos", "frndint", "fscale", "fsin",
+                                    "fcos"][i + (m as usize & 8)],
+                    _ => return None,
+                };
+                op(name, vec!(), 0)
+            }
+            (0xda, 5) if m == 0xe9 => op("fucompp", vec!(), 0),
+            (0xda, 0..=3) | (0xdb, 0..=3) => {
+                let conds = ["b", "e", "be", "u"];
+                let name = format!("fcmov{}{}",
+                                   if opcode == 0xdb { "n" } else { "" },
+                                   conds[ext]);
+                op(&name, vec!(st(), sti), 0)
+            }
+            (0xdb, 4) if m == 0xe2 => op("fnclex", vec!(), 0),
+            (0xdb, 4) if m == 0xe3 => op("fninit", vec!(), 0),
+            (0xdb, 5) => op("fucomi", vec!(st(), sti), 0),
+            (0xdb, 6) => op("fcomi", vec!(st(), sti), 0),
+            (0xdc, 2) | (0xdc, 3) => None,
+            (0xdc, _) => {
+                op(&format!("f{}", arith[ext]), vec!(sti, st()), 0)
+            }
+            (0xdd, 0) => op("ffree", vec!(sti), 0),
+            (0xdd, 2) => op("fst", vec!(sti), 0),
+            (0xdd, 3) => op("fstp", vec!(sti), 0),
+            (0xdd, 4) => op("fucom", vec!(sti), 0),
+            (0xdd, 5) => op("fucomp", vec!(sti), 0),
+            (0xde, 3) if m == 0xd9 => op("fcompp", vec!(), 0),
+            (0xde, 2) | (0xde, 3) => None,
+            (0xde, _) => {
+                op(&format!("f{}p", arith[ext]), vec!(sti, st()), 0)
+            }
+            (0xdf, 4) if m == 0xe0 => {
+                op("fnstsw", vec!(Arg::Reg("%ax".to_string())), 0)
+            }
+            (0xdf, 5) => op("fucomip", vec!(st(), sti), 0),
+            (0xdf, 6) => op("fcomip", vec!(st(), sti), 0),
+            _ => None,
+        }
+    }
+
+    fn insn_0f(&mut self, rep_used: &mut bool) -> Option<Op> {
+        let opcode = some!(self.byte());
+        let size = self.op_size();
+        match opcode {
+            0x05 => op("syscall", vec!(), 0),
+            0x0b => op("ud2", vec!(), 0),
+            0x01 if some!(self.is_reg_form()) => {
+                let name = match some!(self.modrm()) {
+                    0xd0 => "xgetbv",
+                    0xd5 => "xend",
+                    0xd6 => "xtest",
+                    0xee => "rdpkru",
+                    0xef => "wrpkru",
+                    _ => return None,
+                };
+                op(name, vec!(), 0)
+            }
+            0x0d if !some!(self.is_reg_form()) &&
+                some!(self.reg_field()) & 7 == 1 => {
+                op("prefetchw", vec!(Arg::Mem(some!(self.mem()))), 0)
+            }
+            0x18 if !some!(self.is_reg_form()) &&
+                some!(self.reg_field()) & 7 < 4 => {
+                let name = ["prefetchnta", "prefetcht0", "prefetcht1",
+                            "prefetcht2"][some!(self.reg_field()) & 3];
+                op(name, vec!(Arg::Mem(some!(self.mem()))), 0)
+            }
+            0x1e if self.rep == 0xf3 &&
+                self.code.get(self.pos) == Some(&0xfa) => {
+                self.pos += 1;
+                *rep_used = true;
+                op("endbr64", vec!(), 0)
+            }
+            0x1f => op("nop", vec!(some!(self.rm(size))), size),
+            0x31 => op("rdtsc", vec!(), 0),
+            0x40..=0x4f => {
+                let name = format!("cmov{}", CONDS[(opcode & 15) as usize]);
+                let reg = some!(self.reg(size));
+                op(&name, vec!(reg, some!(self.rm(size))), size)
+            }
+            0x80..=0x8f => {
+                let name = format!("j{}", CONDS[(opcode & 15) as usize]);
+                op(&name, vec!(some!(self.rel(4))), 0)
+            }
+            0x90..=0x9f => {
+                let name = format!("set{}", CONDS[(opcode & 15) as usize]);
+                op(&name, vec!(some!(self.rm(1))), 0)
+            }
+            0xa2 => op("cpuid", vec!(), 0),
+            0xa3 | 0xab | 0xb3 | 0xbb => {
+                let name = ["bt", "bts", "btr", "btc"]
+                    [((opcode >> 3) & 3) as usize];
+                let rm = some!(self.rm(size));
+                op(name, vec!(rm, some!(self.reg(size))), size)
+            }
+            0xa4 | 0xa5 | 0xac | 0xad => {
+                let name = if opcode < 0xa8 { "shld" } else { "shrd" };
+                let rm = some!(self.rm(size));
+                let reg = some!(self.reg(size));
+                let count = if opcode & 1 == 0 {
+                    Arg::Imm(mask(some!(self.imm(1)), 1))
+                } else {
+                    Arg::Count("%cl".to_string())
+                };
+                op(name, vec!(rm, reg, count), size)
+            }
+            0xae => {
+                let ext = some!(self.reg_field()) & 7;
+                if some!(self.is_reg_form()) {
+                    return match ext {
+                        5 => op("lfence", vec!(), 0),
+                        6 => op("mfence", vec!(), 0),
+                        7 => op("sfence", vec!(), 0),
+                        _ => None,
+                    };
+                }
+                let name = match ext {
+                    0 => "fxsave",
+                    1 => "fxrstor",
+                    2 => "ldmxcsr",
+                    3 => "stmxcsr",
+                    4 => "xsave",
+                    5 => "xrstor",
+                    6 => "xsaveopt",
+                    _ => "clflush",
+                };
+                let name = if self.rex_w() && ext != 2 && ext != 3 {
+                    format!("{}64", name)
+                } else {
+                    name.to_string()
+                };
+                op(&name, vec!(Arg::Mem(some!(self.mem()))), 0)
+            }
+            0xaf => {
+                let reg = some!(self.reg(size));
+                op("imul", vec!(reg, some!(self.rm(size))), size)
+            }
+            0xb0 | 0xb1 | 0xc0 | 0xc1 => {
+                let name = if opcode < 0xc0 { "cmpxchg" } else { "xadd" };
+                let size = if opcode & 1 == 0 { 1 } else { size };
+                let rm = some!(self.rm(size));
+                op(name, vec!(rm, some!(self.reg(size))), size)
+            }
+            0xb6 | 0xb7 | 0xbe | 0xbf => {
+                let src = if opcode & 1 == 0 { 1 } else { 2 };
+                let name = format!("mov{}{}{}",
+                                   if opcode < 0xb8 { "z" } else { "s" },
+                                   suffix(src), suffix(size));
+                let reg = some!(self.reg(size));
+                op(&name, vec!(reg, some!(self.rm(src))), 0)
+            }
+            0xb8 if self.rep == 0xf3 => {
+                *rep_used = true;
+                let reg = some!(self.reg(size));
+                op("popcnt", vec!(reg, some!(self.rm(size))), size)
+            }
+            0xba => {
+                let ext = some!(self.reg_field()) & 7;
+                if ext < 4 {
+                    return None;
+                }
+                let name = ["bt", "bts", "btr", "btc"][ext - 4];
+                let rm = some!(self.rm(size));
+                let imm = some!(self.imm(1));
+                op(name, vec!(rm, Arg::Imm(mask(imm, 1))), size)
+            }
+            0xbc | 0xbd => {
+                let name = match (opcode, self.rep) {
+                    (0xbc, 0xf3) => "tzcnt",
+                    (0xbd, 0xf3) => "lzcnt",
+                    (0xbc, _) => "bsf",
+                    _ => "bsr",
+                };
+                *rep_used = self.rep == 0xf3;
+                let reg = some!(self.reg(size));
+                op(name, vec!(reg, some!(self.rm(size))), size)
+            }
+            0xc8..=0xcf => {
+                let n = (opcode & 7) as usize | ((self.rex as usize & 1) << 3);
+                op("bswap", vec!(Arg::Reg(self.gpr(n, size))), 0)
+            }
+            0x71..=0x73 if self.opsize => {
+                self.opsize = false;
+                let ext = some!(self.reg_field()) & 7;
+                let name = match (opcode, ext) {
+                    (0x71, 2) => "psrlw",
+                    (0x71, 4) => "psraw",
+                    (0x71, 6) => "psllw",
+                    (0x72, 2) => "psrld",
+                    (0x72, 4) => "psrad",
+                    (0x72, 6) => "pslld",
+                    (0x73, 2) => "psrlq",
+                    (0x73, 3) => "psrldq",
+                    (0x73, 6) => "psllq",
+                    (0x73, 7) => "pslldq",
+                    _ => return None,
+                };
+                if !some!(self.is_reg_form()) {
+                    return None;
+                }
+                let n = some!(self.rm_field());
+                let reg = Arg::Reg(self.xmm_name(n));
+                let imm = some!(self.imm(1));
+                op(name, vec!(reg, Arg::Imm(mask(imm, 1))), 0)
+            }
+            0x38 => {
+                let opcode = some!(self.byte());
+                if (opcode == 0xf0 || opcode == 0xf1) && self.rep == 0 {
+                    if some!(self.is_reg_form()) {
+                        return None;
+                    }
+                    let reg = some!(self.reg(size));
+                    let mem = Arg::Mem(some!(self.mem()));
+                    let args = if opcode == 0xf0 {
+                        vec!(reg, mem)
+                    } else {
+                        vec!(mem, reg)
+                    };
+                    return op("movbe", args, 0);
+                }
+                *rep_used = true;
+                self.sse(0x0f38, opcode)
+            }
+            0x3a => {
+                let opcode = some!(self.byte());
+                *rep_used = true;
+                self.sse(0x0f3a, opcode)
+            }
+            _ => {
+                *rep_used = true;
+                self.sse(0x0f, opcode)
+            }
+        }
+    }
+
+    // Decodes a VEX-encoded instruction.
+    fn insn_vex(&mut self, first: u8) -> Option<Op> {
+        if self.rex != 0 || self.opsize || self.rep != 0 {
+            return None;
+        }
+        let b1 = some!(self.byte());
+        // The inverted REX.R, X and B.
+        let (map, w, b2) = if first == 0xc5 {
+            self.rex = 0x40 | (!b1 >> 5) & 4;
+            (0x0f, false, b1)
+        } else {
+            let b2 = some!(self.byte());
+            self.rex = 0x40 | (!b1 >> 5) & 7;
+            let map = match b1 & 0x1f {
+                1 => 0x0f,
+                2 => 0x0f38,
+                3 => 0x0f3a,
+                _ => return None,
+            };
+            (map, b2 & 0x80 != 0, b2)
+        };
+        if w {
+            self.rex |= 8;
+        }
+        self.vex = Some((b2 & 4 != 0, ((!b2 >> 3) & 15) as usize));
+        match b2 & 3 {
+            1 => self.opsize = true,
+            2 => self.rep = 0xf3,
+            3 => self.rep = 0xf2,
+            _ => {}
+        }
+        let opcode = some!(self.byte());
+        if map == 0x0f && opcode == 0x77 && self.rep == 0 && !self.opsize {
+            let name = if b2 & 4 != 0 { "vzeroall" } else { "vzeroupper" };
+            return op(name, vec!(), 0);
+        }
+        if map == 0x0f38 && opcode == 0xf3 && self.rep == 0 && !self.opsize {
+            let name = match some!(self.reg_field()) & 7 {
+                1 => "blsr",
+                2 => "blsmsk",
+                3 => "blsi",
+                _ => return None,
+            };
+            let size = if w { 8 } else { 4 };
+            let n = ((!b2 >> 3) & 15) as usize;
+            let dst = Arg::Reg(self.gpr(n, size));
+            return op(name, vec!(dst, some!(self.rm(size))), 0);
+        }
+        // BMI instructions on general registers have no "v".
+        let is_bmi = opcode >= 0xf0 && map != 0x0f;
+        let mut op = some!(self.sse(map, opcode));
+        if !is_bmi && !op.name.starts_with('v') {
+            op.name = format!("v{}", op.name);
+        }
+        return Some(op);
+    }
+
+    // Decodes an instruction in SSE_OPS or VEX_OPS.
+    fn sse(&mut self, map: u32, opcode: u8) -> Option<Op> {
+        let prefix = match self.rep {
+            0xf2 | 0xf3 => self.rep,
+            _ if self.opsize => 0x66,
+            _ => 0,
+        };
+        let ops = if self.vex.is_some() {
+            SSE_OPS.iter().chain(VEX_OPS.iter()).collect::<Vec<_>>()
+        } else {
+            SSE_OPS.iter().collect()
+        };
+        let &&(_, _, _, name, operands) = some!(ops.iter().find(|o| {
+            o.0 == map && o.1 == opcode && o.2 == prefix
+        }));
+        // The mandatory prefix is not an operand size prefix.
+        match prefix {
+            0x66 => self.opsize = false,
+            0xf2 | 0xf3 => self.rep = 0,
+            _ => {}
+        }
+        let gp_size = if self.rex_w() { 8 } else { 4 };
+        let mut name = name.to_string();
+        let mut args = vec!();
+        let mut size = 0;
+        for kind in operands.split(',') {
+            let arg = match kind {
+                "V" => some!(self.xmm()),
+                "W" => some!(self.xmm_rm()),
+                "X" => {
+                    // Always an xmm register even with VEX.L.
+                    if some!(self.is_reg_form()) {
+                        let n = some!(self.rm_field());
+                        Arg::Reg(format!("%xmm{}", n))
+                    } else {
+                        Arg::Mem(some!(self.mem()))
+                    }
+                }
+                "U" => {
+                    if !some!(self.is_reg_form()) {
+                        return None;
+                    }
+                    some!(self.xmm_rm())
+                }
+                "M" => {
+                    if some!(self.is_reg_form()) {
+                        return None;
+                    }
+                    Arg::Mem(some!(self.mem()))
+                }
+                "H" => match self.vex {
+                    Some((_, n)) => Arg::Reg(self.xmm_name(n)),
+                    None => continue,
+                },
+                "G" => some!(self.reg(gp_size)),
+                "B" => match self.vex {
+                    Some((_, n)) => Arg::Reg(self.gpr(n, gp_size)),
+                    None => return None,
+                },
+                "R" => some!(self.rm(gp_size)),
+                "E" => {
+                    // A memory operand tells its size by the suffix.
+                    size = gp_size;
+                    match some!(self.rm(gp_size)) {
+                        Arg::Reg(r) => Arg::Count(r),
+                        a => a,
+                    }
+                }
+                _ => Arg::Imm(mask(some!(self.imm(1)), 1)),
+            };
+            args.push(arg);
+        }
+        if some!(self.is_reg_form()) {
+            name = match name.as_str() {
+                "movlps" => "movhlps".to_string(),
+                "movhps" => "movlhps".to_string(),
+                _ => name,
+            };
+        }
+        if self.rex_w() {
+            name = match name.as_str() {
+                "movd" => "movq".to_string(),
+                "pextrd" => "pextrq".to_string(),
+                "pinsrd" => "pinsrq".to_string(),
+                _ => name,
+            };
+        }
+        // Like objdump, show predicates of comparisons in their mnemonics.
+        if name.starts_with("cmp") && name.len() == 5 {
+            if let Some(Arg::Imm(pred)) = args.last().cloned() {
+                if pred < 8 {
+                    name = format!("cmp{}{}", SSE_CMP_PREDS[pred as usize],
+                                   &name[3..]);
+                    args.pop();
+                }
+            }
+        }
+        if name.starts_with("cvtsi2") {
+            // The xmm register does not tell the size of the source.
+            let args = args.into_iter().map(|a| match a {
+                Arg::Reg(r) => Arg::Count(r),
+                a => a,
+            }).collect();
+            return Some(Op { name: name, args: args, size: size });
+        }
+        return op(&name, args, 0);
+    }
+}
+
+fn indirect(arg: Arg) -> Arg {
+    match arg {
+        Arg::Reg(r) => Arg::Reg(format!("*{}", r)),
+        Arg::Mem(m) => Arg::Mem(format!("*{}", m)),
+        a => a,
+    }
+}
+
+#[cfg(test)]
+fn text(code: &[u8]) -> String {
+    let insn = decode(code, 0x401000);
+    assert_eq!(code.len(), insn.len);
+    return insn.text;
+}
+
+#[test]
+fn test_basic() {
+    assert_eq!("push   %rbp", text(&[0x55]));
+    assert_eq!("mov    %rsp,%rbp", text(&[0x48, 0x89, 0xe5]));
+    assert_eq!("sub    $0x18,%rsp", text(&[0x48, 0x83, 0xec, 0x18]));
+    assert_eq!("movl   $0x0,-0x4(%rbp)",
+               text(&[0xc7, 0x45, 0xfc, 0, 0, 0, 0]));
+    assert_eq!("movzbl %al,%eax", text(&[0x0f, 0xb6, 0xc0]));
+    assert_eq!("mov    %fs:0x28,%rax",
+               text(&[0x64, 0x48, 0x8b, 0x04, 0x25, 0x28, 0, 0, 0]));
+    assert_eq!("nopw   0x0(%rax,%rax,1)",
+               text(&[0x66, 0x0f, 0x1f, 0x44, 0, 0]));
+    assert_eq!("endbr64", text(&[0xf3, 0x0f, 0x1e, 0xfa]));
+    assert_eq!("ret", text(&[0xc3]));
+    assert_eq!("(bad)", decode(&[0x0f, 0xff], 0).text);
+    assert_eq!("(bad)", decode(&[0x48, 0x89], 0).text);
+}
+
+#[test]
+fn test_targets() {
+    let call = decode(&[0xe8, 0xfb, 0xff, 0xff, 0xff], 0x401000);
+    assert_eq!("call   0x401000", call.text);
+    assert_eq!(Some(Target::Branch(0x401000)), call.target);
+    let lea = decode(&[0x48, 0x8d, 0x05, 0x10, 0, 0, 0], 0x401000);
+    assert_eq!("lea    0x10(%rip),%rax", lea.text);
+    assert_eq!(Some(Target::Memory(0x401017)), lea.target);
+    let jmp = decode(&[0xff, 0x25, 0x02, 0, 0, 0], 0x401000);
+    assert_eq!("jmp    *0x2(%rip)", jmp.text);
+    assert_eq!(Some(Target::Memory(0x401008)), jmp.target);
+}
+
+#[test]
+fn test_sse() {
+    assert_eq!("pxor   %xmm0,%xmm0", text(&[0x66, 0x0f, 0xef, 0xc0]));
+    assert_eq!("movsd  -0x8(%rbp),%xmm0",
+               text(&[0xf2, 0x0f, 0x10, 0x45, 0xf8]));
+    assert_eq!("cvtsi2sdl -0x4(%rbp),%xmm0",
+               text(&[0xf2, 0x0f, 0x2a, 0x45, 0xfc]));
+    assert_eq!("vmovdqu (%rsi),%ymm0", text(&[0xc5, 0xfe, 0x6f, 0x06]));
+    assert_eq!("vpbroadcastb %xmm0,%ymm0",
+               text(&[0xc4, 0xe2, 0x7d, 0x78, 0xc0]));
+    assert_eq!("fldt   0x8(%rsp)", text(&[0xdb, 0x6c, 0x24, 0x08]));
+}
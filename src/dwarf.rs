@@ -0,0 +1,1571 @@
+use binary;
+use log;
+use std::collections::HashMap;
+
+pub const DW_FORM_ADDR: u64 = 0x01;
+pub const DW_FORM_BLOCK2: u64 = 0x03;
+pub const DW_FORM_BLOCK4: u64 = 0x04;
+pub const DW_FORM_DATA2: u64 = 0x05;
+pub const DW_FORM_DATA4: u64 = 0x06;
+pub const DW_FORM_DATA8: u64 = 0x07;
+pub const DW_FORM_STRING: u64 = 0x08;
+pub const DW_FORM_BLOCK: u64 = 0x09;
+pub const DW_FORM_BLOCK1: u64 = 0x0a;
+pub const DW_FORM_DATA1: u64 = 0x0b;
+pub const DW_FORM_FLAG: u64 = 0x0c;
+pub const DW_FORM_SDATA: u64 = 0x0d;
+pub const DW_FORM_STRP: u64 = 0x0e;
+pub const DW_FORM_UDATA: u64 = 0x0f;
+pub const DW_FORM_REF_ADDR: u64 = 0x10;
+pub const DW_FORM_REF1: u64 = 0x11;
+pub const DW_FORM_REF2: u64 = 0x12;
+pub const DW_FORM_REF4: u64 = 0x13;
+pub const DW_FORM_REF8: u64 = 0x14;
+pub const DW_FORM_REF_UDATA: u64 = 0x15;
+pub const DW_FORM_INDIRECT: u64 = 0x16;
+pub const DW_FORM_SEC_OFFSET: u64 = 0x17;
+pub const DW_FORM_EXPRLOC: u64 = 0x18;
+pub const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+pub const DW_FORM_STRX: u64 = 0x1a;
+pub const DW_FORM_ADDRX: u64 = 0x1b;
+pub const DW_FORM_REF_SUP4: u64 = 0x1c;
+pub const DW_FORM_STRP_SUP: u64 = 0x1d;
+pub const DW_FORM_DATA16: u64 = 0x1e;
+pub const DW_FORM_LINE_STRP: u64 = 0x1f;
+pub const DW_FORM_REF_SIG8: u64 = 0x20;
+pub const DW_FORM_IMPLICIT_CONST: u64 = 0x21;
+pub const DW_FORM_LOCLISTX: u64 = 0x22;
+pub const DW_FORM_RNGLISTX: u64 = 0x23;
+pub const DW_FORM_REF_SUP8: u64 = 0x24;
+pub const DW_FORM_STRX1: u64 = 0x25;
+pub const DW_FORM_STRX2: u64 = 0x26;
+pub const DW_FORM_STRX3: u64 = 0x27;
+pub const DW_FORM_STRX4: u64 = 0x28;
+pub const DW_FORM_ADDRX1: u64 = 0x29;
+pub const DW_FORM_ADDRX2: u64 = 0x2a;
+pub const DW_FORM_ADDRX3: u64 = 0x2b;
+pub const DW_FORM_ADDRX4: u64 = 0x2c;
+
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_NEGATE_STMT: u8 = 6;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+const DW_LNE_DEFINE_FILE: u8 = 3;
+
+const DW_LNCT_PATH: u64 = 1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data: data,
+            pos: 0,
+        }
+    }
+
+    pub fn at(data: &'a [u8], pos: usize) -> Self {
+        Self {
+            data: data,
+            pos: pos,
+        }
+    }
+
+    pub fn pos(&self) -> usize { self.pos }
+    pub fn is_empty(&self) -> bool { self.pos >= self.data.len() }
+
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.pos > self.data.len() || len > self.data.len() - self.pos {
+            return Err(format!("DWARF data truncated at 0x{:x}", self.pos));
+        }
+        let r = &self.data[self.pos..self.pos+len];
+        self.pos += len;
+        return Ok(r);
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<(), String> {
+        try!(self.bytes(len));
+        return Ok(());
+    }
+
+    pub fn uint(&mut self, size: usize) -> Result<u64, String> {
+        if size > 8 {
+            return Err(format!("Unsupported integer size {} at 0x{:x}",
+                               size, self.pos));
+        }
+        let b = try!(self.bytes(size));
+        let mut r: u64 = 0;
+        for i in 0..size {
+            r |= (b[i] as u64) << (i * 8);
+        }
+        return Ok(r);
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(try!(self.uint(1)) as u8)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        Ok(try!(self.uint(2)) as u16)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(try!(self.uint(4)) as u32)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        self.uint(8)
+    }
+
+    pub fn uleb(&mut self) -> Result<u64, String> {
+        let mut r: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let b = try!(self.u8());
+            if shift < 64 {
+                r |= ((b & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if b & 0x80 == 0 {
+                return Ok(r);
+            }
+        }
+    }
+
+    pub fn sleb(&mut self) -> Result<i64, String> {
+        let mut r: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let b = try!(self.u8());
+            if shift < 64 {
+                r |= ((b & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if b & 0x80 == 0 {
+                if shift < 64 && (b & 0x40) != 0 {
+                    r |= -1 << shift;
+                }
+                return Ok(r);
+            }
+        }
+    }
+
+    pub fn cstr(&mut self) -> Result<&'a str, String> {
+        let start = self.pos;
+        while self.pos < self.data.len() && self.data[self.pos] != 0 {
+            self.pos += 1;
+        }
+        if self.pos >= self.data.len() {
+            return Err(format!("Unterminated string at 0x{:x}", start));
+        }
+        self.pos += 1;
+        return ::std::str::from_utf8(&self.data[start..self.pos-1]).or(
+            Err(format!("Invalid string at 0x{:x}", start)));
+    }
+
+    // Reads an initial length and returns (length, offset size).
+    pub fn initial_length(&mut self) -> Result<(usize, usize), String> {
+        let len = try!(self.u32());
+        if len == 0xffffffff {
+            return Ok((try!(self.u64()) as usize, 8));
+        }
+        return Ok((len as usize, 4));
+    }
+}
+
+pub fn cstr_at<'a>(data: &'a [u8], off: usize) -> Result<&'a str, String> {
+    Reader::at(data, off).cstr()
+}
+
+// Raw contents of the DWARF sections of a binary.
+#[derive(Clone, Copy, Default)]
+pub struct Sections<'a> {
+    pub debug_info: &'a [u8],
+    pub debug_abbrev: &'a [u8],
+    pub debug_str: &'a [u8],
+    pub debug_line_str: &'a [u8],
+    pub debug_str_offsets: &'a [u8],
+    pub debug_addr: &'a [u8],
+    pub debug_line: &'a [u8],
+    pub debug_ranges: &'a [u8],
+    pub debug_rnglists: &'a [u8],
+    pub debug_loc: &'a [u8],
+    pub debug_loclists: &'a [u8],
+}
+
+impl<'a> Sections<'a> {
+    pub fn new(bin: &binary::Binary<'a>) -> Self {
+        let get = |name| bin.section(name).map(|s| s.data).unwrap_or(&[]);
+        Self {
+            debug_info: get(".debug_info"),
+            debug_abbrev: get(".debug_abbrev"),
+            debug_str: get(".debug_str"),
+            debug_line_str: get(".debug_line_str"),
+            debug_str_offsets: get(".debug_str_offsets"),
+            debug_addr: get(".debug_addr"),
+            debug_line: get(".debug_line"),
+            debug_ranges: get(".debug_ranges"),
+            debug_rnglists: get(".debug_rnglists"),
+            debug_loc: get(".debug_loc"),
+            debug_loclists: get(".debug_loclists"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FormValue<'a> {
+    Udata(u64),
+    Sdata(i64),
+    Addr(u64),
+    // An absolute offset in .debug_info.
+    Ref(usize),
+    Str(&'a str),
+    Block(&'a [u8]),
+    Flag(bool),
+    SecOffset(u64),
+    // Indices which are resolved by DebugInfo once the unit's bases are known.
+    StrIndex(u64),
+    AddrIndex(u64),
+    ListIndex(u64),
+}
+
+impl<'a> FormValue<'a> {
+    pub fn udata(&self) -> Option<u64> {
+        match *self {
+            FormValue::Udata(v) => Some(v),
+            FormValue::Sdata(v) => Some(v as u64),
+            FormValue::Addr(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn sdata(&self) -> Option<i64> {
+        match *self {
+            FormValue::Udata(v) => Some(v as i64),
+            FormValue::Sdata(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn str(&self) -> Option<&'a str> {
+        match *self {
+            FormValue::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn reference(&self) -> Option<usize> {
+        match *self {
+            FormValue::Ref(r) => Some(r),
+            _ => None,
+        }
+    }
+
+    pub fn flag(&self) -> bool {
+        match *self {
+            FormValue::Flag(f) => f,
+            _ => false,
+        }
+    }
+}
+
+// Unit-level parameters which are needed to decode attribute forms.
+#[derive(Clone, Copy)]
+pub struct FormContext {
+    pub version: u16,
+    pub offset_size: usize,
+    pub addr_size: usize,
+    pub unit_offset: usize,
+}
+
+pub fn read_form<'a>(r: &mut Reader<'a>, form: u64, ctx: &FormContext,
+                     secs: &Sections<'a>) -> Result<FormValue<'a>, String> {
+    let unit_ref = |off: u64| FormValue::Ref(ctx.unit_offset + off as usize);
+    let v = match form {
+        DW_FORM_ADDR => FormValue::Addr(try!(r.uint(ctx.addr_size))),
+        DW_FORM_DATA1 => FormValue::Udata(try!(r.uint(1))),
+        DW_FORM_DATA2 => FormValue::Udata(try!(r.uint(2))),
+        DW_FORM_DATA4 => FormValue::Udata(try!(r.uint(4))),
+        DW_FORM_DATA8 | DW_FORM_REF_SIG8 => FormValue::Udata(try!(r.uint(8))),
+        DW_FORM_DATA16 => FormValue::Block(try!(r.bytes(16))),
+        DW_FORM_UDATA => FormValue::Udata(try!(r.uleb())),
+        DW_FORM_SDATA => FormValue::Sdata(try!(r.sleb())),
+        DW_FORM_REF1 => unit_ref(try!(r.uint(1))),
+        DW_FORM_REF2 => unit_ref(try!(r.uint(2))),
+        DW_FORM_REF4 => unit_ref(try!(r.uint(4))),
+        DW_FORM_REF8 => unit_ref(try!(r.uint(8))),
+        DW_FORM_REF_UDATA => unit_ref(try!(r.uleb())),
+        DW_FORM_REF_ADDR => {
+            let size = if ctx.version <= 2 {
+                ctx.addr_size
+            } else {
+                ctx.offset_size
+            };
+            FormValue::Ref(try!(r.uint(size)) as usize)
+        }
+        DW_FORM_REF_SUP4 => FormValue::Udata(try!(r.uint(4))),
+        DW_FORM_REF_SUP8 => FormValue::Udata(try!(r.uint(8))),
+        DW_FORM_STRING => FormValue::Str(try!(r.cstr())),
+        DW_FORM_STRP | DW_FORM_STRP_SUP => {
+            let off = try!(r.uint(ctx.offset_size)) as usize;
+            FormValue::Str(try!(cstr_at(secs.debug_str, off)))
+        }
+        DW_FORM_LINE_STRP => {
+            let off = try!(r.uint(ctx.offset_size)) as usize;
+            FormValue::Str(try!(cstr_at(secs.debug_line_str, off)))
+        }
+        DW_FORM_STRX => FormValue::StrIndex(try!(r.uleb())),
+        DW_FORM_STRX1 => FormValue::StrIndex(try!(r.uint(1))),
+        DW_FORM_STRX2 => FormValue::StrIndex(try!(r.uint(2))),
+        DW_FORM_STRX3 => FormValue::StrIndex(try!(r.uint(3))),
+        DW_FORM_STRX4 => FormValue::StrIndex(try!(r.uint(4))),
+        DW_FORM_ADDRX => FormValue::AddrIndex(try!(r.uleb())),
+        DW_FORM_ADDRX1 => FormValue::AddrIndex(try!(r.uint(1))),
+        DW_FORM_ADDRX2 => FormValue::AddrIndex(try!(r.uint(2))),
+        DW_FORM_ADDRX3 => FormValue::AddrIndex(try!(r.uint(3))),
+        DW_FORM_ADDRX4 => FormValue::AddrIndex(try!(r.uint(4))),
+        DW_FORM_LOCLISTX | DW_FORM_RNGLISTX =>
+            FormValue::ListIndex(try!(r.uleb())),
+        DW_FORM_SEC_OFFSET =>
+            FormValue::SecOffset(try!(r.uint(ctx.offset_size))),
+        DW_FORM_BLOCK1 => {
+            let len = try!(r.u8()) as usize;
+            FormValue::Block(try!(r.bytes(len)))
+        }
+        DW_FORM_BLOCK2 => {
+            let len = try!(r.u16()) as usize;
+            FormValue::Block(try!(r.bytes(len)))
+        }
+        DW_FORM_BLOCK4 => {
+            let len = try!(r.u32()) as usize;
+            FormValue::Block(try!(r.bytes(len)))
+        }
+        DW_FORM_BLOCK | DW_FORM_EXPRLOC => {
+            let len = try!(r.uleb()) as usize;
+            FormValue::Block(try!(r.bytes(len)))
+        }
+        DW_FORM_FLAG => FormValue::Flag(try!(r.u8()) != 0),
+        DW_FORM_FLAG_PRESENT => FormValue::Flag(true),
+        DW_FORM_INDIRECT => {
+            let form = try!(r.uleb());
+            return read_form(r, form, ctx, secs);
+        }
+        _ => {
+            return Err(format!("Unknown DWARF form: 0x{:x}", form));
+        }
+    };
+    return Ok(v);
+}
+
+pub struct LineRow {
+    pub addr: u64,
+    pub file: usize,
+    pub line: u32,
+    pub is_stmt: bool,
+    pub end_sequence: bool,
+}
+
+// All rows of .debug_line. Rows of a sequence are kept contiguous and
+// `file` indexes into `files`, which is shared by all units.
+pub struct LineTable {
+    pub files: Vec<String>,
+    pub rows: Vec<LineRow>,
+}
+
+fn join_path(dir: &str, name: &str) -> String {
+    if name.starts_with('/') || dir.is_empty() {
+        return name.to_string();
+    }
+    return format!("{}/{}", dir, name);
+}
+
+fn read_entry_formats(r: &mut Reader) -> Result<Vec<(u64, u64)>, String> {
+    let count = try!(r.u8());
+    let mut formats = vec!();
+    for _ in 0..count {
+        let content = try!(r.uleb());
+        let form = try!(r.uleb());
+        formats.push((content, form));
+    }
+    return Ok(formats);
+}
+
+// Reads a DWARF 5 directory or file name table as (path, dir index) pairs.
+fn read_entries<'a>(r: &mut Reader<'a>, ctx: &FormContext,
+                    secs: &Sections<'a>)
+                    -> Result<Vec<(&'a str, usize)>, String> {
+    let formats = try!(read_entry_formats(r));
+    let count = try!(r.uleb());
+    let mut entries = vec!();
+    for _ in 0..count {
+        let mut path = "";
+        let mut dir = 0;
+        for &(content, form) in &formats {
+            let v = try!(read_form(r, form, ctx, secs));
+            if content == DW_LNCT_PATH {
+                path = v.str().unwrap_or("");
+            } else if content == DW_LNCT_DIRECTORY_INDEX {
+                dir = v.udata().unwrap_or(0) as usize;
+            }
+        }
+        entries.push((path, dir));
+    }
+    return Ok(entries);
+}
+
+impl LineTable {
+    pub fn empty() -> Self {
+        Self {
+            files: vec!(),
+            rows: vec!(),
+        }
+    }
+
+    pub fn parse(secs: &Sections) -> Result<Self, String> {
+        let mut table = LineTable::empty();
+        let mut r = Reader::new(secs.debug_line);
+        while !r.is_empty() {
+            try!(table.parse_unit(&mut r, secs));
+        }
+        return Ok(table);
+    }
+
+    fn add_file(&mut self, path: String) -> usize {
+        match self.files.iter().position(|f| *f == path) {
+            Some(i) => i,
+            None => {
+                self.files.push(path);
+                self.files.len() - 1
+            }
+        }
+    }
+
+    fn parse_unit<'a>(&mut self, r: &mut Reader<'a>, secs: &Sections<'a>)
+                  -> Result<(), String> {
+        let (unit_len, offset_size) = try!(r.initial_length());
+        let malformed = |pos| format!("Malformed line program at 0x{:x}", pos);
+        let end = try!(r.pos().checked_add(unit_len)
+                       .ok_or_else(|| malformed(r.pos())));
+        let version = try!(r.u16());
+        let mut addr_size = 8;
+        if version >= 5 {
+            addr_size = try!(r.u8()) as usize;
+            try!(r.u8());
+        }
+        let header_len = try!(r.uint(offset_size)) as usize;
+        let program_start = try!(r.pos().checked_add(header_len)
+                                 .ok_or_else(|| malformed(r.pos())));
+        let min_inst_len = try!(r.u8()) as u64;
+        if version >= 4 {
+            try!(r.u8());
+        }
+        let default_is_stmt = try!(r.u8()) != 0;
+        let line_base = try!(r.u8()) as i8 as i64;
+        let line_range = try!(r.u8()) as u64;
+        if line_range == 0 {
+            return Err(malformed(r.pos() - 1));
+        }
+        let opcode_base = try!(r.u8());
+        let mut std_lengths = vec!();
+        for _ in 1..opcode_base {
+            std_lengths.push(try!(r.u8()));
+        }
+
+        // Map from the unit's file numbers to indices of `self.files`.
+        let mut files = vec!();
+        if version >= 5 {
+            let ctx = FormContext {
+                version: version,
+                offset_size: offset_size,
+                addr_size: addr_size,
+                unit_offset: 0,
+            };
+            let dirs = try!(read_entries(r, &ctx, secs));
+            for (name, dir) in try!(read_entries(r, &ctx, secs)) {
+                // Directory 0 is the compilation directory, which is
+                // implicit before DWARF 5.
+                let dir = if dir == 0 {
+                    ""
+                } else {
+                    dirs.get(dir).map(|d| d.0).unwrap_or("")
+                };
+                let path = join_path(dir, name);
+                files.push(self.add_file(path));
+            }
+        } else {
+            let mut dirs = vec!();
+            loop {
+                let dir = try!(r.cstr());
+                if dir.is_empty() {
+                    break;
+                }
+                dirs.push(dir);
+            }
+            // File numbers start from 1 before DWARF 5.
+            files.push(0);
+            loop {
+                let name = try!(r.cstr());
+                if name.is_empty() {
+                    break;
+                }
+                let dir = try!(r.uleb()) as usize;
+                try!(r.uleb());
+                try!(r.uleb());
+                let dir = if dir == 0 {
+                    ""
+                } else {
+                    try!(dirs.get(dir - 1).ok_or_else(|| malformed(r.pos())))
+                };
+                let path = join_path(dir, name);
+                files.push(self.add_file(path));
+            }
+        }
+
+        r.seek(program_start);
+        let mut addr: u64 = 0;
+        let mut file = 1;
+        let mut line: i64 = 1;
+        let mut is_stmt = default_is_stmt;
+        while r.pos() < end {
+            let op = try!(r.u8());
+            let mut emit = false;
+            let mut end_sequence = false;
+            if op >= opcode_base {
+                let adj = (op - opcode_base) as u64;
+                addr = addr.wrapping_add((adj / line_range) * min_inst_len);
+                line = line.wrapping_add(line_base + (adj % line_range) as i64);
+                emit = true;
+            } else if op == 0 {
+                let len = try!(r.uleb()) as usize;
+                if len == 0 || r.pos() > end || len > end - r.pos() {
+                    return Err(malformed(r.pos()));
+                }
+                let next = r.pos() + len;
+                match try!(r.u8()) {
+                    DW_LNE_END_SEQUENCE => {
+                        emit = true;
+                        end_sequence = true;
+                    }
+                    DW_LNE_SET_ADDRESS => {
+                        addr = try!(r.uint(len - 1));
+                    }
+                    DW_LNE_DEFINE_FILE => {
+                        let name = try!(r.cstr());
+                        files.push(self.add_file(name.to_string()));
+                    }
+                    _ => {}
+                }
+                r.seek(next);
+            } else {
+                match op {
+                    DW_LNS_COPY => {
+                        emit = true;
+                    }
+                    DW_LNS_ADVANCE_PC => {
+                        addr = addr.wrapping_add(
+                            try!(r.uleb()).wrapping_mul(min_inst_len));
+                    }
+                    DW_LNS_ADVANCE_LINE => {
+                        line = line.wrapping_add(try!(r.sleb()));
+                    }
+                    DW_LNS_SET_FILE => {
+                        file = try!(r.uleb()) as usize;
+                    }
+                    DW_LNS_NEGATE_STMT => {
+                        is_stmt = !is_stmt;
+                    }
+                    DW_LNS_CONST_ADD_PC => {
+                        let adj = (255 - opcode_base) as u64;
+                        addr = addr.wrapping_add(
+                            (adj / line_range) * min_inst_len);
+                    }
+                    DW_LNS_FIXED_ADVANCE_PC => {
+                        addr = addr.wrapping_add(try!(r.u16()) as u64);
+                    }
+                    _ => {
+                        for _ in 0..std_lengths[op as usize - 1] {
+                            try!(r.uleb());
+                        }
+                    }
+                }
+            }
+
+            if emit {
+                self.rows.push(LineRow {
+                    addr: addr,
+                    file: files.get(file).map(|f| *f).unwrap_or(0),
+                    line: line as u32,
+                    is_stmt: is_stmt,
+                    end_sequence: end_sequence,
+                });
+            }
+            if end_sequence {
+                addr = 0;
+                file = 1;
+                line = 1;
+                is_stmt = default_is_stmt;
+            }
+        }
+        r.seek(end);
+        return Ok(());
+    }
+
+    fn file_matches(&self, index: usize, name: &str) -> bool {
+        let path = &self.files[index];
+        return path == name || path.ends_with(&format!("/{}", name));
+    }
+
+    pub fn has_file(&self, name: &str) -> bool {
+        (0..self.files.len()).any(|i| self.file_matches(i, name))
+    }
+
+    // Returns the lowest address for `line` in `file`. If no code is
+    // generated for the line, the next line which has code is used.
+    pub fn find_line(&self, file: &str, line: u32) -> Option<(u64, u32)> {
+        let mut best: Option<(u64, u32)> = None;
+        for row in &self.rows {
+            if row.end_sequence || !row.is_stmt || row.line < line ||
+                !self.file_matches(row.file, file) {
+                continue;
+            }
+            best = match best {
+                Some((addr, l)) if l < row.line ||
+                    (l == row.line && addr <= row.addr) => Some((addr, l)),
+                _ => Some((row.addr, row.line)),
+            };
+        }
+        return best;
+    }
+
+    // Returns the file and the line which contain `addr`.
+    pub fn find_addr(&self, addr: u64) -> Option<(&str, u32)> {
+        for i in 1..self.rows.len() {
+            let prev = &self.rows[i-1];
+            if prev.end_sequence {
+                continue;
+            }
+            if prev.addr <= addr && addr < self.rows[i].addr {
+                return Some((&self.files[prev.file], prev.line));
+            }
+        }
+        return None;
+    }
+}
+
+pub const DW_TAG_FORMAL_PARAMETER: u64 = 0x05;
+pub const DW_TAG_LEXICAL_BLOCK: u64 = 0x0b;
+pub const DW_TAG_POINTER_TYPE: u64 = 0x0f;
+pub const DW_TAG_TYPEDEF: u64 = 0x16;
+pub const DW_TAG_INLINED_SUBROUTINE: u64 = 0x1d;
+pub const DW_TAG_BASE_TYPE: u64 = 0x24;
+pub const DW_TAG_CONST_TYPE: u64 = 0x26;
+pub const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+pub const DW_TAG_VARIABLE: u64 = 0x34;
+pub const DW_TAG_VOLATILE_TYPE: u64 = 0x35;
+
+pub const DW_ATE_BOOLEAN: u64 = 0x02;
+pub const DW_ATE_FLOAT: u64 = 0x04;
+pub const DW_ATE_SIGNED: u64 = 0x05;
+pub const DW_ATE_SIGNED_CHAR: u64 = 0x06;
+pub const DW_ATE_UNSIGNED: u64 = 0x07;
+pub const DW_ATE_UNSIGNED_CHAR: u64 = 0x08;
+
+pub const DW_AT_LOCATION: u64 = 0x02;
+pub const DW_AT_NAME: u64 = 0x03;
+pub const DW_AT_BYTE_SIZE: u64 = 0x0b;
+pub const DW_AT_LOW_PC: u64 = 0x11;
+pub const DW_AT_HIGH_PC: u64 = 0x12;
+pub const DW_AT_ABSTRACT_ORIGIN: u64 = 0x31;
+pub const DW_AT_DECLARATION: u64 = 0x3c;
+pub const DW_AT_ENCODING: u64 = 0x3e;
+pub const DW_AT_FRAME_BASE: u64 = 0x40;
+pub const DW_AT_SPECIFICATION: u64 = 0x47;
+pub const DW_AT_TYPE: u64 = 0x49;
+pub const DW_AT_RANGES: u64 = 0x55;
+pub const DW_AT_STR_OFFSETS_BASE: u64 = 0x72;
+pub const DW_AT_ADDR_BASE: u64 = 0x73;
+pub const DW_AT_RNGLISTS_BASE: u64 = 0x74;
+pub const DW_AT_LOCLISTS_BASE: u64 = 0x8c;
+
+const DW_CHILDREN_YES: u8 = 1;
+
+struct AttrSpec {
+    at: u64,
+    form: u64,
+    implicit_const: i64,
+}
+
+struct Abbrev {
+    tag: u64,
+    has_children: bool,
+    attrs: Vec<AttrSpec>,
+}
+
+fn parse_abbrevs(data: &[u8], offset: usize)
+                 -> Result<HashMap<u64, Abbrev>, String> {
+    let mut abbrevs = HashMap::new();
+    let mut r = Reader::at(data, offset);
+    loop {
+        let code = try!(r.uleb());
+        if code == 0 {
+            return Ok(abbrevs);
+        }
+        let tag = try!(r.uleb());
+        let has_children = try!(r.u8()) == DW_CHILDREN_YES;
+        let mut attrs = vec!();
+        loop {
+            let at = try!(r.uleb());
+            let form = try!(r.uleb());
+            if at == 0 && form == 0 {
+                break;
+            }
+            let implicit_const = if form == DW_FORM_IMPLICIT_CONST {
+                try!(r.sleb())
+            } else {
+                0
+            };
+            attrs.push(AttrSpec {
+                at: at,
+                form: form,
+                implicit_const: implicit_const,
+            });
+        }
+        abbrevs.insert(code, Abbrev {
+            tag: tag,
+            has_children: has_children,
+            attrs: attrs,
+        });
+    }
+}
+
+pub struct Unit {
+    pub form_ctx: FormContext,
+    pub root: usize,
+    pub base_addr: u64,
+    addr_base: usize,
+    str_offsets_base: usize,
+    rnglists_base: usize,
+    loclists_base: usize,
+}
+
+pub struct Die<'a> {
+    pub tag: u64,
+    pub attrs: Vec<(u64, FormValue<'a>)>,
+    pub children: Vec<usize>,
+    pub unit: usize,
+}
+
+impl<'a> Die<'a> {
+    pub fn attr(&self, at: u64) -> Option<&FormValue<'a>> {
+        self.attrs.iter().find(|a| a.0 == at).map(|a| &a.1)
+    }
+
+    pub fn has_attr(&self, at: u64) -> bool {
+        self.attr(at).is_some()
+    }
+
+    pub fn udata(&self, at: u64) -> Option<u64> {
+        self.attr(at).and_then(|v| v.udata())
+    }
+
+    pub fn reference(&self, at: u64) -> Option<usize> {
+        self.attr(at).and_then(|v| v.reference())
+    }
+}
+
+// The location of an object, as computed by a DWARF expression.
+#[derive(Debug, PartialEq)]
+pub enum Location<'a> {
+    Memory(u64),
+    Register(u16),
+    Value(u64),
+    Implicit(&'a [u8]),
+    OptimizedOut,
+}
+
+// The machine state which location expressions refer to.
+pub trait Frame {
+    fn reg(&self, reg: u16) -> Result<u64, String>;
+    fn frame_base(&self) -> Result<u64, String>;
+    fn cfa(&self) -> Result<u64, String>;
+    fn read_word(&self, addr: u64) -> Result<u64, String>;
+}
+
+pub struct DebugInfo<'a> {
+    secs: Sections<'a>,
+    units: Vec<Unit>,
+    dies: Vec<Die<'a>>,
+    by_offset: HashMap<usize, usize>,
+}
+
+impl<'a> DebugInfo<'a> {
+    pub fn empty(secs: Sections<'a>) -> Self {
+        Self {
+            secs: secs,
+            units: vec!(),
+            dies: vec!(),
+            by_offset: HashMap::new(),
+        }
+    }
+
+    pub fn parse(secs: Sections<'a>) -> Result<Self, String> {
+        let mut info = DebugInfo::empty(secs);
+        let mut abbrev_cache = HashMap::new();
+        let mut r = Reader::new(secs.debug_info);
+        while !r.is_empty() {
+            try!(info.parse_unit(&mut r, &mut abbrev_cache));
+        }
+        return Ok(info);
+    }
+
+    fn parse_unit(&mut self, r: &mut Reader<'a>,
+                  abbrev_cache: &mut HashMap<usize, HashMap<u64, Abbrev>>)
+                  -> Result<(), String> {
+        let unit_offset = r.pos();
+        let (unit_len, offset_size) = try!(r.initial_length());
+        let end = r.pos() + unit_len;
+        let version = try!(r.u16());
+        let abbrev_off;
+        let addr_size;
+        if version >= 5 {
+            let unit_type = try!(r.u8());
+            addr_size = try!(r.u8()) as usize;
+            abbrev_off = try!(r.uint(offset_size)) as usize;
+            match unit_type {
+                // DW_UT_skeleton and DW_UT_split_compile.
+                4 | 5 => try!(r.skip(8)),
+                // DW_UT_type and DW_UT_split_type.
+                2 | 6 => try!(r.skip(8 + offset_size)),
+                _ => {}
+            }
+        } else {
+            abbrev_off = try!(r.uint(offset_size)) as usize;
+            addr_size = try!(r.u8()) as usize;
+        }
+        if !abbrev_cache.contains_key(&abbrev_off) {
+            let abbrevs = try!(parse_abbrevs(self.secs.debug_abbrev,
+                                             abbrev_off));
+            abbrev_cache.insert(abbrev_off, abbrevs);
+        }
+        let abbrevs = &abbrev_cache[&abbrev_off];
+
+        let form_ctx = FormContext {
+            version: version,
+            offset_size: offset_size,
+            addr_size: addr_size,
+            unit_offset: unit_offset,
+        };
+        let unit_index = self.units.len();
+        let first_die = self.dies.len();
+        let mut parents: Vec<usize> = vec!();
+        while r.pos() < end {
+            let offset = r.pos();
+            let code = try!(r.uleb());
+            if code == 0 {
+                parents.pop();
+                continue;
+            }
+            let abbrev = try!(abbrevs.get(&code).ok_or(
+                format!("Unknown abbrev {} at 0x{:x}", code, offset)));
+            let mut attrs = vec!();
+            for spec in &abbrev.attrs {
+                let v = if spec.form == DW_FORM_IMPLICIT_CONST {
+                    FormValue::Sdata(spec.implicit_const)
+                } else {
+                    try!(read_form(r, spec.form, &form_ctx, &self.secs))
+                };
+                attrs.push((spec.at, v));
+            }
+            let index = self.dies.len();
+            if let Some(&parent) = parents.last() {
+                self.dies[parent].children.push(index);
+            }
+            self.dies.push(Die {
+                tag: abbrev.tag,
+                attrs: attrs,
+                children: vec!(),
+                unit: unit_index,
+            });
+            self.by_offset.insert(offset, index);
+            if abbrev.has_children {
+                parents.push(index);
+            }
+        }
+        r.seek(end);
+        if first_die == self.dies.len() {
+            return Ok(());
+        }
+
+        let mut unit = Unit {
+            form_ctx: form_ctx,
+            root: first_die,
+            base_addr: 0,
+            // Defaults are the sizes of the section headers.
+            addr_base: 8,
+            str_offsets_base: 8,
+            rnglists_base: 12,
+            loclists_base: 12,
+        };
+        {
+            let root = &self.dies[first_die];
+            let base = |at, default| match root.attr(at) {
+                Some(&FormValue::SecOffset(v)) => v as usize,
+                _ => default,
+            };
+            unit.addr_base = base(DW_AT_ADDR_BASE, unit.addr_base);
+            unit.str_offsets_base = base(DW_AT_STR_OFFSETS_BASE,
+                                         unit.str_offsets_base);
+            unit.rnglists_base = base(DW_AT_RNGLISTS_BASE,
+                                      unit.rnglists_base);
+            unit.loclists_base = base(DW_AT_LOCLISTS_BASE,
+                                      unit.loclists_base);
+        }
+
+        // Resolve indirect strings and addresses now the bases are known.
+        for i in first_die..self.dies.len() {
+            for j in 0..self.dies[i].attrs.len() {
+                let v = match self.dies[i].attrs[j].1 {
+                    FormValue::StrIndex(index) => {
+                        let off = try!(self.read_offset(
+                            &unit, self.secs.debug_str_offsets,
+                            unit.str_offsets_base, index));
+                        FormValue::Str(try!(cstr_at(self.secs.debug_str, off)))
+                    }
+                    FormValue::AddrIndex(index) => {
+                        FormValue::Addr(try!(self.read_addr(&unit, index)))
+                    }
+                    _ => continue,
+                };
+                self.dies[i].attrs[j].1 = v;
+            }
+        }
+        unit.base_addr = self.dies[first_die].udata(DW_AT_LOW_PC).unwrap_or(0);
+        self.units.push(unit);
+        return Ok(());
+    }
+
+    fn read_offset(&self, unit: &Unit, data: &[u8], base: usize, index: u64)
+                   -> Result<usize, String> {
+        let size = unit.form_ctx.offset_size;
+        let mut r = Reader::at(data, base + index as usize * size);
+        return Ok(try!(r.uint(size)) as usize);
+    }
+
+    fn read_addr(&self, unit: &Unit, index: u64) -> Result<u64, String> {
+        let size = unit.form_ctx.addr_size;
+        let mut r = Reader::at(self.secs.debug_addr,
+                               unit.addr_base + index as usize * size);
+        return r.uint(size);
+    }
+
+    pub fn die(&self, index: usize) -> &Die<'a> { &self.dies[index] }
+
+    pub fn addr_size(&self, index: usize) -> usize {
+        self.units[self.dies[index].unit].form_ctx.addr_size
+    }
+
+    pub fn die_at(&self, offset: usize) -> Option<usize> {
+        self.by_offset.get(&offset).map(|i| *i)
+    }
+
+    // Follows DW_AT_abstract_origin and DW_AT_specification to find the DIE
+    // which has the attribute.
+    pub fn attr(&self, index: usize, at: u64) -> Option<FormValue<'a>> {
+        let mut index = index;
+        for _ in 0..8 {
+            let die = &self.dies[index];
+            if let Some(v) = die.attr(at) {
+                return Some(*v);
+            }
+            let origin = die.reference(DW_AT_ABSTRACT_ORIGIN).or(
+                die.reference(DW_AT_SPECIFICATION));
+            match origin.and_then(|o| self.die_at(o)) {
+                Some(o) => index = o,
+                None => return None,
+            }
+        }
+        return None;
+    }
+
+    pub fn name(&self, index: usize) -> Option<&'a str> {
+        self.attr(index, DW_AT_NAME).and_then(|v| v.str())
+    }
+
+    pub fn type_of(&self, index: usize) -> Option<usize> {
+        self.attr(index, DW_AT_TYPE)
+            .and_then(|v| v.reference())
+            .and_then(|o| self.die_at(o))
+    }
+
+    // Returns the address ranges covered by a DIE as [start, end) pairs.
+    pub fn ranges(&self, index: usize) -> Vec<(u64, u64)> {
+        let die = &self.dies[index];
+        let unit = &self.units[die.unit];
+        if let Some(low) = die.udata(DW_AT_LOW_PC) {
+            return match die.attr(DW_AT_HIGH_PC) {
+                Some(&FormValue::Addr(high)) => vec!((low, high)),
+                Some(v) => match v.udata() {
+                    Some(len) => vec!((low, low + len)),
+                    None => vec!(),
+                },
+                None => vec!((low, low + 1)),
+            };
+        }
+        let r = match die.attr(DW_AT_RANGES) {
+            Some(&FormValue::ListIndex(index)) => {
+                self.read_offset(unit, self.secs.debug_rnglists,
+                                 unit.rnglists_base, index)
+                    .map(|off| off + unit.rnglists_base)
+            }
+            Some(v) => match v.udata().or(match *v {
+                FormValue::SecOffset(off) => Some(off),
+                _ => None,
+            }) {
+                Some(off) => Ok(off as usize),
+                None => return vec!(),
+            },
+            None => return vec!(),
+        };
+        let r = match r {
+            Ok(off) => self.read_ranges(unit, off),
+            Err(msg) => Err(msg),
+        };
+        match r {
+            Ok(ranges) => ranges,
+            Err(msg) => {
+                log_info!("Failed to read ranges: {}", msg);
+                vec!()
+            }
+        }
+    }
+
+    fn read_ranges(&self, unit: &Unit, off: usize)
+                   -> Result<Vec<(u64, u64)>, String> {
+        let addr_size = unit.form_ctx.addr_size;
+        let mut base = unit.base_addr;
+        let mut ranges = vec!();
+        if unit.form_ctx.version < 5 {
+            let mut r = Reader::at(self.secs.debug_ranges, off);
+            loop {
+                let start = try!(r.uint(addr_size));
+                let end = try!(r.uint(addr_size));
+                if start == 0 && end == 0 {
+                    return Ok(ranges);
+                }
+                if start == !0 >> (64 - addr_size * 8) {
+                    base = end;
+                } else {
+                    ranges.push((base + start, base + end));
+                }
+            }
+        }
+
+        let mut r = Reader::at(self.secs.debug_rnglists, off);
+        loop {
+            match try!(r.u8()) {
+                // DW_RLE_end_of_list
+                0 => return Ok(ranges),
+                // DW_RLE_base_addressx
+                1 => base = try!(self.read_addr(unit, try!(r.uleb()))),
+                // DW_RLE_startx_endx
+                2 => {
+                    let start = try!(self.read_addr(unit, try!(r.uleb())));
+                    let end = try!(self.read_addr(unit, try!(r.uleb())));
+                    ranges.push((start, end));
+                }
+                // DW_RLE_startx_length
+                3 => {
+                    let start = try!(self.read_addr(unit, try!(r.uleb())));
+                    ranges.push((start, start + try!(r.uleb())));
+                }
+                // DW_RLE_offset_pair
+                4 => {
+                    let start = try!(r.uleb());
+                    let end = try!(r.uleb());
+                    ranges.push((base + start, base + end));
+                }
+                // DW_RLE_base_address
+                5 => base = try!(r.uint(addr_size)),
+                // DW_RLE_start_end
+                6 => {
+                    let start = try!(r.uint(addr_size));
+                    ranges.push((start, try!(r.uint(addr_size))));
+                }
+                // DW_RLE_start_length
+                7 => {
+                    let start = try!(r.uint(addr_size));
+                    ranges.push((start, start + try!(r.uleb())));
+                }
+                op => return Err(format!("Unknown range list entry: {}", op)),
+            }
+        }
+    }
+
+    pub fn contains(&self, index: usize, pc: u64) -> bool {
+        self.ranges(index).iter().any(|&(start, end)| start <= pc && pc < end)
+    }
+
+    // Returns the innermost subprogram which contains `pc`.
+    pub fn find_function(&self, pc: u64) -> Option<usize> {
+        for unit in &self.units {
+            let ranges = self.ranges(unit.root);
+            if !ranges.is_empty() &&
+                !ranges.iter().any(|&(start, end)| start <= pc && pc < end) {
+                continue;
+            }
+            if let Some(f) = self.find_function_in(unit.root, pc) {
+                return Some(f);
+            }
+        }
+        return None;
+    }
+
+    fn find_function_in(&self, index: usize, pc: u64) -> Option<usize> {
+        for &child in &self.dies[index].children {
+            let tag = self.dies[child].tag;
+            if tag != DW_TAG_SUBPROGRAM && tag != DW_TAG_LEXICAL_BLOCK &&
+                tag != DW_TAG_INLINED_SUBROUTINE {
+                continue;
+            }
+            if !self.contains(child, pc) {
+                continue;
+            }
+            if let Some(inner) = self.find_function_in(child, pc) {
+                return Some(inner);
+            }
+            if tag == DW_TAG_SUBPROGRAM {
+                return Some(child);
+            }
+        }
+        return None;
+    }
+
+    fn find_in_scope(&self, scope: usize, pc: Option<u64>, name: &str)
+                     -> Option<usize> {
+        // Look into nested blocks first so inner variables shadow outer ones.
+        if let Some(pc) = pc {
+            for &child in &self.dies[scope].children {
+                if self.dies[child].tag == DW_TAG_LEXICAL_BLOCK &&
+                    self.contains(child, pc) {
+                    if let Some(v) = self.find_in_scope(child, Some(pc), name) {
+                        return Some(v);
+                    }
+                }
+            }
+        }
+        for &child in &self.dies[scope].children {
+            let tag = self.dies[child].tag;
+            if (tag == DW_TAG_VARIABLE || tag == DW_TAG_FORMAL_PARAMETER) &&
+                self.name(child) == Some(name) &&
+                !self.dies[child].attr(DW_AT_DECLARATION).map_or(
+                    false, |d| d.flag()) {
+                return Some(child);
+            }
+        }
+        return None;
+    }
+
+    // Finds a variable visible from `pc`: locals of the enclosing function
+    // first, then globals of its unit, then globals of any unit.
+    pub fn find_variable(&self, pc: Option<u64>, name: &str) -> Option<usize> {
+        if let Some(pc) = pc {
+            if let Some(func) = self.find_function(pc) {
+                if let Some(v) = self.find_in_scope(func, Some(pc), name) {
+                    return Some(v);
+                }
+                let root = self.units[self.dies[func].unit].root;
+                if let Some(v) = self.find_in_scope(root, None, name) {
+                    return Some(v);
+                }
+            }
+        }
+        for unit in &self.units {
+            if let Some(v) = self.find_in_scope(unit.root, None, name) {
+                if self.dies[v].has_attr(DW_AT_LOCATION) {
+                    return Some(v);
+                }
+            }
+        }
+        return None;
+    }
+
+    // Finds a function definition by its name.
+    pub fn find_function_by_name(&self, name: &str) -> Option<usize> {
+        for unit in &self.units {
+            for &child in &self.dies[unit.root].children {
+                if self.dies[child].tag == DW_TAG_SUBPROGRAM &&
+                    self.name(child) == Some(name) &&
+                    !self.ranges(child).is_empty() {
+                    return Some(child);
+                }
+            }
+        }
+        return None;
+    }
+
+    // Finds a top-level type DIE whose tag satisfies `pred`. Complete
+    // definitions are preferred over declarations.
+    pub fn find_type<F>(&self, name: &str, pred: F) -> Option<usize>
+        where F: Fn(u64) -> bool {
+        let mut decl = None;
+        for unit in &self.units {
+            for &child in &self.dies[unit.root].children {
+                let die = &self.dies[child];
+                if !pred(die.tag) || self.name(child) != Some(name) {
+                    continue;
+                }
+                if die.attr(DW_AT_DECLARATION).map_or(false, |d| d.flag()) {
+                    decl = decl.or(Some(child));
+                } else {
+                    return Some(child);
+                }
+            }
+        }
+        return decl;
+    }
+
+    // Returns the location expression of a DIE's attribute which is valid
+    // at `pc`, choosing from a location list if necessary.
+    pub fn location_expr(&self, index: usize, at: u64, pc: u64)
+                         -> Result<Option<&'a [u8]>, String> {
+        let die = &self.dies[index];
+        let unit = &self.units[die.unit];
+        let off = match die.attr(at) {
+            None => return Ok(None),
+            Some(&FormValue::Block(expr)) => return Ok(Some(expr)),
+            Some(&FormValue::ListIndex(index)) => {
+                try!(self.read_offset(unit, self.secs.debug_loclists,
+                                      unit.loclists_base, index)) +
+                    unit.loclists_base
+            }
+            Some(&FormValue::SecOffset(off)) => off as usize,
+            Some(v) => match v.udata() {
+                Some(off) => off as usize,
+                None => return Err(format!("Invalid location: {:?}", v)),
+            },
+        };
+
+        let addr_size = unit.form_ctx.addr_size;
+        let mut base = unit.base_addr;
+        if unit.form_ctx.version < 5 {
+            let mut r = Reader::at(self.secs.debug_loc, off);
+            loop {
+                let start = try!(r.uint(addr_size));
+                let end = try!(r.uint(addr_size));
+                if start == 0 && end == 0 {
+                    return Ok(None);
+                }
+                if start == !0 >> (64 - addr_size * 8) {
+                    base = end;
+                    continue;
+                }
+                let len = try!(r.u16()) as usize;
+                let expr = try!(r.bytes(len));
+                if base + start <= pc && pc < base + end {
+                    return Ok(Some(expr));
+                }
+            }
+        }
+
+        let mut r = Reader::at(self.secs.debug_loclists, off);
+        loop {
+            let (start, end) = match try!(r.u8()) {
+                // DW_LLE_end_of_list
+                0 => return Ok(None),
+                // DW_LLE_base_addressx
+                1 => {
+                    base = try!(self.read_addr(unit, try!(r.uleb())));
+                    continue;
+                }
+                // DW_LLE_startx_endx
+                2 => (try!(self.read_addr(unit, try!(r.uleb()))),
+                      try!(self.read_addr(unit, try!(r.uleb())))),
+                // DW_LLE_startx_length
+                3 => {
+                    let start = try!(self.read_addr(unit, try!(r.uleb())));
+                    (start, start + try!(r.uleb()))
+                }
+                // DW_LLE_offset_pair
+                4 => {
+                    let start = try!(r.uleb());
+                    (base + start, base + try!(r.uleb()))
+                }
+                // DW_LLE_default_location
+                5 => (0, !0),
+                // DW_LLE_base_address
+                6 => {
+                    base = try!(r.uint(addr_size));
+                    continue;
+                }
+                // DW_LLE_start_end
+                7 => (try!(r.uint(addr_size)), try!(r.uint(addr_size))),
+                // DW_LLE_start_length
+                8 => {
+                    let start = try!(r.uint(addr_size));
+                    (start, start + try!(r.uleb()))
+                }
+                op => return Err(format!("Unknown location list entry: {}",
+                                         op)),
+            };
+            let len = try!(r.uleb()) as usize;
+            let expr = try!(r.bytes(len));
+            if start <= pc && pc < end {
+                return Ok(Some(expr));
+            }
+        }
+    }
+}
+
+// Evaluates a DWARF location expression.
+// Addresses given by DW_OP_addr are relocated by `bias`.
+pub fn eval_location<'a>(expr: &'a [u8], addr_size: usize, bias: u64,
+                         frame: &dyn Frame) -> Result<Location<'a>, String> {
+    if expr.is_empty() {
+        return Ok(Location::OptimizedOut);
+    }
+    let mut stack: Vec<u64> = vec!();
+    let mut r = Reader::new(expr);
+    macro_rules! pop {
+        () => (try!(stack.pop().ok_or("DWARF stack underflow".to_string())))
+    }
+    while !r.is_empty() {
+        let op = try!(r.u8());
+        match op {
+            // DW_OP_addr
+            0x03 => stack.push(try!(r.uint(addr_size)) + bias),
+            // DW_OP_deref
+            0x06 => {
+                let addr = pop!();
+                stack.push(try!(frame.read_word(addr)));
+            }
+            // DW_OP_const1u .. DW_OP_const8s
+            0x08 => stack.push(try!(r.uint(1))),
+            0x09 => stack.push(try!(r.u8()) as i8 as u64),
+            0x0a => stack.push(try!(r.uint(2))),
+            0x0b => stack.push(try!(r.u16()) as i16 as u64),
+            0x0c => stack.push(try!(r.uint(4))),
+            0x0d => stack.push(try!(r.u32()) as i32 as u64),
+            0x0e | 0x0f => stack.push(try!(r.uint(8))),
+            // DW_OP_constu and DW_OP_consts
+            0x10 => stack.push(try!(r.uleb())),
+            0x11 => stack.push(try!(r.sleb()) as u64),
+            // DW_OP_dup
+            0x12 => {
+                let v = pop!();
+                stack.push(v);
+                stack.push(v);
+            }
+            // DW_OP_drop
+            0x13 => {
+                pop!();
+            }
+            // DW_OP_over and DW_OP_pick
+            0x14 | 0x15 => {
+                let n = if op == 0x14 { 1 } else { try!(r.u8()) as usize };
+                if n >= stack.len() {
+                    return Err("DWARF stack underflow".to_string());
+                }
+                let v = stack[stack.len() - 1 - n];
+                stack.push(v);
+            }
+            // DW_OP_swap
+            0x16 => {
+                let a = pop!();
+                let b = pop!();
+                stack.push(a);
+                stack.push(b);
+            }
+            // DW_OP_rot
+            0x17 => {
+                let a = pop!();
+                let b = pop!();
+                let c = pop!();
+                stack.push(a);
+                stack.push(c);
+                stack.push(b);
+            }
+            // DW_OP_abs, DW_OP_neg and DW_OP_not
+            0x19 => {
+                let v = pop!() as i64;
+                stack.push(v.abs() as u64);
+            }
+            0x1f => {
+                let v = pop!() as i64;
+                stack.push(v.wrapping_neg() as u64);
+            }
+            0x20 => {
+                let v = pop!();
+                stack.push(!v);
+            }
+            // Binary operators.
+            0x1a | 0x1b | 0x1c | 0x1d | 0x1e | 0x21 | 0x22 | 0x24 | 0x25 |
+            0x26 | 0x27 | 0x29..=0x2e => {
+                let b = pop!();
+                let a = pop!();
+                let v = match op {
+                    0x1a => a & b,
+                    0x1b if b == 0 => return Err("Division by zero".to_string()),
+                    0x1b => ((a as i64) / (b as i64)) as u64,
+                    0x1c => a.wrapping_sub(b),
+                    0x1d if b == 0 => return Err("Division by zero".to_string()),
+                    0x1d => a % b,
+                    0x1e => a.wrapping_mul(b),
+                    0x21 => a | b,
+                    0x22 => a.wrapping_add(b),
+                    0x24 => a.wrapping_shl(b as u32),
+                    0x25 => a.wrapping_shr(b as u32),
+                    0x26 => (a as i64).wrapping_shr(b as u32) as u64,
+                    0x27 => a ^ b,
+                    0x29 => (a == b) as u64,
+                    0x2a => (a as i64 >= b as i64) as u64,
+                    0x2b => (a as i64 > b as i64) as u64,
+                    0x2c => (a as i64 <= b as i64) as u64,
+                    0x2d => ((a as i64) < (b as i64)) as u64,
+                    _ => (a != b) as u64,
+                };
+                stack.push(v);
+            }
+            // DW_OP_plus_uconst
+            0x23 => {
+                let v = pop!();
+                stack.push(v.wrapping_add(try!(r.uleb())));
+            }
+            // DW_OP_bra
+            0x28 => {
+                let off = try!(r.u16()) as i16;
+                if pop!() != 0 {
+                    let pos = r.pos() as i64 + off as i64;
+                    r.seek(pos as usize);
+                }
+            }
+            // DW_OP_skip
+            0x2f => {
+                let off = try!(r.u16()) as i16;
+                let pos = r.pos() as i64 + off as i64;
+                r.seek(pos as usize);
+            }
+            // DW_OP_lit0 .. DW_OP_lit31
+            0x30..=0x4f => stack.push((op - 0x30) as u64),
+            // DW_OP_reg0 .. DW_OP_reg31 and DW_OP_regx
+            0x50..=0x6f | 0x90 => {
+                let reg = if op == 0x90 {
+                    try!(r.uleb()) as u16
+                } else {
+                    (op - 0x50) as u16
+                };
+                if !r.is_empty() {
+                    return Err("Composite locations are not supported"
+                               .to_string());
+                }
+                return Ok(Location::Register(reg));
+            }
+            // DW_OP_breg0 .. DW_OP_breg31 and DW_OP_bregx
+            0x70..=0x8f | 0x92 => {
+                let reg = if op == 0x92 {
+                    try!(r.uleb()) as u16
+                } else {
+                    (op - 0x70) as u16
+                };
+                let off = try!(r.sleb());
+                let v = try!(frame.reg(reg));
+                stack.push(v.wrapping_add(off as u64));
+            }
+            // DW_OP_fbreg
+            0x91 => {
+                let off = try!(r.sleb());
+                let v = try!(frame.frame_base());
+                stack.push(v.wrapping_add(off as u64));
+            }
+            // DW_OP_deref_size
+            0x94 => {
+                let size = try!(r.u8()) as usize;
+                let addr = pop!();
+                let v = try!(frame.read_word(addr));
+                let v = if size >= 8 { v } else { v & ((1 << (size * 8)) - 1) };
+                stack.push(v);
+            }
+            // DW_OP_nop
+            0x96 => {}
+            // DW_OP_call_frame_cfa
+            0x9c => stack.push(try!(frame.cfa())),
+            // DW_OP_implicit_value
+            0x9e => {
+                let len = try!(r.uleb()) as usize;
+                return Ok(Location::Implicit(try!(r.bytes(len))));
+            }
+            // DW_OP_stack_value
+            0x9f => return Ok(Location::Value(pop!())),
+            _ => {
+                return Err(format!("Unsupported DWARF operation: 0x{:x}", op));
+            }
+        }
+    }
+    return Ok(Location::Memory(pop!()));
+}
+
+#[test]
+fn test_leb128() {
+    let data = [0xe5, 0x8e, 0x26, 0x7f, 0x80, 0x7f];
+    let mut r = Reader::new(&data);
+    assert_eq!(624485, r.uleb().unwrap());
+    assert_eq!(-1, r.sleb().unwrap());
+    assert_eq!(-128, r.sleb().unwrap());
+    assert!(r.is_empty());
+    assert!(r.u8().is_err());
+}
+
+#[test]
+fn test_line_table() {
+    let bin = binary::Binary::new("test/data/hello".to_string()).unwrap();
+    let lines = bin.lines();
+    assert!(lines.has_file("hello.c"));
+    assert!(!lines.has_file("ello.c"));
+    assert_eq!(Some((0x4005d0, 2)), lines.find_line("hello.c", 1));
+    assert_eq!(Some((0x4005e2, 3)), lines.find_line("hello.c", 3));
+    assert_eq!(None, lines.find_line("hello.c", 5));
+    assert_eq!(Some(("hello.c", 3)), lines.find_addr(0x4005e5));
+    assert_eq!(None, lines.find_addr(0x4005f4));
+}
+
+#[test]
+fn test_malformed_line_table() {
+    // A DWARF 3 unit with a file in directory `dir` and `program`.
+    let unit = |line_range: u8, dir: u8, program: &[u8]| {
+        let mut header = vec!(1, 1, 0xfb, line_range, 13);
+        header.extend(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        header.extend(b"\0a.c\0");
+        header.extend(&[dir, 0, 0, 0]);
+        let mut body = vec!(3, 0);
+        body.extend(&[header.len() as u8, 0, 0, 0]);
+        body.extend(header);
+        body.extend(program);
+        let mut data = vec!(body.len() as u8, 0, 0, 0);
+        data.extend(body);
+        return data;
+    };
+    let parse = |data: &[u8]| {
+        let mut secs = Sections::default();
+        secs.debug_line = data;
+        LineTable::parse(&secs).map(|t| t.rows.len())
+    };
+    assert_eq!(Ok(1), parse(&unit(14, 0, &[1])));
+    assert!(parse(&unit(0, 0, &[1])).is_err());
+    assert!(parse(&unit(14, 1, &[1])).is_err());
+    assert!(parse(&unit(14, 0, &[0, 0])).is_err());
+    assert!(parse(&unit(14, 0, &[0, 10, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0]))
+            .is_err());
+}
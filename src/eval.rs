@@ -1,16 +1,770 @@
+use binary;
 use context;
-use expr::Expr;
+use std;
+use dwarf;
+use expr::{BinaryOp, Expr, UnaryOp};
+use types::{Member, StructType, Type};
 
-pub fn eval(ctx: &context::Context, e: Expr) -> u64 {
-    match e {
-        // TODO
-        Expr::Empty => 0,
-        Expr::Num(v) => v as u64,
-        Expr::Ident(v) => {
-            match ctx.resolve(&v) {
-                Some(v) => v,
-                None => 0,
+// Where the contents of a value are.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Loc {
+    Memory(u64),
+    // A DWARF register number.
+    Register(u16),
+    Bytes(Vec<u8>),
+    OptimizedOut,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Value {
+    pub ty: Type,
+    pub loc: Loc,
+}
+
+// Limits the number of printed array elements and string characters.
+const PRINT_ELEMENTS: usize = 200;
+
+fn to_bytes(v: u64, size: usize) -> Vec<u8> {
+    (0..size).map(|i| if i < 8 { (v >> (i * 8)) as u8 } else { 0 }).collect()
+}
+
+fn to_u64(data: &[u8]) -> u64 {
+    let mut v = 0;
+    for i in 0..data.len().min(8) {
+        v |= (data[i] as u64) << (i * 8);
+    }
+    return v;
+}
+
+fn sign_extend(v: u64, bits: usize) -> i64 {
+    if bits == 0 || bits >= 64 {
+        return v as i64;
+    }
+    return ((v << (64 - bits)) as i64) >> (64 - bits);
+}
+
+// Converts an x87 80-bit extended precision float.
+fn f80_to_f64(data: &[u8]) -> f64 {
+    let mant = to_u64(&data[0..8]);
+    let se = data[8] as u16 | (data[9] as u16) << 8;
+    let sign = if se & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = (se & 0x7fff) as i32;
+    if exp == 0 && mant == 0 {
+        return sign * 0.0;
+    }
+    if exp == 0x7fff {
+        return if mant << 1 == 0 { sign * std::f64::INFINITY } else {
+            std::f64::NAN
+        };
+    }
+    return sign * (mant as f64) * 2f64.powi(exp - 16383 - 63);
+}
+
+fn f64_to_f80(v: f64) -> Vec<u8> {
+    let bits = v.to_bits();
+    let sign = ((bits >> 63) as u16) << 15;
+    let exp = ((bits >> 52) & 0x7ff) as i32;
+    let frac = bits & ((1 << 52) - 1);
+    let (mant, exp) = match exp {
+        0 if frac == 0 => (0, 0),
+        0 => {
+            let shift = frac.leading_zeros() as i32;
+            (frac << shift, 16383 + 63 - 1074 - shift)
+        }
+        0x7ff => (1 << 63 | frac << 11, 0x7fff),
+        _ => (1 << 63 | frac << 11, exp - 1023 + 16383),
+    };
+    let mut data = to_bytes(mant, 8);
+    data.extend(to_bytes((sign | exp as u16) as u64, 2));
+    return data;
+}
+
+fn float_from_bytes(data: &[u8]) -> f64 {
+    match data.len() {
+        4 => f32::from_bits(to_u64(data) as u32) as f64,
+        8 => f64::from_bits(to_u64(data)),
+        n if n >= 10 => f80_to_f64(data),
+        _ => 0.0,
+    }
+}
+
+// Formats a float like printf's %g with `digits` significant digits, e.g.,
+// 9 for float and 17 for double, which are enough to tell the values apart.
+fn format_float(v: f64, digits: usize) -> String {
+    if v.is_nan() {
+        return "nan".to_string();
+    }
+    if v.is_infinite() {
+        return if v < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+    // The exponent after the rounding to `digits` decides the notation.
+    let sci = format!("{:.*e}", digits - 1, v);
+    let e = sci.find('e').unwrap();
+    let exp: i32 = sci[e + 1..].parse().unwrap();
+    let trim = |s: &str| {
+        if s.contains('.') {
+            s.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            s.to_string()
+        }
+    };
+    if exp < -4 || exp >= digits as i32 {
+        return format!("{}e{}{:02}", trim(&sci[..e]),
+                       if exp < 0 { '-' } else { '+' }, exp.abs());
+    }
+    return trim(&format!("{:.*}", (digits as i32 - 1 - exp) as usize, v));
+}
+
+// The digits of format_float for a float of `size` bytes. Wider floats
+// are converted to double.
+fn float_digits(size: usize) -> usize {
+    if size == 4 { 9 } else { 17 }
+}
+
+impl Value {
+    pub fn new(ty: Type, loc: Loc) -> Value {
+        Value {
+            ty: ty,
+            loc: loc,
+        }
+    }
+
+    pub fn from_u64(ty: Type, v: u64) -> Value {
+        let size = ty.size();
+        Value::new(ty, Loc::Bytes(to_bytes(v, size)))
+    }
+
+    fn from_f64(ty: Type, v: f64) -> Value {
+        let size = ty.size();
+        if size >= 10 {
+            let mut data = f64_to_f80(v);
+            data.resize(size, 0);
+            return Value::new(ty, Loc::Bytes(data));
+        }
+        let bits = if size == 4 {
+            (v as f32).to_bits() as u64
+        } else {
+            v.to_bits()
+        };
+        Value::from_u64(ty, bits)
+    }
+
+    fn from_bool(v: bool) -> Value {
+        Value::from_u64(Type::int(), v as u64)
+    }
+
+    pub fn address(&self) -> Result<u64, String> {
+        match self.loc {
+            Loc::Memory(addr) => Ok(addr),
+            _ => Err("Attempt to take address of value not located in memory."
+                     .to_string()),
+        }
+    }
+
+    pub fn bytes(&self, ctx: &context::Context) -> Result<Vec<u8>, String> {
+        let size = self.ty.size();
+        match self.loc {
+            Loc::Memory(addr) => ctx.read_memory(addr, size),
+            Loc::Register(reg) => {
+                Ok(to_bytes(try!(ctx.read_register(reg)), size))
+            }
+            Loc::Bytes(ref data) => Ok(data.clone()),
+            Loc::OptimizedOut => {
+                Err("value has been optimized out".to_string())
+            }
+        }
+    }
+
+    // Returns the value as an integer. Arrays and functions decay to their
+    // addresses.
+    pub fn to_u64(&self, ctx: &context::Context) -> Result<u64, String> {
+        match *self.ty.strip() {
+            Type::Array(..) | Type::Function(..) => return self.address(),
+            Type::Struct(_) | Type::Void => {
+                return Err("Invalid cast.".to_string());
+            }
+            _ => {}
+        }
+        let data = try!(self.bytes(ctx));
+        if self.ty.is_float() {
+            return Ok(float_from_bytes(&data) as i64 as u64);
+        }
+        if self.ty.is_signed() {
+            return Ok(sign_extend(to_u64(&data), data.len() * 8) as u64);
+        }
+        return Ok(to_u64(&data));
+    }
+
+    pub fn to_f64(&self, ctx: &context::Context) -> Result<f64, String> {
+        if self.ty.is_float() {
+            return Ok(float_from_bytes(&try!(self.bytes(ctx))));
+        }
+        let v = try!(self.to_u64(ctx));
+        if self.ty.is_signed() {
+            return Ok(v as i64 as f64);
+        }
+        return Ok(v as f64);
+    }
+}
+
+fn is_pointer_like(ty: &Type) -> bool {
+    match *ty.strip() {
+        Type::Pointer(_) | Type::Array(..) => true,
+        _ => false,
+    }
+}
+
+fn is_scalar(ty: &Type) -> bool {
+    ty.is_integral() || ty.is_float() || is_pointer_like(ty)
+}
+
+// The type of an integer operation after the usual conversions.
+fn arith_type(l: &Type, r: &Type) -> Type {
+    let size = l.size().max(r.size()).max(4).min(8);
+    let unsigned = [l, r].iter().any(|t| t.size() == size && !t.is_signed());
+    let name = match (size, unsigned) {
+        (8, false) => "long",
+        (8, true) => "unsigned long",
+        (_, false) => "int",
+        (_, true) => "unsigned int",
+    };
+    Type::Base {
+        name: name.to_string(),
+        encoding: if unsigned {
+            dwarf::DW_ATE_UNSIGNED
+        } else {
+            dwarf::DW_ATE_SIGNED
+        },
+        size: size,
+    }
+}
+
+fn is_true(ctx: &context::Context, v: &Value) -> Result<bool, String> {
+    if v.ty.is_float() {
+        return Ok(try!(v.to_f64(ctx)) != 0.0);
+    }
+    return Ok(try!(v.to_u64(ctx)) != 0);
+}
+
+fn lookup(ctx: &context::Context, name: &str) -> Result<Value, String> {
+    if name.starts_with('$') {
+        if let Some(r) = ctx.lookup_register(&name[1..]) {
+            return r;
+        }
+    }
+    if let Some(r) = ctx.lookup_variable(name) {
+        return r;
+    }
+    if let Some(v) = ctx.lookup_function(name) {
+        return Ok(v);
+    }
+    match ctx.resolve_symbol(name) {
+        // Symbols without debug info. Like older gdb, data is read as int.
+        Some((addr, binary::SymbolType::Object)) => {
+            Ok(Value::new(Type::int(), Loc::Memory(addr)))
+        }
+        Some((addr, _)) => Ok(Value::new(Type::Function(Box::new(Type::int()),
+                                                        vec!(), true),
+                                         Loc::Memory(addr))),
+        None => Err(format!("No symbol \"{}\" in current context.", name)),
+    }
+}
+
+fn deref(ctx: &context::Context, v: Value) -> Result<Value, String> {
+    match *v.ty.strip() {
+        Type::Pointer(ref t) => {
+            if let Type::Void = *t.strip() {
+                return Err("Attempt to take contents of a non-pointer value."
+                           .to_string());
             }
+            let addr = try!(v.to_u64(ctx));
+            return Ok(Value::new(ctx.complete_type(t), Loc::Memory(addr)));
+        }
+        Type::Array(ref t, _) => {
+            let addr = try!(v.address());
+            return Ok(Value::new(ctx.complete_type(t), Loc::Memory(addr)));
+        }
+        Type::Function(..) => return Ok(v.clone()),
+        ref t if t.is_integral() => {
+            let addr = try!(v.to_u64(ctx));
+            return Ok(Value::new(t.clone(), Loc::Memory(addr)));
         }
+        _ => {}
     }
+    return Err("Attempt to take contents of a non-pointer value.".to_string());
+}
+
+// Converts a value to `ty` like a C cast or an assignment does.
+pub fn cast(ctx: &context::Context, v: Value, ty: &Type)
+            -> Result<Value, String> {
+    match *ty.strip() {
+        Type::Struct(_) => {
+            if v.ty.strip().name() == ty.strip().name() {
+                return Ok(Value::new(ty.clone(), v.loc));
+            }
+        }
+        ref t if t.is_float() => {
+            let f = try!(v.to_f64(ctx));
+            return Ok(Value::from_f64(ty.clone(), f));
+        }
+        Type::Base { encoding: dwarf::DW_ATE_BOOLEAN, .. } => {
+            let b = try!(is_true(ctx, &v));
+            return Ok(Value::from_u64(ty.clone(), b as u64));
+        }
+        ref t if t.is_integral() || t.is_pointer() => {
+            let u = try!(v.to_u64(ctx));
+            return Ok(Value::from_u64(ty.clone(), u));
+        }
+        _ => {}
+    }
+    return Err("Invalid cast.".to_string());
+}
+
+fn unary(ctx: &context::Context, op: UnaryOp, v: Value)
+         -> Result<Value, String> {
+    match op {
+        UnaryOp::Neg => {
+            if v.ty.is_float() {
+                let f = try!(v.to_f64(ctx));
+                return Ok(Value::from_f64(v.ty.strip().clone(), -f));
+            }
+            if !v.ty.is_integral() {
+                return Err("Argument to arithmetic operation not a number \
+                            or boolean.".to_string());
+            }
+            let ty = arith_type(&v.ty, &v.ty);
+            return Ok(Value::from_u64(ty, 0u64.wrapping_sub(
+                try!(v.to_u64(ctx)))));
+        }
+        UnaryOp::Not => {
+            return Ok(Value::from_bool(!try!(is_true(ctx, &v))));
+        }
+        UnaryOp::BitNot => {
+            if !v.ty.is_integral() {
+                return Err("Argument to complement operation not an integer, \
+                            boolean.".to_string());
+            }
+            let ty = arith_type(&v.ty, &v.ty);
+            return Ok(Value::from_u64(ty, !try!(v.to_u64(ctx))));
+        }
+        UnaryOp::Deref => return deref(ctx, v),
+        UnaryOp::AddrOf => {
+            let addr = try!(v.address());
+            return Ok(Value::from_u64(Type::pointer_to(v.ty), addr));
+        }
+    }
+}
+
+fn binary(ctx: &context::Context, op: BinaryOp, l: Value, r: Value)
+          -> Result<Value, String> {
+    let l_ptr = is_pointer_like(&l.ty);
+    let r_ptr = is_pointer_like(&r.ty);
+    match op {
+        BinaryOp::Add if r_ptr && l.ty.is_integral() => {
+            return binary(ctx, op, r, l);
+        }
+        BinaryOp::Add | BinaryOp::Sub if l_ptr && r.ty.is_integral() => {
+            let elem = l.ty.target().unwrap().clone();
+            let size = elem.size().max(1) as u64;
+            let base = try!(l.to_u64(ctx));
+            let n = try!(r.to_u64(ctx)).wrapping_mul(size);
+            let addr = if op == BinaryOp::Add {
+                base.wrapping_add(n)
+            } else {
+                base.wrapping_sub(n)
+            };
+            return Ok(Value::from_u64(Type::pointer_to(elem), addr));
+        }
+        BinaryOp::Sub if l_ptr && r_ptr => {
+            let size = l.ty.target().unwrap().size().max(1) as i64;
+            let diff = try!(l.to_u64(ctx)).wrapping_sub(try!(r.to_u64(ctx)));
+            return Ok(Value::from_u64(Type::long(),
+                                      (diff as i64 / size) as u64));
+        }
+        _ => {}
+    }
+    if !is_scalar(&l.ty) || !is_scalar(&r.ty) {
+        return Err("Argument to arithmetic operation not a number or \
+                    boolean.".to_string());
+    }
+
+    match op {
+        BinaryOp::And => {
+            let v = try!(is_true(ctx, &l)) && try!(is_true(ctx, &r));
+            return Ok(Value::from_bool(v));
+        }
+        BinaryOp::Or => {
+            let v = try!(is_true(ctx, &l)) || try!(is_true(ctx, &r));
+            return Ok(Value::from_bool(v));
+        }
+        _ => {}
+    }
+
+    let is_bitwise = match op {
+        BinaryOp::Rem | BinaryOp::Shl | BinaryOp::Shr | BinaryOp::BitAnd |
+        BinaryOp::BitOr | BinaryOp::BitXor => true,
+        _ => false,
+    };
+    if (l.ty.is_float() || r.ty.is_float()) && !l_ptr && !r_ptr {
+        if is_bitwise {
+            return Err("Integer only operation on a floating point number."
+                       .to_string());
+        }
+        let ty = if l.ty.size() == 4 && r.ty.size() == 4 {
+            l.ty.strip().clone()
+        } else {
+            Type::double()
+        };
+        let (a, b) = (try!(l.to_f64(ctx)), try!(r.to_f64(ctx)));
+        let v = match op {
+            BinaryOp::Add => a + b,
+            BinaryOp::Sub => a - b,
+            BinaryOp::Mul => a * b,
+            BinaryOp::Div => a / b,
+            BinaryOp::Eq => return Ok(Value::from_bool(a == b)),
+            BinaryOp::Ne => return Ok(Value::from_bool(a != b)),
+            BinaryOp::Lt => return Ok(Value::from_bool(a < b)),
+            BinaryOp::Gt => return Ok(Value::from_bool(a > b)),
+            BinaryOp::Le => return Ok(Value::from_bool(a <= b)),
+            BinaryOp::Ge => return Ok(Value::from_bool(a >= b)),
+            _ => unreachable!(),
+        };
+        return Ok(Value::from_f64(ty, v));
+    }
+
+    let ty = if l_ptr || r_ptr {
+        Type::Base {
+            name: "unsigned long".to_string(),
+            encoding: dwarf::DW_ATE_UNSIGNED,
+            size: 8,
+        }
+    } else {
+        arith_type(&l.ty, &r.ty)
+    };
+    let signed = ty.is_signed();
+    let bits = ty.size() * 8;
+    let (a, b) = (try!(l.to_u64(ctx)), try!(r.to_u64(ctx)));
+    let (sa, sb) = (sign_extend(a, bits), sign_extend(b, bits));
+    let cmp = |s: bool, u: bool| Ok(Value::from_bool(if signed { s } else { u }));
+    let v = match op {
+        BinaryOp::Add => a.wrapping_add(b),
+        BinaryOp::Sub => a.wrapping_sub(b),
+        BinaryOp::Mul => a.wrapping_mul(b),
+        BinaryOp::Div | BinaryOp::Rem if b == 0 => {
+            return Err("Division by zero".to_string());
+        }
+        BinaryOp::Div if signed => sa.wrapping_div(sb) as u64,
+        BinaryOp::Div => a / b,
+        BinaryOp::Rem if signed => sa.wrapping_rem(sb) as u64,
+        BinaryOp::Rem => a % b,
+        BinaryOp::Shl => a.wrapping_shl(b as u32),
+        BinaryOp::Shr if signed => sa.wrapping_shr(b as u32) as u64,
+        BinaryOp::Shr => a.wrapping_shr(b as u32),
+        BinaryOp::BitAnd => a & b,
+        BinaryOp::BitOr => a | b,
+        BinaryOp::BitXor => a ^ b,
+        BinaryOp::Eq => return Ok(Value::from_bool(a == b)),
+        BinaryOp::Ne => return Ok(Value::from_bool(a != b)),
+        BinaryOp::Lt => return cmp(sa < sb, a < b),
+        BinaryOp::Gt => return cmp(sa > sb, a > b),
+        BinaryOp::Le => return cmp(sa <= sb, a <= b),
+        BinaryOp::Ge => return cmp(sa >= sb, a >= b),
+        BinaryOp::And | BinaryOp::Or => unreachable!(),
+    };
+    return Ok(Value::from_u64(ty, v));
+}
+
+// Finds a member, looking into anonymous structs and unions. The returned
+// offset is relative to `s`.
+fn find_member(s: &StructType, name: &str) -> Option<Member> {
+    for m in s.members.as_ref().map_or(&[][..], |m| &m[..]) {
+        if m.name.as_ref().map(|n| n.as_str()) == Some(name) {
+            return Some(m.clone());
+        }
+        if m.name.is_none() {
+            if let Type::Struct(ref inner) = *m.ty.strip() {
+                if let Some(mut found) = find_member(inner, name) {
+                    found.offset += m.offset;
+                    return Some(found);
+                }
+            }
+        }
+    }
+    return None;
+}
+
+fn extract_bits(data: &[u8], m: &Member) -> u64 {
+    let end = (m.offset + 8).min(data.len());
+    let v = to_u64(&data[m.offset..end]) >> m.bit_offset;
+    let v = v & (!0u64 >> (64 - m.bit_size));
+    if m.ty.is_signed() {
+        return sign_extend(v, m.bit_size) as u64;
+    }
+    return v;
+}
+
+fn member(ctx: &context::Context, v: Value, name: &str)
+          -> Result<Value, String> {
+    let ty = ctx.complete_type(&v.ty);
+    let s = match *ty.strip() {
+        Type::Struct(ref s) => s.clone(),
+        _ => {
+            return Err(format!("Attempt to extract a component of a value \
+                                that is not a structure."));
+        }
+    };
+    let m = try!(find_member(&s, name).ok_or(
+        format!("There is no member named {}.", name)));
+    if m.bit_size > 0 {
+        let data = try!(v.bytes(ctx));
+        return Ok(Value::from_u64(m.ty.clone(), extract_bits(&data, &m)));
+    }
+    let loc = match v.loc {
+        Loc::Memory(addr) => Loc::Memory(addr + m.offset as u64),
+        _ => {
+            let data = try!(Value::new(ty.clone(), v.loc).bytes(ctx));
+            let end = (m.offset + m.ty.size()).min(data.len());
+            Loc::Bytes(data[m.offset..end].to_vec())
+        }
+    };
+    return Ok(Value::new(m.ty, loc));
+}
+
+pub fn eval(ctx: &context::Context, e: &Expr) -> Result<Value, String> {
+    match *e {
+        Expr::Empty => {
+            Err("Argument required (expression to compute).".to_string())
+        }
+        Expr::Num(v) => {
+            let ty = if v as i32 as i64 == v { Type::int() } else {
+                Type::long()
+            };
+            Ok(Value::from_u64(ty, v as u64))
+        }
+        Expr::Ident(ref name) => lookup(ctx, name),
+        Expr::Unary(op, ref e) => unary(ctx, op, try!(eval(ctx, e))),
+        Expr::Binary(op, ref l, ref r) => {
+            let l = try!(eval(ctx, l));
+            // Short-circuit like C.
+            match op {
+                BinaryOp::And if !try!(is_true(ctx, &l)) => {
+                    return Ok(Value::from_bool(false));
+                }
+                BinaryOp::Or if try!(is_true(ctx, &l)) => {
+                    return Ok(Value::from_bool(true));
+                }
+                _ => {}
+            }
+            let r = try!(eval(ctx, r));
+            binary(ctx, op, l, r)
+        }
+        Expr::Index(ref base, ref index) => {
+            let base = try!(eval(ctx, base));
+            let index = try!(eval(ctx, index));
+            if !is_pointer_like(&base.ty) {
+                return Err("cannot subscript something of type `".to_string() +
+                           &base.ty.name() + "'");
+            }
+            // Arrays in registers, e.g., lanes of vector registers.
+            if let Type::Array(ref t, _) = *base.ty.strip() {
+                if let Loc::Memory(_) = base.loc {
+                } else {
+                    let data = try!(base.bytes(ctx));
+                    let i = try!(index.to_u64(ctx)) as usize;
+                    let size = t.size().max(1);
+                    if i >= data.len() / size {
+                        return Err("no such vector element".to_string());
+                    }
+                    let elem = data[i * size..(i + 1) * size].to_vec();
+                    return Ok(Value::new((**t).clone(), Loc::Bytes(elem)));
+                }
+            }
+            deref(ctx, try!(binary(ctx, BinaryOp::Add, base, index)))
+        }
+        Expr::Member(ref e, ref name) => member(ctx, try!(eval(ctx, e)), name),
+        Expr::Arrow(ref e, ref name) => {
+            let v = try!(deref(ctx, try!(eval(ctx, e))));
+            member(ctx, v, name)
+        }
+        Expr::Cast(ref name, ref e) => {
+            let ty = try!(ctx.lookup_type(name).ok_or(
+                format!("No symbol \"{}\" in current context.", name)));
+            cast(ctx, try!(eval(ctx, e)), &ty)
+        }
+    }
+}
+
+// Evaluates an expression used as an address, e.g., for `break *EXPR`.
+pub fn eval_address(ctx: &context::Context, e: &Expr) -> Result<u64, String> {
+    let v = try!(eval(ctx, e));
+    if !is_scalar(&v.ty) {
+        if let Type::Function(..) = *v.ty.strip() {
+        } else {
+            return Err("Invalid cast.".to_string());
+        }
+    }
+    return v.to_u64(ctx);
+}
+
+fn quote(data: &[u8]) -> String {
+    let mut r = String::from("\"");
+    for &c in data {
+        match c {
+            b'"' => r += "\\\"",
+            b'\\' => r += "\\\\",
+            b'\n' => r += "\\n",
+            b'\t' => r += "\\t",
+            0x20..=0x7e => r.push(c as char),
+            _ => r += &format!("\\{:03o}", c),
+        }
+    }
+    r.push('"');
+    return r;
+}
+
+fn read_c_string(ctx: &context::Context, addr: u64) -> String {
+    let mut data = vec!();
+    while data.len() < PRINT_ELEMENTS {
+        match ctx.read_memory(addr + data.len() as u64, 1) {
+            Ok(ref c) if c[0] != 0 => data.push(c[0]),
+            Ok(_) => return quote(&data),
+            Err(_) if data.is_empty() => {
+                return format!("<error: Cannot access memory at address \
+                                0x{:x}>", addr);
+            }
+            Err(_) => break,
+        }
+    }
+    return quote(&data) + "...";
+}
+
+fn format_char(c: u8, v: i64) -> String {
+    match c {
+        0x20..=0x7e if c != b'\'' && c != b'\\' => {
+            format!("{} '{}'", v, c as char)
+        }
+        b'\'' => format!("{} '\\''", v),
+        b'\\' => format!("{} '\\\\'", v),
+        _ => format!("{} '\\{:03o}'", v, c),
+    }
+}
+
+fn format_data(ctx: &context::Context, ty: &Type, data: &[u8]) -> String {
+    match *ty.strip() {
+        Type::Void => "void".to_string(),
+        Type::Base { encoding, .. } => {
+            match encoding {
+                dwarf::DW_ATE_BOOLEAN => {
+                    if to_u64(data) != 0 { "true" } else { "false" }
+                    .to_string()
+                }
+                dwarf::DW_ATE_FLOAT => {
+                    format_float(float_from_bytes(data),
+                                 float_digits(data.len()))
+                }
+                dwarf::DW_ATE_SIGNED_CHAR => {
+                    format_char(data[0], data[0] as i8 as i64)
+                }
+                dwarf::DW_ATE_UNSIGNED_CHAR => {
+                    format_char(data[0], data[0] as i64)
+                }
+                dwarf::DW_ATE_SIGNED => {
+                    format!("{}", sign_extend(to_u64(data), data.len() * 8))
+                }
+                _ => format!("{}", to_u64(data)),
+            }
+        }
+        Type::Enum(ref e) => {
+            let v = if ty.is_signed() {
+                sign_extend(to_u64(data), data.len() * 8)
+            } else {
+                to_u64(data) as i64
+            };
+            match e.values.iter().find(|ev| ev.1 == v) {
+                Some(ev) => ev.0.clone(),
+                None => format!("{}", v),
+            }
+        }
+        Type::Pointer(ref t) => {
+            let addr = to_u64(data);
+            if t.is_char() && addr != 0 {
+                format!("0x{:x} {}", addr, read_c_string(ctx, addr))
+            } else {
+                ctx.format_addr(addr)
+            }
+        }
+        Type::Array(ref t, _) => {
+            let size = t.size();
+            if size == 0 {
+                return "{}".to_string();
+            }
+            if t.is_char() {
+                let len = data.iter().position(|&c| c == 0)
+                    .unwrap_or(data.len());
+                return quote(&data[..len.min(PRINT_ELEMENTS)]);
+            }
+            let mut elems = vec!();
+            for chunk in data.chunks(size) {
+                if elems.len() == PRINT_ELEMENTS {
+                    elems.push("...".to_string());
+                    break;
+                }
+                elems.push(format_data(ctx, t, chunk));
+            }
+            format!("{{{}}}", elems.join(", "))
+        }
+        Type::Struct(ref s) => {
+            let members = match s.members {
+                Some(ref members) => members,
+                None => return "<incomplete type>".to_string(),
+            };
+            let mut fields = vec!();
+            for m in members {
+                let v = if m.bit_size > 0 {
+                    let v = extract_bits(data, m);
+                    format_data(ctx, &m.ty, &to_bytes(v, m.ty.size()))
+                } else {
+                    let end = (m.offset + m.ty.size()).min(data.len());
+                    format_data(ctx, &m.ty, &data[m.offset.min(end)..end])
+                };
+                match m.name {
+                    Some(ref name) => fields.push(format!("{} = {}", name, v)),
+                    None => fields.push(v),
+                }
+            }
+            format!("{{{}}}", fields.join(", "))
+        }
+        Type::Function(..) => "{...}".to_string(),
+        Type::Typedef(..) | Type::Const(_) | Type::Volatile(_) => {
+            unreachable!()
+        }
+    }
+}
+
+// Formats a value for `print`.
+pub fn format(ctx: &context::Context, v: &Value) -> Result<String, String> {
+    if v.loc == Loc::OptimizedOut {
+        return Ok("<optimized out>".to_string());
+    }
+    match *v.ty.strip() {
+        Type::Pointer(ref t) if !t.is_char() => {
+            let addr = try!(v.to_u64(ctx));
+            return Ok(format!("({}) {}", v.ty.name(),
+                              ctx.format_addr(addr)));
+        }
+        Type::Function(..) => {
+            let addr = try!(v.address());
+            return Ok(format!("{{{}}} {}", v.ty.name(),
+                              ctx.format_addr(addr)));
+        }
+        _ => {}
+    }
+    let ty = ctx.complete_type(&v.ty);
+    let data = try!(Value::new(ty.clone(), v.loc.clone()).bytes(ctx));
+    return Ok(format_data(ctx, &ty, &data));
+}
+
+pub fn print(ctx: &context::Context, e: &Expr) -> Result<String, String> {
+    let v = try!(eval(ctx, e));
+    return format(ctx, &v);
 }
@@ -1,38 +1,368 @@
 use std;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+    Deref,
+    AddrOf,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub enum Expr {
     Empty,
     Num (i64),
     Ident (String),
+    Unary (UnaryOp, Box<Expr>),
+    Binary (BinaryOp, Box<Expr>, Box<Expr>),
+    Index (Box<Expr>, Box<Expr>),
+    Member (Box<Expr>, String),
+    Arrow (Box<Expr>, String),
+    // A type name like "unsigned long *" and the operand.
+    Cast (String, Box<Expr>),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Token {
+    Num (i64),
+    Ident (String),
+    Punct (&'static str),
 }
 
+// Longer operators come first so they win over their prefixes.
+const PUNCTS: [&'static str; 28] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "->",
+    "+", "-", "*", "/", "%", "&", "|", "^", "~", "!", "<", ">",
+    "(", ")", "[", "]", ".", ",", "=",
+];
+
 fn parse_num_from_result(r: Result<i64, std::num::ParseIntError>,
-                         e: &str) -> Result<Expr, String> {
+                         e: &str) -> Result<i64, String> {
     match r {
-        Ok(v) => Ok(Expr::Num(v)),
+        Ok(v) => Ok(v),
         Err(_) => Err(format!("Invalid number \"{}\".", e))
     }
 }
 
-pub fn parse(s: &str) -> Result<Expr, String> {
-    let s = s.trim();
-    if s.len() == 0 {
-        return Ok(Expr::Empty);
-    }
-
+fn parse_num(s: &str) -> Result<i64, String> {
     if s.starts_with("0x") {
         if s.len() == 2 {
             return Err(format!("Invalid number \"{}\".", s));
         }
-        return parse_num_from_result(i64::from_str_radix(&s[2..], 16), s);
-    } else if s.starts_with("0") {
+        // Allow addresses above i64::MAX.
+        return u64::from_str_radix(&s[2..], 16).map(|v| v as i64).or(
+            Err(format!("Invalid number \"{}\".", s)));
+    } else if s.starts_with("0") && s.len() > 1 {
         return parse_num_from_result(i64::from_str_radix(&s[1..], 8), s);
-    } else if s.chars().nth(0).unwrap().is_digit(10) {
-        return parse_num_from_result(i64::from_str_radix(s, 10), s);
     } else {
-        return Ok(Expr::Ident(String::from(s)));
+        return parse_num_from_result(i64::from_str_radix(s, 10), s);
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec!();
+    let mut i = 0;
+    let bytes = s.as_bytes();
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_digit(10) {
+            let start = i;
+            while i < bytes.len() && (bytes[i] as char).is_ascii_alphanumeric() {
+                i += 1;
+            }
+            tokens.push(Token::Num(try!(parse_num(&s[start..i]))));
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '$' ||
+            c == '\'' {
+            let start = i;
+            // A name in a file scope, e.g. 'foo.c'::counter.
+            if c == '\'' {
+                let quoted = s[i + 1..].find('\'').map(|end| i + end + 2);
+                match quoted {
+                    Some(end) if s[end..].starts_with("::") => i = end + 2,
+                    _ => {
+                        return Err("Unmatched single quote.".to_string());
+                    }
+                }
+            }
+            let is_ident = |c: u8| {
+                (c as char).is_ascii_alphanumeric() || c == b'_' || c == b'$'
+            };
+            while i < bytes.len() {
+                // Qualified names like ns::Foo::bar are single identifiers.
+                if s[i..].starts_with("::") && i + 2 < bytes.len() &&
+                    is_ident(bytes[i + 2]) {
+                    i += 2;
+                    continue;
+                }
+                if !is_ident(bytes[i]) {
+                    break;
+                }
+                i += 1;
+            }
+            tokens.push(Token::Ident(s[start..i].to_string()));
+        } else {
+            match PUNCTS.iter().find(|p| s[i..].starts_with(*p)) {
+                Some(p) => {
+                    tokens.push(Token::Punct(*p));
+                    i += p.len();
+                }
+                None => {
+                    return Err(format!("Invalid character '{}' in expression.",
+                                       c));
+                }
+            }
+        }
     }
+    return Ok(tokens);
+}
+
+// Words which start a type name in a cast without a pointer declarator.
+const TYPE_KEYWORDS: [&'static str; 13] = [
+    "struct", "union", "enum", "unsigned", "signed", "void", "char", "short",
+    "int", "long", "float", "double", "_Bool",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+fn binary_op(p: &str) -> Option<(BinaryOp, i32)> {
+    let r = match p {
+        "||" => (BinaryOp::Or, 1),
+        "&&" => (BinaryOp::And, 2),
+        "|" => (BinaryOp::BitOr, 3),
+        "^" => (BinaryOp::BitXor, 4),
+        "&" => (BinaryOp::BitAnd, 5),
+        "==" => (BinaryOp::Eq, 6),
+        "!=" => (BinaryOp::Ne, 6),
+        "<" => (BinaryOp::Lt, 7),
+        ">" => (BinaryOp::Gt, 7),
+        "<=" => (BinaryOp::Le, 7),
+        ">=" => (BinaryOp::Ge, 7),
+        "<<" => (BinaryOp::Shl, 8),
+        ">>" => (BinaryOp::Shr, 8),
+        "+" => (BinaryOp::Add, 9),
+        "-" => (BinaryOp::Sub, 9),
+        "*" => (BinaryOp::Mul, 10),
+        "/" => (BinaryOp::Div, 10),
+        "%" => (BinaryOp::Rem, 10),
+        _ => return None,
+    };
+    return Some(r);
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_punct(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(&Token::Punct(p)) => Some(p),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.peek_punct() == Some(punct) {
+            self.pos += 1;
+            return Ok(());
+        }
+        return Err(self.syntax_error());
+    }
+
+    fn syntax_error(&self) -> String {
+        match self.peek() {
+            Some(&Token::Num(v)) => {
+                format!("A syntax error in expression, near `{}'.", v)
+            }
+            Some(&Token::Ident(ref v)) => {
+                format!("A syntax error in expression, near `{}'.", v)
+            }
+            Some(&Token::Punct(p)) => {
+                format!("A syntax error in expression, near `{}'.", p)
+            }
+            None => "A syntax error in expression, near `'.".to_string(),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        if let Some(&Token::Ident(ref name)) = self.peek() {
+            let name = name.clone();
+            self.pos += 1;
+            return Ok(name);
+        }
+        return Err(self.syntax_error());
+    }
+
+    // Returns the type name if the tokens at the current position are a
+    // parenthesized type, e.g., "(long *)". Without type information, only
+    // names with a pointer declarator or a C keyword are taken as types.
+    fn cast_type(&self) -> Option<String> {
+        if self.peek_punct() != Some("(") {
+            return None;
+        }
+        let mut words = vec!();
+        let mut stars = 0;
+        for token in &self.tokens[self.pos + 1..] {
+            match *token {
+                Token::Ident(ref w) if stars == 0 => words.push(w.as_str()),
+                Token::Punct("*") if !words.is_empty() => stars += 1,
+                Token::Punct(")") => break,
+                _ => return None,
+            }
+        }
+        if words.is_empty() ||
+            (stars == 0 && !TYPE_KEYWORDS.contains(&words[0])) {
+            return None;
+        }
+        if stars == 0 {
+            return Some(words.join(" "));
+        }
+        return Some(words.join(" ") + " " + &"*".repeat(stars));
+    }
+
+    fn binary(&mut self, min_prec: i32) -> Result<Expr, String> {
+        let mut lhs = try!(self.unary());
+        loop {
+            let (op, prec) = match self.peek_punct().and_then(binary_op) {
+                Some((op, prec)) if prec >= min_prec => (op, prec),
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = try!(self.binary(prec + 1));
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let op = match self.peek_punct() {
+            Some("-") => UnaryOp::Neg,
+            Some("!") => UnaryOp::Not,
+            Some("~") => UnaryOp::BitNot,
+            Some("*") => UnaryOp::Deref,
+            Some("&") => UnaryOp::AddrOf,
+            Some("+") => {
+                self.pos += 1;
+                return self.unary();
+            }
+            _ => {
+                if let Some(ty) = self.cast_type() {
+                    // Skips the parenthesized type name.
+                    while self.peek_punct() != Some(")") {
+                        self.pos += 1;
+                    }
+                    self.pos += 1;
+                    let e = try!(self.unary());
+                    return Ok(Expr::Cast(ty, Box::new(e)));
+                }
+                return self.postfix();
+            }
+        };
+        self.pos += 1;
+        let e = try!(self.unary());
+        if let (UnaryOp::Neg, Expr::Num(v)) = (op, &e) {
+            return Ok(Expr::Num(v.wrapping_neg()));
+        }
+        return Ok(Expr::Unary(op, Box::new(e)));
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut e = try!(self.primary());
+        loop {
+            match self.peek_punct() {
+                Some("[") => {
+                    self.pos += 1;
+                    let index = try!(self.binary(0));
+                    try!(self.expect("]"));
+                    e = Expr::Index(Box::new(e), Box::new(index));
+                }
+                Some(".") => {
+                    self.pos += 1;
+                    e = Expr::Member(Box::new(e), try!(self.ident()));
+                }
+                Some("->") => {
+                    self.pos += 1;
+                    e = Expr::Arrow(Box::new(e), try!(self.ident()));
+                }
+                _ => return Ok(e),
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let e = match self.peek() {
+            Some(&Token::Num(v)) => Expr::Num(v),
+            Some(&Token::Ident(ref v)) => Expr::Ident(v.clone()),
+            Some(&Token::Punct("(")) => {
+                self.pos += 1;
+                let e = try!(self.binary(0));
+                try!(self.expect(")"));
+                return Ok(e);
+            }
+            _ => return Err(self.syntax_error()),
+        };
+        self.pos += 1;
+        return Ok(e);
+    }
+}
+
+pub fn parse(s: &str) -> Result<Expr, String> {
+    let s = s.trim();
+    if s.len() == 0 {
+        return Ok(Expr::Empty);
+    }
+
+    let mut parser = Parser {
+        tokens: try!(tokenize(s)),
+        pos: 0,
+    };
+    let e = try!(parser.binary(0));
+    if parser.pos != parser.tokens.len() {
+        return Err(parser.syntax_error());
+    }
+    return Ok(e);
+}
+
+// Parses "LHS = RHS" of `set var`.
+pub fn parse_assignment(s: &str) -> Result<(Expr, Expr), String> {
+    let mut parser = Parser {
+        tokens: try!(tokenize(s.trim())),
+        pos: 0,
+    };
+    let lhs = try!(parser.binary(0));
+    try!(parser.expect("="));
+    let rhs = try!(parser.binary(0));
+    if parser.pos != parser.tokens.len() {
+        return Err(parser.syntax_error());
+    }
+    return Ok((lhs, rhs));
 }
 
 #[test]
@@ -40,9 +370,79 @@ fn test_num() {
     assert_eq!(Ok(Expr::Num(42)), parse("42"));
     assert_eq!(Ok(Expr::Num(0xcc)), parse("0xcc"));
     assert_eq!(Ok(Expr::Num(493)), parse("0755"));
+    assert_eq!(Ok(Expr::Num(0)), parse("0"));
+    assert_eq!(Ok(Expr::Num(-1)), parse("0xffffffffffffffff"));
+    assert_eq!(Err("Invalid number \"09\".".to_string()), parse("09"));
 }
 
 #[test]
 fn test_ident() {
     assert_eq!(Ok(Expr::Ident("foo".to_string())), parse("foo"));
+    assert_eq!(Ok(Expr::Ident("'foo.c'::counter".to_string())),
+               parse("'foo.c'::counter"));
+    assert_eq!(Err("Unmatched single quote.".to_string()), parse("'foo.c"));
+    assert_eq!(Ok(Expr::Ident("ns::Foo::bar".to_string())),
+               parse("ns::Foo::bar"));
+}
+
+#[test]
+fn test_operators() {
+    let ident = |s: &str| Box::new(Expr::Ident(s.to_string()));
+    assert_eq!(Ok(Expr::Binary(
+        BinaryOp::Add, Box::new(Expr::Num(1)),
+        Box::new(Expr::Binary(BinaryOp::Mul, Box::new(Expr::Num(2)),
+                              Box::new(Expr::Num(3)))))),
+               parse("1 + 2 * 3"));
+    assert_eq!(Ok(Expr::Binary(
+        BinaryOp::Sub,
+        Box::new(Expr::Binary(BinaryOp::Sub, Box::new(Expr::Num(5)),
+                              Box::new(Expr::Num(2)))),
+        Box::new(Expr::Num(1)))),
+               parse("5 - 2 - 1"));
+    assert_eq!(Ok(Expr::Unary(UnaryOp::Deref,
+                              Box::new(Expr::Arrow(ident("head"),
+                                                   "next".to_string())))),
+               parse("*head->next"));
+    assert_eq!(Ok(Expr::Member(Box::new(Expr::Index(ident("a"),
+                                                    Box::new(Expr::Num(1)))),
+                               "x".to_string())),
+               parse("a[1].x"));
+    assert_eq!(Ok(Expr::Unary(UnaryOp::AddrOf, ident("x"))), parse("&x"));
+    assert_eq!(Ok(Expr::Num(-3)), parse("-3"));
+    assert_eq!(Err("A syntax error in expression, near `b'.".to_string()),
+               parse("a b"));
+    assert_eq!(Err("A syntax error in expression, near `'.".to_string()),
+               parse("(1"));
+}
+
+#[test]
+fn test_cast() {
+    let ident = |s: &str| Box::new(Expr::Ident(s.to_string()));
+    assert_eq!(Ok(Expr::Unary(UnaryOp::Deref,
+                              Box::new(Expr::Cast("long *".to_string(),
+                                                  Box::new(Expr::Num(16)))))),
+               parse("*(long*)16"));
+    assert_eq!(Ok(Expr::Binary(
+        BinaryOp::Add,
+        Box::new(Expr::Cast("unsigned char".to_string(), ident("x"))),
+        Box::new(Expr::Num(1)))),
+               parse("(unsigned char)x + 1"));
+    assert_eq!(Ok(Expr::Cast("struct point **".to_string(), ident("p"))),
+               parse("(struct point **) p"));
+    assert_eq!(Ok(Expr::Binary(BinaryOp::Mul, ident("x"), ident("y"))),
+               parse("(x) * y"));
+}
+
+#[test]
+fn test_assignment() {
+    assert_eq!(Ok((Expr::Ident("x".to_string()), Expr::Num(3))),
+               parse_assignment("x = 3"));
+    assert_eq!(Ok((Expr::Ident("$rax".to_string()),
+                   Expr::Binary(BinaryOp::Eq, Box::new(Expr::Num(1)),
+                                Box::new(Expr::Num(2))))),
+               parse_assignment("$rax = 1 == 2"));
+    assert_eq!(Err("A syntax error in expression, near `'.".to_string()),
+               parse_assignment("x"));
+    assert_eq!(Err("A syntax error in expression, near `='.".to_string()),
+               parse_assignment("x = 1 = 2"));
 }
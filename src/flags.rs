@@ -1,11 +1,14 @@
 pub struct Flags {
     pub args: Vec<String>,
     pub core: Option<String>,
+    // The process to attach to.
+    pub pid: Option<i32>,
 }
 
 pub fn parse(argv: Vec<String>) -> Flags {
     let mut exec: Option<String> = None;
     let mut core: Option<String> = None;
+    let mut pid: Option<i32> = None;
     let mut args: Vec<String> = vec!();
 
     let mut i = 1;
@@ -17,6 +20,12 @@ pub fn parse(argv: Vec<String>) -> Flags {
                 args.push(argv[i].clone());
                 i += 1;
             }
+        } else if arg == "-p" {
+            i += 1;
+            match argv.get(i).and_then(|p| p.parse().ok()) {
+                Some(p) => pid = Some(p),
+                None => panic!("-p needs a process ID"),
+            }
         } else if arg.starts_with('-') {
             panic!("Unknown flag: {}", arg);
         } else {
@@ -40,5 +49,6 @@ pub fn parse(argv: Vec<String>) -> Flags {
     Flags {
         args: args,
         core: core,
+        pid: pid,
     }
 }
@@ -0,0 +1,172 @@
+// Decoding of the x87 and SSE control state for `info float` and
+// `info vector`.
+
+use target_desc;
+
+// The classes of x87 registers in the full tag word.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tag {
+    Valid = 0,
+    Zero = 1,
+    Special = 2,
+    Empty = 3,
+}
+
+const STATUS_FLAGS: [(u32, &'static str); 12] = [
+    (0, "IE"), (1, "DE"), (2, "ZE"), (3, "OE"), (4, "UE"), (5, "PE"),
+    (6, "SF"), (7, "ES"), (8, "C0"), (9, "C1"), (10, "C2"), (14, "C3"),
+];
+
+const CONTROL_FLAGS: [(u32, &'static str); 6] = [
+    (0, "IM"), (1, "DM"), (2, "ZM"), (3, "OM"), (4, "UM"), (5, "PM"),
+];
+
+const MXCSR_FLAGS: [(u32, &'static str); 14] = [
+    (0, "IE"), (1, "DE"), (2, "ZE"), (3, "OE"), (4, "UE"), (5, "PE"),
+    (6, "DAZ"), (7, "IM"), (8, "DM"), (9, "ZM"), (10, "OM"), (11, "UM"),
+    (12, "PM"), (15, "FZ"),
+];
+
+const PRECISIONS: [&'static str; 4] = [
+    "Single Precision (24-bits)",
+    "Reserved",
+    "Double Precision (53-bits)",
+    "Extended Precision (64-bits)",
+];
+
+const ROUNDINGS: [&'static str; 4] = [
+    "Round to nearest",
+    "Round down",
+    "Round up",
+    "Round toward zero",
+];
+
+fn to_u64(data: &[u8]) -> u64 {
+    data.iter().rev().fold(0, |v, &b| v << 8 | b as u64)
+}
+
+// Reads a control or status register like "fstat" from an FXSAVE area.
+pub fn ctrl(target: &target_desc::Target, data: &[u8], name: &str)
+            -> Option<u64> {
+    target.fp_ctrl.iter().find(|c| c.0 == name)
+        .map(|&(_, off, size)| to_u64(&data[off..off + size]))
+}
+
+// Classifies the 80-bit contents of a non-empty register.
+pub fn classify(raw: &[u8]) -> Tag {
+    let mant = to_u64(&raw[0..8]);
+    let exp = to_u64(&raw[8..10]) & 0x7fff;
+    if exp == 0x7fff {
+        return Tag::Special;
+    }
+    if exp == 0 {
+        return if mant == 0 { Tag::Zero } else { Tag::Special };
+    }
+    // Unnormals lack the explicit integer bit.
+    if mant >> 63 == 0 {
+        return Tag::Special;
+    }
+    return Tag::Valid;
+}
+
+// Returns the names of set bits like "[ IM DM ]".
+pub fn bit_names(v: u64, names: &[(u32, &'static str)]) -> String {
+    let set: Vec<&str> = names.iter().filter(|&&(bit, _)| v & (1 << bit) != 0)
+        .map(|&(_, name)| name).collect();
+    if set.is_empty() {
+        return "[ ]".to_string();
+    }
+    return format!("[ {} ]", set.join(" "));
+}
+
+pub fn format_mxcsr(v: u64) -> String {
+    bit_names(v, &MXCSR_FLAGS)
+}
+
+// Formats the physical x87 registers from R7 to R0 and the control state.
+// `format_value` formats the 80-bit contents of a register.
+pub fn info_float<F>(target: &target_desc::Target, data: &[u8],
+                     format_value: F) -> String
+    where F: Fn(&[u8]) -> String {
+    let get = |name| ctrl(target, data, name).unwrap();
+    let (fstat, fctrl) = (get("fstat"), get("fctrl"));
+    let top = (fstat >> 11 & 7) as usize;
+    // FXSAVE keeps only whether each register is empty.
+    let ftag = get("ftag");
+    let mut tag_word = 0;
+    let mut lines = vec!();
+    for i in (0..target.st_count).rev() {
+        // The stack registers are stored from ST(0).
+        let st = (i + target.st_count - top) % target.st_count;
+        let off = target.st_off + st * 16;
+        let raw = &data[off..off + 10];
+        let tag = if ftag & 1 << i == 0 { Tag::Empty } else { classify(raw) };
+        tag_word |= (tag as u64) << (i * 2);
+        let hex: String = raw.iter().rev().map(|b| format!("{:02x}", b))
+            .collect();
+        let mut line = format!("{}R{}: {:<8}0x{}",
+                               if i == top { "=>" } else { "  " }, i,
+                               format!("{:?}", tag), hex);
+        if tag != Tag::Empty {
+            line += &format!(" {}", format_value(raw));
+        }
+        lines.push(line);
+    }
+    lines.push(String::new());
+    lines.push(format!("Status Word:         0x{:04x}   {}", fstat,
+                       bit_names(fstat, &STATUS_FLAGS)));
+    lines.push(format!("                       TOP: {}", top));
+    lines.push(format!("Control Word:        0x{:04x}   {}", fctrl,
+                       bit_names(fctrl, &CONTROL_FLAGS)));
+    lines.push(format!("                       PC: {}",
+                       PRECISIONS[(fctrl >> 8 & 3) as usize]));
+    lines.push(format!("                       RC: {}",
+                       ROUNDINGS[(fctrl >> 10 & 3) as usize]));
+    lines.push(format!("Tag Word:            0x{:04x}", tag_word));
+    lines.push(format!("Instruction Pointer: 0x{:x}", get("fioff")));
+    lines.push(format!("Operand Pointer:     0x{:x}", get("fooff")));
+    lines.push(format!("Opcode:              0x{:04x}", get("fop")));
+    return lines.join("\n");
+}
+
+#[test]
+fn test_classify() {
+    let f80 = |mant: u64, exp: u16| {
+        let mut raw: Vec<u8> = (0..8).map(|i| (mant >> (i * 8)) as u8)
+            .collect();
+        raw.push(exp as u8);
+        raw.push((exp >> 8) as u8);
+        raw
+    };
+    assert_eq!(Tag::Valid, classify(&f80(1 << 63, 0x3fff)));
+    assert_eq!(Tag::Zero, classify(&f80(0, 0x8000)));
+    assert_eq!(Tag::Special, classify(&f80(1 << 63, 0x7fff)));
+    assert_eq!(Tag::Special, classify(&f80(1, 0)));
+    assert_eq!(Tag::Special, classify(&f80(1, 0x3fff)));
+}
+
+#[test]
+fn test_info_float() {
+    let target = target_desc::get_target();
+    let mut data = vec![0; target.fp_size];
+    // 1.0 pushed after fninit.
+    data[0] = 0x7f;
+    data[1] = 0x03;
+    data[3] = 0x38;
+    data[4] = 0x80;
+    data[target.st_off + 7] = 0x80;
+    data[target.st_off + 8] = 0xff;
+    data[target.st_off + 9] = 0x3f;
+    let info = info_float(&target, &data, |_| "1".to_string());
+    let lines: Vec<&str> = info.lines().collect();
+    assert_eq!("=>R7: Valid   0x3fff8000000000000000 1", lines[0]);
+    assert_eq!("  R6: Empty   0x00000000000000000000", lines[1]);
+    assert_eq!("Status Word:         0x3800   [ ]", lines[9]);
+    assert_eq!("                       TOP: 7", lines[10]);
+    assert_eq!("Control Word:        0x037f   [ IM DM ZM OM UM PM ]",
+               lines[11]);
+    assert_eq!("                       PC: Extended Precision (64-bits)",
+               lines[12]);
+    assert_eq!("Tag Word:            0x3fff", lines[14]);
+    assert_eq!("[ IM DM ZM OM UM PM ]", format_mxcsr(0x1f80));
+}
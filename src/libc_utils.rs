@@ -8,22 +8,9 @@ pub fn errno() -> i32 {
     }
 }
 
-pub fn abort_libc(msg: &str) {
-    let strerror: String;
+pub fn strerror(errno: i32) -> String {
     unsafe {
-        strerror = std::ffi::CString::from_raw(
-            libc::strerror(errno())).into_string().unwrap();
+        return std::ffi::CStr::from_ptr(libc::strerror(errno))
+            .to_string_lossy().into_owned();
     }
-    panic!("{}: {} (errno={})", msg, strerror, errno());
-}
-
-pub fn check_libc<'a>(retval: i32, msg: &'a str) {
-    if retval < 0 {
-        abort_libc(msg);
-    }
-}
-
-#[macro_export]
-macro_rules! check_libc {
-    ($e:expr) => (check_libc($e, stringify!($e)));
 }
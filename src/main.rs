@@ -7,11 +7,19 @@ mod binary;
 mod breakpoint;
 mod command;
 mod context;
+mod corefile;
+mod demangle;
+mod disasm;
+mod dwarf;
 mod eval;
 mod expr;
 mod flags;
+mod fp;
 mod ptracer;
+mod signal;
 mod target_desc;
+mod types;
+mod unwind;
 
 extern crate colored;
 use colored::*;
@@ -29,6 +37,18 @@ fn main() {
             Err(msg) => println!("{}", msg.red()),
         }
     }
+    if let Some(pid) = flags.pid {
+        match ctx.attach(pid) {
+            Ok(msg) => println!("{}", msg),
+            Err(msg) => println!("{}", msg.red()),
+        }
+    }
+    if let Some(ref core) = flags.core {
+        match ctx.load_core(core) {
+            Ok(msg) => println!("{}", msg),
+            Err(msg) => println!("{}", msg.red()),
+        }
+    }
 
     let mut rl = Editor::<()>::new();
     match std::env::home_dir() {
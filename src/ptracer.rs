@@ -2,15 +2,27 @@ extern crate errno;
 extern crate libc;
 
 use std;
+use std::cell::RefCell;
+use std::collections::HashMap;
 
 use libc_utils::*;
+use log;
+use signal;
 use target_desc;
 
 #[derive(Debug)]
 pub enum ProcessState {
+    // Stopped by a signal, which is SIGTRAP for breakpoints and steps.
     Stop (i32),
     Exit (i32),
+    // Killed by a signal.
     Signal (i32),
+    // The process forked a child, which is stopped and traced.
+    Fork (libc::pid_t),
+    // Same as Fork, but the child shares the memory until it execs.
+    VFork (libc::pid_t),
+    // The process has executed a new program.
+    Exec,
 }
 
 impl ProcessState {
@@ -22,6 +34,7 @@ impl ProcessState {
     }
 }
 
+#[derive(Clone)]
 pub struct Registers {
     gps: Vec<u64>,
     ip: u64,
@@ -33,6 +46,8 @@ impl Registers {
     pub fn ip(&self) -> u64 { self.ip }
     pub fn sp(&self) -> u64 { self.sp }
     pub fn bp(&self) -> u64 { self.bp }
+    pub fn gp(&self, index: usize) -> u64 { self.gps[index] }
+    pub fn gps(&self) -> &[u64] { &self.gps }
 
     pub fn empty() -> Self {
         Self {
@@ -43,90 +58,414 @@ impl Registers {
         }
     }
 
-    // pub fn clone(&self) -> Self {
-    //     Self {
-    //         gps: self.gps.clone(),
-    //         ip: self.ip,
-    //         sp: self.sp,
-    //         bp: self.bp,
-    //     }
-    // }
+    pub fn new(gps: Vec<u64>, target: &target_desc::Target) -> Self {
+        Self {
+            ip: gps[target.ip_index],
+            sp: gps[target.sp_index],
+            bp: gps[target.bp_index],
+            gps: gps,
+        }
+    }
 
     pub fn update_ip(&mut self, ip: u64, target: &target_desc::Target) {
-        self.ip = ip;
-        self.gps[target.ip_index] = ip;
+        self.set_gp(target.ip_index, ip, target);
+    }
+
+    pub fn set_gp(&mut self, index: usize, v: u64,
+                  target: &target_desc::Target) {
+        self.gps[index] = v;
+        *self = Registers::new(self.gps.clone(), target);
+    }
+}
+
+// The x86 debug registers for hardware watchpoints: the addresses of
+// DR0-DR3 and the control bits of DR7.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DebugRegs {
+    pub addrs: Vec<u64>,
+    pub control: u64,
+}
+
+// Failures of operations on the traced process.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    // A system call failed with the errno.
+    Sys (&'static str, i32),
+    // The memory at the address is not accessible.
+    Memory (u64),
+    // The program could not be executed.
+    Exec (String, i32),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Error::Sys(call, errno) => {
+                write!(f, "{} failed: {}", call, strerror(errno))
+            }
+            Error::Memory(addr) => {
+                write!(f, "Cannot access memory at address 0x{:x}", addr)
+            }
+            Error::Exec(ref path, errno) => {
+                write!(f, "{}: {}.", path, strerror(errno))
+            }
+        }
+    }
+}
+
+impl From<Error> for String {
+    fn from(err: Error) -> String {
+        return err.to_string();
+    }
+}
+
+fn check_libc(retval: i32, call: &'static str) -> Result<i32, Error> {
+    if retval < 0 {
+        return Err(Error::Sys(call, errno()));
     }
+    return Ok(retval);
 }
 
-fn check_ptrace_helper<'a>(retval: i64, msg: &'a str) -> i64 {
-    if retval == -1 && errno::errno() != errno::Errno(0) {
-        abort_libc(msg);
+fn check_ptrace_helper(retval: i64, call: &'static str) -> Result<i64, Error> {
+    if retval == -1 && errno() != 0 {
+        return Err(Error::Sys(call, errno()));
     }
-    return retval;
+    return Ok(retval);
 }
 
+// PTRACE_PEEK* may return -1 as data, so errno is cleared beforehand.
 #[macro_export]
 macro_rules! check_ptrace {
     ($r:expr, $e1:expr, $e2:expr, $e3:expr) => {
         check_ptrace_helper(unsafe {
+            errno::set_errno(errno::Errno(0));
             libc::ptrace($r, $e1, $e2, $e3)
-        }, stringify!($r));
+        }, stringify!($r))
     }
 }
 
+// A line of /proc/PID/maps.
+pub struct Mapping {
+    pub start: u64,
+    pub end: u64,
+    // E.g. "r-xp".
+    pub perms: String,
+    pub offset: u64,
+    // The mapped file, a pseudo path like "[stack]", or empty.
+    pub path: String,
+}
+
+// Parses an auxiliary vector into (type, value) pairs.
+pub fn parse_auxv(data: &[u8]) -> Vec<(u64, u64)> {
+    let word = |i: usize| {
+        let mut v = 0;
+        for j in 0..8 {
+            v |= (data[i + j] as u64) << (j * 8);
+        }
+        v
+    };
+    let mut r = vec![];
+    let mut i = 0;
+    while i + 16 <= data.len() {
+        let (key, value) = (word(i), word(i + 8));
+        if key == 0 {
+            break;
+        }
+        r.push((key, value));
+        i += 16;
+    }
+    return r;
+}
+
+// A thread of the traced process.
+struct Thread {
+    // The number shown to users, which starts from 1.
+    num: usize,
+    tid: libc::pid_t,
+    // Resumed and not waited yet.
+    running: bool,
+    // Resumed by PTRACE_SINGLESTEP.
+    stepping: bool,
+    // A stop seen while stopping all threads, which is reported later.
+    pending: Option<i32>,
+    // A SIGSTOP we sent has not arrived yet.
+    stop_requested: bool,
+    // The signal delivered when the thread is resumed next.
+    signal: i32,
+    // The debug registers set in the thread.
+    debug_regs: DebugRegs,
+}
+
+const PAGE_SIZE: u64 = 4096;
+
+// The regset of PTRACE_GETREGSET for the XSAVE area.
+const NT_X86_XSTATE: u64 = 0x202;
+
+// Pages of memory read since the process last ran or was written, with
+// the counts of lookups for them.
+struct MemoryCache {
+    // Page addresses to their contents, which are shorter than a page or
+    // empty where the memory is not readable.
+    pages: HashMap<u64, Vec<u8>>,
+    hits: u64,
+    misses: u64,
+}
+
 pub struct Ptracer {
     pid: libc::pid_t,
+    // The selected thread, which registers and single steps are for.
+    tid: libc::pid_t,
+    threads: Vec<Thread>,
+    next_thread_num: usize,
     target: target_desc::Target,
+    cache: RefCell<MemoryCache>,
+    // The debug registers for all threads. New threads get them when they
+    // stop first.
+    debug_regs: DebugRegs,
+    // The last reported stop ended a single step.
+    stepped: bool,
 }
 
 impl Ptracer {
-    pub fn new(args: &Vec<String>) -> Self {
-        let pid: libc::pid_t;
-        unsafe {
-            pid = libc::fork();
-        }
-        check_libc(pid, "fork");
+    pub fn new(args: &Vec<String>) -> Result<Self, Error> {
+        // Ensure args are null terminated.
+        let cargs: Vec<String> =
+            args.iter().map(|a|format!("{}\0", a)).collect();
+        let mut cargs: Vec<*const libc::c_char> =
+            cargs.iter().map(|a|a.as_bytes().as_ptr()
+                             as *const libc::c_char).collect();
+        cargs.push(std::ptr::null());
 
+        // The child reports the errno of a failed exec through the pipe,
+        // which is closed by a successful one.
+        let mut fds = [0 as libc::c_int; 2];
+        try!(check_libc(unsafe {
+            libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC)
+        }, "pipe2"));
+        let pid = unsafe { libc::fork() };
         if pid == 0 {
-            // Ensure args are null terminated.
-            let args: Vec<String> =
-                args.iter().map(|a|format!("{}\0", a)).collect();
-            let mut args: Vec<*const libc::c_char> =
-                args.iter().map(|a|a.as_bytes().as_ptr()
-                                as *const libc::c_char).collect();
-            args.push(std::ptr::null());
-            check_ptrace!(libc::PTRACE_TRACEME, 0, 0, 0);
             unsafe {
-                check_libc!(libc::execv(args[0], args.as_mut_ptr()));
-                libc::_exit(1);
+                libc::close(fds[0]);
+                if libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0) == 0 {
+                    libc::execv(cargs[0], cargs.as_mut_ptr());
+                }
+                let err = errno();
+                libc::write(fds[1], &err as *const i32 as *const libc::c_void,
+                            4);
+                libc::_exit(127);
             }
         }
+        let mut err: i32 = 0;
+        let read = unsafe {
+            libc::close(fds[1]);
+            let read = if pid > 0 {
+                libc::read(fds[0], &mut err as *mut i32 as *mut libc::c_void,
+                           4)
+            } else {
+                0
+            };
+            libc::close(fds[0]);
+            read
+        };
+        try!(check_libc(pid, "fork"));
 
+        let mut ptracer = Ptracer::with_pid(pid);
+        // A child which failed to exec has exited.
+        let status = try!(ptracer.wait());
+        if read == 4 {
+            return Err(Error::Exec(args[0].clone(), err));
+        }
+        if !status.is_stopped() {
+            return Err(Error::Exec(args[0].clone(), libc::ECHILD));
+        }
+        try!(ptracer.set_options(pid));
+
+        return Ok(ptracer);
+    }
+
+    fn with_pid(pid: libc::pid_t) -> Self {
         let mut ptracer = Ptracer {
             pid: pid,
+            tid: pid,
+            threads: vec!(),
+            next_thread_num: 1,
             target: target_desc::get_target(),
+            cache: RefCell::new(MemoryCache {
+                pages: HashMap::new(),
+                hits: 0,
+                misses: 0,
+            }),
+            debug_regs: DebugRegs::default(),
+            stepped: false,
         };
+        ptracer.add_thread(pid, true);
+        return ptracer;
+    }
 
-        let status = ptracer.wait();
-        if !status.is_stopped() {
-            // TODO: Handle error properly.
-            println!("Starting a child process ({}) failed ({:?})",
-                     args[0], status);
-            std::process::exit(-1);
-        }
+    fn add_thread(&mut self, tid: libc::pid_t, running: bool) {
+        self.threads.push(Thread {
+            num: self.next_thread_num,
+            tid: tid,
+            running: running,
+            stepping: false,
+            pending: None,
+            stop_requested: false,
+            signal: 0,
+            debug_regs: DebugRegs::default(),
+        });
+        self.next_thread_num += 1;
+    }
+
+    // Makes new threads and processes traced too, and reports execs.
+    fn set_options(&self, tid: libc::pid_t) -> Result<(), Error> {
+        try!(check_ptrace!(libc::PTRACE_SETOPTIONS, tid, 0,
+                           libc::PTRACE_O_TRACECLONE |
+                           libc::PTRACE_O_TRACEFORK |
+                           libc::PTRACE_O_TRACEVFORK |
+                           libc::PTRACE_O_TRACEEXEC));
+        return Ok(());
+    }
 
+    // Makes a ptracer for a child reported by ProcessState::Fork, which
+    // has the same options as its parent.
+    pub fn from_fork(pid: libc::pid_t) -> Self {
+        let mut ptracer = Ptracer::with_pid(pid);
+        ptracer.threads[0].running = false;
         return ptracer;
     }
 
+    // Attaches to a running process and waits until it stops.
+    pub fn attach(pid: libc::pid_t) -> Result<Self, Error> {
+        try!(check_ptrace!(libc::PTRACE_ATTACH, pid, 0, 0));
+        let mut ptracer = Ptracer::with_pid(pid);
+        ptracer.threads[0].running = false;
+        try!(wait_attached(pid));
+        try!(ptracer.set_options(pid));
+
+        // Threads which already exist are attached one by one.
+        let tasks = try!(std::fs::read_dir(format!("/proc/{}/task", pid))
+                         .map_err(|e| {
+                             Error::Sys("opendir",
+                                        e.raw_os_error().unwrap_or(0))
+                         }));
+        let mut tids: Vec<libc::pid_t> = tasks
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_string_lossy().parse().ok())
+            .filter(|&tid| tid != pid)
+            .collect();
+        tids.sort();
+        for tid in tids {
+            // The thread may have exited meanwhile.
+            if check_ptrace!(libc::PTRACE_ATTACH, tid, 0, 0).is_err() ||
+                wait_attached(tid).is_err() {
+                continue;
+            }
+            try!(ptracer.set_options(tid));
+            ptracer.add_thread(tid, false);
+        }
+        return Ok(ptracer);
+    }
+
+    // Releases the process, which continues running. All threads are
+    // detached even if some of them fail.
+    pub fn detach(&self) -> Result<(), Error> {
+        let mut r = Ok(());
+        for thread in self.threads.iter() {
+            if let Err(err) = check_ptrace!(libc::PTRACE_DETACH, thread.tid,
+                                            0, 0) {
+                r = Err(err);
+            }
+        }
+        // Sending SIGCONT discards the SIGSTOPs which have not arrived.
+        if self.threads.iter().any(|t| t.stop_requested) {
+            unsafe {
+                libc::kill(self.pid, libc::SIGCONT);
+            }
+        }
+        return r;
+    }
+
     pub fn pid(&self) -> libc::pid_t { self.pid }
 
-    pub fn single_step(&self) {
-        check_ptrace!(libc::PTRACE_SINGLESTEP, self.pid, 0, 0);
+    pub fn tid(&self) -> libc::pid_t { self.tid }
+
+    // The numbers and the IDs of the threads, in the order of creation.
+    pub fn threads(&self) -> Vec<(usize, libc::pid_t)> {
+        return self.threads.iter().map(|t| (t.num, t.tid)).collect();
+    }
+
+    pub fn thread_num(&self, tid: libc::pid_t) -> usize {
+        return self.threads.iter().find(|t| t.tid == tid).map_or(0, |t| t.num);
+    }
+
+    pub fn select_thread(&mut self, num: usize)
+                         -> Result<libc::pid_t, String> {
+        match self.threads.iter().find(|t| t.num == num) {
+            Some(thread) => {
+                self.tid = thread.tid;
+                return Ok(thread.tid);
+            }
+            None => return Err(format!("Invalid thread ID: {}", num)),
+        }
+    }
+
+    // Steps the selected thread while the others stay stopped.
+    pub fn single_step(&mut self) -> Result<(), Error> {
+        let tid = self.tid;
+        return self.single_step_thread(tid);
+    }
+
+    pub fn single_step_thread(&mut self, tid: libc::pid_t)
+                              -> Result<(), Error> {
+        self.invalidate_cache();
+        let thread = self.threads.iter_mut().find(|t| t.tid == tid).unwrap();
+        thread.stepping = true;
+        return resume(thread);
+    }
+
+    // Sets the signal which a thread receives when it is resumed.
+    pub fn set_signal(&mut self, tid: libc::pid_t, sig: i32) {
+        if let Some(thread) = self.threads.iter_mut().find(|t| t.tid == tid) {
+            thread.signal = sig;
+        }
+    }
+
+    // Discards the signals to be delivered for which `f` returns false.
+    pub fn retain_signals<F: Fn(i32) -> bool>(&mut self, f: F) {
+        for thread in self.threads.iter_mut() {
+            if thread.signal != 0 && !f(thread.signal) {
+                thread.signal = 0;
+            }
+        }
+    }
+
+    // Fetches siginfo_t of the signal which stopped a thread.
+    pub fn get_siginfo(&self, tid: libc::pid_t)
+                       -> Result<signal::SigInfo, Error> {
+        let mut buf = [0 as u8; 128];
+        try!(check_ptrace!(libc::PTRACE_GETSIGINFO, tid, 0,
+                           buf.as_mut_ptr()));
+        let i32_at = |off: usize| {
+            (0..4).fold(0, |v, i| v | (buf[off + i] as u32) << (i * 8)) as i32
+        };
+        // si_addr and si_pid share the union after the three ints.
+        let addr = (0..8).fold(0, |v, i| v | (buf[16 + i] as u64) << (i * 8));
+        return Ok(signal::SigInfo {
+            signo: i32_at(0),
+            code: i32_at(8),
+            addr: addr,
+            pid: i32_at(16),
+        });
     }
 
-    pub fn get_regs(&self) -> Registers {
+    pub fn get_regs(&self) -> Result<Registers, Error> {
+        return self.get_thread_regs(self.tid);
+    }
+
+    pub fn get_thread_regs(&self, tid: libc::pid_t)
+                           -> Result<Registers, Error> {
         let buf = vec![0 as u8; self.target.user_size];
-        check_ptrace!(libc::PTRACE_GETREGS, self.pid, 0, buf.as_ptr());
+        try!(check_ptrace!(libc::PTRACE_GETREGS, tid, 0, buf.as_ptr()));
 
         let mut gps = vec![0; self.target.gp_names.len()];
         let gp_ptr = unsafe {
@@ -145,64 +484,647 @@ impl Ptracer {
             gps[i] = r;
         }
 
-        return Registers {
-            ip: gps[self.target.ip_index],
-            sp: gps[self.target.sp_index],
-            bp: gps[self.target.bp_index],
-            gps: gps,
+        return Ok(Registers::new(gps, &self.target));
+    }
+
+    pub fn set_regs(&self, regs: &Registers) -> Result<(), Error> {
+        // Keeps the parts of `struct user` other than the registers.
+        let mut buf = vec![0 as u8; self.target.user_size];
+        try!(check_ptrace!(libc::PTRACE_GETREGS, self.tid, 0, buf.as_ptr()));
+        let gp_size = self.target.gp_size;
+        assert!(gp_size == 8);
+        for (i, r) in regs.gps.iter().enumerate() {
+            let off = self.target.gp_off as usize + gp_size * i;
+            for j in 0..gp_size {
+                let shift = if self.target.le { j } else { gp_size - 1 - j };
+                buf[off + j] = (r >> (shift * 8)) as u8;
+            }
         }
+        try!(check_ptrace!(libc::PTRACE_SETREGS, self.tid, 0,
+                           buf.as_ptr()));
+        return Ok(());
     }
 
-    pub fn set_regs(&self, regs: &Registers) {
-        check_ptrace!(libc::PTRACE_SETREGS, self.pid, 0, regs.gps.as_ptr());
+    // Returns the XSAVE area of the current thread, or only its FXSAVE part
+    // if the kernel cannot provide it.
+    pub fn get_fp_regs(&self) -> Result<Vec<u8>, Error> {
+        // Large enough for the XSAVE area of current CPUs.
+        let mut buf = vec![0 as u8; 16384];
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let r = check_ptrace!(libc::PTRACE_GETREGSET, self.tid,
+                              NT_X86_XSTATE, &mut iov as *mut libc::iovec);
+        if r.is_ok() && iov.iov_len >= self.target.fp_size {
+            buf.truncate(iov.iov_len);
+            return Ok(buf);
+        }
+        buf.truncate(self.target.fp_size);
+        try!(check_ptrace!(libc::PTRACE_GETFPREGS, self.tid, 0,
+                           buf.as_mut_ptr()));
+        return Ok(buf);
     }
 
-    pub fn peek_word(&self, addr: u64) -> u64 {
-        return check_ptrace!(libc::PTRACE_PEEKDATA, self.pid, addr, 0) as u64;
+    // Sets the debug registers of all threads, which must be stopped.
+    pub fn set_debug_regs(&mut self, regs: DebugRegs) -> Result<(), Error> {
+        self.debug_regs = regs;
+        for index in 0..self.threads.len() {
+            try!(self.sync_debug_regs(index));
+        }
+        return Ok(());
     }
 
-    pub fn poke_word(&self, addr: u64, data: u64) {
-        check_ptrace!(libc::PTRACE_POKEDATA, self.pid, addr, data) as u64;
+    fn sync_debug_regs(&mut self, index: usize) -> Result<(), Error> {
+        if self.threads[index].debug_regs == self.debug_regs {
+            return Ok(());
+        }
+        let tid = self.threads[index].tid;
+        let (base, size) = (self.target.debugreg_off, self.target.gp_size);
+        let off = |i: usize| (base + i * size) as u64;
+        // DR7 is cleared first as the kernel validates it with the
+        // addresses.
+        try!(check_ptrace!(libc::PTRACE_POKEUSER, tid, off(7), 0));
+        self.threads[index].debug_regs = DebugRegs::default();
+        if self.debug_regs.control != 0 {
+            for (i, &addr) in self.debug_regs.addrs.iter().enumerate() {
+                try!(check_ptrace!(libc::PTRACE_POKEUSER, tid, off(i), addr));
+            }
+            try!(check_ptrace!(libc::PTRACE_POKEUSER, tid, off(7),
+                               self.debug_regs.control));
+        }
+        self.threads[index].debug_regs = self.debug_regs.clone();
+        return Ok(());
+    }
+
+    // Returns the bits of DR6 telling which of DR0-DR3 made the selected
+    // thread stop, and clears them.
+    pub fn take_debug_status(&self) -> Result<u64, Error> {
+        let dr6 = (self.target.debugreg_off + 6 * self.target.gp_size) as u64;
+        let status = try!(check_ptrace!(libc::PTRACE_PEEKUSER, self.tid, dr6,
+                                        0)) as u64 & 0xf;
+        if status != 0 {
+            try!(check_ptrace!(libc::PTRACE_POKEUSER, self.tid, dr6, 0));
+        }
+        return Ok(status);
+    }
+
+    // Reads the auxiliary vector as (type, value) pairs.
+    pub fn auxv(&self) -> Result<Vec<(u64, u64)>, String> {
+        let data = try!(self.read_proc_file("auxv"));
+        return Ok(parse_auxv(&data));
+    }
+
+    fn read_proc_file(&self, name: &str) -> Result<Vec<u8>, String> {
+        use std::io::Read;
+        let path = format!("/proc/{}/{}", self.pid, name);
+        let mut data = vec![];
+        try!(std::fs::File::open(&path)
+             .and_then(|mut f| f.read_to_end(&mut data))
+             .map_err(|e| format!("Failed to read {}: {}", path, e)));
+        return Ok(data);
+    }
+
+    pub fn maps(&self) -> Result<Vec<Mapping>, String> {
+        let data = try!(self.read_proc_file("maps"));
+        let mut r = vec![];
+        for line in String::from_utf8_lossy(&data).lines() {
+            let cols: Vec<&str> = line.splitn(6, ' ').collect();
+            if cols.len() < 5 {
+                continue;
+            }
+            let range: Vec<&str> = cols[0].split('-').collect();
+            let hex = |s: &str| u64::from_str_radix(s, 16).unwrap_or(0);
+            r.push(Mapping {
+                start: hex(range[0]),
+                end: hex(range.get(1).cloned().unwrap_or("0")),
+                perms: cols[1].to_string(),
+                offset: hex(cols[2]),
+                path: cols.get(5).map_or("", |p| p.trim()).to_string(),
+            });
+        }
+        return Ok(r);
     }
 
-    pub fn poke_byte(&self, addr: u64, data: u8) -> u8 {
+    pub fn thread_name(&self, tid: libc::pid_t) -> Result<String, String> {
+        let data = try!(self.read_proc_file(&format!("task/{}/comm", tid)));
+        return Ok(String::from_utf8_lossy(&data).trim().to_string());
+    }
+
+    // The command line with spaces between arguments.
+    pub fn cmdline(&self) -> Result<String, String> {
+        let data = try!(self.read_proc_file("cmdline"));
+        let args: Vec<String> = data.split(|&c| c == 0)
+            .filter(|a| !a.is_empty())
+            .map(|a| String::from_utf8_lossy(a).into_owned())
+            .collect();
+        return Ok(args.join(" "));
+    }
+
+    // Reads `len` bytes at `addr`. Fails with the first unreadable address
+    // if the range reaches unmapped memory.
+    pub fn read_memory(&self, addr: u64, len: usize)
+                       -> Result<Vec<u8>, Error> {
+        let data = self.read_partial(addr, len);
+        if data.len() < len {
+            return Err(Error::Memory(addr + data.len() as u64));
+        }
+        return Ok(data);
+    }
+
+    // Reads up to `len` bytes at `addr`, stopping at unreadable memory.
+    // Pages are cached until the process runs, and consecutive pages which
+    // are not cached yet are read at once.
+    pub fn read_partial(&self, addr: u64, len: usize) -> Vec<u8> {
+        // The last page is left out to avoid overflows.
+        let limit = (u64::max_value() & !(PAGE_SIZE - 1)).saturating_sub(addr);
+        let len = std::cmp::min(len as u64, limit) as usize;
+        let mut cache = self.cache.borrow_mut();
+        let mut data = vec!();
+        while data.len() < len {
+            let cur = addr + data.len() as u64;
+            let page = cur & !(PAGE_SIZE - 1);
+            if cache.pages.contains_key(&page) {
+                cache.hits += 1;
+            } else {
+                let last = (addr + len as u64 - 1) & !(PAGE_SIZE - 1);
+                let mut end = page;
+                while end <= last && !cache.pages.contains_key(&end) {
+                    end += PAGE_SIZE;
+                }
+                let bytes = self.read_uncached(page, (end - page) as usize);
+                let mut off = 0;
+                while page + off as u64 != end {
+                    let next = off + PAGE_SIZE as usize;
+                    let chunk = &bytes[std::cmp::min(off, bytes.len())..
+                                       std::cmp::min(next, bytes.len())];
+                    cache.pages.insert(page + off as u64, chunk.to_vec());
+                    cache.misses += 1;
+                    off = next;
+                }
+            }
+            let bytes = &cache.pages[&page];
+            let off = (cur - page) as usize;
+            if off >= bytes.len() {
+                break;
+            }
+            let n = std::cmp::min(bytes.len() - off, len - data.len());
+            data.extend_from_slice(&bytes[off..off + n]);
+        }
+        return data;
+    }
+
+    // Forgets the cached memory, which is done whenever the memory may
+    // change.
+    fn invalidate_cache(&self) {
+        self.cache.borrow_mut().pages.clear();
+    }
+
+    // The numbers of cached pages, hits and misses.
+    pub fn cache_stats(&self) -> (usize, u64, u64) {
+        let cache = self.cache.borrow();
+        return (cache.pages.len(), cache.hits, cache.misses);
+    }
+
+    // Reads memory up to the first byte which cannot be read, bypassing the
+    // cache, e.g. to dump all the memory once.
+    //
+    // process_vm_readv takes a syscall for the whole range but cannot read
+    // pages without PROT_READ, so the rest is tried with /proc/PID/mem, and
+    // then with PTRACE_PEEKDATA in case it is not available either.
+    pub fn read_uncached(&self, addr: u64, len: usize) -> Vec<u8> {
+        let mut data = vec![0; len];
+        let mut n = self.read_vm(addr, &mut data);
+        if n < len {
+            n += self.read_mem_file(addr + n as u64, &mut data[n..]);
+        }
+        if n < len {
+            n += self.read_peek(addr + n as u64, &mut data[n..]);
+        }
+        data.truncate(n);
+        return data;
+    }
+
+    fn read_vm(&self, addr: u64, buf: &mut [u8]) -> usize {
+        let mut n = 0;
+        while n < buf.len() {
+            let local = libc::iovec {
+                iov_base: buf[n..].as_mut_ptr() as *mut libc::c_void,
+                iov_len: buf.len() - n,
+            };
+            let remote = libc::iovec {
+                iov_base: (addr + n as u64) as *mut libc::c_void,
+                iov_len: buf.len() - n,
+            };
+            // This stops at the first page which cannot be read.
+            let r = unsafe {
+                libc::process_vm_readv(self.pid, &local, 1, &remote, 1, 0)
+            };
+            if r <= 0 {
+                break;
+            }
+            n += r as usize;
+        }
+        return n;
+    }
+
+    fn read_mem_file(&self, addr: u64, buf: &mut [u8]) -> usize {
+        use std::os::unix::fs::FileExt;
+        let path = format!("/proc/{}/mem", self.pid);
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(_) => return 0,
+        };
+        let mut n = 0;
+        while n < buf.len() {
+            match file.read_at(&mut buf[n..], addr + n as u64) {
+                Ok(0) | Err(_) => break,
+                Ok(r) => n += r,
+            }
+        }
+        return n;
+    }
+
+    // Peeks aligned words so a word is not across the end of a mapping.
+    fn read_peek(&self, addr: u64, buf: &mut [u8]) -> usize {
+        let mut n = 0;
+        while n < buf.len() {
+            let cur = addr + n as u64;
+            let word = match self.peek_word(cur & !7) {
+                Ok(word) => word,
+                Err(_) => break,
+            };
+            for off in (cur & 7)..8 {
+                if n == buf.len() {
+                    break;
+                }
+                buf[n] = (word >> (off * 8)) as u8;
+                n += 1;
+            }
+        }
+        return n;
+    }
+
+    // Writes `data` at `addr`. Like ptrace, /proc/PID/mem can write into
+    // read-only mappings such as code, which process_vm_writev cannot.
+    pub fn write_memory(&self, addr: u64, data: &[u8]) -> Result<(), Error> {
+        self.invalidate_cache();
+        let n = self.write_mem_file(addr, data);
+        if n < data.len() {
+            try!(self.write_poke(addr + n as u64, &data[n..]));
+        }
+        return Ok(());
+    }
+
+    fn write_mem_file(&self, addr: u64, data: &[u8]) -> usize {
+        use std::os::unix::fs::FileExt;
+        let path = format!("/proc/{}/mem", self.pid);
+        let file = match std::fs::OpenOptions::new().write(true).open(&path) {
+            Ok(file) => file,
+            Err(_) => return 0,
+        };
+        let mut n = 0;
+        while n < data.len() {
+            match file.write_at(&data[n..], addr + n as u64) {
+                Ok(0) | Err(_) => break,
+                Ok(r) => n += r,
+            }
+        }
+        return n;
+    }
+
+    // Updates the bytes in aligned words, keeping the other bytes.
+    fn write_poke(&self, addr: u64, data: &[u8]) -> Result<(), Error> {
+        let mut n = 0;
+        while n < data.len() {
+            let cur = addr + n as u64;
+            let aligned = cur & !7;
+            let mut word = try!(self.peek_word(aligned)
+                                .map_err(|_| Error::Memory(cur)));
+            for off in (cur & 7)..8 {
+                if n == data.len() {
+                    break;
+                }
+                word &= !(0xff << (off * 8));
+                word |= (data[n] as u64) << (off * 8);
+                n += 1;
+            }
+            try!(self.poke_word(aligned, word)
+                 .map_err(|_| Error::Memory(cur)));
+        }
+        return Ok(());
+    }
+
+    pub fn peek_word(&self, addr: u64) -> Result<u64, Error> {
+        return check_ptrace!(libc::PTRACE_PEEKDATA, self.tid, addr, 0)
+            .map(|v| v as u64).map_err(|_| Error::Memory(addr));
+    }
+
+    pub fn poke_word(&self, addr: u64, data: u64) -> Result<(), Error> {
+        self.invalidate_cache();
+        try!(check_ptrace!(libc::PTRACE_POKEDATA, self.tid, addr, data)
+             .map_err(|_| Error::Memory(addr)));
+        return Ok(());
+    }
+
+    pub fn poke_byte(&self, addr: u64, data: u8) -> Result<u8, Error> {
         assert!(self.target.le);
-        let orig = self.peek_word(addr);
+        let orig = try!(self.peek_word(addr));
         let word = (orig & !0xff) | (data as u64);
-        self.poke_word(addr, word);
-        return (orig & 0xff) as u8;
+        try!(self.poke_word(addr, word));
+        return Ok((orig & 0xff) as u8);
     }
 
-    pub fn poke_breakpoint(&self, addr: u64) -> u8 {
+    pub fn poke_breakpoint(&self, addr: u64) -> Result<u8, Error> {
         assert_eq!(self.target.breakpoint_size, 1);
         return self.poke_byte(addr, self.target.breakpoint_op as u8);
     }
 
-    pub fn cont(&self) {
-        check_ptrace!(libc::PTRACE_CONT, self.pid, 0, 0);
+    // Resumes all threads. Threads with pending stops stay stopped and the
+    // stops are reported by the next wait.
+    pub fn cont(&mut self) -> Result<(), Error> {
+        return self.resume_all(None);
     }
 
-    pub fn wait(&mut self) -> ProcessState {
-        let mut status: i32 = -1;
-        unsafe {
-            check_libc!(libc::waitpid(self.pid, &mut status, 0));
+    // Steps the selected thread while the others run, so a step waiting for
+    // another thread, e.g. on a lock, finishes. Another thread may stop
+    // first, and then the step is reported by a later wait.
+    pub fn single_step_with_others(&mut self) -> Result<(), Error> {
+        let tid = self.tid;
+        return self.resume_all(Some(tid));
+    }
+
+    fn resume_all(&mut self, step_tid: Option<libc::pid_t>)
+                  -> Result<(), Error> {
+        self.invalidate_cache();
+        for thread in self.threads.iter_mut() {
+            if !thread.running && thread.pending.is_none() {
+                thread.stepping = step_tid == Some(thread.tid);
+                try!(resume(thread));
+            }
+            thread.running = true;
+        }
+        return Ok(());
+    }
+
+    // Whether the stop reported last is the end of a single step.
+    pub fn stopped_by_step(&self) -> bool { self.stepped }
+
+    // Waits until a thread stops and then stops all the other threads. The
+    // stopped thread gets selected.
+    pub fn wait(&mut self) -> Result<ProcessState, Error> {
+        loop {
+            let pending = self.threads.iter_mut()
+                .find(|t| t.running && t.pending.is_some())
+                .map(|t| (t.tid, t.pending.take().unwrap()));
+            let (tid, status) = match pending {
+                Some(pending) => pending,
+                None => try!(self.wait_any()),
+            };
+            if let Some(state) = try!(self.handle_status(tid, status)) {
+                return Ok(state);
+            }
         }
+    }
 
-        unsafe {
-            if libc::WIFSIGNALED(status) {
-                return ProcessState::Signal(libc::WTERMSIG(status));
-            } else if libc::WIFEXITED(status) {
-                return ProcessState::Exit(libc::WEXITSTATUS(status));
-            } else if libc::WIFSTOPPED(status) {
-                let sig = libc::WSTOPSIG(status);
-                if sig == libc::SIGTRAP {
-                    return ProcessState::Stop(sig);
+    // Waits for a status of any of our threads. Statuses of threads which
+    // are not known yet, e.g. the first stop of a new thread reported
+    // before its clone event, are kept for later.
+    fn wait_any(&mut self) -> Result<(libc::pid_t, i32), Error> {
+        assert!(self.threads.iter().any(|t| t.running));
+        loop {
+            let known = {
+                let threads = &self.threads;
+                take_stray_status(|tid| threads.iter().any(|t| t.tid == tid))
+            };
+            let (tid, status) = match known {
+                Some(known) => known,
+                None => {
+                    let mut status: i32 = -1;
+                    // Only for our own tracees, not those of other threads
+                    // of the debugger.
+                    let r = unsafe {
+                        libc::waitpid(-1, &mut status,
+                                      libc::__WALL | libc::__WNOTHREAD)
+                    };
+                    (try!(check_libc(r, "waitpid")), status)
+                }
+            };
+            let thread = match self.threads.iter_mut().find(|t| t.tid == tid) {
+                Some(thread) => thread,
+                None => {
+                    STRAY_STATUSES.with(|s| s.borrow_mut().push((tid, status)));
+                    continue;
+                }
+            };
+            if thread.running {
+                return Ok((tid, status));
+            }
+            // E.g. a stopped thread killed from outside, which is reported
+            // when it is resumed.
+            thread.pending = Some(status);
+        }
+    }
+
+    // Returns the state to report, or None if the stop was only for us.
+    fn handle_status(&mut self, tid: libc::pid_t, status: i32)
+                     -> Result<Option<ProcessState>, Error> {
+        let index = self.threads.iter().position(|t| t.tid == tid).unwrap();
+        self.threads[index].running = false;
+        if libc::WIFSIGNALED(status) || libc::WIFEXITED(status) {
+            if tid == self.pid {
+                if libc::WIFSIGNALED(status) {
+                    return Ok(Some(ProcessState::Signal(
+                        libc::WTERMSIG(status))));
+                }
+                return Ok(Some(ProcessState::Exit(
+                    libc::WEXITSTATUS(status))));
+            }
+            log_info!("Thread {} exited", tid);
+            self.threads.remove(index);
+            if self.tid == tid {
+                self.tid = self.pid;
+            }
+            return Ok(None);
+        } else if !libc::WIFSTOPPED(status) {
+            panic!("Unknown status: {}", status);
+        }
+
+        let sig = libc::WSTOPSIG(status);
+        if try!(self.add_clone(tid, status)) {
+            try!(resume(&mut self.threads[index]));
+            return Ok(None);
+        }
+        if sig == libc::SIGSTOP && self.threads[index].stop_requested {
+            self.threads[index].stop_requested = false;
+            try!(self.sync_debug_regs(index));
+            try!(resume(&mut self.threads[index]));
+            return Ok(None);
+        }
+
+        self.stepped = self.threads[index].stepping &&
+            sig == libc::SIGTRAP && status >> 16 == 0;
+        self.threads[index].stepping = false;
+        self.tid = tid;
+        let state = match (sig, status >> 16) {
+            (libc::SIGTRAP, libc::PTRACE_EVENT_FORK) |
+            (libc::SIGTRAP, libc::PTRACE_EVENT_VFORK) => {
+                let mut child: libc::c_ulong = 0;
+                try!(check_ptrace!(libc::PTRACE_GETEVENTMSG, tid, 0,
+                                   &mut child));
+                let child = child as libc::pid_t;
+                // The child starts with a SIGSTOP.
+                if let Err(err) = wait_attached(child) {
+                    log_info!("{}", err);
+                }
+                if status >> 16 == libc::PTRACE_EVENT_VFORK {
+                    ProcessState::VFork(child)
                 } else {
-                    return ProcessState::Signal(sig);
+                    ProcessState::Fork(child)
+                }
+            }
+            (libc::SIGTRAP, libc::PTRACE_EVENT_EXEC) => {
+                try!(self.exec_threads(tid));
+                ProcessState::Exec
+            }
+            _ => ProcessState::Stop(sig),
+        };
+        try!(self.stop_others());
+        return Ok(Some(state));
+    }
+
+    // Only the exec'ing thread survives an exec, with the ID of the thread
+    // group leader. Other threads are reaped by stop_others.
+    fn exec_threads(&mut self, tid: libc::pid_t) -> Result<(), Error> {
+        let mut former: libc::c_ulong = 0;
+        try!(check_ptrace!(libc::PTRACE_GETEVENTMSG, tid, 0, &mut former));
+        let pid = self.pid;
+        self.threads.retain(|t| t.tid == pid || t.tid != former as i32);
+        for thread in self.threads.iter_mut() {
+            thread.pending = None;
+            thread.stop_requested = false;
+            thread.running = thread.tid != pid;
+            // The kernel clears them for the new program.
+            thread.debug_regs = DebugRegs::default();
+        }
+        self.debug_regs = DebugRegs::default();
+        self.tid = pid;
+        return Ok(());
+    }
+
+    // Adds the new thread if the status is a stop for clone.
+    fn add_clone(&mut self, tid: libc::pid_t, status: i32)
+                 -> Result<bool, Error> {
+        if status >> 8 != libc::SIGTRAP | (libc::PTRACE_EVENT_CLONE << 8) {
+            return Ok(false);
+        }
+        let mut new_tid: libc::c_ulong = 0;
+        try!(check_ptrace!(libc::PTRACE_GETEVENTMSG, tid, 0, &mut new_tid));
+        log_info!("New thread {}", new_tid);
+        // The new thread starts with a SIGSTOP.
+        self.add_thread(new_tid as libc::pid_t, true);
+        self.threads.last_mut().unwrap().stop_requested = true;
+        return Ok(true);
+    }
+
+    // Stops the running threads for all-stop mode. Stops other than ours
+    // are kept as pending.
+    fn stop_others(&mut self) -> Result<(), Error> {
+        for thread in self.threads.iter_mut() {
+            if thread.pending.is_some() {
+                thread.running = false;
+            }
+            if thread.running && !thread.stop_requested {
+                unsafe {
+                    libc::syscall(libc::SYS_tgkill, self.pid, thread.tid,
+                                  libc::SIGSTOP);
+                }
+                thread.stop_requested = true;
+            }
+        }
+
+        loop {
+            let tid = match self.threads.iter().find(|t| t.running) {
+                Some(thread) => thread.tid,
+                None => break,
+            };
+            let status = match waitpid_thread(tid) {
+                Err(Error::Sys(_, libc::ECHILD)) if tid != self.pid => {
+                    self.threads.retain(|t| t.tid != tid);
+                    continue;
                 }
+                r => try!(r),
+            };
+            let index =
+                self.threads.iter().position(|t| t.tid == tid).unwrap();
+            self.threads[index].running = false;
+            let exited = libc::WIFSIGNALED(status) || libc::WIFEXITED(status);
+            if exited && tid != self.pid {
+                self.threads.remove(index);
+            } else if try!(self.add_clone(tid, status)) {
+                // The SIGSTOP we sent is still to come.
+            } else if libc::WSTOPSIG(status) == libc::SIGSTOP &&
+                self.threads[index].stop_requested {
+                self.threads[index].stop_requested = false;
+                try!(self.sync_debug_regs(index));
             } else {
-                panic!("Unknown status: {}", status);
+                self.threads[index].pending = Some(status);
             }
         }
+        return Ok(());
+    }
+}
+
+thread_local! {
+    // Statuses which waitpid(-1) took for processes not known then.
+    static STRAY_STATUSES: RefCell<Vec<(libc::pid_t, i32)>> =
+        RefCell::new(vec!());
+}
+
+// Takes the first stray status of a process for which `f` returns true.
+fn take_stray_status<F: Fn(libc::pid_t) -> bool>(f: F)
+                                                 -> Option<(libc::pid_t, i32)> {
+    STRAY_STATUSES.with(|s| {
+        let mut statuses = s.borrow_mut();
+        statuses.iter().position(|&(tid, _)| f(tid))
+            .map(|i| statuses.remove(i))
+    })
+}
+
+// Waits for a status of a thread, which may have been taken already.
+fn waitpid_thread(tid: libc::pid_t) -> Result<i32, Error> {
+    if let Some((_, status)) = take_stray_status(|t| t == tid) {
+        return Ok(status);
+    }
+    let mut status: i32 = -1;
+    let r = unsafe { libc::waitpid(tid, &mut status, libc::__WALL) };
+    try!(check_libc(r, "waitpid"));
+    return Ok(status);
+}
+
+// Waits for the SIGSTOP sent by PTRACE_ATTACH.
+// A thread which has exited instead is reported as gone.
+fn wait_attached(tid: libc::pid_t) -> Result<(), Error> {
+    let status = try!(waitpid_thread(tid));
+    if !libc::WIFSTOPPED(status) {
+        return Err(Error::Sys("waitpid", libc::ESRCH));
+    }
+    return Ok(());
+}
+
+// Resumes a stopped thread. A thread which is gone, e.g. as another thread
+// has exited the process, is not an error as waitpid reports it later.
+fn resume(thread: &mut Thread) -> Result<(), Error> {
+    thread.running = true;
+    let r = if thread.stepping {
+        check_ptrace!(libc::PTRACE_SINGLESTEP, thread.tid, 0, thread.signal)
+    } else {
+        check_ptrace!(libc::PTRACE_CONT, thread.tid, 0, thread.signal)
+    };
+    thread.signal = 0;
+    match r {
+        Err(Error::Sys(_, libc::ESRCH)) => return Ok(()),
+        Err(err) => return Err(err),
+        Ok(_) => return Ok(()),
     }
 }
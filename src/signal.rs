@@ -0,0 +1,314 @@
+// Signal names and the table of how signals stopping the process are
+// handled, like the `handle` command of gdb.
+
+pub const SIGTRAP: i32 = 5;
+
+// The largest real-time signal on Linux.
+const MAX_SIGNAL: i32 = 64;
+
+// Names and descriptions indexed by the signal numbers of x86-64 Linux.
+const SIGNALS: [(&'static str, &'static str); 32] = [
+    ("0", "Signal 0"),
+    ("SIGHUP", "Hangup"),
+    ("SIGINT", "Interrupt"),
+    ("SIGQUIT", "Quit"),
+    ("SIGILL", "Illegal instruction"),
+    ("SIGTRAP", "Trace/breakpoint trap"),
+    ("SIGABRT", "Aborted"),
+    ("SIGBUS", "Bus error"),
+    ("SIGFPE", "Arithmetic exception"),
+    ("SIGKILL", "Killed"),
+    ("SIGUSR1", "User defined signal 1"),
+    ("SIGSEGV", "Segmentation fault"),
+    ("SIGUSR2", "User defined signal 2"),
+    ("SIGPIPE", "Broken pipe"),
+    ("SIGALRM", "Alarm clock"),
+    ("SIGTERM", "Terminated"),
+    ("SIGSTKFLT", "Stack fault"),
+    ("SIGCHLD", "Child status changed"),
+    ("SIGCONT", "Continued"),
+    ("SIGSTOP", "Stopped (signal)"),
+    ("SIGTSTP", "Stopped (user)"),
+    ("SIGTTIN", "Stopped (tty input)"),
+    ("SIGTTOU", "Stopped (tty output)"),
+    ("SIGURG", "Urgent I/O condition"),
+    ("SIGXCPU", "CPU time limit exceeded"),
+    ("SIGXFSZ", "File size limit exceeded"),
+    ("SIGVTALRM", "Virtual timer expired"),
+    ("SIGPROF", "Profiling timer expired"),
+    ("SIGWINCH", "Window size changed"),
+    ("SIGIO", "I/O possible"),
+    ("SIGPWR", "Power fail/restart"),
+    ("SIGSYS", "Bad system call"),
+];
+
+// Signals which are routine for programs and do not stop them by default.
+const QUIET_SIGNALS: [&'static str; 7] = [
+    "SIGALRM", "SIGURG", "SIGCHLD", "SIGWINCH", "SIGIO", "SIGVTALRM",
+    "SIGPROF",
+];
+
+pub fn name(sig: i32) -> String {
+    match SIGNALS.get(sig as usize) {
+        Some(&(name, _)) => name.to_string(),
+        None => format!("SIG{}", sig),
+    }
+}
+
+pub fn description(sig: i32) -> String {
+    match SIGNALS.get(sig as usize) {
+        Some(&(_, desc)) => desc.to_string(),
+        None => format!("Real-time event {}", sig),
+    }
+}
+
+// Accepts names with or without "SIG" in any case, e.g. "segv".
+pub fn from_name(name: &str) -> Option<i32> {
+    let name = name.to_uppercase();
+    let name = if name.starts_with("SIG") {
+        name
+    } else {
+        format!("SIG{}", name)
+    };
+    for sig in 1..(MAX_SIGNAL + 1) {
+        if self::name(sig) == name {
+            return Some(sig);
+        }
+    }
+    return None;
+}
+
+// The fields of siginfo_t which tell why a signal was raised.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SigInfo {
+    pub signo: i32,
+    pub code: i32,
+    // si_addr of faults.
+    pub addr: u64,
+    // si_pid of signals sent by processes.
+    pub pid: i32,
+}
+
+const SI_KERNEL: i32 = 0x80;
+
+// si_code values which any signal can have.
+const GENERIC_CODES: [(i32, &'static str, &'static str); 8] = [
+    (0, "SI_USER", "Sent by kill"),
+    (-1, "SI_QUEUE", "Sent by sigqueue"),
+    (-2, "SI_TIMER", "POSIX timer expired"),
+    (-3, "SI_MESGQ", "POSIX message queue state changed"),
+    (-4, "SI_ASYNCIO", "AIO completed"),
+    (-5, "SI_SIGIO", "Queued SIGIO"),
+    (-6, "SI_TKILL", "Sent by tkill"),
+    (SI_KERNEL, "SI_KERNEL", "Sent by the kernel"),
+];
+
+// Signal specific si_code values, which start from 1.
+const ILL_CODES: [(&'static str, &'static str); 8] = [
+    ("ILL_ILLOPC", "Illegal opcode"),
+    ("ILL_ILLOPN", "Illegal operand"),
+    ("ILL_ILLADR", "Illegal addressing mode"),
+    ("ILL_ILLTRP", "Illegal trap"),
+    ("ILL_PRVOPC", "Privileged opcode"),
+    ("ILL_PRVREG", "Privileged register"),
+    ("ILL_COPROC", "Coprocessor error"),
+    ("ILL_BADSTK", "Internal stack error"),
+];
+const FPE_CODES: [(&'static str, &'static str); 8] = [
+    ("FPE_INTDIV", "Integer divide by zero"),
+    ("FPE_INTOVF", "Integer overflow"),
+    ("FPE_FLTDIV", "Floating-point divide by zero"),
+    ("FPE_FLTOVF", "Floating-point overflow"),
+    ("FPE_FLTUND", "Floating-point underflow"),
+    ("FPE_FLTRES", "Floating-point inexact result"),
+    ("FPE_FLTINV", "Floating-point invalid operation"),
+    ("FPE_FLTSUB", "Subscript out of range"),
+];
+const SEGV_CODES: [(&'static str, &'static str); 4] = [
+    ("SEGV_MAPERR", "Address not mapped to object"),
+    ("SEGV_ACCERR", "Invalid permissions for mapped object"),
+    ("SEGV_BNDERR", "Failed address bound checks"),
+    ("SEGV_PKUERR", "Failed protection key checks"),
+];
+const BUS_CODES: [(&'static str, &'static str); 5] = [
+    ("BUS_ADRALN", "Invalid address alignment"),
+    ("BUS_ADRERR", "Nonexistent physical address"),
+    ("BUS_OBJERR", "Object-specific hardware error"),
+    ("BUS_MCEERR_AR", "Hardware memory error consumed on a machine check"),
+    ("BUS_MCEERR_AO", "Hardware memory error detected in process"),
+];
+const TRAP_CODES: [(&'static str, &'static str); 4] = [
+    ("TRAP_BRKPT", "Process breakpoint"),
+    ("TRAP_TRACE", "Process trace trap"),
+    ("TRAP_BRANCH", "Process taken branch trap"),
+    ("TRAP_HWBKPT", "Hardware breakpoint/watchpoint"),
+];
+const CHLD_CODES: [(&'static str, &'static str); 6] = [
+    ("CLD_EXITED", "Child has exited"),
+    ("CLD_KILLED", "Child was killed"),
+    ("CLD_DUMPED", "Child terminated abnormally"),
+    ("CLD_TRAPPED", "Traced child has trapped"),
+    ("CLD_STOPPED", "Child has stopped"),
+    ("CLD_CONTINUED", "Stopped child has continued"),
+];
+
+// Returns the name and the description of si_code of a signal.
+pub fn code_name(sig: i32, code: i32)
+                 -> Option<(&'static str, &'static str)> {
+    if code <= 0 || code == SI_KERNEL {
+        return GENERIC_CODES.iter().find(|c| c.0 == code)
+            .map(|&(_, name, desc)| (name, desc));
+    }
+    let codes: &[(&'static str, &'static str)] = match name(sig).as_str() {
+        "SIGILL" => &ILL_CODES,
+        "SIGFPE" => &FPE_CODES,
+        "SIGSEGV" => &SEGV_CODES,
+        "SIGBUS" => &BUS_CODES,
+        "SIGTRAP" => &TRAP_CODES,
+        "SIGCHLD" => &CHLD_CODES,
+        _ => return None,
+    };
+    return codes.get(code as usize - 1).cloned();
+}
+
+// Whether si_addr is the address of the fault which raised the signal.
+pub fn has_fault_addr(info: &SigInfo) -> bool {
+    let faults = ["SIGILL", "SIGFPE", "SIGSEGV", "SIGBUS"];
+    return faults.contains(&name(info.signo).as_str()) &&
+        info.code > 0 && info.code != SI_KERNEL;
+}
+
+// Whether si_pid is the process which sent the signal.
+pub fn has_sender(info: &SigInfo) -> bool {
+    // SI_USER, SI_QUEUE and SI_TKILL.
+    return [0, -1, -6].contains(&info.code);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Handling {
+    pub stop: bool,
+    pub print: bool,
+    // Whether the signal is delivered when the process continues.
+    pub pass: bool,
+}
+
+pub struct SignalTable {
+    // Indexed by signal numbers.
+    handlings: Vec<Handling>,
+}
+
+impl SignalTable {
+    pub fn new() -> Self {
+        let mut handlings = vec!();
+        for sig in 0..(MAX_SIGNAL + 1) {
+            let quiet = QUIET_SIGNALS.contains(&name(sig).as_str());
+            handlings.push(Handling {
+                stop: !quiet,
+                print: !quiet,
+                // The debugger uses these.
+                pass: sig != SIGTRAP && name(sig) != "SIGINT",
+            });
+        }
+        Self {
+            handlings: handlings,
+        }
+    }
+
+    pub fn get(&self, sig: i32) -> Handling {
+        return self.handlings[sig as usize];
+    }
+
+    // Applies a keyword of the `handle` command to a signal. "stop" implies
+    // "print" and "noprint" implies "nostop".
+    pub fn set(&mut self, sig: i32, keyword: &str) -> Result<(), String> {
+        let h = &mut self.handlings[sig as usize];
+        match keyword {
+            "stop" => {
+                h.stop = true;
+                h.print = true;
+            }
+            "nostop" => h.stop = false,
+            "print" => h.print = true,
+            "noprint" => {
+                h.print = false;
+                h.stop = false;
+            }
+            "pass" | "noignore" => h.pass = true,
+            "nopass" | "ignore" => h.pass = false,
+            _ => {
+                return Err(format!("Unrecognized or ambiguous flag word: \
+                                    \"{}\".", keyword));
+            }
+        }
+        return Ok(());
+    }
+
+    // Formats the handling of signals, or all signals if `sigs` is empty.
+    pub fn format(&self, sigs: &[i32]) -> String {
+        let all: Vec<i32> = (1..(MAX_SIGNAL + 1)).collect();
+        let sigs = if sigs.is_empty() { &all } else { sigs };
+        let yes_no = |b: bool| if b { "Yes" } else { "No" };
+        let mut lines = vec!(format!("{:<14}{:<6}{:<6}{:<16}{}", "Signal",
+                                     "Stop", "Print", "Pass to program",
+                                     "Description"));
+        for &sig in sigs {
+            let h = self.get(sig);
+            lines.push(format!("{:<14}{:<6}{:<6}{:<16}{}", name(sig),
+                               yes_no(h.stop), yes_no(h.print),
+                               yes_no(h.pass), description(sig)));
+        }
+        return lines.join("\n");
+    }
+}
+
+#[test]
+fn test_names() {
+    assert_eq!("SIGSEGV", name(11));
+    assert_eq!("Segmentation fault", description(11));
+    assert_eq!("SIG34", name(34));
+    assert_eq!(Some(11), from_name("SIGSEGV"));
+    assert_eq!(Some(11), from_name("segv"));
+    assert_eq!(Some(34), from_name("SIG34"));
+    assert_eq!(None, from_name("SIGFOO"));
+}
+
+#[test]
+fn test_signal_table() {
+    let mut table = SignalTable::new();
+    assert_eq!(Handling { stop: true, print: true, pass: true },
+               table.get(11));
+    assert_eq!(Handling { stop: false, print: false, pass: true },
+               table.get(17));
+    assert!(!table.get(SIGTRAP).pass);
+    assert!(table.set(11, "noprint").is_ok());
+    assert!(table.set(11, "nopass").is_ok());
+    assert_eq!(Handling { stop: false, print: false, pass: false },
+               table.get(11));
+    assert!(table.set(17, "stop").is_ok());
+    assert_eq!(Handling { stop: true, print: true, pass: true },
+               table.get(17));
+    assert!(table.set(17, "bogus").is_err());
+    assert_eq!(concat!("Signal        Stop  Print Pass to program ",
+                       "Description\n",
+                       "SIGSEGV       No    No    No              ",
+                       "Segmentation fault"),
+               table.format(&[11]));
+}
+
+#[test]
+fn test_code_name() {
+    assert_eq!(Some(("SEGV_MAPERR", "Address not mapped to object")),
+               code_name(11, 1));
+    assert_eq!(Some(("BUS_ADRALN", "Invalid address alignment")),
+               code_name(7, 1));
+    assert_eq!(Some(("SI_TKILL", "Sent by tkill")), code_name(10, -6));
+    assert_eq!(Some(("SI_KERNEL", "Sent by the kernel")), code_name(11, 0x80));
+    assert_eq!(None, code_name(10, 1));
+    assert_eq!(None, code_name(11, 9));
+    let info = SigInfo { signo: 11, code: 2, addr: 0x1000, pid: 0x1000 };
+    assert!(has_fault_addr(&info));
+    assert!(!has_sender(&info));
+    let info = SigInfo { signo: 11, code: -6, addr: 42, pid: 42 };
+    assert!(!has_fault_addr(&info));
+    assert!(has_sender(&info));
+}
@@ -6,6 +6,27 @@ pub struct Target {
     pub ip_index: usize,
     pub sp_index: usize,
     pub bp_index: usize,
+    // The index of the flags register and the names of its bits.
+    pub flags_index: usize,
+    pub flag_names: Vec<(u32, &'static str)>,
+    // The layout of the FXSAVE area from PTRACE_GETFPREGS, which is also the
+    // beginning of the XSAVE area from PTRACE_GETREGSET.
+    pub fp_size: usize,
+    // The names, offsets and sizes of the control and status registers.
+    pub fp_ctrl: Vec<(&'static str, usize, usize)>,
+    // The x87 stack registers, 16 bytes apart.
+    pub st_off: usize,
+    pub st_count: usize,
+    // The SSE registers, 16 bytes each.
+    pub xmm_off: usize,
+    pub xmm_count: usize,
+    // The upper halves of the AVX registers in the XSAVE area.
+    pub ymmh_off: usize,
+    // The offset of the debug registers in `struct user` for
+    // PTRACE_POKEUSER.
+    pub debugreg_off: usize,
+    // Maps DWARF register numbers to indices of `gp_names`.
+    pub dwarf_regs: Vec<usize>,
     pub le: bool,
     pub breakpoint_op: u64,
     pub breakpoint_size: i32,
@@ -51,10 +72,13 @@ pub fn get_target() -> Target {
     //   printf("user_size: %zu,\n", sizeof(struct user));
     //   printf("gp_size: %zu,\n", sizeof(void*));
     //   printf("gp_off: %zu,\n", offsetof(struct user, regs));
-    // #if 0
-    //   printf("fp_size: %zu,\n", sizeof(double));
-    //   printf("fp_off: %zu,\n", offsetof(struct user, i387));
-    // #endif
+    //   printf("fp_size: %zu,\n", sizeof(struct user_fpregs_struct));
+    //   printf("st_off: %zu,\n",
+    //          offsetof(struct user_fpregs_struct, st_space));
+    //   printf("xmm_off: %zu,\n",
+    //          offsetof(struct user_fpregs_struct, xmm_space));
+    //   printf("debugreg_off: %zu,\n",
+    //          offsetof(struct user, u_debugreg));
     //   printf("ip_index: %zu,\n",
     //          offsetof(struct user_regs_struct, rip) / sizeof(void*));
     //   printf("sp_index: %zu,\n",
@@ -70,6 +94,25 @@ pub fn get_target() -> Target {
         ip_index: 16,
         sp_index: 19,
         bp_index: 4,
+        flags_index: 18,
+        flag_names: vec!((0, "CF"), (2, "PF"), (4, "AF"), (6, "ZF"),
+                         (7, "SF"), (8, "TF"), (9, "IF"), (10, "DF"),
+                         (11, "OF"), (14, "NT"), (16, "RF"), (17, "VM"),
+                         (18, "AC"), (19, "VIF"), (20, "VIP"), (21, "ID")),
+        fp_size: 512,
+        fp_ctrl: vec!(("fctrl", 0, 2), ("fstat", 2, 2), ("ftag", 4, 1),
+                      ("fop", 6, 2), ("fioff", 8, 8), ("fooff", 16, 8),
+                      ("mxcsr", 24, 4)),
+        st_off: 32,
+        st_count: 8,
+        xmm_off: 160,
+        xmm_count: 16,
+        // The offset of the AVX state in the standard XSAVE format.
+        ymmh_off: 576,
+        debugreg_off: 848,
+        // rax, rdx, rcx, rbx, rsi, rdi, rbp, rsp, r8-r15 and rip.
+        dwarf_regs: vec!(10, 12, 11, 5, 13, 14, 4, 19,
+                         9, 8, 7, 6, 3, 2, 1, 0, 16),
         le: true,
         breakpoint_op: 0xcc,
         breakpoint_size: 1,
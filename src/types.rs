@@ -0,0 +1,811 @@
+use dwarf;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StructKind {
+    Struct,
+    Union,
+    Class,
+}
+
+impl StructKind {
+    pub fn keyword(&self) -> &'static str {
+        match *self {
+            StructKind::Struct => "struct",
+            StructKind::Union => "union",
+            StructKind::Class => "class",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Member {
+    pub name: Option<String>,
+    pub ty: Type,
+    pub offset: usize,
+    // Bit-field members have non-zero `bit_size`. `bit_offset` counts from
+    // the least significant bit of the byte at `offset`.
+    pub bit_size: usize,
+    pub bit_offset: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructType {
+    pub kind: StructKind,
+    pub name: Option<String>,
+    pub size: usize,
+    // None for an incomplete type, i.e., a declaration or a struct which
+    // was reached through a pointer. Use Context::complete_type to get
+    // the members.
+    pub members: Option<Vec<Member>>,
+    // The file name of the binary and the index of the DIE.
+    pub origin: (String, usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumType {
+    pub name: Option<String>,
+    pub size: usize,
+    pub values: Vec<(String, i64)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Void,
+    Base { name: String, encoding: u64, size: usize },
+    Pointer(Box<Type>),
+    Array(Box<Type>, Option<usize>),
+    Struct(StructType),
+    Enum(EnumType),
+    Typedef(String, Box<Type>),
+    Const(Box<Type>),
+    Volatile(Box<Type>),
+    // The return type, the parameter types and whether it has varargs or
+    // no prototype.
+    Function(Box<Type>, Vec<Type>, bool),
+}
+
+pub const POINTER_SIZE: usize = 8;
+
+const DW_TAG_ARRAY_TYPE: u64 = 0x01;
+const DW_TAG_CLASS_TYPE: u64 = 0x02;
+const DW_TAG_ENUMERATION_TYPE: u64 = 0x04;
+const DW_TAG_MEMBER: u64 = 0x0d;
+const DW_TAG_REFERENCE_TYPE: u64 = 0x10;
+const DW_TAG_STRUCTURE_TYPE: u64 = 0x13;
+const DW_TAG_SUBROUTINE_TYPE: u64 = 0x15;
+const DW_TAG_UNION_TYPE: u64 = 0x17;
+const DW_TAG_UNSPECIFIED_PARAMETERS: u64 = 0x18;
+const DW_TAG_SUBRANGE_TYPE: u64 = 0x21;
+const DW_TAG_ENUMERATOR: u64 = 0x28;
+const DW_TAG_RESTRICT_TYPE: u64 = 0x37;
+const DW_TAG_UNSPECIFIED_TYPE: u64 = 0x3b;
+const DW_TAG_RVALUE_REFERENCE_TYPE: u64 = 0x42;
+const DW_TAG_ATOMIC_TYPE: u64 = 0x47;
+
+const DW_AT_BIT_OFFSET: u64 = 0x0c;
+const DW_AT_BIT_SIZE: u64 = 0x0d;
+const DW_AT_CONST_VALUE: u64 = 0x1c;
+const DW_AT_PROTOTYPED: u64 = 0x27;
+const DW_AT_UPPER_BOUND: u64 = 0x2f;
+const DW_AT_COUNT: u64 = 0x37;
+const DW_AT_DATA_MEMBER_LOCATION: u64 = 0x38;
+const DW_AT_DATA_BIT_OFFSET: u64 = 0x6b;
+
+impl Type {
+    pub fn int() -> Type {
+        Type::Base {
+            name: "int".to_string(),
+            encoding: dwarf::DW_ATE_SIGNED,
+            size: 4,
+        }
+    }
+
+    pub fn long() -> Type {
+        Type::Base {
+            name: "long".to_string(),
+            encoding: dwarf::DW_ATE_SIGNED,
+            size: 8,
+        }
+    }
+
+    pub fn double() -> Type {
+        Type::Base {
+            name: "double".to_string(),
+            encoding: dwarf::DW_ATE_FLOAT,
+            size: 8,
+        }
+    }
+
+    pub fn pointer_to(ty: Type) -> Type {
+        Type::Pointer(Box::new(ty))
+    }
+
+    // Skips typedefs and qualifiers.
+    pub fn strip(&self) -> &Type {
+        match *self {
+            Type::Typedef(_, ref t) | Type::Const(ref t) |
+            Type::Volatile(ref t) => t.strip(),
+            ref t => t,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match *self {
+            Type::Void => 1,
+            Type::Base { size, .. } => size,
+            Type::Pointer(_) => POINTER_SIZE,
+            Type::Array(ref t, n) => t.size() * n.unwrap_or(0),
+            Type::Struct(ref s) => s.size,
+            Type::Enum(ref e) => e.size,
+            Type::Typedef(_, ref t) | Type::Const(ref t) |
+            Type::Volatile(ref t) => t.size(),
+            Type::Function(..) => 1,
+        }
+    }
+
+    pub fn is_integral(&self) -> bool {
+        match *self.strip() {
+            Type::Base { encoding, .. } => encoding != dwarf::DW_ATE_FLOAT,
+            Type::Enum(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        match *self.strip() {
+            Type::Base { encoding, .. } => encoding == dwarf::DW_ATE_FLOAT,
+            _ => false,
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        match *self.strip() {
+            Type::Base { encoding, .. } => {
+                encoding == dwarf::DW_ATE_SIGNED ||
+                    encoding == dwarf::DW_ATE_SIGNED_CHAR ||
+                    encoding == dwarf::DW_ATE_FLOAT
+            }
+            Type::Enum(ref e) => e.values.iter().any(|v| v.1 < 0),
+            _ => false,
+        }
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        match *self.strip() {
+            Type::Pointer(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_char(&self) -> bool {
+        match *self.strip() {
+            Type::Base { encoding, size, .. } => {
+                size == 1 && (encoding == dwarf::DW_ATE_SIGNED_CHAR ||
+                              encoding == dwarf::DW_ATE_UNSIGNED_CHAR)
+            }
+            _ => false,
+        }
+    }
+
+    // The pointee of a pointer or the element of an array.
+    pub fn target(&self) -> Option<&Type> {
+        match *self.strip() {
+            Type::Pointer(ref t) | Type::Array(ref t, _) => Some(t),
+            _ => None,
+        }
+    }
+
+    // The name used by `whatis`, e.g., "struct point" or "int *".
+    pub fn name(&self) -> String {
+        declaration(self, "")
+    }
+}
+
+// The part of a declaration before the declarator, e.g., "const int".
+fn base_name(ty: &Type) -> String {
+    match *ty {
+        Type::Void => "void".to_string(),
+        Type::Base { ref name, .. } => name.clone(),
+        Type::Struct(ref s) => match s.name {
+            Some(ref name) => format!("{} {}", s.kind.keyword(), name),
+            None => format!("{} {{...}}", s.kind.keyword()),
+        },
+        Type::Enum(ref e) => match e.name {
+            Some(ref name) => format!("enum {}", name),
+            None => "enum {...}".to_string(),
+        },
+        Type::Typedef(ref name, _) => name.clone(),
+        Type::Const(ref t) => format!("const {}", base_name(t)),
+        Type::Volatile(ref t) => format!("volatile {}", base_name(t)),
+        Type::Pointer(_) | Type::Array(..) | Type::Function(..) => {
+            panic!("Not a base type: {:?}", ty)
+        }
+    }
+}
+
+fn params_string(params: &Vec<Type>, varargs: bool) -> String {
+    let mut names: Vec<String> = params.iter().map(|p| p.name()).collect();
+    if varargs {
+        if names.is_empty() {
+            return String::new();
+        }
+        names.push("...".to_string());
+    } else if names.is_empty() {
+        names.push("void".to_string());
+    }
+    return names.join(", ");
+}
+
+// Formats a C declaration of `name` with type `ty`, e.g., "int (*fp)(int)".
+pub fn declaration(ty: &Type, name: &str) -> String {
+    let sep = |inner: &str| {
+        if inner.is_empty() || inner.starts_with('*') ||
+            inner.starts_with('(') {
+            inner.to_string()
+        } else {
+            format!(" {}", inner)
+        }
+    };
+    match *ty {
+        Type::Pointer(ref t) => {
+            let inner = format!("*{}", name);
+            match **t {
+                Type::Array(..) | Type::Function(..) => {
+                    declaration(t, &format!("({})", inner))
+                }
+                _ => {
+                    let base = declaration(t, "");
+                    if base.ends_with('*') {
+                        format!("{}{}", base, inner)
+                    } else {
+                        format!("{} {}", base, inner)
+                    }
+                }
+            }
+        }
+        Type::Const(ref t) if t.is_pointer() => {
+            declaration(t, &format!(" const{}", sep(name)))
+        }
+        Type::Array(ref t, n) => {
+            let dim = n.map(|n| n.to_string()).unwrap_or(String::new());
+            declaration(t, &format!("{}[{}]", name, dim))
+        }
+        Type::Function(ref ret, ref params, varargs) => {
+            let inner = format!("{}({})", name, params_string(params, varargs));
+            let inner = if name.is_empty() {
+                format!(" {}", inner)
+            } else {
+                inner
+            };
+            declaration(ret, &inner)
+        }
+        _ => {
+            let base = base_name(ty);
+            if name.is_empty() {
+                base
+            } else if name.starts_with(' ') {
+                format!("{}{}", base, name)
+            } else {
+                format!("{} {}", base, name)
+            }
+        }
+    }
+}
+
+fn struct_body(s: &StructType, indent: usize) -> String {
+    let pad = " ".repeat(indent + 4);
+    let mut r = String::from("{\n");
+    match s.members {
+        Some(ref members) => {
+            for m in members {
+                let name = m.name.clone().unwrap_or(String::new());
+                let decl = match *m.ty.strip() {
+                    // Expand anonymous aggregates inline.
+                    Type::Struct(ref inner) if inner.name.is_none() &&
+                        !is_typedef(&m.ty) => {
+                        let body = struct_body(inner, indent + 4);
+                        if name.is_empty() {
+                            format!("{} {}", inner.kind.keyword(), body)
+                        } else {
+                            format!("{} {} {}", inner.kind.keyword(), body,
+                                    name)
+                        }
+                    }
+                    _ => declaration(&m.ty, &name),
+                };
+                if m.bit_size > 0 {
+                    r += &format!("{}{} : {};\n", pad, decl, m.bit_size);
+                } else {
+                    r += &format!("{}{};\n", pad, decl);
+                }
+            }
+        }
+        None => {
+            r += &format!("{}<incomplete type>\n", pad);
+        }
+    }
+    r += &" ".repeat(indent);
+    r += "}";
+    return r;
+}
+
+fn is_typedef(ty: &Type) -> bool {
+    match *ty {
+        Type::Typedef(..) => true,
+        _ => false,
+    }
+}
+
+fn enum_body(e: &EnumType) -> String {
+    let mut next = 0;
+    let mut values = vec!();
+    for &(ref name, v) in &e.values {
+        if v == next {
+            values.push(name.clone());
+        } else {
+            values.push(format!("{} = {}", name, v));
+        }
+        next = v + 1;
+    }
+    return format!("{{{}}}", values.join(", "));
+}
+
+// Formats the full definition of a type for `ptype`.
+pub fn definition(ty: &Type) -> String {
+    let mut quals = String::new();
+    let mut t = ty;
+    loop {
+        match *t {
+            Type::Typedef(_, ref inner) => t = inner,
+            Type::Const(ref inner) if !inner.is_pointer() => {
+                quals += "const ";
+                t = inner;
+            }
+            Type::Volatile(ref inner) if !inner.is_pointer() => {
+                quals += "volatile ";
+                t = inner;
+            }
+            _ => break,
+        }
+    }
+    match *t {
+        Type::Struct(ref s) => {
+            let name = match s.name {
+                Some(ref name) => format!("{} ", name),
+                None => String::new(),
+            };
+            format!("{}{} {}{}", quals, s.kind.keyword(), name,
+                    struct_body(s, 0))
+        }
+        Type::Enum(ref e) => {
+            let name = match e.name {
+                Some(ref name) => format!("{} ", name),
+                None => String::new(),
+            };
+            format!("{}enum {}{}", quals, name, enum_body(e))
+        }
+        Type::Pointer(_) | Type::Array(..) | Type::Function(..) => {
+            // Show the definition of the base aggregate, e.g.,
+            // "struct node {...} *".
+            let t = expand_typedefs(t);
+            let decl = declaration(&t, "");
+            let base = innermost(&t);
+            let prefix = base_name(base);
+            match *base.strip() {
+                Type::Struct(_) | Type::Enum(_) if decl.starts_with(&prefix) => {
+                    format!("{}{}{}", quals, definition(base),
+                            &decl[prefix.len()..])
+                }
+                _ => format!("{}{}", quals, decl),
+            }
+        }
+        _ => format!("{}{}", quals, declaration(t, "")),
+    }
+}
+
+// The type at the bottom of pointer, array and function types.
+fn innermost(ty: &Type) -> &Type {
+    match *ty {
+        Type::Pointer(ref t) | Type::Array(ref t, _) |
+        Type::Function(ref t, _, _) => innermost(t),
+        Type::Const(ref t) | Type::Volatile(ref t) if t.is_pointer() => {
+            innermost(t)
+        }
+        ref t => t,
+    }
+}
+
+// Rebuilds a pointer, array or function type with its innermost type
+// replaced by `f`.
+pub fn map_base<F: Fn(&Type) -> Type>(ty: &Type, f: &F) -> Type {
+    match *ty {
+        Type::Pointer(ref t) => Type::Pointer(Box::new(map_base(t, f))),
+        Type::Array(ref t, n) => Type::Array(Box::new(map_base(t, f)), n),
+        Type::Function(ref r, ref p, v) => {
+            Type::Function(Box::new(map_base(r, f)), p.clone(), v)
+        }
+        Type::Const(ref t) if t.is_pointer() => {
+            Type::Const(Box::new(map_base(t, f)))
+        }
+        ref t => f(t),
+    }
+}
+
+// Replaces typedefs in pointer, array and function types, which is what
+// `ptype` shows for them.
+fn expand_typedefs(ty: &Type) -> Type {
+    match *ty {
+        Type::Typedef(_, ref t) => expand_typedefs(t),
+        Type::Pointer(ref t) => Type::Pointer(Box::new(expand_typedefs(t))),
+        Type::Array(ref t, n) => Type::Array(Box::new(expand_typedefs(t)), n),
+        Type::Const(ref t) => Type::Const(Box::new(expand_typedefs(t))),
+        Type::Volatile(ref t) => Type::Volatile(Box::new(expand_typedefs(t))),
+        Type::Function(ref r, ref p, v) => {
+            Type::Function(Box::new(expand_typedefs(r)),
+                           p.iter().map(expand_typedefs).collect(), v)
+        }
+        ref t => t.clone(),
+    }
+}
+
+// Decodes DWARF type DIEs of a binary.
+pub struct Decoder<'b, 'a: 'b> {
+    info: &'b dwarf::DebugInfo<'a>,
+    filename: &'b str,
+}
+
+impl<'b, 'a> Decoder<'b, 'a> {
+    pub fn new(info: &'b dwarf::DebugInfo<'a>, filename: &'b str) -> Self {
+        Self {
+            info: info,
+            filename: filename,
+        }
+    }
+
+    // Decodes the type referred by DW_AT_type of a DIE.
+    pub fn type_of(&self, index: usize) -> Type {
+        match self.info.type_of(index) {
+            Some(ty) => self.decode(ty, false),
+            None => Type::Void,
+        }
+    }
+
+    fn type_attr(&self, index: usize, shallow: bool) -> Type {
+        match self.info.type_of(index) {
+            Some(ty) => self.decode(ty, shallow),
+            None => Type::Void,
+        }
+    }
+
+    fn name(&self, index: usize) -> Option<String> {
+        self.info.name(index).map(|n| n.to_string())
+    }
+
+    fn size(&self, index: usize) -> usize {
+        self.info.die(index).udata(dwarf::DW_AT_BYTE_SIZE).unwrap_or(0)
+            as usize
+    }
+
+    // Decodes a type DIE. Aggregates reached through pointers are left
+    // incomplete when `shallow` is set so recursive types terminate.
+    pub fn decode(&self, index: usize, shallow: bool) -> Type {
+        let die = self.info.die(index);
+        match die.tag {
+            dwarf::DW_TAG_BASE_TYPE => Type::Base {
+                name: self.name(index).unwrap_or("?".to_string()),
+                encoding: die.udata(dwarf::DW_AT_ENCODING).unwrap_or(0),
+                size: self.size(index),
+            },
+            dwarf::DW_TAG_POINTER_TYPE | DW_TAG_REFERENCE_TYPE |
+            DW_TAG_RVALUE_REFERENCE_TYPE => {
+                Type::Pointer(Box::new(self.type_attr(index, true)))
+            }
+            dwarf::DW_TAG_CONST_TYPE => {
+                Type::Const(Box::new(self.type_attr(index, shallow)))
+            }
+            dwarf::DW_TAG_VOLATILE_TYPE => {
+                Type::Volatile(Box::new(self.type_attr(index, shallow)))
+            }
+            DW_TAG_RESTRICT_TYPE | DW_TAG_ATOMIC_TYPE => {
+                self.type_attr(index, shallow)
+            }
+            dwarf::DW_TAG_TYPEDEF => {
+                Type::Typedef(self.name(index).unwrap_or("?".to_string()),
+                              Box::new(self.type_attr(index, shallow)))
+            }
+            DW_TAG_ARRAY_TYPE => self.decode_array(index, shallow),
+            DW_TAG_STRUCTURE_TYPE | DW_TAG_UNION_TYPE | DW_TAG_CLASS_TYPE => {
+                self.decode_struct(index, shallow)
+            }
+            DW_TAG_ENUMERATION_TYPE => self.decode_enum(index),
+            DW_TAG_SUBROUTINE_TYPE | dwarf::DW_TAG_SUBPROGRAM => {
+                self.decode_function(index)
+            }
+            DW_TAG_UNSPECIFIED_TYPE => match self.name(index) {
+                Some(name) => Type::Base {
+                    name: name,
+                    encoding: 0,
+                    size: POINTER_SIZE,
+                },
+                None => Type::Void,
+            },
+            _ => Type::Void,
+        }
+    }
+
+    fn decode_array(&self, index: usize, shallow: bool) -> Type {
+        let mut dims = vec!();
+        for &child in &self.info.die(index).children {
+            let die = self.info.die(child);
+            if die.tag != DW_TAG_SUBRANGE_TYPE {
+                continue;
+            }
+            let count = match die.attr(DW_AT_COUNT).and_then(|v| v.udata()) {
+                Some(count) => Some(count as usize),
+                None => die.attr(DW_AT_UPPER_BOUND)
+                    .and_then(|v| v.sdata())
+                    .map(|ub| (ub + 1) as usize),
+            };
+            dims.push(count);
+        }
+        let mut ty = self.type_attr(index, shallow);
+        if dims.is_empty() {
+            dims.push(None);
+        }
+        for count in dims.into_iter().rev() {
+            ty = Type::Array(Box::new(ty), count);
+        }
+        return ty;
+    }
+
+    fn decode_struct(&self, index: usize, shallow: bool) -> Type {
+        let die = self.info.die(index);
+        let kind = match die.tag {
+            DW_TAG_UNION_TYPE => StructKind::Union,
+            DW_TAG_CLASS_TYPE => StructKind::Class,
+            _ => StructKind::Struct,
+        };
+        let is_decl = die.attr(dwarf::DW_AT_DECLARATION)
+            .map_or(false, |d| d.flag());
+        let members = if shallow || is_decl {
+            None
+        } else {
+            let mut members = vec!();
+            for &child in &die.children {
+                if self.info.die(child).tag == DW_TAG_MEMBER {
+                    members.push(self.decode_member(child));
+                }
+            }
+            Some(members)
+        };
+        Type::Struct(StructType {
+            kind: kind,
+            name: self.name(index),
+            size: self.size(index),
+            members: members,
+            origin: (self.filename.to_string(), index),
+        })
+    }
+
+    fn decode_member(&self, index: usize) -> Member {
+        let die = self.info.die(index);
+        let ty = self.type_attr(index, false);
+        let mut offset = match die.attr(DW_AT_DATA_MEMBER_LOCATION) {
+            Some(&dwarf::FormValue::Block(expr)) => {
+                // Usually DW_OP_plus_uconst.
+                if expr.len() > 1 && expr[0] == 0x23 {
+                    dwarf::Reader::at(expr, 1).uleb().unwrap_or(0) as usize
+                } else {
+                    0
+                }
+            }
+            Some(v) => v.udata().unwrap_or(0) as usize,
+            None => 0,
+        };
+        let bit_size = die.udata(DW_AT_BIT_SIZE).unwrap_or(0) as usize;
+        let mut bit_offset = 0;
+        if bit_size > 0 {
+            if let Some(bits) = die.udata(DW_AT_DATA_BIT_OFFSET) {
+                offset += bits as usize / 8;
+                bit_offset = bits as usize % 8;
+            } else if let Some(bits) = die.udata(DW_AT_BIT_OFFSET) {
+                // DWARF 2 and 3 count from the most significant bit of the
+                // storage unit.
+                let storage = die.udata(dwarf::DW_AT_BYTE_SIZE)
+                    .map(|s| s as usize).unwrap_or(ty.size());
+                let bits = storage * 8 - bits as usize - bit_size;
+                offset += bits / 8;
+                bit_offset = bits % 8;
+            }
+        }
+        Member {
+            name: self.name(index),
+            ty: ty,
+            offset: offset,
+            bit_size: bit_size,
+            bit_offset: bit_offset,
+        }
+    }
+
+    fn decode_enum(&self, index: usize) -> Type {
+        let mut values = vec!();
+        for &child in &self.info.die(index).children {
+            let die = self.info.die(child);
+            if die.tag != DW_TAG_ENUMERATOR {
+                continue;
+            }
+            let v = die.attr(DW_AT_CONST_VALUE).and_then(|v| v.sdata());
+            values.push((self.name(child).unwrap_or("?".to_string()),
+                         v.unwrap_or(0)));
+        }
+        Type::Enum(EnumType {
+            name: self.name(index),
+            size: self.size(index),
+            values: values,
+        })
+    }
+
+    fn decode_function(&self, index: usize) -> Type {
+        let die = self.info.die(index);
+        let mut params = vec!();
+        let mut varargs = !die.attr(DW_AT_PROTOTYPED).map_or(false, |p| p.flag());
+        for &child in &die.children {
+            match self.info.die(child).tag {
+                dwarf::DW_TAG_FORMAL_PARAMETER => {
+                    params.push(self.type_attr(child, true));
+                }
+                DW_TAG_UNSPECIFIED_PARAMETERS => varargs = true,
+                _ => {}
+            }
+        }
+        Type::Function(Box::new(self.type_attr(index, true)), params, varargs)
+    }
+
+    // Finds a type definition, e.g., "struct point" or "uint".
+    pub fn find(&self, name: &str) -> Option<Type> {
+        let (tags, name): (&[u64], &str) =
+            if name.starts_with("struct ") {
+                (&[DW_TAG_STRUCTURE_TYPE, DW_TAG_CLASS_TYPE], &name[7..])
+            } else if name.starts_with("union ") {
+                (&[DW_TAG_UNION_TYPE], &name[6..])
+            } else if name.starts_with("enum ") {
+                (&[DW_TAG_ENUMERATION_TYPE], &name[5..])
+            } else {
+                (&[dwarf::DW_TAG_TYPEDEF, dwarf::DW_TAG_BASE_TYPE,
+                   DW_TAG_STRUCTURE_TYPE, DW_TAG_CLASS_TYPE,
+                   DW_TAG_UNION_TYPE, DW_TAG_ENUMERATION_TYPE], name)
+            };
+        let name = name.trim();
+        let found = self.info.find_type(name, |tag| tags.contains(&tag));
+        return found.map(|index| self.decode(index, false));
+    }
+}
+
+// Returns a C base type from its name, e.g., "unsigned long".
+pub fn c_base_type(name: &str) -> Option<Type> {
+    let mut words: Vec<&str> = name.split_whitespace().collect();
+    let unsigned = words.contains(&"unsigned");
+    let signed = words.contains(&"signed");
+    words.retain(|w| *w != "unsigned" && *w != "signed");
+    let longs = words.iter().filter(|w| **w == "long").count();
+    words.retain(|w| *w != "long" && *w != "int");
+    let (size, encoding) = match (words.as_slice(), longs) {
+        (&[], 0) if !unsigned && !signed && !name.contains("int") => {
+            return None;
+        }
+        (&[], 0) => (4, dwarf::DW_ATE_SIGNED),
+        (&[], _) => (8, dwarf::DW_ATE_SIGNED),
+        (&["short"], 0) => (2, dwarf::DW_ATE_SIGNED),
+        (&["char"], 0) if unsigned => (1, dwarf::DW_ATE_UNSIGNED_CHAR),
+        (&["char"], 0) => (1, dwarf::DW_ATE_SIGNED_CHAR),
+        (&["_Bool"], 0) | (&["bool"], 0) => (1, dwarf::DW_ATE_BOOLEAN),
+        (&["float"], 0) => (4, dwarf::DW_ATE_FLOAT),
+        (&["double"], 0) => (8, dwarf::DW_ATE_FLOAT),
+        (&["double"], 1) => (16, dwarf::DW_ATE_FLOAT),
+        (&["void"], 0) => return Some(Type::Void),
+        _ => return None,
+    };
+    let encoding = if unsigned && encoding == dwarf::DW_ATE_SIGNED {
+        dwarf::DW_ATE_UNSIGNED
+    } else {
+        encoding
+    };
+    return Some(Type::Base {
+        name: name.split_whitespace().collect::<Vec<&str>>().join(" "),
+        encoding: encoding,
+        size: size,
+    });
+}
+
+// The type of x87 registers, named like gdb.
+pub fn x87_ext() -> Type {
+    Type::Base {
+        name: "i387_ext".to_string(),
+        encoding: dwarf::DW_ATE_FLOAT,
+        size: 10,
+    }
+}
+
+// Returns the type of vector registers like gdb's "union vec128", which
+// has members such as v4_float and v2_int64 viewing the lanes.
+pub fn vector_union(size: usize) -> Type {
+    let lanes = [
+        ("float", "float", dwarf::DW_ATE_FLOAT, 4),
+        ("double", "double", dwarf::DW_ATE_FLOAT, 8),
+        ("int8", "int8_t", dwarf::DW_ATE_SIGNED, 1),
+        ("int16", "int16_t", dwarf::DW_ATE_SIGNED, 2),
+        ("int32", "int32_t", dwarf::DW_ATE_SIGNED, 4),
+        ("int64", "int64_t", dwarf::DW_ATE_SIGNED, 8),
+    ];
+    let members = lanes.iter().map(|&(lane, name, encoding, lane_size)| {
+        let elem = Type::Base {
+            name: name.to_string(),
+            encoding: encoding,
+            size: lane_size,
+        };
+        Member {
+            name: Some(format!("v{}_{}", size / lane_size, lane)),
+            ty: Type::Array(Box::new(elem), Some(size / lane_size)),
+            offset: 0,
+            bit_size: 0,
+            bit_offset: 0,
+        }
+    }).collect();
+    Type::Struct(StructType {
+        kind: StructKind::Union,
+        name: Some(format!("vec{}", size * 8)),
+        size: size,
+        members: Some(members),
+        origin: (String::new(), 0),
+    })
+}
+
+#[test]
+fn test_declaration() {
+    let int = Type::int();
+    let ptr = Type::pointer_to(int.clone());
+    assert_eq!("int", declaration(&int, ""));
+    assert_eq!("int x", declaration(&int, "x"));
+    assert_eq!("int *", declaration(&ptr, ""));
+    assert_eq!("int **pp", declaration(&Type::pointer_to(ptr.clone()), "pp"));
+    assert_eq!("int [5]", declaration(&Type::Array(Box::new(int.clone()),
+                                                   Some(5)), ""));
+    assert_eq!("int m[2][3]", declaration(
+        &Type::Array(Box::new(Type::Array(Box::new(int.clone()), Some(3))),
+                     Some(2)), "m"));
+    let func = Type::Function(Box::new(int.clone()),
+                              vec!(int.clone(), int.clone()), false);
+    assert_eq!("int (int, int)", declaration(&func, ""));
+    assert_eq!("int (*cb)(int, int)",
+               declaration(&Type::pointer_to(func), "cb"));
+    let cchar = Type::Const(Box::new(c_base_type("char").unwrap()));
+    assert_eq!("const char *s", declaration(&Type::pointer_to(cchar), "s"));
+    assert_eq!("int * const p",
+               declaration(&Type::Const(Box::new(ptr.clone())), "p"));
+}
+
+#[test]
+fn test_c_base_type() {
+    assert_eq!(Some(Type::long()), c_base_type("long"));
+    assert_eq!(8, c_base_type("unsigned long int").unwrap().size());
+    assert!(!c_base_type("unsigned").unwrap().is_signed());
+    assert_eq!(2, c_base_type("short").unwrap().size());
+    assert!(c_base_type("char").unwrap().is_char());
+    assert_eq!(None, c_base_type("point"));
+}
+
+#[test]
+fn test_vector_union() {
+    let ty = vector_union(32);
+    assert_eq!("union vec256", ty.name());
+    assert_eq!(32, ty.size());
+    assert_eq!(concat!("union vec256 {\n",
+                       "    float v8_float[8];\n",
+                       "    double v4_double[4];\n",
+                       "    int8_t v32_int8[32];\n",
+                       "    int16_t v16_int16[16];\n",
+                       "    int32_t v8_int32[8];\n",
+                       "    int64_t v4_int64[4];\n",
+                       "}"),
+               definition(&ty));
+}
@@ -0,0 +1,600 @@
+use binary;
+use dwarf;
+use std::collections::HashMap;
+
+// The DWARF registers tracked while unwinding: rax-r15 and the return
+// address.
+pub const NUM_REGS: usize = 17;
+pub const FP_REG: u16 = 6;
+pub const SP_REG: u16 = 7;
+pub const RA_REG: u16 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CfaRule<'a> {
+    RegOffset(u16, i64),
+    Expr(&'a [u8]),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegRule<'a> {
+    Undefined,
+    SameValue,
+    Offset(i64),
+    ValOffset(i64),
+    Register(u16),
+    Expr(&'a [u8]),
+    ValExpr(&'a [u8]),
+}
+
+// A row of the CFI table, i.e., how to compute the CFA and the registers
+// of the caller at a pc.
+#[derive(Clone, Debug)]
+pub struct Row<'a> {
+    pub cfa: CfaRule<'a>,
+    pub regs: HashMap<u16, RegRule<'a>>,
+    pub ra_reg: u16,
+}
+
+struct Cie<'a> {
+    code_align: u64,
+    data_align: i64,
+    ra_reg: u16,
+    fde_encoding: u8,
+    // Whether FDEs have augmentation data, i.e., the augmentation string
+    // starts with 'z'.
+    aug_data: bool,
+    addr_size: usize,
+    instructions: &'a [u8],
+}
+
+struct Fde<'a> {
+    cie: usize,
+    start: u64,
+    end: u64,
+    instructions: &'a [u8],
+}
+
+// The contents of .eh_frame and .debug_frame.
+pub struct CallFrameInfo<'a> {
+    cies: Vec<Cie<'a>>,
+    // Sorted by `start`.
+    fdes: Vec<Fde<'a>>,
+}
+
+const DW_EH_PE_OMIT: u8 = 0xff;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_DATAREL: u8 = 0x30;
+
+// Reads a pointer in .eh_frame encoded as `enc`. `base` is the address of
+// the section.
+fn read_encoded(r: &mut dwarf::Reader, enc: u8, base: u64, addr_size: usize)
+                -> Result<u64, String> {
+    if enc == DW_EH_PE_OMIT {
+        return Ok(0);
+    }
+    let pos = base + r.pos() as u64;
+    let v = match enc & 0x0f {
+        0x00 => try!(r.uint(addr_size)),
+        0x01 => try!(r.uleb()),
+        0x02 => try!(r.u16()) as u64,
+        0x03 => try!(r.u32()) as u64,
+        0x04 => try!(r.u64()),
+        0x09 => try!(r.sleb()) as u64,
+        0x0a => try!(r.u16()) as i16 as u64,
+        0x0b => try!(r.u32()) as i32 as u64,
+        0x0c => try!(r.u64()),
+        _ => return Err(format!("Unknown pointer encoding: 0x{:x}", enc)),
+    };
+    match enc & 0x70 {
+        0 => Ok(v),
+        DW_EH_PE_PCREL => Ok(pos.wrapping_add(v)),
+        DW_EH_PE_DATAREL => Ok(base.wrapping_add(v)),
+        _ => Err(format!("Unsupported pointer encoding: 0x{:x}", enc)),
+    }
+}
+
+impl<'a> CallFrameInfo<'a> {
+    pub fn empty() -> Self {
+        Self {
+            cies: vec!(),
+            fdes: vec!(),
+        }
+    }
+
+    pub fn parse(bin: &binary::Binary<'a>) -> Result<Self, String> {
+        let mut cfi = CallFrameInfo::empty();
+        if let Some(sec) = bin.section(".eh_frame") {
+            try!(cfi.parse_section(sec, true));
+        }
+        if let Some(sec) = bin.section(".debug_frame") {
+            try!(cfi.parse_section(sec, false));
+        }
+        cfi.fdes.sort_by_key(|f| f.start);
+        return Ok(cfi);
+    }
+
+    fn parse_section(&mut self, sec: binary::Section<'a>, is_eh: bool)
+                     -> Result<(), String> {
+        let mut cie_offsets = HashMap::new();
+        let mut r = dwarf::Reader::new(sec.data);
+        while !r.is_empty() {
+            let start = r.pos();
+            let (len, offset_size) = try!(r.initial_length());
+            if len == 0 {
+                if is_eh {
+                    break;
+                }
+                continue;
+            }
+            let id_pos = r.pos();
+            let end = id_pos + len;
+            let id = try!(r.uint(offset_size));
+            let is_cie = if is_eh {
+                id == 0
+            } else {
+                id == 0xffffffff || id == 0xffffffffffffffff
+            };
+            if is_cie {
+                let cie = try!(self.parse_cie(&mut r, end, is_eh));
+                cie_offsets.insert(start, self.cies.len());
+                self.cies.push(cie);
+            } else {
+                let cie_off = if is_eh {
+                    id_pos - id as usize
+                } else {
+                    id as usize
+                };
+                let cie = match cie_offsets.get(&cie_off) {
+                    Some(&cie) => cie,
+                    None => {
+                        // A CIE after its FDE, which is legal but rare.
+                        let mut cr = dwarf::Reader::at(sec.data, cie_off);
+                        let (len, offset_size) = try!(cr.initial_length());
+                        let end = cr.pos() + len;
+                        try!(cr.uint(offset_size));
+                        let cie = try!(self.parse_cie(&mut cr, end, is_eh));
+                        cie_offsets.insert(cie_off, self.cies.len());
+                        self.cies.push(cie);
+                        self.cies.len() - 1
+                    }
+                };
+                let (enc, addr_size) = {
+                    let cie = &self.cies[cie];
+                    (cie.fde_encoding, cie.addr_size)
+                };
+                let start = if is_eh {
+                    try!(read_encoded(&mut r, enc, sec.addr, addr_size))
+                } else {
+                    try!(r.uint(addr_size))
+                };
+                let range = if is_eh {
+                    try!(read_encoded(&mut r, enc & 0x0f, 0, addr_size))
+                } else {
+                    try!(r.uint(addr_size))
+                };
+                if self.cies[cie].aug_data {
+                    let len = try!(r.uleb()) as usize;
+                    try!(r.skip(len));
+                }
+                if end > sec.data.len() || r.pos() > end {
+                    return Err("Broken FDE".to_string());
+                }
+                self.fdes.push(Fde {
+                    cie: cie,
+                    start: start,
+                    end: start.wrapping_add(range),
+                    instructions: &sec.data[r.pos()..end],
+                });
+            }
+            r.seek(end);
+        }
+        return Ok(());
+    }
+
+    fn parse_cie(&self, r: &mut dwarf::Reader<'a>, end: usize, is_eh: bool)
+                 -> Result<Cie<'a>, String> {
+        let version = try!(r.u8());
+        let aug = try!(r.cstr());
+        let mut addr_size = 8;
+        if version >= 4 {
+            addr_size = try!(r.u8()) as usize;
+            try!(r.u8());
+        }
+        let code_align = try!(r.uleb());
+        let data_align = try!(r.sleb());
+        let ra_reg = if version == 1 {
+            try!(r.u8()) as u16
+        } else {
+            try!(r.uleb()) as u16
+        };
+        let mut fde_encoding = if is_eh { 0 } else { DW_EH_PE_OMIT };
+        if aug.starts_with('z') {
+            let len = try!(r.uleb()) as usize;
+            let aug_end = r.pos() + len;
+            for c in aug[1..].chars() {
+                match c {
+                    'L' => {
+                        try!(r.u8());
+                    }
+                    'P' => {
+                        let enc = try!(r.u8());
+                        try!(read_encoded(r, enc & 0x7f, 0, addr_size));
+                    }
+                    'R' => fde_encoding = try!(r.u8()),
+                    _ => {}
+                }
+            }
+            r.seek(aug_end);
+        } else if aug.len() > 0 && aug != "eh" {
+            return Err(format!("Unknown CIE augmentation: {}", aug));
+        }
+        if r.pos() > end {
+            return Err("Broken CIE".to_string());
+        }
+        return Ok(Cie {
+            code_align: code_align,
+            data_align: data_align,
+            ra_reg: ra_reg,
+            fde_encoding: fde_encoding,
+            aug_data: aug.starts_with('z'),
+            addr_size: addr_size,
+            instructions: try!(r.bytes(end - r.pos())),
+        });
+    }
+
+    // Returns the CFI row for `pc`, or None if no FDE covers it.
+    pub fn find_row(&self, pc: u64) -> Option<Result<Row<'a>, String>> {
+        let index = match self.fdes.binary_search_by_key(&pc, |f| f.start) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let fde = &self.fdes[index];
+        if pc >= fde.end {
+            return None;
+        }
+        let cie = &self.cies[fde.cie];
+        let mut row = Row {
+            cfa: CfaRule::RegOffset(SP_REG, 0),
+            regs: HashMap::new(),
+            ra_reg: cie.ra_reg,
+        };
+        let r = execute(cie, cie.instructions, &mut row, None, fde.start,
+                        u64::max_value()).and_then(|_| {
+            let initial = row.clone();
+            execute(cie, fde.instructions, &mut row, Some(&initial),
+                    fde.start, pc)
+        });
+        return Some(r.map(|_| row));
+    }
+}
+
+// Runs CFA instructions until the location exceeds `pc`.
+fn execute<'a>(cie: &Cie<'a>, insts: &'a [u8], row: &mut Row<'a>,
+               initial: Option<&Row<'a>>, start: u64, pc: u64)
+               -> Result<(), String> {
+    let mut r = dwarf::Reader::new(insts);
+    let mut loc = start;
+    let mut stack: Vec<Row<'a>> = vec!();
+    let restore = |row: &mut Row<'a>, reg: u16| {
+        match initial.and_then(|i| i.regs.get(&reg)) {
+            Some(rule) => {
+                row.regs.insert(reg, *rule);
+            }
+            None => {
+                row.regs.remove(&reg);
+            }
+        }
+    };
+    while !r.is_empty() {
+        let op = try!(r.u8());
+        let mut advance = 0;
+        match op >> 6 {
+            // DW_CFA_advance_loc
+            1 => advance = (op & 0x3f) as u64 * cie.code_align,
+            // DW_CFA_offset
+            2 => {
+                let off = try!(r.uleb()) as i64 * cie.data_align;
+                row.regs.insert((op & 0x3f) as u16, RegRule::Offset(off));
+            }
+            // DW_CFA_restore
+            3 => restore(row, (op & 0x3f) as u16),
+            _ => match op {
+                // DW_CFA_nop
+                0x00 => {}
+                // DW_CFA_set_loc
+                0x01 => {
+                    let new_loc = try!(r.uint(cie.addr_size));
+                    if new_loc > pc {
+                        return Ok(());
+                    }
+                    loc = new_loc;
+                }
+                // DW_CFA_advance_loc1, 2 and 4
+                0x02 => advance = try!(r.u8()) as u64 * cie.code_align,
+                0x03 => advance = try!(r.u16()) as u64 * cie.code_align,
+                0x04 => advance = try!(r.u32()) as u64 * cie.code_align,
+                // DW_CFA_offset_extended
+                0x05 => {
+                    let reg = try!(r.uleb()) as u16;
+                    let off = try!(r.uleb()) as i64 * cie.data_align;
+                    row.regs.insert(reg, RegRule::Offset(off));
+                }
+                // DW_CFA_restore_extended
+                0x06 => {
+                    let reg = try!(r.uleb()) as u16;
+                    restore(row, reg);
+                }
+                // DW_CFA_undefined
+                0x07 => {
+                    row.regs.insert(try!(r.uleb()) as u16, RegRule::Undefined);
+                }
+                // DW_CFA_same_value
+                0x08 => {
+                    row.regs.insert(try!(r.uleb()) as u16, RegRule::SameValue);
+                }
+                // DW_CFA_register
+                0x09 => {
+                    let reg = try!(r.uleb()) as u16;
+                    let other = try!(r.uleb()) as u16;
+                    row.regs.insert(reg, RegRule::Register(other));
+                }
+                // DW_CFA_remember_state
+                0x0a => stack.push(row.clone()),
+                // DW_CFA_restore_state
+                // The CFA is restored too, like libgcc does, as compilers
+                // emit epilogues in the middle of functions expecting it.
+                0x0b => {
+                    *row = try!(stack.pop().ok_or(
+                        "CFI state stack underflow".to_string()));
+                }
+                // DW_CFA_def_cfa
+                0x0c => {
+                    let reg = try!(r.uleb()) as u16;
+                    let off = try!(r.uleb()) as i64;
+                    row.cfa = CfaRule::RegOffset(reg, off);
+                }
+                // DW_CFA_def_cfa_register
+                0x0d => {
+                    let reg = try!(r.uleb()) as u16;
+                    if let CfaRule::RegOffset(_, off) = row.cfa {
+                        row.cfa = CfaRule::RegOffset(reg, off);
+                    }
+                }
+                // DW_CFA_def_cfa_offset
+                0x0e => {
+                    let off = try!(r.uleb()) as i64;
+                    if let CfaRule::RegOffset(reg, _) = row.cfa {
+                        row.cfa = CfaRule::RegOffset(reg, off);
+                    }
+                }
+                // DW_CFA_def_cfa_expression
+                0x0f => {
+                    let len = try!(r.uleb()) as usize;
+                    row.cfa = CfaRule::Expr(try!(r.bytes(len)));
+                }
+                // DW_CFA_expression and DW_CFA_val_expression
+                0x10 | 0x16 => {
+                    let reg = try!(r.uleb()) as u16;
+                    let len = try!(r.uleb()) as usize;
+                    let expr = try!(r.bytes(len));
+                    row.regs.insert(reg, if op == 0x10 {
+                        RegRule::Expr(expr)
+                    } else {
+                        RegRule::ValExpr(expr)
+                    });
+                }
+                // DW_CFA_offset_extended_sf
+                0x11 => {
+                    let reg = try!(r.uleb()) as u16;
+                    let off = try!(r.sleb()) * cie.data_align;
+                    row.regs.insert(reg, RegRule::Offset(off));
+                }
+                // DW_CFA_def_cfa_sf
+                0x12 => {
+                    let reg = try!(r.uleb()) as u16;
+                    let off = try!(r.sleb()) * cie.data_align;
+                    row.cfa = CfaRule::RegOffset(reg, off);
+                }
+                // DW_CFA_def_cfa_offset_sf
+                0x13 => {
+                    let off = try!(r.sleb()) * cie.data_align;
+                    if let CfaRule::RegOffset(reg, _) = row.cfa {
+                        row.cfa = CfaRule::RegOffset(reg, off);
+                    }
+                }
+                // DW_CFA_val_offset and DW_CFA_val_offset_sf
+                0x14 | 0x15 => {
+                    let reg = try!(r.uleb()) as u16;
+                    let off = if op == 0x14 {
+                        try!(r.uleb()) as i64
+                    } else {
+                        try!(r.sleb())
+                    } * cie.data_align;
+                    row.regs.insert(reg, RegRule::ValOffset(off));
+                }
+                // DW_CFA_GNU_args_size
+                0x2e => {
+                    try!(r.uleb());
+                }
+                // DW_CFA_GNU_negative_offset_extended
+                0x2f => {
+                    let reg = try!(r.uleb()) as u16;
+                    let off = -(try!(r.uleb()) as i64) * cie.data_align;
+                    row.regs.insert(reg, RegRule::Offset(off));
+                }
+                _ => return Err(format!("Unknown CFA instruction: 0x{:x}", op)),
+            },
+        }
+        if advance > 0 {
+            if loc + advance > pc {
+                return Ok(());
+            }
+            loc += advance;
+        }
+    }
+    return Ok(());
+}
+
+// Registers of a frame in DWARF numbering. None for unknown values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Regs {
+    pub regs: [Option<u64>; NUM_REGS],
+}
+
+impl Regs {
+    pub fn get(&self, reg: u16) -> Option<u64> {
+        self.regs.get(reg as usize).and_then(|r| *r)
+    }
+
+    pub fn pc(&self) -> Option<u64> { self.get(RA_REG) }
+    pub fn sp(&self) -> Option<u64> { self.get(SP_REG) }
+}
+
+// Evaluates CFI expressions against the registers of the callee.
+struct ExprFrame<'r> {
+    regs: &'r Regs,
+    cfa: u64,
+    read_word: &'r dyn Fn(u64) -> Result<u64, String>,
+}
+
+impl<'r> dwarf::Frame for ExprFrame<'r> {
+    fn reg(&self, reg: u16) -> Result<u64, String> {
+        self.regs.get(reg).ok_or(format!("Unknown DWARF register: {}", reg))
+    }
+
+    fn frame_base(&self) -> Result<u64, String> {
+        Err("No frame base in CFI.".to_string())
+    }
+
+    fn cfa(&self) -> Result<u64, String> { Ok(self.cfa) }
+
+    fn read_word(&self, addr: u64) -> Result<u64, String> {
+        (self.read_word)(addr)
+    }
+}
+
+pub fn compute_cfa(row: &Row, regs: &Regs, bias: u64,
+                   read_word: &dyn Fn(u64) -> Result<u64, String>)
+                   -> Result<u64, String> {
+    match row.cfa {
+        CfaRule::RegOffset(reg, off) => {
+            let v = try!(regs.get(reg).ok_or(
+                format!("Unknown DWARF register: {}", reg)));
+            Ok(v.wrapping_add(off as u64))
+        }
+        CfaRule::Expr(expr) => {
+            let frame = ExprFrame {
+                regs: regs,
+                cfa: 0,
+                read_word: read_word,
+            };
+            match try!(dwarf::eval_location(expr, 8, bias, &frame)) {
+                dwarf::Location::Memory(v) | dwarf::Location::Value(v) => Ok(v),
+                _ => Err("Invalid CFA expression".to_string()),
+            }
+        }
+    }
+}
+
+// Computes the registers of the caller using a CFI row.
+pub fn unwind_step(row: &Row, regs: &Regs, bias: u64,
+                   read_word: &dyn Fn(u64) -> Result<u64, String>)
+                   -> Result<Regs, String> {
+    let cfa = try!(compute_cfa(row, regs, bias, read_word));
+    let frame = ExprFrame {
+        regs: regs,
+        cfa: cfa,
+        read_word: read_word,
+    };
+    let eval = |expr: &[u8]| {
+        // The CFA is pushed on the stack before evaluation.
+        let mut e = vec!(0x9c);
+        e.extend_from_slice(expr);
+        match try!(dwarf::eval_location(&e, 8, bias, &frame)) {
+            dwarf::Location::Memory(v) | dwarf::Location::Value(v) => Ok(v),
+            _ => Err("Invalid CFI expression".to_string()),
+        }
+    };
+    let mut caller = Regs { regs: [None; NUM_REGS] };
+    for reg in 0..NUM_REGS as u16 {
+        let rule = if reg == RA_REG {
+            row.regs.get(&row.ra_reg)
+        } else {
+            row.regs.get(&reg)
+        };
+        let value = match rule.cloned().unwrap_or(RegRule::SameValue) {
+            RegRule::Undefined => None,
+            RegRule::SameValue => regs.get(reg),
+            RegRule::Offset(off) => {
+                Some(try!(read_word(cfa.wrapping_add(off as u64))))
+            }
+            RegRule::ValOffset(off) => Some(cfa.wrapping_add(off as u64)),
+            RegRule::Register(other) => regs.get(other),
+            RegRule::Expr(expr) => Some(try!(read_word(try!(eval(expr))))),
+            RegRule::ValExpr(expr) => Some(try!(eval(expr))),
+        };
+        caller.regs[reg as usize] = value;
+    }
+    if let Some(&RegRule::Undefined) = row.regs.get(&row.ra_reg) {
+        caller.regs[RA_REG as usize] = None;
+    }
+    caller.regs[SP_REG as usize] = Some(cfa);
+    return Ok(caller);
+}
+
+// Computes the registers of the caller by following the saved frame
+// pointer, for code without CFI.
+pub fn frame_pointer_step(regs: &Regs,
+                          read_word: &dyn Fn(u64) -> Result<u64, String>)
+                          -> Result<Regs, String> {
+    let bp = match regs.get(FP_REG) {
+        Some(bp) if bp != 0 => bp,
+        _ => return Err("No frame pointer.".to_string()),
+    };
+    let mut caller = regs.clone();
+    caller.regs[FP_REG as usize] = Some(try!(read_word(bp)));
+    caller.regs[RA_REG as usize] = Some(try!(read_word(bp + 8)));
+    caller.regs[SP_REG as usize] = Some(bp + 16);
+    return Ok(caller);
+}
+
+#[test]
+fn test_find_row() {
+    let bin = binary::Binary::new("test/data/hello".to_string()).unwrap();
+    let cfi = bin.cfi();
+    assert!(cfi.find_row(0x4005cf).is_none());
+    let row = cfi.find_row(0x4005d0).unwrap().unwrap();
+    assert_eq!(CfaRule::RegOffset(SP_REG, 8), row.cfa);
+    assert_eq!(Some(&RegRule::Offset(-8)), row.regs.get(&RA_REG));
+    let row = cfi.find_row(0x4005d1).unwrap().unwrap();
+    assert_eq!(CfaRule::RegOffset(SP_REG, 16), row.cfa);
+    assert_eq!(Some(&RegRule::Offset(-16)), row.regs.get(&FP_REG));
+    let row = cfi.find_row(0x4005f3).unwrap().unwrap();
+    assert_eq!(CfaRule::RegOffset(FP_REG, 16), row.cfa);
+    let row = cfi.find_row(0x4004c0).unwrap().unwrap();
+    match row.cfa {
+        CfaRule::Expr(_) => {}
+        cfa => panic!("Unexpected CFA: {:?}", cfa),
+    }
+}
+
+#[test]
+fn test_unwind_step() {
+    let bin = binary::Binary::new("test/data/hello".to_string()).unwrap();
+    let row = bin.cfi().find_row(0x4005e2).unwrap().unwrap();
+    let mut regs = Regs { regs: [None; NUM_REGS] };
+    regs.regs[FP_REG as usize] = Some(0x1000);
+    regs.regs[SP_REG as usize] = Some(0xff0);
+    regs.regs[RA_REG as usize] = Some(0x4005e2);
+    let read_word = |addr| match addr {
+        0x1000 => Ok(0x2000),
+        0x1008 => Ok(0x400500),
+        _ => Err(format!("Cannot access memory at address 0x{:x}", addr)),
+    };
+    let caller = unwind_step(&row, &regs, 0, &read_word).unwrap();
+    assert_eq!(Some(0x2000), caller.get(FP_REG));
+    assert_eq!(Some(0x1010), caller.sp());
+    assert_eq!(Some(0x400500), caller.pc());
+    assert_eq!(caller, frame_pointer_step(&regs, &read_word).unwrap());
+}
@@ -0,0 +1,12 @@
+int counter = 41;
+
+void crash(int* p, int depth) {
+  int local = depth * 2;
+  *p = local;
+}
+
+int main() {
+  counter++;
+  crash(0, 3);
+  return 0;
+}
@@ -0,0 +1,28 @@
+namespace ns {
+
+struct Foo {
+  int bar(int x);
+  int bar(const char* s);
+};
+
+int Foo::bar(int x) {
+  return x * 2;
+}
+
+int Foo::bar(const char* s) {
+  return s[0];
+}
+
+template <typename T>
+T twice(T x) {
+  return x + x;
+}
+
+}  // namespace ns
+
+int main() {
+  ns::Foo foo;
+  int r = foo.bar(3);
+  r += foo.bar("a");
+  return ns::twice(r) + ns::twice<long>(1) == 0;
+}
@@ -0,0 +1,11 @@
+#include <unistd.h>
+
+int again(int argc) {
+  return argc;
+}
+
+int main(int argc, char* argv[]) {
+  if (argc > 1)
+    execv(argv[1], argv + 1);
+  return again(argc);
+}
@@ -0,0 +1,18 @@
+double scale(double x, double y) {
+  return x * y;
+}
+
+long double half(long double x) {
+  return x / 2;
+}
+
+float shrink(float x) {
+  return x * 0.5f;
+}
+
+int main() {
+  double d = scale(1.5, -2.0);
+  long double ld = half(5.0L);
+  float f = shrink(0.1f);
+  return d + ld == -0.5 && f < 0.1f ? 0 : 1;
+}
@@ -0,0 +1,15 @@
+#include <sys/wait.h>
+#include <unistd.h>
+
+int in_child(void) {
+  return 3;
+}
+
+int main() {
+  pid_t pid = fork();
+  if (pid == 0)
+    return in_child();
+  int status;
+  waitpid(pid, &status, 0);
+  return WEXITSTATUS(status) + 40;
+}
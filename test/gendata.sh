@@ -5,3 +5,18 @@ cd $(dirname $0)
 clang -g hello.c -o data/hello
 clang -g segv.c -o data/segv
 clang -g neg_one.c -o data/neg_one
+gcc -g -no-pie vars.c -o data/vars
+gcc -g -no-pie types.c -o data/types
+gcc -g -fPIE -pie pie.c -o data/pie
+gcc -g statics.c statics2.c -o data/statics
+g++ -g -no-pie cxx.cc -o data/cxx
+gcc -g -no-pie loop.c -o data/loop
+gcc -g -no-pie -pthread -Wl,-z,now thread.c -o data/thread
+gcc -g -no-pie fork.c -o data/fork
+gcc -g -no-pie exec.c -o data/exec
+gcc -g -no-pie signal.c -o data/signal
+gcc -g -no-pie crash.c -o data/crash
+gcc -g -no-pie float.c -o data/float
+gcc -g -no-pie watch.c -o data/watch
+# Needs /proc/sys/kernel/core_pattern to be "core".
+(cd data && ulimit -c unlimited && ./crash; mv core crash.core)
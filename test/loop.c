@@ -0,0 +1,16 @@
+#include <unistd.h>
+
+int counter;
+
+void tick(void) {
+  counter++;
+}
+
+int main() {
+  /* Tells a debugger waiting to attach that main is running. */
+  write(1, "ready\n", 6);
+  for (;;) {
+    tick();
+    usleep(1000);
+  }
+}
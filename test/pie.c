@@ -0,0 +1,13 @@
+#include <stdio.h>
+
+int counter = 3;
+
+int twice(int x) {
+  return x * 2;
+}
+
+int main(void) {
+  counter = twice(counter);
+  printf("%d\n", counter);
+  return 0;
+}
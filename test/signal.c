@@ -0,0 +1,14 @@
+#include <signal.h>
+
+int handled;
+
+void on_usr1(int sig) {
+  handled += sig;
+}
+
+int main() {
+  signal(SIGUSR1, on_usr1);
+  raise(SIGUSR1);
+  raise(SIGUSR1);
+  return handled;
+}
@@ -0,0 +1,7 @@
+static int counter = 1;
+
+int other_counter(void);
+
+int main(void) {
+  return counter + other_counter();
+}
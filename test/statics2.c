@@ -0,0 +1,5 @@
+static int counter = 2;
+
+int other_counter(void) {
+  return counter;
+}
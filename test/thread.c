@@ -0,0 +1,22 @@
+#include <pthread.h>
+
+int done[4];  /* Large enough to be watched by software. */
+volatile int joining;
+
+void hit(int n) {
+  done[0] = n;
+}
+
+void* worker(void* arg) {
+  /* Lets main() leave pthread_create() before the breakpoint. */
+  while (!joining) {}
+  hit(42);
+  return arg;
+}
+
+int main() {
+  pthread_t th;
+  pthread_create(&th, NULL, worker, NULL);
+  joining = 1; pthread_join(th, NULL);
+  return done[0];
+}
@@ -0,0 +1,40 @@
+#include <stddef.h>
+
+typedef unsigned int uint;
+enum color { RED, GREEN = 4, BLUE };
+struct point { int x; int y; };
+struct node { int value; struct node* next; };
+union number { int i; float f; };
+typedef struct {
+  char name[8];
+  struct point pos;
+  enum color color;
+  unsigned flags : 3;
+  unsigned mode : 5;
+} shape_t;
+
+struct point origin = { 1, 2 };
+struct node second = { 2, NULL };
+struct node first = { 1, &second };
+union number num = { .f = 1.5f };
+shape_t shape = { "box", { 3, 4 }, GREEN, 5, 17 };
+int primes[5] = { 2, 3, 5, 7, 11 };
+int matrix[2][3] = { { 1, 2, 3 }, { 4, 5, 6 } };
+const char* greeting = "hello";
+uint counter = 7;
+enum color color = BLUE;
+int (*callback)(int, int);
+float ratio = 0.25f;
+float third = 1.0f / 3;
+double avogadro = 6.02214076e23;
+float tiny = -1.5e-7f;
+
+int add(int a, int b) {
+  return a + b;
+}
+
+int main(void) {
+  struct node* head = &first;
+  callback = add;
+  return head->next->value + callback(1, 2) - 5;
+}
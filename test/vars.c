@@ -0,0 +1,21 @@
+int global_int = 42;
+static short static_short = -3;
+char global_char = 'A';
+unsigned long global_ulong = 18446744073709551615UL;
+double global_double = 2.5;
+_Bool global_bool = 1;
+int* global_ptr = &global_int;
+
+int add(int a, int b) {
+  int sum = a + b;
+  return sum;
+}
+
+int main(int argc, char** argv) {
+  int local = add(3, 4);
+  {
+    int local = 11;
+    static_short = local;
+  }
+  return local - 7;
+}
@@ -0,0 +1,20 @@
+#include <stdlib.h>
+
+int counter;
+int sink;
+long pair[2];
+
+void bump(int n) {
+  counter += n;
+}
+
+int main(void) {
+  int i;
+  for (i = 1; i <= 3; i++) {
+    bump(i);
+    sink = counter;
+  }
+  /* The first call to atoi goes through the dynamic linker. */
+  pair[1] = sink + atoi("0");
+  return sink;
+}